@@ -0,0 +1,58 @@
+use crate::audio::AudioSource;
+use anyhow::{Context, Result};
+use std::fs;
+
+// The kernel's lists, which need neither alsa-lib nor a sound server to read.
+const CARDS: &str = "/proc/asound/cards";
+const PCMS: &str = "/proc/asound/pcm";
+
+// " 0 [PCH            ]: HDA-Intel - HDA Intel PCH" gives (0, "PCH", "HDA Intel PCH").
+fn parse_card(line: &str) -> Option<(u32, String, String)> {
+    let (number, rest) = line.trim_start().split_once(' ')?;
+    let (id, rest) = rest.trim_start().strip_prefix('[')?.split_once(']')?;
+    let name = rest.split_once(" - ").map_or(rest, |(_, name)| name).trim();
+    Some((number.parse().ok()?, id.trim().to_string(), name.to_string()))
+}
+
+// "00-00: ALC892 Analog : ALC892 Analog : playback 1 : capture 1" gives (0, 0, "ALC892
+// Analog") for devices that capture.
+fn parse_pcm(line: &str) -> Option<(u32, u32, String)> {
+    let (address, rest) = line.split_once(": ")?;
+    let (card, device) = address.split_once('-')?;
+    let mut fields = rest.split(" : ");
+    let name = fields.next()?.trim().to_string();
+    fields.any(|field| field.trim().starts_with("capture")).then_some(())?;
+    Some((card.parse().ok()?, device.parse().ok()?, name))
+}
+
+// Whether something records from the device now, e.g. another stream.
+fn is_running(card: u32, device: u32) -> bool {
+    fs::read_to_string(format!("/proc/asound/card{}/pcm{}c/sub0/status", card, device)).is_ok_and(|status| status.contains("RUNNING"))
+}
+
+// Every capture device of every card, by card ID so the name survives reboots and
+// cards being plugged in another order. plughw converts to whatever rate and format
+// ffmpeg asks for, which the hardware itself may not offer. The first card is ALSA's
+// default; there are no monitors without a sound server to mix the outputs.
+pub fn sources() -> Result<Vec<AudioSource>> {
+    let cards: Vec<(u32, String, String)> = fs::read_to_string(CARDS).with_context(|| format!("Failed to read {}", CARDS))?.lines().filter_map(parse_card).collect();
+    let pcms = fs::read_to_string(PCMS).with_context(|| format!("Failed to read {}; is there a sound card?", PCMS))?;
+    let mut sources: Vec<AudioSource> = pcms
+        .lines()
+        .filter_map(parse_pcm)
+        .filter_map(|(card, device, name)| {
+            let (_, id, card_name) = cards.iter().find(|(number, ..)| *number == card)?;
+            Some(AudioSource {
+                name: format!("plughw:CARD={},DEV={}", id, device),
+                description: format!("{} ({})", name, card_name),
+                is_monitor: false,
+                is_running: is_running(card, device),
+                is_default: card == 0 && device == 0,
+                card: Some(card_name.clone()),
+                spec: None,
+            })
+        })
+        .collect();
+    sources.sort_by_key(|source| !source.is_default);
+    Ok(sources)
+}
@@ -1,5 +1,20 @@
+use crate::{alsa, config::Config, filters::ChannelMode, jack};
 use anyhow::{Context, Result};
-use std::process::Command;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::process::Command;
+
+const CAPTURE_STREAM_ATTEMPTS: usize = 8; // 2 s at 250 ms
+// Each way when switching sources: out, move, back in.
+const SWITCH_FADE: Duration = Duration::from_millis(150);
+const SWITCH_FADE_STEPS: u32 = 5;
 
 #[derive(Debug, Clone)]
 pub struct AudioSource {
@@ -8,14 +23,173 @@ pub struct AudioSource {
     pub is_monitor: bool,
     pub is_running: bool, // Now accurately reflects RUNNING vs IDLE/SUSPENDED
     pub is_default: bool, // Now accurately reflects the default SINK
+    pub card: Option<String>, // Sound card (or Bluetooth device) the source belongs to
+    pub spec: Option<SampleSpec>,
+}
+
+// What the source delivers, from pactl's "Sample Specification: s16le 2ch 44100Hz".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleSpec {
+    pub format: String, // PulseAudio's name, e.g. "s16le" or "float32le"
+    pub channels: u8,
+    pub rate: u32,
+}
+
+impl SampleSpec {
+    fn parse(spec: &str) -> Option<Self> {
+        let mut words = spec.split_whitespace();
+        let format = words.next()?.to_string();
+        let channels = words.next()?.strip_suffix("ch")?.parse().ok()?;
+        let rate = words.next()?.strip_suffix("Hz")?.parse().ok()?;
+        Some(Self { format, channels, rate })
+    }
+
+    // "44.1 kHz · 2 ch · s16le"
+    pub fn label(&self) -> String {
+        format!("{} kHz · {} ch · {}", self.rate as f64 / 1000.0, self.channels, self.format)
+    }
+
+    // Encodes at the source's own rate and channel count where that is no more than
+    // configured, so ffmpeg doesn't resample 44.1 kHz to 48 kHz or upmix a mono mic.
+    pub fn match_config(&self, config: &mut Config) {
+        if self.rate < config.sample_rate && encoder_accepts(&config.audio_codec, self.rate) {
+            config.sample_rate = self.rate;
+        }
+        // The other modes pick their channels themselves.
+        if config.channel_mode == ChannelMode::Passthrough && self.channels < config.channels {
+            config.channels = self.channels;
+        }
+    }
+}
+
+// Opus and MP3 only encode at a few rates; the others take any common one.
+fn encoder_accepts(codec: &str, rate: u32) -> bool {
+    match codec {
+        "libopus" => matches!(rate, 8000 | 12000 | 16000 | 24000 | 48000),
+        "libmp3lame" => matches!(rate, 8000 | 11025 | 12000 | 16000 | 22050 | 24000 | 32000 | 44100 | 48000),
+        _ => true,
+    }
+}
+
+// Where the capture comes from. JACK is for pro-audio setups: the streamer is a
+// JACK client whose input ports a DAW's outputs are patched into, see `jack.rs`.
+// ALSA reads a sound card directly, on systems without a sound server, see `alsa.rs`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureBackend {
+    #[default]
+    Pulse, // PulseAudio, or PipeWire through pipewire-pulse
+    Jack,
+    Alsa,
+}
+
+// Whether a server answered `pactl info`, as of the last source list; see `effective`.
+static PULSE_AVAILABLE: OnceLock<AtomicBool> = OnceLock::new();
+
+impl CaptureBackend {
+    pub const ALL: [CaptureBackend; 3] = [CaptureBackend::Pulse, CaptureBackend::Jack, CaptureBackend::Alsa];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CaptureBackend::Pulse => "PulseAudio / PipeWire",
+            CaptureBackend::Jack => "JACK",
+            CaptureBackend::Alsa => "ALSA (direct)",
+        }
+    }
+
+    // ffmpeg's input device for it.
+    pub fn ffmpeg_format(self) -> &'static str {
+        match self {
+            CaptureBackend::Pulse => "pulse",
+            CaptureBackend::Jack => "jack",
+            CaptureBackend::Alsa => "alsa",
+        }
+    }
+
+    // What is used: ALSA where PulseAudio is asked for but no server answers, as on a
+    // minimal system. Asked again with every source list, so a server started later
+    // (or one that went away) is noticed on the next refresh.
+    pub fn effective(self) -> Self {
+        let pulse = || {
+            PULSE_AVAILABLE
+                .get_or_init(|| AtomicBool::new(std::process::Command::new("pactl").arg("info").output().is_ok_and(|output| output.status.success())))
+                .load(Ordering::Relaxed)
+        };
+        if self == CaptureBackend::Pulse && !pulse() { CaptureBackend::Alsa } else { self }
+    }
+}
+
+// Whether a sound server answers now, remembered for `CaptureBackend::effective`.
+pub async fn pulse_available() -> bool {
+    let available = Command::new("pactl").arg("info").output().await.is_ok_and(|output| output.status.success());
+    PULSE_AVAILABLE.get_or_init(|| AtomicBool::new(available)).store(available, Ordering::Relaxed);
+    available
+}
+
+// User tweaks for one source, kept in `Config.source_overrides` under the source name,
+// which stays the same across reboots unlike the description's index suffixes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourceOverride {
+    pub nickname: Option<String>, // e.g. "TV" for "HDMI / DisplayPort 2 Output Monitor"
+    pub hidden: bool,             // e.g. echo-cancel sources or a rear mic nobody streams
+}
+
+pub type SourceOverrides = BTreeMap<String, SourceOverride>;
+
+// Quick filter above the source list; most people stream a monitor, a few a microphone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    All,
+    Monitors,
+    Microphones,
+}
+
+impl SourceKind {
+    pub const ALL: [SourceKind; 3] = [SourceKind::All, SourceKind::Monitors, SourceKind::Microphones];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SourceKind::All => "All",
+            SourceKind::Monitors => "🔊 Monitors only",
+            SourceKind::Microphones => "🎤 Microphones only",
+        }
+    }
+
+    pub fn matches(self, source: &AudioSource) -> bool {
+        match self {
+            SourceKind::All => true,
+            SourceKind::Monitors => source.is_monitor,
+            SourceKind::Microphones => !source.is_monitor,
+        }
+    }
+}
+
+impl AudioSource {
+    // The nickname if one is set, otherwise PulseAudio's description.
+    pub fn label<'a>(&'a self, overrides: &'a SourceOverrides) -> &'a str {
+        overrides.get(&self.name).and_then(|o| o.nickname.as_deref()).unwrap_or(&self.description)
+    }
+
+    pub fn is_hidden(&self, overrides: &SourceOverrides) -> bool {
+        overrides.get(&self.name).is_some_and(|o| o.hidden)
+    }
+
+    // Group heading for the source selector, e.g. "HDA Intel PCH — outputs".
+    pub fn group(&self) -> String {
+        let kind = if self.is_monitor { "outputs" } else { "inputs" };
+        format!("{} — {}", self.card.as_deref().unwrap_or("Other"), kind)
+    }
 }
 
 // Fetches the name of the monitor for the default *output* device (speakers/headphones).
 // This is what you actually want to stream to "hear what's playing".
 async fn get_default_sink_monitor_name() -> Result<String> {
     let output = Command::new("pactl")
-        .args(&["get-default-sink"])
+        .args(["get-default-sink"])
         .output()
+        .await
         .context("Failed to run 'pactl get-default-sink'")?;
 
     if !output.status.success() {
@@ -26,10 +200,19 @@ async fn get_default_sink_monitor_name() -> Result<String> {
     Ok(format!("{}.monitor", sink_name))
 }
 
+// One block of `pactl list sources`, before it becomes an `AudioSource`.
+struct ParsedSource {
+    name: String,
+    description: String,
+    state: String,
+    card: Option<String>,
+    spec: Option<SampleSpec>,
+}
+
 // A robust parser for `pactl list sources` that handles the block-based output correctly.
 // This ensures that the state (RUNNING, IDLE, SUSPENDED) is always correctly
 // associated with its source name.
-fn parse_pactl_sources_output(output: &str) -> Vec<(String, String, String)> {
+fn parse_pactl_sources_output(output: &str) -> Vec<ParsedSource> {
     let mut sources = Vec::new();
     // Split the output into blocks for each source. Each block starts with "Source #".
     for block in output.split("Source #") {
@@ -40,6 +223,8 @@ fn parse_pactl_sources_output(output: &str) -> Vec<(String, String, String)> {
         let mut name: Option<String> = None;
         let mut description: Option<String> = None;
         let mut state: Option<String> = None;
+        let mut card: Option<String> = None;
+        let mut spec: Option<SampleSpec> = None;
 
         for line in block.lines() {
             let trimmed = line.trim();
@@ -49,21 +234,41 @@ fn parse_pactl_sources_output(output: &str) -> Vec<(String, String, String)> {
                 description = Some(val.trim().to_string());
             } else if let Some(val) = trimmed.strip_prefix("State:") {
                 state = Some(val.trim().to_string());
+            } else if let Some(val) = trimmed.strip_prefix("Sample Specification:") {
+                spec = SampleSpec::parse(val);
+            } else if let Some((key, val)) = trimmed.split_once(" = ") {
+                // Property names differ between PulseAudio, PipeWire and Bluetooth devices.
+                let is_card_name = matches!(key, "alsa.card_name" | "api.alsa.card.name" | "device.product.name");
+                if is_card_name && card.is_none() {
+                    card = Some(val.trim_matches('"').to_string());
+                }
             }
         }
 
         if let (Some(name), Some(description), Some(state)) = (name, description, state) {
-            sources.push((name, description, state));
+            sources.push(ParsedSource { name, description, state, card, spec });
         }
     }
     sources
 }
 
+// What `backend` can capture, best first.
+pub async fn get_audio_sources(backend: CaptureBackend) -> Result<Vec<AudioSource>> {
+    if backend == CaptureBackend::Pulse {
+        pulse_available().await;
+    }
+    match backend.effective() {
+        CaptureBackend::Pulse => pulse_sources().await,
+        CaptureBackend::Jack => Ok(vec![jack::source()]),
+        CaptureBackend::Alsa => alsa::sources(),
+    }
+}
 
-pub async fn get_audio_sources() -> Result<Vec<AudioSource>> {
+async fn pulse_sources() -> Result<Vec<AudioSource>> {
     let sources_list_output = Command::new("pactl")
-        .args(&["list", "sources"])
+        .args(["list", "sources"])
         .output()
+        .await
         .context("Failed to run 'pactl list sources'")?;
 
     if !sources_list_output.status.success() {
@@ -76,14 +281,14 @@ pub async fn get_audio_sources() -> Result<Vec<AudioSource>> {
     let default_sink_monitor = get_default_sink_monitor_name().await.unwrap_or_default();
 
     let mut sources: Vec<AudioSource> = parsed_sources.into_iter()
-        .map(|(name, description, state)| {
+        .map(|ParsedSource { name, description, state, card, spec }| {
             let is_monitor = name.contains(".monitor");
             // THIS IS THE CRITICAL FIX: Only a state of "RUNNING" counts.
             // "IDLE" and "SUSPENDED" will correctly be treated as not running.
             let is_running = state == "RUNNING";
             let is_default = name == default_sink_monitor;
 
-            AudioSource { name, description, is_monitor, is_running, is_default }
+            AudioSource { name, description, is_monitor, is_running, is_default, card, spec }
         })
         .collect();
 
@@ -104,8 +309,88 @@ pub async fn get_audio_sources() -> Result<Vec<AudioSource>> {
     Ok(sources)
 }
 
-pub fn get_best_source_index(sources: &[AudioSource]) -> usize {
+pub fn get_best_source_index(sources: &[AudioSource], overrides: &SourceOverrides) -> usize {
     // Because the list is now sorted with the highest-priority device at the top,
-    // the best source is always the first one.
-    0
-}
\ No newline at end of file
+    // the best source is the first one the user hasn't hidden.
+    sources.iter().position(|s| !s.is_hidden(overrides)).unwrap_or(0)
+}
+// Our capture process's own recording stream, so the volume we set affects only what
+// is streamed, not the source itself or other apps recording it.
+fn find_capture_stream(output: &str, pid: u32) -> Option<String> {
+    let pid = format!("\"{}\"", pid);
+    output.split("Source Output #").skip(1).find_map(|block| {
+        let index = block.lines().next()?.trim().to_string();
+        block
+            .lines()
+            .any(|line| line.trim().split_once(" = ").is_some_and(|(key, val)| key == "application.process.id" && val == pid))
+            .then_some(index)
+    })
+}
+
+// The recording stream of process `pid`, as pactl numbers it. The process may only
+// just have started, so it gets a moment to open its stream.
+pub async fn capture_stream(pid: u32) -> Result<String> {
+    for _ in 0..CAPTURE_STREAM_ATTEMPTS {
+        let output = Command::new("pactl")
+            .args(["list", "source-outputs"])
+            .output()
+            .await
+            .context("Failed to run 'pactl list source-outputs'")?;
+        if let Some(stream) = find_capture_stream(&String::from_utf8_lossy(&output.stdout), pid) {
+            return Ok(stream);
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+    Err(anyhow::anyhow!("The capture process has no recording stream"))
+}
+
+// Sets the volume (in percent) and mute of one recording stream.
+pub async fn set_stream_volume(stream: &str, percent: u8, muted: bool) -> Result<()> {
+    for args in [
+        ["set-source-output-volume", stream, &format!("{}%", percent)],
+        ["set-source-output-mute", stream, if muted { "1" } else { "0" }],
+    ] {
+        let status = Command::new("pactl").args(args).status().await.context("Failed to run pactl")?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("'pactl {}' failed", args.join(" ")));
+        }
+    }
+    Ok(())
+}
+
+// Sets the volume (in percent) and mute of the stream process `pid` records with.
+pub async fn set_capture_volume(pid: u32, percent: u8, muted: bool) -> Result<()> {
+    set_stream_volume(&capture_stream(pid).await?, percent, muted).await
+}
+
+// Points one recording stream at another source; the recording process notices nothing.
+pub async fn move_stream(stream: &str, source: &str) -> Result<()> {
+    let status = Command::new("pactl").args(["move-source-output", stream, source]).status().await.context("Failed to run pactl")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("'pactl move-source-output {} {}' failed", stream, source));
+    }
+    Ok(())
+}
+
+// Moves what process `pid` records to `source` without restarting it, so the encoder
+// and everything after it carry on. With `fade` the volume dips around the move, so
+// the cut doesn't click; otherwise it's a straight cut.
+pub async fn switch_capture(pid: u32, source: &str, percent: u8, muted: bool, fade: bool) -> Result<()> {
+    let stream = capture_stream(pid).await?;
+    let fade = fade && !muted && percent > 0;
+    let step = SWITCH_FADE / SWITCH_FADE_STEPS;
+    if fade {
+        for i in (0..SWITCH_FADE_STEPS).rev() {
+            set_stream_volume(&stream, (percent as u32 * i / SWITCH_FADE_STEPS) as u8, false).await?;
+            tokio::time::sleep(step).await;
+        }
+    }
+    let moved = move_stream(&stream, source).await;
+    if fade {
+        for i in 1..=SWITCH_FADE_STEPS {
+            tokio::time::sleep(step).await;
+            set_stream_volume(&stream, (percent as u32 * i / SWITCH_FADE_STEPS) as u8, false).await?;
+        }
+    }
+    moved
+}
@@ -0,0 +1,142 @@
+use crate::{config::Config, ffmpeg::locate_ffmpeg};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    f64::consts::PI,
+    io::{Read, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+const BURST_SECS: f64 = 0.05;
+// The receiver decodes to mono at this rate just for detection.
+const DETECT_RATE: u32 = 48000;
+const BLOCK_SAMPLES: usize = 240; // 5 ms, so an onset is placed to within a block
+
+// A short tone the sender mixes in every few seconds, on its stream clock, so the
+// receiver can tell how long it took to come out of the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Beacon {
+    Off,
+    Inaudible, // 18 kHz and quiet; needs 44.1 kHz or more and a codec that keeps the top octave
+    Audible,   // 1 kHz blip, for codecs or rates that cut the inaudible one
+}
+
+impl Beacon {
+    pub const ALL: [Beacon; 3] = [Beacon::Off, Beacon::Inaudible, Beacon::Audible];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Beacon::Off => "Off",
+            Beacon::Inaudible => "Inaudible (18 kHz)",
+            Beacon::Audible => "Audible (1 kHz)",
+        }
+    }
+
+    fn tone(self) -> Option<(f64, f64)> {
+        match self {
+            Beacon::Off => None,
+            Beacon::Inaudible => Some((18000.0, 0.03)),
+            Beacon::Audible => Some((1000.0, 0.1)),
+        }
+    }
+}
+
+// Mixed into every channel of the captured audio. ffmpeg's `t` counts from the start
+// of capture, which begins right after the relay's stream clock does, so beacon k
+// goes out at roughly k intervals on that clock. Commas are escaped for the filtergraph parser.
+pub fn beacon_filter(config: &Config) -> Option<String> {
+    let (frequency, amplitude) = config.beacon.tone()?;
+    Some(format!(
+        "aeval=exprs=val(ch)+if(lt(mod(t\\,{})\\,{})\\,{}*sin(2*PI*{}*t)\\,0):c=same",
+        config.beacon_interval_secs.max(1), BURST_SECS, amplitude, frequency
+    ))
+}
+
+// Goertzel magnitude of `frequency` in the block, scaled so a full-scale sine gives 1.0.
+fn tone_amplitude(block: &[f64], frequency: f64) -> f64 {
+    let coefficient = 2.0 * (2.0 * PI * frequency / DETECT_RATE as f64).cos();
+    let (mut previous, mut before) = (0.0, 0.0);
+    for sample in block {
+        let current = sample + coefficient * previous - before;
+        before = previous;
+        previous = current;
+    }
+    let power = previous * previous + before * before - coefficient * previous * before;
+    2.0 * power.max(0.0).sqrt() / block.len() as f64
+}
+
+// Receiver side: a second decoder fed the same MPEG-TS as the player, whose output is
+// scanned for beacon onsets. The times it reports are when the decoder produced the
+// beacon, so the player's own output buffer is not included.
+pub struct BeaconDetector {
+    decoder: Child,
+    input: ChildStdin,
+    pub onsets: UnboundedReceiver<Instant>,
+}
+
+impl BeaconDetector {
+    pub fn start(config: &Config) -> Result<Option<Self>> {
+        let Some((frequency, amplitude)) = config.beacon.tone() else {
+            return Ok(None);
+        };
+        let ffmpeg = locate_ffmpeg(config)?;
+        let rate = DETECT_RATE.to_string();
+        let mut decoder = Command::new(ffmpeg)
+            .args(["-loglevel", "error", "-fflags", "nobuffer", "-f", "mpegts", "-i", "pipe:0"])
+            // Without per-packet flushing the pipe buffer alone would add a third of a second.
+            .args(["-ac", "1", "-ar", rate.as_str(), "-flush_packets", "1", "-f", "s16le", "pipe:1"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to start the beacon decoder")?;
+        let input = decoder.stdin.take().context("Beacon decoder has no stdin")?;
+        let mut output = decoder.stdout.take().context("Beacon decoder has no stdout")?;
+
+        let (onset_tx, onsets) = unbounded_channel();
+        // Half the beacon's amplitude, so codec losses and masking still leave it detectable.
+        let threshold = amplitude / 2.0;
+        let holdoff = Duration::from_secs(config.beacon_interval_secs.max(1) as u64) / 2;
+        thread::spawn(move || {
+            let mut bytes = [0u8; BLOCK_SAMPLES * 2];
+            let mut last_onset: Option<Instant> = None;
+            while output.read_exact(&mut bytes).is_ok() {
+                let block: Vec<f64> = bytes.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]]) as f64 / 32768.0).collect();
+                if tone_amplitude(&block, frequency) < threshold {
+                    continue;
+                }
+                let now = Instant::now();
+                if last_onset.is_none_or(|last| now - last >= holdoff) {
+                    last_onset = Some(now);
+                    if onset_tx.send(now).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Some(Self { decoder, input, onsets }))
+    }
+
+    // Errors once the decoder is gone; the caller then stops feeding it.
+    pub fn feed(&mut self, payload: &[u8]) -> std::io::Result<()> {
+        self.input.write_all(payload)
+    }
+}
+
+impl Drop for BeaconDetector {
+    fn drop(&mut self) {
+        let _ = self.decoder.kill();
+        let _ = self.decoder.wait();
+    }
+}
+
+// Beacon k is emitted at k intervals on the sender's stream clock, so how far past
+// the last multiple an onset was heard is the latency, as long as it stays under one interval.
+pub fn beacon_latency(sender_now_us: u64, interval_secs: u32) -> Duration {
+    let interval_us = interval_secs.max(1) as u64 * 1_000_000;
+    Duration::from_micros(sender_now_us % interval_us)
+}
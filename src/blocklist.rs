@@ -0,0 +1,189 @@
+use crate::log;
+use anyhow::{Context, Result, bail};
+use std::{
+    collections::BTreeSet,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    time::Duration,
+};
+use tokio::{process::Command, runtime::Handle};
+
+// A notification sound is over in a second or two, so it has to be caught quickly.
+const TICK: Duration = Duration::from_millis(250);
+
+// One block of `pactl list sink-inputs`: something an app plays into a sink.
+struct Playback {
+    index: String,
+    sink: String,
+    muted: bool,
+    app: Option<String>,    // application.name, e.g. "Firefox" or "ZOOM VoiceEngine"
+    binary: Option<String>, // application.process.binary, e.g. "firefox" or "zoom"
+}
+
+impl Playback {
+    fn label(&self) -> &str {
+        self.app.as_deref().or(self.binary.as_deref()).unwrap_or(&self.index)
+    }
+}
+
+fn parse_sink_inputs(output: &str) -> Vec<Playback> {
+    output
+        .split("Sink Input #")
+        .skip(1)
+        .filter_map(|block| {
+            let mut lines = block.lines();
+            let index = lines.next()?.trim().to_string();
+            let (mut sink, mut muted, mut app, mut binary) = (None, false, None, None);
+            for line in lines {
+                let line = line.trim();
+                if let Some(val) = line.strip_prefix("Sink:") {
+                    sink = Some(val.trim().to_string());
+                } else if let Some(val) = line.strip_prefix("Mute:") {
+                    muted = val.trim() == "yes";
+                } else if let Some((key, val)) = line.split_once(" = ") {
+                    let val = Some(val.trim_matches('"').to_string());
+                    match key {
+                        "application.name" => app = val,
+                        "application.process.binary" => binary = val,
+                        _ => {}
+                    }
+                }
+            }
+            Some(Playback { index, sink: sink?, muted, app, binary })
+        })
+        .collect()
+}
+
+async fn sink_inputs() -> Result<Vec<Playback>> {
+    let output = Command::new("pactl").args(["list", "sink-inputs"]).output().await.context("Failed to run 'pactl list sink-inputs'")?;
+    if !output.status.success() {
+        bail!("Failed to list sink inputs");
+    }
+    Ok(parse_sink_inputs(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Sink inputs name their sink by index, which changes when it comes back, so it's
+// looked up by name every time.
+async fn sink_index(name: &str) -> Result<Option<String>> {
+    let output = Command::new("pactl").args(["list", "short", "sinks"]).output().await.context("Failed to run 'pactl list short sinks'")?;
+    Ok(String::from_utf8_lossy(&output.stdout).lines().find_map(|line| {
+        let mut fields = line.split('\t');
+        let index = fields.next()?;
+        (fields.next()? == name).then(|| index.to_string())
+    }))
+}
+
+async fn set_mute(index: &str, muted: bool) -> Result<()> {
+    let status = Command::new("pactl").args(["set-sink-input-mute", index, if muted { "1" } else { "0" }]).status().await.context("Failed to run pactl")?;
+    if !status.success() {
+        bail!("'pactl set-sink-input-mute {}' failed", index);
+    }
+    Ok(())
+}
+
+// Entries are matched against the app's name and its program, ignoring case, so
+// "zoom" and "KeePassXC" both work.
+fn is_blocked(apps: &[String], playback: &Playback) -> bool {
+    apps.iter()
+        .map(|app| app.trim())
+        .any(|app| [&playback.app, &playback.binary].into_iter().flatten().any(|name| name.eq_ignore_ascii_case(app)))
+}
+
+// For `StreamEvent::AppBlocked`.
+pub fn blocked_message(app: &str) -> String {
+    format!("⚠ {} started playing and was muted: it's on the do-not-stream list", app)
+}
+
+// Mutes what blocked apps play into the sink whose monitor is streamed, until the
+// stream stops, so a password manager's alert or a video call stays in the room.
+// Muting is the only way to keep one app out of a monitor, which carries everything
+// the sink plays, so they go quiet locally too. Apps the user had muted already are
+// left muted when it stops.
+pub struct Blocker {
+    muted: Arc<Mutex<BTreeSet<String>>>, // Sink inputs muted by us, to unmute when done
+    blocked_rx: Receiver<String>,        // What got muted, for a warning
+    stopped: Arc<AtomicBool>,
+    runtime: Handle,
+}
+
+// Lists what plays into `sink`, or None while it is gone, e.g. a Bluetooth headset reconnecting.
+async fn playing_into(sink: &str) -> Result<Option<Vec<Playback>>> {
+    let Some(index) = sink_index(sink).await? else {
+        return Ok(None);
+    };
+    Ok(Some(sink_inputs().await?.into_iter().filter(|playback| playback.sink == index).collect()))
+}
+
+impl Blocker {
+    // `sink` is the name of the sink, i.e. the monitor's without ".monitor".
+    pub fn start(sink: &str, apps: Vec<String>, runtime_handle: &Handle) -> Self {
+        let (muted, stopped) = (Arc::new(Mutex::new(BTreeSet::new())), Arc::new(AtomicBool::new(false)));
+        let (blocked_tx, blocked_rx) = mpsc::channel();
+        let (ours, stop, sink) = (muted.clone(), stopped.clone(), sink.to_string());
+        runtime_handle.spawn(async move {
+            let mut ticks = tokio::time::interval(TICK);
+            while !stop.load(Ordering::Relaxed) {
+                ticks.tick().await;
+                let playing = match playing_into(&sink).await {
+                    Ok(Some(playing)) => playing,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        log!("Do-not-stream list off: {:#}", e);
+                        return;
+                    }
+                };
+                // The lock is only held between pactl calls, never across one.
+                let again: BTreeSet<String> = {
+                    let mut ours = ours.lock().unwrap();
+                    ours.retain(|index| playing.iter().any(|playback| &playback.index == index));
+                    ours.clone() // Unmuted by hand since, and warned about once
+                };
+                for playback in playing.iter().filter(|playback| !playback.muted && is_blocked(&apps, playback)) {
+                    if let Err(e) = set_mute(&playback.index, true).await {
+                        log!("Could not mute {}: {:#}", playback.label(), e);
+                        continue;
+                    }
+                    let first = !again.contains(&playback.index);
+                    let kept = {
+                        let mut ours = ours.lock().unwrap();
+                        // `stop` has already given back what it found.
+                        let kept = !stop.load(Ordering::Relaxed);
+                        if kept {
+                            ours.insert(playback.index.clone());
+                        }
+                        kept
+                    };
+                    if !kept {
+                        let _ = set_mute(&playback.index, false).await;
+                        return;
+                    }
+                    if first {
+                        let _ = blocked_tx.send(playback.label().to_string());
+                    }
+                }
+            }
+        });
+        Self { muted, blocked_rx, stopped, runtime: runtime_handle.clone() }
+    }
+
+    // Apps muted since the last call.
+    pub fn blocked(&self) -> Vec<String> {
+        self.blocked_rx.try_iter().collect()
+    }
+
+    // Gives the muted apps their sound back straight away, since the app may be exiting.
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        let muted = std::mem::take(&mut *self.muted.lock().unwrap());
+        self.runtime.spawn(async move {
+            for index in muted {
+                if let Err(e) = set_mute(&index, false).await {
+                    log!("Could not unmute sink input {}: {:#}", index, e);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result, bail};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use zbus::{
+    Connection,
+    fdo::{ManagedObjects, ObjectManagerProxy},
+};
+
+// The A2DP sink role: headphones and speakers that play what we send them.
+const AUDIO_SINK_UUID: &str = "0000110b-0000-1000-8000-00805f9b34fb";
+// After connecting, the sound server takes a moment to create the device's sink.
+const SINK_WAIT: Duration = Duration::from_secs(10);
+const SINK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const LOOPBACK_LATENCY_MS: u32 = 60;
+
+#[derive(Debug, Clone)]
+pub struct BluetoothSink {
+    pub address: String,
+    pub name: String,
+    pub connected: bool,
+    path: String, // BlueZ object path, e.g. /org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF
+}
+
+impl BluetoothSink {
+    pub fn label(&self) -> String {
+        let status = if self.connected { "" } else { " (not connected)" };
+        format!("🎧 {}{}", self.name, status)
+    }
+}
+
+async fn managed_objects() -> Result<ManagedObjects> {
+    let connection = Connection::system().await?;
+    Ok(ObjectManagerProxy::builder(&connection).destination("org.bluez")?.path("/")?.build().await?.get_managed_objects().await?)
+}
+
+// Paired A2DP sinks from BlueZ's object tree on the system bus.
+pub async fn paired_sinks() -> Vec<BluetoothSink> {
+    let Ok(objects) = managed_objects().await else {
+        return Vec::new();
+    };
+    let mut sinks: Vec<BluetoothSink> = objects
+        .iter()
+        .filter_map(|(path, interfaces)| {
+            let (_, device) = interfaces.iter().find(|(name, _)| name.as_str() == "org.bluez.Device1")?;
+            let text = |key: &str| device.get(key).and_then(|value| <&str>::try_from(value).ok());
+            let flag = |key: &str| device.get(key).and_then(|value| bool::try_from(value).ok());
+            let uuids = Vec::<String>::try_from(device.get("UUIDs")?.try_clone().ok()?).ok()?;
+            if !flag("Paired")? || !uuids.iter().any(|uuid| uuid == AUDIO_SINK_UUID) {
+                return None;
+            }
+            let address = text("Address")?.to_string();
+            let name = text("Alias").or(text("Name")).unwrap_or(&address).to_string();
+            let connected = flag("Connected").unwrap_or(false);
+            Some(BluetoothSink { address, name, connected, path: path.to_string() })
+        })
+        .collect();
+    sinks.sort_by(|a, b| b.connected.cmp(&a.connected).then_with(|| a.name.cmp(&b.name)));
+    sinks
+}
+
+async fn connect(sink: &BluetoothSink) -> Result<()> {
+    let connection = Connection::system().await.context("Could not reach the system bus")?;
+    connection
+        .call_method(Some("org.bluez"), sink.path.as_str(), Some("org.bluez.Device1"), "Connect", &())
+        .await
+        .with_context(|| format!("Could not connect to {}", sink.name))?;
+    Ok(())
+}
+
+// PulseAudio names it bluez_sink.AA_BB_CC_DD_EE_FF.a2dp_sink, PipeWire bluez_output.AA_BB_CC_DD_EE_FF.1.
+async fn wait_for_pulse_sink(sink: &BluetoothSink) -> Result<String> {
+    let needle = sink.address.replace(':', "_");
+    let deadline = Instant::now() + SINK_WAIT;
+    loop {
+        let output = Command::new("pactl").args(["list", "short", "sinks"]).output().await.context("Failed to run 'pactl list short sinks'")?;
+        let sinks = String::from_utf8_lossy(&output.stdout);
+        if let Some(name) = sinks.lines().filter_map(|line| line.split('\t').nth(1)).find(|name| name.contains(&needle)) {
+            return Ok(name.to_string());
+        }
+        if Instant::now() >= deadline {
+            bail!("{} is connected but the sound server has no sink for it; is A2DP enabled for it?", sink.name);
+        }
+        tokio::time::sleep(SINK_POLL_INTERVAL).await;
+    }
+}
+
+// Once connected, the sound server owns the A2DP link and its codec, so the stream
+// goes through it: a loopback module plays the captured source into the device's sink.
+pub struct BluetoothRoute {
+    module: String,
+    pub device: String,
+}
+
+impl BluetoothRoute {
+    pub async fn start(source: &str, sink: &BluetoothSink) -> Result<Self> {
+        if !sink.connected {
+            connect(sink).await?;
+        }
+        let pulse_sink = wait_for_pulse_sink(sink).await?;
+        // Capturing the headphones' own output and playing it back into them would feed back.
+        if source == format!("{}.monitor", pulse_sink) {
+            bail!("Pick a source other than {}'s own monitor", sink.name);
+        }
+        let output = Command::new("pactl")
+            .args(["load-module", "module-loopback"])
+            .arg(format!("source={}", source))
+            .arg(format!("sink={}", pulse_sink))
+            .arg(format!("latency_msec={}", LOOPBACK_LATENCY_MS))
+            .args(["source_dont_move=true", "sink_dont_move=true"])
+            .output()
+            .await
+            .context("Failed to run pactl")?;
+        if !output.status.success() {
+            bail!("Could not route audio to {}: {}", sink.name, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(Self { module: String::from_utf8_lossy(&output.stdout).trim().to_string(), device: sink.name.clone() })
+    }
+
+    // The module belongs to the sound server, so it outlives us unless unloaded.
+    pub async fn stop(self) {
+        let _ = Command::new("pactl").args(["unload-module", self.module.as_str()]).status().await;
+    }
+}
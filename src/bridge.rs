@@ -0,0 +1,248 @@
+use crate::{
+    access::{AccessPolicy, Listeners},
+    outputs::serve_http,
+    rendezvous::resolve_with_default,
+    tag::random_uuid,
+    transport::{Packet, PacketKind},
+};
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    io::{Write, stdout},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{net::UdpSocket, sync::broadcast, time::interval};
+
+pub const DEFAULT_PORT: u16 = 9000;
+// Receivers keep pulling by sending anything at least this often; ours send a keepalive every second.
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(60);
+// Senders register every second, also while paused, so this long without it means it stopped.
+const SENDER_TIMEOUT: Duration = Duration::from_secs(5);
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+const HTTP_QUEUE: usize = 256;
+const MPEGTS_SYNC: u8 = 0x47;
+// Text datagrams that start with this, like the rendezvous helper's.
+const MAGIC: &str = "ASRL";
+// Subscribes are padded to this, longer than the challenge they get, so one sent from a
+// spoofed address can't make us send its victim more than it cost.
+const SUBSCRIBE_LEN: usize = 128;
+// Receivers subscribe again this often, which also gets them back in after a server restart.
+pub const SUBSCRIBE_INTERVAL: Duration = Duration::from_secs(10);
+// Senders register this often; the main path and a second one each count.
+pub const REGISTER_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_SENDERS: usize = 4;
+const MAX_SUBSCRIBERS: usize = 32;
+// A challenge is good for the minute it was made in and the next.
+const COOKIE_PERIOD_SECS: u64 = 60;
+
+// What senders, receivers and the server say to each other, e.g. "ASRL register 5f1c…".
+// The key is the server's, which it prints when it starts; without it the stream can't
+// be sent or pulled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Register { key: String },               // From the sender: its stream is taken from this address
+    Subscribe { key: String },              // From a receiver, answered with a challenge
+    Challenge { cookie: String },           // Only reaches a receiver that really has its address
+    Join { key: String, cookie: String },   // The challenge sent back, which makes it a subscriber
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut text = match self {
+            Message::Register { key } => format!("{} register {}", MAGIC, key),
+            Message::Subscribe { key } => format!("{} subscribe {}", MAGIC, key),
+            Message::Challenge { cookie } => format!("{} challenge {}", MAGIC, cookie),
+            Message::Join { key, cookie } => format!("{} join {} {}", MAGIC, key, cookie),
+        };
+        if let Message::Subscribe { .. } = self {
+            text = format!("{:<width$}", text, width = SUBSCRIBE_LEN);
+        }
+        text.into_bytes()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data).ok()?;
+        let mut words = text.strip_prefix(MAGIC)?.split_whitespace();
+        let message = match (words.next()?, words.next()?) {
+            ("register", key) => Message::Register { key: key.to_string() },
+            ("subscribe", key) if data.len() >= SUBSCRIBE_LEN => Message::Subscribe { key: key.to_string() },
+            ("challenge", cookie) => Message::Challenge { cookie: cookie.to_string() },
+            ("join", key) => Message::Join { key: key.to_string(), cookie: words.next()?.to_string() },
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+// The key must be one word, since it goes in datagrams and HTTP URLs.
+pub fn check_key(key: &str) -> Result<()> {
+    if key.is_empty() || key.contains(|c: char| c.is_whitespace() || c == '&' || c == '#') {
+        bail!("The relay server key must be one word, without '&' or '#'");
+    }
+    Ok(())
+}
+
+// "host" or "host:port"; the relay server's port defaults to 9000.
+pub async fn resolve(server: &str) -> Result<SocketAddr> {
+    resolve_with_default(server, DEFAULT_PORT).await
+}
+
+// A native receiver's keepalive, remote command or resume report, which goes on to the
+// sender. Clock sync needs a direct path, so time requests only keep the subscription.
+fn for_sender(data: &[u8]) -> bool {
+    Packet::decode(data).is_some_and(|packet| matches!(packet.kind, PacketKind::Keepalive | PacketKind::Control | PacketKind::Resumed))
+}
+
+// Secret to this run of the server, so only it can make and check challenges.
+struct Cookies {
+    secret: String,
+}
+
+impl Cookies {
+    fn make(&self, address: SocketAddr, period: u64) -> String {
+        let digest = Sha256::digest(format!("{} {} {}", self.secret, address, period));
+        digest.iter().take(16).map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn period() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) / COOKIE_PERIOD_SECS
+    }
+
+    fn issue(&self, address: SocketAddr) -> String {
+        self.make(address, Self::period())
+    }
+
+    fn check(&self, address: SocketAddr, cookie: &str) -> bool {
+        let period = Self::period();
+        [period, period.saturating_sub(1)].into_iter().any(|period| self.make(address, period) == cookie)
+    }
+}
+
+// What HTTP clients get: the MPEG-TS, unwrapped from the native transport. RTP has none.
+fn mpegts(data: &[u8]) -> Option<Vec<u8>> {
+    match Packet::decode(data) {
+        Some(packet) if packet.kind == PacketKind::Data => Some(packet.payload),
+        Some(_) => None,
+        None => (data.first() == Some(&MPEGTS_SYNC)).then(|| data.to_vec()),
+    }
+}
+
+// Relay server mode, for a machine both sides can reach (e.g. a VPS): takes one sender's
+// stream on UDP `listen` and re-serves it, so neither the sender nor the receivers need a
+// public address. Everyone needs `key` (a random one when None): the sender registers
+// with it, receivers pull over UDP once they have answered a challenge, and players open
+// http://<server>:<port>/?token=<key> for the MPEG-TS.
+pub async fn run_server(listen: SocketAddr, key: Option<String>) -> Result<()> {
+    let key = key.unwrap_or_else(|| random_uuid().replace('-', ""));
+    check_key(&key)?;
+    let socket = UdpSocket::bind(listen).await.with_context(|| format!("Failed to listen on UDP {}", listen))?;
+    let http = std::net::TcpListener::bind(listen).with_context(|| format!("Failed to listen for HTTP on {}", listen))?;
+    http.set_nonblocking(true)?;
+    let (chunks_tx, chunks) = broadcast::channel(HTTP_QUEUE);
+    let listeners = Listeners::new(AccessPolicy::with_token(&key));
+    tokio::spawn(serve_http(http, chunks, listeners.clone(), "HTTP".to_string(), None));
+    println!("Relay server on UDP and HTTP {} with key {} (Ctrl+C to stop)", listen, key);
+    println!("Send to it with the UDP or native transport and this key as the relay server key; receivers pull with");
+    println!("  audio-streamer receive 5000 --relay <this server>:{} --relay-key {}", listen.port(), key);
+    println!("  or any player at http://<this server>:{}/?token={} (not with the built-in engine, whose RTP has no MPEG-TS)", listen.port(), key);
+
+    let cookies = Cookies { secret: random_uuid() };
+    let mut senders: HashMap<SocketAddr, Instant> = HashMap::new(); // By when they last registered
+    let mut subscribers: HashMap<SocketAddr, Instant> = HashMap::new();
+    let mut bytes_in = 0usize;
+    let mut buf = vec![0u8; 65536];
+    let mut stats_timer = interval(STATS_INTERVAL);
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let Ok((len, from)) = received else {
+                    continue;
+                };
+                let data = &buf[..len];
+                match Message::decode(data) {
+                    Some(Message::Register { key: given }) if given == key => {
+                        // A restarted sender comes back from a new port; the oldest goes.
+                        if !senders.contains_key(&from) {
+                            println!("\nSender {} connected", from);
+                            if senders.len() >= MAX_SENDERS
+                                && let Some(oldest) = senders.iter().min_by_key(|(_, seen)| **seen).map(|(address, _)| *address)
+                            {
+                                senders.remove(&oldest);
+                            }
+                        }
+                        senders.insert(from, Instant::now());
+                        continue;
+                    }
+                    Some(Message::Subscribe { key: given }) if given == key => {
+                        let _ = socket.send_to(&Message::Challenge { cookie: cookies.issue(from) }.encode(), from).await;
+                        continue;
+                    }
+                    Some(Message::Join { key: given, cookie }) if given == key && cookies.check(from, &cookie) => {
+                        if subscribers.contains_key(&from) || subscribers.len() < MAX_SUBSCRIBERS {
+                            subscribe(&mut subscribers, from);
+                        } else {
+                            println!("\nReceiver {} turned away, {} are pulling already", from, MAX_SUBSCRIBERS);
+                        }
+                        continue;
+                    }
+                    Some(_) => continue, // A wrong key, or a bad challenge
+                    None => {}
+                }
+                if !senders.contains_key(&from) {
+                    // Only subscribers are heard, and only refresh their subscription.
+                    if let Some(seen) = subscribers.get_mut(&from) {
+                        *seen = Instant::now();
+                        if for_sender(data) {
+                            for sender in senders.keys() {
+                                let _ = socket.send_to(data, sender).await;
+                            }
+                        }
+                    }
+                    continue;
+                }
+                bytes_in += len;
+                for subscriber in subscribers.keys() {
+                    let _ = socket.send_to(data, subscriber).await;
+                }
+                if let Some(chunk) = mpegts(data) {
+                    let _ = chunks_tx.send(Arc::from(chunk));
+                }
+            }
+            _ = stats_timer.tick() => {
+                senders.retain(|address, seen| {
+                    let alive = seen.elapsed() < SENDER_TIMEOUT;
+                    if !alive {
+                        println!("\nSender {} stopped", address);
+                    }
+                    alive
+                });
+                subscribers.retain(|address, seen| {
+                    let alive = seen.elapsed() < SUBSCRIPTION_TIMEOUT;
+                    if !alive {
+                        println!("\nReceiver {} left", address);
+                    }
+                    alive
+                });
+                let from = senders.keys().next().map_or_else(|| "nobody".to_string(), SocketAddr::to_string);
+                print!(
+                    "\rfrom {} at {:>4} kbit/s | {} UDP, {} HTTP receivers   ",
+                    from,
+                    bytes_in * 8 / 1000,
+                    subscribers.len(),
+                    listeners.list().len()
+                );
+                let _ = stdout().flush();
+                bytes_in = 0;
+            }
+        }
+    }
+}
+
+fn subscribe(subscribers: &mut HashMap<SocketAddr, Instant>, from: SocketAddr) {
+    if subscribers.insert(from, Instant::now()).is_none() {
+        println!("\nReceiver {} joined", from);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::{
+    access::{Admission, Listeners},
+    log,
+    tls::{CERTIFICATE_PATH, TlsIdentity, certificate_response},
+};
+use anyhow::{Context, Result, bail};
+use std::{
+    net::SocketAddr,
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, UdpSocket},
+    process::{Child, Command},
+    sync::{Notify, Semaphore},
+    time::timeout,
+};
+use webrtc::{
+    api::{
+        APIBuilder, API,
+        interceptor_registry::register_default_interceptors,
+        media_engine::{MIME_TYPE_OPUS, MediaEngine},
+    },
+    ice_transport::ice_server::RTCIceServer,
+    interceptor::registry::Registry,
+    peer_connection::{
+        RTCPeerConnection, configuration::RTCConfiguration, peer_connection_state::RTCPeerConnectionState,
+        sdp::session_description::RTCSessionDescription,
+    },
+    rtp_transceiver::rtp_codec::RTCRtpCodecCapability,
+    track::track_local::{TrackLocal, TrackLocalWriter, track_local_static_rtp::TrackLocalStaticRTP},
+};
+
+const MAX_REQUEST_BYTES: usize = 64 * 1024; // An offer SDP is a few kB
+// A client that hasn't sent its whole request by then is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Connections being answered at once; more wait in the kernel's backlog.
+const MAX_CONNECTIONS: usize = 16;
+
+// The whole player: a button (browsers only play audio after a tap), then a
+// non-trickle offer/answer over POST /offer, with the page's own query (an access
+// token) passed on. ICE_SERVERS is filled in when served.
+const PAGE: &str = r#"<!doctype html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1">
+<title>audio-streamer</title></head>
+<body style="font-family: sans-serif; text-align: center; padding-top: 30vh">
+<button id="play" style="font-size: 2em">▶ Play</button>
+<p id="status"></p>
+<audio id="audio"></audio>
+<script>
+const status = document.getElementById("status");
+document.getElementById("play").onclick = async () => {
+  const pc = new RTCPeerConnection({ iceServers: ICE_SERVERS });
+  pc.addTransceiver("audio", { direction: "recvonly" });
+  pc.ontrack = (event) => {
+    event.receiver.jitterBufferTarget = 0; // As little buffering as the network allows
+    const audio = document.getElementById("audio");
+    audio.srcObject = event.streams[0];
+    audio.play();
+  };
+  pc.onconnectionstatechange = () => { status.textContent = pc.connectionState; };
+  await pc.setLocalDescription(await pc.createOffer());
+  await new Promise((done) => {
+    if (pc.iceGatheringState === "complete") return done();
+    pc.onicegatheringstatechange = () => { if (pc.iceGatheringState === "complete") done(); };
+  });
+  const answer = await fetch("/offer" + location.search, { method: "POST", body: JSON.stringify(pc.localDescription) });
+  if (!answer.ok) { status.textContent = await answer.text(); return; }
+  await pc.setRemoteDescription(await answer.json());
+};
+</script></body></html>
+"#;
+
+// Browsers only take Opus, so the MPEG-TS is re-encoded for them, in small frames
+// to keep the latency down, and sent to us as RTP to hand to every peer.
+pub fn start_encoder(ffmpeg: &Path, rtp_target: SocketAddr) -> Result<Child> {
+    Command::new(ffmpeg)
+        .args(["-loglevel", "error", "-fflags", "nobuffer", "-f", "mpegts", "-i", "pipe:0"])
+        .args(["-c:a", "libopus", "-b:a", "128k", "-application", "lowdelay", "-frame_duration", "10", "-ar", "48000", "-ac", "2"])
+        .args(["-f", "rtp"])
+        .arg(format!("rtp://{}?pkt_size=1200", rtp_target))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start the WebRTC encoder")
+}
+
+// Peers are closed with the output; dropping one alone leaves its ICE agent running.
+struct Peers(Vec<Arc<RTCPeerConnection>>);
+
+impl Drop for Peers {
+    fn drop(&mut self) {
+        for peer in self.0.drain(..) {
+            tokio::spawn(async move {
+                let _ = peer.close().await;
+            });
+        }
+    }
+}
+
+pub struct BrowserPlayer {
+    signaling: Arc<Signaling>,
+}
+
+// What each connection is answered with, shared by their tasks.
+struct Signaling {
+    api: API,
+    track: Arc<TrackLocalStaticRTP>,
+    ice_servers: Vec<RTCIceServer>,
+    peers: Mutex<Peers>,
+    listeners: Listeners,
+    name: String, // How its peers are listed, e.g. "WebRTC :8081"
+    tls: Option<Arc<TlsIdentity>>, // Served over HTTPS when set
+}
+
+impl BrowserPlayer {
+    // Without a STUN server only host candidates are gathered, so it works on the LAN (or a VPN) only.
+    pub fn new(stun: &str, listeners: Listeners, name: String, tls: Option<Arc<TlsIdentity>>) -> Result<Self> {
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs()?;
+        let registry = register_default_interceptors(Registry::new(), &mut media_engine)?;
+        let api = APIBuilder::new().with_media_engine(media_engine).with_interceptor_registry(registry).build();
+        let codec = RTCRtpCodecCapability { mime_type: MIME_TYPE_OPUS.to_string(), clock_rate: 48000, channels: 2, ..Default::default() };
+        let track = Arc::new(TrackLocalStaticRTP::new(codec, "audio".to_string(), "audio-streamer".to_string()));
+        let ice_servers = match stun.trim() {
+            "" => Vec::new(),
+            stun => vec![RTCIceServer { urls: vec![format!("stun:{}", stun.trim_start_matches("stun:"))], ..Default::default() }],
+        };
+        let signaling = Signaling { api, track, ice_servers, peers: Mutex::new(Peers(Vec::new())), listeners, name, tls };
+        Ok(Self { signaling: Arc::new(signaling) })
+    }
+
+    // Serves the page and its signaling, and forwards the encoder's RTP to every peer.
+    // Each client gets a task of its own, so a slow one doesn't hold up the audio.
+    pub async fn run(self, listener: std::net::TcpListener, rtp: std::net::UdpSocket) -> Result<()> {
+        let listener = TcpListener::from_std(listener)?;
+        let rtp = UdpSocket::from_std(rtp)?;
+        let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+        let mut packet = vec![0u8; 1500];
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let Ok((client, address)) = accepted else {
+                        continue;
+                    };
+                    let Ok(permit) = Arc::clone(&connections).try_acquire_owned() else {
+                        continue; // Dropping it closes the connection
+                    };
+                    let signaling = Arc::clone(&self.signaling);
+                    tokio::spawn(async move {
+                        let _permit = permit;
+                        let handled = match &signaling.tls {
+                            Some(tls) => match tls.accept(client).await {
+                                Ok(client) => signaling.handle(client, address).await,
+                                Err(_) => Ok(()), // Typically a browser that doesn't trust the certificate yet
+                            },
+                            None => signaling.handle(client, address).await,
+                        };
+                        if let Err(e) = handled {
+                            log!("WebRTC signaling failed: {:#}", e);
+                        }
+                    });
+                }
+                received = rtp.recv(&mut packet) => {
+                    let len = received?;
+                    let _ = self.signaling.track.write(&packet[..len]).await; // Fails only while nobody is connected
+                    self.signaling.listeners.sent_to_all(&self.signaling.name, len);
+                }
+            }
+        }
+    }
+}
+
+impl Signaling {
+    fn page(&self) -> String {
+        let urls: Vec<String> = self.ice_servers.iter().flat_map(|server| &server.urls).map(|url| format!("{{\"urls\":\"{}\"}}", url)).collect();
+        PAGE.replace("ICE_SERVERS", &format!("[{}]", urls.join(",")))
+    }
+
+    async fn answer(&self, offer: RTCSessionDescription, admission: Admission) -> Result<RTCSessionDescription> {
+        let configuration = RTCConfiguration { ice_servers: self.ice_servers.clone(), ..Default::default() };
+        let peer = Arc::new(self.api.new_peer_connection(configuration).await?);
+        let sender = peer.add_track(Arc::clone(&self.track) as Arc<dyn TrackLocal + Send + Sync>).await?;
+        // RTCP has to be read for the interceptors to run; nothing in it is needed here.
+        tokio::spawn(async move {
+            let mut rtcp = vec![0u8; 1500];
+            while sender.read(&mut rtcp).await.is_ok() {}
+        });
+        let closing = Arc::downgrade(&peer);
+        let ended = Arc::new(Notify::new());
+        let peer_ended = ended.clone();
+        peer.on_peer_connection_state_change(Box::new(move |state| {
+            let (closing, ended) = (closing.clone(), peer_ended.clone());
+            Box::pin(async move {
+                if matches!(state, RTCPeerConnectionState::Failed | RTCPeerConnectionState::Disconnected | RTCPeerConnectionState::Closed) {
+                    ended.notify_one();
+                }
+                if matches!(state, RTCPeerConnectionState::Failed | RTCPeerConnectionState::Disconnected)
+                    && let Some(peer) = closing.upgrade()
+                {
+                    let _ = peer.close().await;
+                }
+            })
+        }));
+        // Listed as a listener until it closes, or is kicked.
+        let kicking = Arc::downgrade(&peer);
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = admission.kicked() => {
+                    if let Some(peer) = kicking.upgrade() {
+                        let _ = peer.close().await;
+                    }
+                }
+                _ = ended.notified() => {}
+            }
+        });
+
+        peer.set_remote_description(offer).await?;
+        let answer = peer.create_answer(None).await?;
+        let mut gathered = peer.gathering_complete_promise().await;
+        peer.set_local_description(answer).await?;
+        let _ = gathered.recv().await; // Non-trickle: the page waits for one complete answer
+        let answer = peer.local_description().await.context("No local description after gathering")?;
+        let mut peers = self.peers.lock().unwrap();
+        peers.0.retain(|peer| peer.connection_state() != RTCPeerConnectionState::Closed);
+        peers.0.push(peer);
+        Ok(answer)
+    }
+
+    async fn handle<S: AsyncRead + AsyncWrite + Unpin>(&self, mut client: S, address: SocketAddr) -> Result<()> {
+        let (head, body) = timeout(REQUEST_TIMEOUT, read_request(&mut client)).await.context("Timed out reading the request")??;
+        let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+        let method = request_line.next().unwrap_or_default();
+        let path = request_line.next().unwrap_or_default().split('?').next().unwrap_or_default();
+        if let Some(tls) = &self.tls
+            && method == "GET"
+            && path == CERTIFICATE_PATH
+        {
+            client.write_all(&certificate_response(&tls.certificate)).await?;
+            return Ok(());
+        }
+        let response = if let Err(refusal) = self.listeners.check(address, &head) {
+            refusal.response()
+        } else if method == "POST" && path == "/offer" {
+            match serde_json::from_slice::<RTCSessionDescription>(&body) {
+                Ok(offer) => match self.answer(offer, self.listeners.join(address, &self.name)).await {
+                    Ok(answer) => http_response("200 OK", "application/json", &serde_json::to_string(&answer)?),
+                    Err(e) => http_response("500 Internal Server Error", "text/plain", &format!("{:#}", e)),
+                },
+                Err(e) => http_response("400 Bad Request", "text/plain", &e.to_string()),
+            }
+        } else if method == "GET" && path == "/" {
+            http_response("200 OK", "text/html; charset=utf-8", &self.page())
+        } else {
+            http_response("404 Not Found", "text/plain", "Not found")
+        };
+        client.write_all(response.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+// The head (request line and headers) and the body.
+async fn read_request<S: AsyncRead + Unpin>(client: &mut S) -> Result<(String, Vec<u8>)> {
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let read = client.read(&mut chunk).await?;
+        if read == 0 {
+            bail!("Client closed the connection mid-request");
+        }
+        request.extend_from_slice(&chunk[..read]);
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if request.len() > MAX_REQUEST_BYTES {
+            bail!("Request too large");
+        }
+    };
+    let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+    let content_length = head
+        .lines()
+        .find_map(|line| line.split_once(':').filter(|(name, _)| name.eq_ignore_ascii_case("content-length")))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+        .min(MAX_REQUEST_BYTES);
+    while request.len() < header_end + content_length {
+        let read = client.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..read]);
+    }
+    Ok((head, request[header_end..].to_vec()))
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.0 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
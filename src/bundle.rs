@@ -0,0 +1,291 @@
+use crate::{config::Config, history::unix_now, outputs::Output, profiles, rollback, secrets};
+use anyhow::{Context, Result, anyhow, bail};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{collections::BTreeMap, num::NonZeroU32, path::Path};
+
+const FORMAT: &str = "audio-streamer-settings";
+const VERSION: u32 = 2;
+const SALT_LEN: usize = 16;
+// OWASP's current figure for PBKDF2-HMAC-SHA256.
+const PBKDF2_ROUNDS: NonZeroU32 = NonZeroU32::new(600_000).unwrap();
+
+// The config and every profile in one file, to move a setup to another machine.
+// Secrets are left out, or carried encrypted with a passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+struct Bundle {
+    format: String,
+    version: u32,
+    exported_at: u64,
+    config: Config,
+    profiles: BTreeMap<String, Config>,
+    secrets: Option<Sealed>,
+}
+
+// The secrets of the config and of each profile, by their `secrets::fields` path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Secrets {
+    config: BTreeMap<String, String>,
+    profiles: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+// Version 1 listed them in `secrets::fields` order instead, which moved with the outputs.
+#[derive(Debug, Deserialize)]
+struct Listed {
+    config: Vec<String>,
+    profiles: BTreeMap<String, Vec<String>>,
+}
+
+impl Listed {
+    fn by_path(self, config: &Config, profiles: &BTreeMap<String, Config>) -> Secrets {
+        let by_path = |config: &Config, values: Vec<String>| {
+            secrets::fields(&mut config.clone()).into_iter().map(|(path, _)| path).zip(values).collect()
+        };
+        let profiles = self
+            .profiles
+            .into_iter()
+            .filter_map(|(name, values)| Some((name.clone(), by_path(profiles.get(&name)?, values))))
+            .collect();
+        Secrets { config: by_path(config, self.config), profiles }
+    }
+}
+
+// AES-256-GCM under a key derived from the passphrase with PBKDF2; hex-encoded.
+#[derive(Debug, Serialize, Deserialize)]
+struct Sealed {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    // This machine's source, Bluetooth sink and source nicknames are kept, and so are
+    // profiles the bundle doesn't have.
+    Merge,
+    // The config and profiles become exactly the bundle's, but for the hooks and ffmpeg path.
+    Replace,
+}
+
+pub struct ImportSummary {
+    pub config: Config,
+    pub profiles: usize,
+    pub secrets: bool, // False when they weren't in the bundle, or no passphrase was given
+}
+
+fn take_secrets(config: &mut Config) -> BTreeMap<String, String> {
+    secrets::fields(config).into_iter().map(|(path, field)| (path, std::mem::take(field))).collect()
+}
+
+fn put_secrets(config: &mut Config, mut values: BTreeMap<String, String>) {
+    for (path, field) in secrets::fields(config) {
+        if let Some(value) = values.remove(&path) {
+            *field = value;
+        }
+    }
+}
+
+fn key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, PBKDF2_ROUNDS, salt, passphrase.as_bytes(), &mut key);
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("32 bytes is an AES-256 key"))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        bail!("Invalid hex");
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).context("Invalid hex")).collect()
+}
+
+fn seal(secrets: &Secrets, passphrase: &str) -> Result<Sealed> {
+    let random = SystemRandom::new();
+    let (mut salt, mut nonce) = ([0u8; SALT_LEN], [0u8; NONCE_LEN]);
+    random.fill(&mut salt).map_err(|_| anyhow!("No randomness available"))?;
+    random.fill(&mut nonce).map_err(|_| anyhow!("No randomness available"))?;
+    let mut data = serde_json::to_vec(secrets)?;
+    key(passphrase, &salt)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    Ok(Sealed { salt: to_hex(&salt), nonce: to_hex(&nonce), ciphertext: to_hex(&data) })
+}
+
+fn open<T: DeserializeOwned>(sealed: &Sealed, passphrase: &str) -> Result<T> {
+    let nonce: [u8; NONCE_LEN] = from_hex(&sealed.nonce)?.try_into().map_err(|_| anyhow!("Invalid nonce"))?;
+    let mut data = from_hex(&sealed.ciphertext)?;
+    let plain = key(passphrase, &from_hex(&sealed.salt)?)
+        .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Wrong passphrase, or the bundle was changed"))?;
+    Ok(serde_json::from_slice(plain)?)
+}
+
+// `passphrase` includes the secrets, encrypted with it.
+pub fn export(config_path: &Path, config: &Config, passphrase: Option<&str>) -> Result<String> {
+    let mut config = config.clone();
+    let mut secrets = Secrets { config: take_secrets(&mut config), ..Default::default() };
+    let mut bundled = BTreeMap::new();
+    for name in profiles::list(config_path)? {
+        let mut profile = profiles::load(config_path, &name)?;
+        secrets.profiles.insert(name.clone(), take_secrets(&mut profile));
+        bundled.insert(name, profile);
+    }
+    let secrets = match passphrase {
+        Some(passphrase) if !passphrase.is_empty() => Some(seal(&secrets, passphrase)?),
+        Some(_) => bail!("Enter a passphrase to include the secrets"),
+        None => None,
+    };
+    let bundle = Bundle { format: FORMAT.to_string(), version: VERSION, exported_at: unix_now(), config, profiles: bundled, secrets };
+    Ok(serde_json::to_string_pretty(&bundle)?)
+}
+
+// Hooks run shell commands and the ffmpeg path names a program to run, so no bundle
+// brings its own, whatever the mode.
+fn keep_commands(imported: &mut Config, local: &Config) {
+    imported.ffmpeg_path = local.ffmpeg_path.clone();
+    imported.hooks = local.hooks.clone();
+}
+
+// What stays with the machine rather than the setup, and, for a bundle without
+// secrets, ours: the token, the relay server key, the rendezvous secret, and the
+// passwords of Icecast outputs to the same mount. Integrations reach out to a broker,
+// so a merged bundle never brings its own.
+fn keep_local(imported: &mut Config, local: &Config, keep_secrets: bool) {
+    keep_commands(imported, local);
+    imported.preferred_source = local.preferred_source.clone();
+    imported.bluetooth_sink = local.bluetooth_sink.clone();
+    imported.source_overrides = local.source_overrides.clone();
+    imported.integrations = local.integrations.clone();
+    if !keep_secrets {
+        return;
+    }
+    imported.listen_token = local.listen_token.clone();
+    imported.relay_server_key = local.relay_server_key.clone();
+    imported.rendezvous_secret = local.rendezvous_secret.clone();
+    for output in &mut imported.outputs {
+        if let Output::Icecast { server, mount, password, .. } = output {
+            let ours = local.outputs.iter().find_map(|output| match output {
+                Output::Icecast { server: our_server, mount: our_mount, password, .. } if our_server == server && our_mount == mount => Some(password),
+                _ => None,
+            });
+            if let Some(ours) = ours {
+                password.clone_from(ours);
+            }
+        }
+    }
+}
+
+// Writes the config file and profiles; the caller switches to the returned config.
+pub fn import(config_path: &Path, current: &Config, json: &str, passphrase: Option<&str>, mode: ImportMode) -> Result<ImportSummary> {
+    let bundle: Bundle = serde_json::from_str(json).context("Not a settings bundle")?;
+    if bundle.format != FORMAT {
+        bail!("Not an audio-streamer settings bundle");
+    }
+    if bundle.version > VERSION {
+        bail!("The bundle is from a newer version of audio-streamer");
+    }
+    let mut secrets = match (&bundle.secrets, passphrase) {
+        (Some(sealed), Some(passphrase)) if !passphrase.is_empty() && bundle.version < 2 => {
+            Some(open::<Listed>(sealed, passphrase)?.by_path(&bundle.config, &bundle.profiles))
+        }
+        (Some(sealed), Some(passphrase)) if !passphrase.is_empty() => Some(open(sealed, passphrase)?),
+        _ => None,
+    };
+    let found_secrets = secrets.is_some();
+
+    let mut config = bundle.config;
+    if let Some(secrets) = &mut secrets {
+        put_secrets(&mut config, std::mem::take(&mut secrets.config));
+    }
+    match mode {
+        ImportMode::Merge => keep_local(&mut config, current, !found_secrets),
+        ImportMode::Replace => keep_commands(&mut config, current),
+    }
+    let count = bundle.profiles.len();
+    let dropped: Vec<String> = match mode {
+        ImportMode::Merge => Vec::new(),
+        ImportMode::Replace => profiles::list(config_path)?.into_iter().filter(|name| !bundle.profiles.contains_key(name)).collect(),
+    };
+    for (name, mut profile) in bundle.profiles {
+        if let Some(values) = secrets.as_mut().and_then(|secrets| secrets.profiles.remove(&name)) {
+            put_secrets(&mut profile, values);
+        }
+        // A profile new to this machine takes what is local from the current config.
+        let local = profiles::load(config_path, &name).unwrap_or_else(|_| current.clone());
+        match mode {
+            ImportMode::Merge => keep_local(&mut profile, &local, !found_secrets),
+            ImportMode::Replace => keep_commands(&mut profile, &local),
+        }
+        profiles::save(config_path, &name, &profile)?;
+    }
+    // Only once the bundle's are saved, so a failure leaves the old ones in place.
+    for name in dropped {
+        profiles::delete(config_path, &name)?;
+    }
+    rollback::save(config_path, &config)?;
+    Ok(ImportSummary { config, profiles: count, secrets: found_secrets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn icecast(password: &str) -> Output {
+        Output::Icecast {
+            server: "example.org:8000".to_string(),
+            mount: "live".to_string(),
+            password: password.to_string(),
+            name: String::new(),
+            description: String::new(),
+        }
+    }
+
+    fn passwords(config: &Config) -> Vec<&str> {
+        config
+            .outputs
+            .iter()
+            .filter_map(|output| match output {
+                Output::Icecast { password, .. } => Some(password.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn secrets_go_back_by_path() {
+        let mut config = Config::default();
+        config.integrations.mqtt.password = "broker".to_string();
+        config.outputs = vec![icecast("first"), icecast("second")];
+        let mut values = take_secrets(&mut config);
+        assert_eq!(values["outputs[1].password"], "second");
+        assert!(passwords(&config).iter().all(|password| password.is_empty()));
+        // A profile with one output fewer takes only the secrets it has a place for.
+        values.remove("outputs[1].password");
+        config.outputs.truncate(1);
+        put_secrets(&mut config, values);
+        assert_eq!(config.integrations.mqtt.password, "broker");
+        assert_eq!(passwords(&config), ["first"]);
+    }
+
+    #[test]
+    fn listed_secrets_are_read_in_order() {
+        let mut config = Config { outputs: vec![icecast(""), icecast("")], ..Default::default() };
+        let values = ["token", "broker", "first", "second", "key", "room"].map(String::from).to_vec();
+        let profiles = BTreeMap::from([("other".to_string(), Config::default())]);
+        let listed = Listed { config: values, profiles: BTreeMap::from([("other".to_string(), vec!["profile token".to_string()])]) };
+        let mut secrets = listed.by_path(&config, &profiles);
+        assert_eq!(secrets.profiles["other"]["listen_token"], "profile token");
+        put_secrets(&mut config, std::mem::take(&mut secrets.config));
+        assert_eq!(config.listen_token, "token");
+        assert_eq!(config.integrations.mqtt.password, "broker");
+        assert_eq!(passwords(&config), ["first", "second"]);
+        assert_eq!((config.relay_server_key.as_str(), config.rendezvous_secret.as_str()), ("key", "room"));
+    }
+}
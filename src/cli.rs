@@ -0,0 +1,350 @@
+use anyhow::{Context, Result, anyhow, bail};
+use audio_streamer::{
+    audio::get_audio_sources,
+    blocklist::blocked_message,
+    compare::{Setting, compare as run_comparison, play},
+    config::{Config, parse_target},
+    diagnose::diagnose as run_diagnosis,
+    events::Event,
+    ffmpeg::{check_ffmpeg, locate_ffmpeg},
+    fallback::Engine,
+    history::{History, format_utc},
+    ipc::{self, Request},
+    load::overload_message,
+    log,
+    netwatch, power, profiles, rollback, sdp,
+    selftest::run_self_test,
+    streamer::Streamer,
+    streams::{EngineOptions, StreamEvent},
+    tls::TlsIdentity,
+};
+use clap::ArgMatches;
+use serde_json::{Value, json};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::Path,
+};
+use tokio::runtime::Handle;
+
+// `--source`, or the preferred source, or the first one that isn't hidden.
+pub async fn resolve_source(config: &Config, requested: Option<&String>) -> Result<String> {
+    if let Some(source) = requested.or(config.preferred_source.as_ref()) {
+        return Ok(source.clone());
+    }
+    get_audio_sources(config.capture_backend)
+        .await?
+        .into_iter()
+        .find(|source| !source.is_hidden(&config.source_overrides))
+        .map(|source| source.name)
+        .context("No audio sources found")
+}
+
+fn print_json(value: &Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+// One line per source: name, then what the GUI shows for it.
+pub async fn list_sources(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let sources = get_audio_sources(config.capture_backend).await?;
+    if matches.get_flag("json") {
+        let sources: Vec<Value> = sources
+            .iter()
+            .map(|source| {
+                json!({
+                    "name": source.name,
+                    "label": source.label(&config.source_overrides),
+                    "description": source.description,
+                    "card": source.card,
+                    "monitor": source.is_monitor,
+                    "running": source.is_running,
+                    "default": source.is_default,
+                    "hidden": source.is_hidden(&config.source_overrides),
+                    "sample_spec": source.spec.as_ref().map(|spec| json!({ "format": spec.format, "channels": spec.channels, "rate": spec.rate })),
+                })
+            })
+            .collect();
+        return print_json(&Value::Array(sources));
+    }
+    for source in sources {
+        let mut flags = Vec::new();
+        if source.is_default {
+            flags.push("default");
+        }
+        if source.is_running {
+            flags.push("running");
+        }
+        if source.is_hidden(&config.source_overrides) {
+            flags.push("hidden");
+        }
+        let flags = if flags.is_empty() { String::new() } else { format!(" [{}]", flags.join(", ")) };
+        println!("{}\t{}{}", source.name, source.label(&config.source_overrides), flags);
+    }
+    Ok(())
+}
+
+// Hands the request to a running instance and prints its answer. False when none runs.
+pub async fn forward(request: &Request) -> Result<bool> {
+    let Some(reply) = ipc::send(request).await? else {
+        return Ok(false);
+    };
+    if !reply.ok {
+        bail!("{}", reply.message);
+    }
+    println!("{}", reply.message);
+    Ok(true)
+}
+
+// `--target` and `--codec` on top of the config, for this run only.
+fn apply_overrides(config: &mut Config, matches: &ArgMatches) -> Result<()> {
+    if let Some(target) = matches.get_one::<String>("target") {
+        let (ip, port) = parse_target(target)?;
+        config.target_ip = ip.to_string();
+        if let Some(port) = port {
+            config.target_port = port;
+        }
+        config.bluetooth_sink = None;
+    }
+    if let Some(codec) = matches.get_one::<String>("codec") {
+        config.audio_codec = codec.clone();
+    }
+    Ok(())
+}
+
+// Streams without any UI until Ctrl+C or until the stream fails, which is the exit code.
+// With the GUI open, the stream starts there instead.
+pub async fn stream(mut config: Config, config_path: &Path, matches: &ArgMatches) -> Result<()> {
+    let request = Request::Stream {
+        source: matches.get_one::<String>("source").cloned(),
+        target: matches.get_one::<String>("target").cloned(),
+        codec: matches.get_one::<String>("codec").cloned(),
+    };
+    if forward(&request).await? {
+        return Ok(());
+    }
+    apply_overrides(&mut config, matches)?;
+    if config.bluetooth_sink.is_none() && !config.is_ip_configured() {
+        bail!("No target set; pass --target or set one in the GUI");
+    }
+    let source = resolve_source(&config, matches.get_one::<String>("source")).await?;
+    let options = EngineOptions::detect(&config).await;
+    // So the packets are sized for the route from the start.
+    if let Ok(ip) = config.target_ip.parse() {
+        netwatch::resolve(ip).await;
+    }
+    let sdp = sdp::applies(&config, options.engine).then(|| {
+        let target = SocketAddr::new(config.target_ip.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)), config.target_port);
+        format!("SDP at {} (saved to {})", sdp::url(sdp::local_address(target.ip()), target.port()), sdp::path_for(target).display())
+    });
+    let mut streamer = Streamer::new(options, Handle::current());
+    let (id, _) = streamer.start(&source, config.clone()).await?;
+    let mut history = History::load(History::path_for(config_path));
+    let mut interrupted = false;
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c(), if !interrupted => {
+                interrupted = true;
+                streamer.stop(id);
+            }
+            event = streamer.next_event() => match event {
+                Some(Event::Stream(StreamEvent::Started { warning, .. })) => {
+                    println!("Streaming {} to {}:{}", source, config.target_ip, config.target_port);
+                    if let Some(sdp) = &sdp {
+                        println!("{}", sdp);
+                    }
+                    // What a phone's browser should show before it is trusted.
+                    if config.listen_tls && let Ok(identity) = TlsIdentity::load_or_create() {
+                        println!("HTTPS certificate SHA-256 fingerprint: {}", identity.fingerprint);
+                    }
+                    if let Some(warning) = warning {
+                        log!("⚠ {}", warning);
+                    }
+                }
+                Some(Event::Stream(StreamEvent::Connected { device, .. })) => println!("Playing on {} over Bluetooth", device),
+                Some(Event::Stream(StreamEvent::FailedOver { .. })) => {
+                    log!("⚠ Target stopped responding, switched to backup {}:{}", config.backup_target_ip, config.backup_target_port);
+                }
+                Some(Event::Stream(StreamEvent::ReceiverResumed { receiver, resumption, .. })) => log!("{}", resumption.describe(receiver.as_deref())),
+                Some(Event::Stream(StreamEvent::AppBlocked { app, .. })) => log!("{}", blocked_message(&app)),
+                Some(Event::Stream(StreamEvent::MuteFailed { reason, .. })) => log!("Mute failed ({}); holding the stream back", reason),
+                Some(Event::Stream(StreamEvent::Overloaded { speed, lighter, .. })) => log!("{}", overload_message(speed, lighter.as_deref())),
+                Some(Event::Stream(StreamEvent::SessionEnded { session: Some(session), .. })) => {
+                    println!("Sent {:.1} MB in {} s", session.bytes_sent as f64 / 1_000_000.0, session.duration_secs);
+                    if let Err(e) = history.record(session) {
+                        log!("Failed to save session history: {}", e);
+                    }
+                }
+                Some(Event::Stream(StreamEvent::Error { reason, .. })) => return Err(anyhow!("Streaming stopped: {}", reason)),
+                Some(Event::Stream(StreamEvent::Stopped { .. })) | None => return Ok(()),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+// The GUI's self test; fails the command when the test does.
+pub async fn self_test(mut config: Config, matches: &ArgMatches) -> Result<()> {
+    apply_overrides(&mut config, matches)?;
+    let source = resolve_source(&config, matches.get_one::<String>("source")).await?;
+    let (engine, ffmpeg_path) = match check_ffmpeg(&config).await {
+        Ok(info) => (Engine::Ffmpeg, Some(info.path)),
+        Err(_) => (Engine::BuiltIn, None),
+    };
+    let report = run_self_test(config, source, engine, ffmpeg_path, Handle::current()).await;
+    if matches.get_flag("json") {
+        print_json(&json!({ "passed": report.passed, "lines": report.lines }))?;
+    } else {
+        for line in &report.lines {
+            println!("{}", line);
+        }
+    }
+    if !report.passed {
+        bail!("Self test failed");
+    }
+    if !matches.get_flag("json") {
+        println!("Self test passed");
+    }
+    Ok(())
+}
+
+// Fails the command when something was found, so scripts can check the network.
+pub async fn diagnose(config: Config, matches: &ArgMatches) -> Result<()> {
+    let (target, port) = match matches.get_one::<String>("target") {
+        Some(target) => parse_target(target)?,
+        None => (config.target_ip.parse().context("No target set; pass --target")?, None),
+    };
+    let findings = run_diagnosis(target, port.unwrap_or(config.target_port)).await;
+    if matches.get_flag("json") {
+        let findings: Vec<Value> = findings.iter().map(|finding| json!({ "problem": finding.problem, "remedy": finding.remedy })).collect();
+        print_json(&json!({ "target": target, "findings": findings }))?;
+    } else if findings.is_empty() {
+        println!("No common network problems found for {}", target);
+    } else {
+        for finding in &findings {
+            println!("⚠ {}\n  {}", finding.problem, finding.remedy);
+        }
+    }
+    if !findings.is_empty() {
+        bail!("Found possible network problems");
+    }
+    Ok(())
+}
+
+pub async fn compare(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let source = resolve_source(config, matches.get_one::<String>("source")).await?;
+    let a = match matches.get_one::<String>("a") {
+        Some(a) => Setting::parse(a)?,
+        None => Setting { codec: config.audio_codec.clone(), bitrate: config.bitrate.clone() },
+    };
+    let b = Setting::parse(matches.get_one::<String>("b").expect("required"))?;
+    let seconds = *matches.get_one::<u32>("seconds").expect("has a default");
+    let ffmpeg = locate_ffmpeg(config)?;
+    if !matches.get_flag("json") {
+        println!("Capturing {} s of {}...", seconds, source);
+    }
+    let comparison = run_comparison(&ffmpeg, &source, &a, &b, seconds).await?;
+    if matches.get_flag("json") {
+        print_json(&serde_json::to_value(&comparison)?)?;
+    } else {
+        println!("A  {}", comparison.a.describe());
+        println!("B  {}", comparison.b.describe());
+    }
+    if matches.get_flag("play") {
+        for (side, encoded) in [("A", &comparison.a), ("B", &comparison.b)] {
+            println!("▶ {} ({})", side, encoded.setting.label());
+            play(&encoded.path)?.wait().await?;
+        }
+    }
+    Ok(())
+}
+
+// What streaming would use right now, and how the last session went.
+pub async fn status(config: &Config, config_path: &Path, matches: &ArgMatches) -> Result<()> {
+    let ffmpeg = check_ffmpeg(config).await;
+    let source = resolve_source(config, None).await.ok();
+    let on_battery = tokio::task::spawn_blocking(power::on_battery).await.unwrap_or(false);
+    let history = History::load(History::path_for(config_path));
+    let last = history.sessions().last();
+    let instance = ipc::send(&Request::Status).await.ok().flatten().map(|reply| reply.message);
+    if matches.get_flag("json") {
+        return print_json(&json!({
+            "config": config_path,
+            "running": instance.as_ref().map(|streams| streams.lines().collect::<Vec<_>>()),
+            "target": config.is_ip_configured().then(|| format!("{}:{}", config.target_ip, config.target_port)),
+            "bluetooth_sink": config.bluetooth_sink,
+            "transport": config.transport, // As in the config file
+            "codec": config.audio_codec,
+            "bitrate": config.bitrate,
+            "source": source,
+            "engine": if ffmpeg.is_ok() { "ffmpeg" } else { "builtin" },
+            "ffmpeg": match &ffmpeg {
+                Ok(info) => json!({ "path": info.path, "version": info.version }),
+                Err(e) => json!({ "error": format!("{:#}", e) }),
+            },
+            "on_battery": on_battery,
+            "last_session": last.map(|session| json!({
+                "started_at": session.started_at,
+                "duration_secs": session.duration_secs,
+                "target": session.target,
+                "codec": session.codec,
+                "bytes_sent": session.bytes_sent,
+                "end_reason": session.end_reason,
+            })),
+        }));
+    }
+    println!("Config:     {}", config_path.display());
+    match &config.bluetooth_sink {
+        Some(address) => println!("Target:     🎧 {}", address),
+        None if config.is_ip_configured() => println!("Target:     {}:{} ({})", config.target_ip, config.target_port, config.transport.label()),
+        None => println!("Target:     not set"),
+    }
+    println!("Codec:      {} at {}", config.audio_codec, config.bitrate);
+    println!("Source:     {}", source.as_deref().unwrap_or("none found"));
+    match &ffmpeg {
+        Ok(info) => println!("Engine:     ffmpeg {} at {}", info.version, info.path.display()),
+        Err(e) => println!("Engine:     {} ({:#})", Engine::BuiltIn.label(), e),
+    }
+    println!("Power:      {}", if on_battery { "battery" } else { "mains" });
+    if let Some(streams) = instance {
+        println!("Running:    {}", streams.replace('\n', "\n            "));
+    }
+    if let Some(session) = last {
+        println!(
+            "Last:       {} to {}, {} s, {:.1} MB{}",
+            format_utc(session.started_at),
+            session.target,
+            session.duration_secs,
+            session.bytes_sent as f64 / 1_000_000.0,
+            session.end_reason.as_ref().map(|reason| format!(" ({})", reason)).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+pub fn profiles(config_path: &Path, config: &Config, matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("save", sub)) => {
+            let name = sub.get_one::<String>("name").expect("required");
+            profiles::save(config_path, name, config)?;
+            println!("Saved the current settings as '{}'", name);
+        }
+        // Makes the profile the config the GUI opens with.
+        Some(("use", sub)) => {
+            let name = sub.get_one::<String>("name").expect("required");
+            let profile = profiles::load(config_path, name)?;
+            rollback::save(config_path, &profile)?;
+            println!("Switched to '{}'", name);
+        }
+        Some(("delete", sub)) => {
+            let name = sub.get_one::<String>("name").expect("required");
+            profiles::delete(config_path, name)?;
+            println!("Deleted '{}'", name);
+        }
+        _ => {
+            for name in profiles::list(config_path)? {
+                println!("{}", name);
+            }
+        }
+    }
+    Ok(())
+}
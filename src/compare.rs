@@ -0,0 +1,207 @@
+use crate::paths;
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::{Child, Command},
+    time::timeout,
+};
+
+// Opus only takes 48 kHz, so both sides are encoded from that.
+const RATE: u32 = 48000;
+const CHANNELS: u32 = 2;
+const FRAME_BYTES: usize = 4; // s16le stereo
+// Encoders delay their output by up to this many frames (AAC 1024 + 1 frame, MP3 1105 + 529).
+const MAX_CODEC_DELAY: usize = 4096;
+pub const DEFAULT_CLIP_SECS: u32 = 5;
+
+// One side of the comparison.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Setting {
+    pub codec: String,
+    pub bitrate: String,
+}
+
+impl Setting {
+    // "aac:192k"; the bitrate may be left out for lossless codecs, e.g. "flac".
+    pub fn parse(text: &str) -> Result<Self> {
+        let (codec, bitrate) = text.split_once(':').unwrap_or((text, ""));
+        if codec.trim().is_empty() {
+            bail!("Expected CODEC[:BITRATE], e.g. aac:192k, not '{}'", text);
+        }
+        Ok(Self { codec: codec.trim().to_string(), bitrate: bitrate.trim().to_string() })
+    }
+
+    pub fn label(&self) -> String {
+        if self.bitrate.is_empty() { self.codec.clone() } else { format!("{} {}", self.codec, self.bitrate) }
+    }
+}
+
+// What encoding the clip with one setting gave.
+#[derive(Debug, Clone, Serialize)]
+pub struct Encoded {
+    pub setting: Setting,
+    pub path: PathBuf, // MPEG-TS, as streamed, for playing back
+    pub kbps: f64,     // What it actually took, container included
+    pub speed: f64,    // Times real time; below 1 it can't keep up with a live stream
+    // Signal to the difference from the original, once decoded again. Only a rough guide:
+    // codecs put their noise where it is least heard, which this can't tell.
+    pub snr_db: Option<f64>, // None for a silent clip; infinite when lossless
+}
+
+impl Encoded {
+    pub fn describe(&self) -> String {
+        let snr = match self.snr_db {
+            Some(snr) if snr.is_infinite() => "identical to the original".to_string(),
+            Some(snr) => format!("SNR {:.1} dB", snr),
+            None => "SNR unknown (silent clip)".to_string(),
+        };
+        format!("{}: {:.0} kbps, encodes at {:.0}× real time, {}", self.setting.label(), self.kbps, self.speed, snr)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub seconds: u32,
+    pub a: Encoded,
+    pub b: Encoded,
+}
+
+fn clip_dir() -> PathBuf {
+    paths::cache_dir().join("compare")
+}
+
+// `seconds` of the source as s16le, straight from PulseAudio/PipeWire.
+async fn capture_clip(source: &str, seconds: u32) -> Result<Vec<u8>> {
+    let mut parec = Command::new("parec")
+        .args([&format!("--device={}", source), "--format=s16le", &format!("--rate={}", RATE), &format!("--channels={}", CHANNELS), "--raw"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start parec")?;
+    let mut stdout = parec.stdout.take().context("parec has no stdout")?;
+    let mut clip = vec![0u8; seconds as usize * RATE as usize * FRAME_BYTES];
+    timeout(Duration::from_secs(seconds as u64 + 5), stdout.read_exact(&mut clip))
+        .await
+        .map_err(|_| anyhow!("{} sent no audio", source))?
+        .with_context(|| format!("parec stopped before {} s of {} were captured", seconds, source))?;
+    Ok(clip)
+}
+
+// Runs ffmpeg with `input` on stdin, returning its stdout.
+async fn run_ffmpeg(ffmpeg: &Path, args: &[&str], input: Vec<u8>) -> Result<Vec<u8>> {
+    let mut child = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run ffmpeg")?;
+    let mut stdin = child.stdin.take().context("ffmpeg has no stdin")?;
+    // Written alongside reading its output, so neither side fills a pipe and stalls.
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(&input).await;
+    });
+    let output = child.wait_with_output().await.context("Failed to run ffmpeg")?;
+    let _ = writer.await;
+    if !output.status.success() {
+        bail!("ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+fn mono(pcm: &[u8]) -> Vec<f32> {
+    pcm.chunks_exact(FRAME_BYTES)
+        .map(|frame| (i16::from_le_bytes([frame[0], frame[1]]) as f32 + i16::from_le_bytes([frame[2], frame[3]]) as f32) / 2.0)
+        .collect()
+}
+
+// The codec's delay, as the lag where the decoded audio best matches a stretch of the original.
+fn codec_delay(original: &[f32], decoded: &[f32]) -> usize {
+    let window = (RATE as usize / 2).min(original.len() / 2);
+    let start = (original.len() / 4).min(RATE as usize / 2); // Past any fade-in at the start
+    let reference = &original[start..start + window];
+    (0..MAX_CODEC_DELAY)
+        .filter(|lag| start + lag + window <= decoded.len())
+        .map(|lag| {
+            let candidate = &decoded[start + lag..start + lag + window];
+            let dot: f64 = reference.iter().zip(candidate).map(|(a, b)| *a as f64 * *b as f64).sum();
+            let energy: f64 = candidate.iter().map(|b| *b as f64 * *b as f64).sum();
+            (lag, if energy > 0.0 { dot / energy.sqrt() } else { 0.0 })
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(lag, _)| lag)
+}
+
+fn snr_db(original: &[u8], decoded: &[u8]) -> Option<f64> {
+    let (original, decoded) = (mono(original), mono(decoded));
+    let delay = codec_delay(&original, &decoded);
+    let decoded = decoded.get(delay..)?;
+    let (mut signal, mut noise) = (0.0f64, 0.0f64);
+    for (a, b) in original.iter().zip(decoded) {
+        signal += *a as f64 * *a as f64;
+        noise += (*a as f64 - *b as f64).powi(2);
+    }
+    if signal == 0.0 {
+        return None;
+    }
+    Some(if noise == 0.0 { f64::INFINITY } else { 10.0 * (signal / noise).log10() })
+}
+
+async fn encode(ffmpeg: &Path, clip: &[u8], setting: &Setting, path: PathBuf) -> Result<Encoded> {
+    let (rate, channels) = (RATE.to_string(), CHANNELS.to_string());
+    let raw = ["-f", "s16le", "-ar", rate.as_str(), "-ac", channels.as_str()];
+    let mut args = raw.to_vec();
+    args.extend(["-i", "pipe:0", "-c:a", setting.codec.as_str()]);
+    if !setting.bitrate.is_empty() {
+        args.extend(["-b:a", setting.bitrate.as_str()]);
+    }
+    let destination = path.to_string_lossy().to_string();
+    args.extend(["-f", "mpegts", destination.as_str()]);
+    let started = Instant::now();
+    run_ffmpeg(ffmpeg, &args, clip.to_vec()).await.with_context(|| format!("Could not encode {}", setting.label()))?;
+    let elapsed = started.elapsed().as_secs_f64().max(0.001);
+
+    let seconds = clip.len() as f64 / (RATE as usize * FRAME_BYTES) as f64;
+    let size = std::fs::metadata(&path).with_context(|| format!("ffmpeg wrote no {}", path.display()))?.len();
+    let mut args = vec!["-i", destination.as_str()];
+    args.extend(raw);
+    args.push("pipe:1");
+    let decoded = run_ffmpeg(ffmpeg, &args, Vec::new()).await.with_context(|| format!("Could not decode {}", setting.label()))?;
+    Ok(Encoded {
+        setting: setting.clone(),
+        kbps: size as f64 * 8.0 / seconds / 1000.0,
+        speed: seconds / elapsed,
+        snr_db: snr_db(clip, &decoded),
+        path,
+    })
+}
+
+// Captures `seconds` of `source` once and encodes that same clip with both settings,
+// so the difference heard is the codec's and not the music's.
+pub async fn compare(ffmpeg: &Path, source: &str, a: &Setting, b: &Setting, seconds: u32) -> Result<Comparison> {
+    let dir = clip_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let clip = capture_clip(source, seconds).await?;
+    let encoded_a = encode(ffmpeg, &clip, a, dir.join("a.ts")).await?;
+    let encoded_b = encode(ffmpeg, &clip, b, dir.join("b.ts")).await?;
+    Ok(Comparison { seconds, a: encoded_a, b: encoded_b })
+}
+
+// Plays one side on this machine; ends by itself at the end of the clip.
+pub fn play(path: &Path) -> Result<Child> {
+    Command::new("ffplay")
+        .args(["-nodisp", "-autoexit", "-loglevel", "error"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Playing back needs ffplay")
+}
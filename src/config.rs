@@ -1,6 +1,10 @@
+use crate::{audio::{CaptureBackend, SourceKind, SourceOverrides}, beacon::Beacon, filters::{ChannelMode, Dither, DownmixMatrix, NoiseSuppression, Resampler, ResamplerQuality, SampleFormat, capture_channels, filter_chain}, hooks::Hooks, jitter::LatePacketPolicy, mqtt::MqttSettings, outputs::Output, qos::Dscp, sdp, secrets, tag::StreamTag, template::expand_template, theme::{DEFAULT_ACCENT, ThemeMode}, transport::Transport};
+use anyhow::{Context, Result, anyhow, bail};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)] // Fields missing from older config files fall back to their defaults
 pub struct Config {
     pub target_ip: String,
     pub target_port: u16,
@@ -8,9 +12,103 @@ pub struct Config {
     pub bitrate: String,
     pub sample_rate: u32,
     pub channels: u8,
-    pub buffer_size: u32,
+    pub mtu: u32, // 0 takes it from the route to the target, see `mtu.rs`
     pub low_latency: bool,
     pub preferred_source: Option<String>,
+    pub ffmpeg_path: Option<String>, // Overrides the PATH lookup
+    // Advanced: replaces the generated ffmpeg arguments, see `template.rs` for placeholders.
+    pub ffmpeg_args_template: Option<String>,
+    pub transport: Transport,
+    pub fec_group_size: u8, // Data packets per XOR parity packet in native transport; 0 disables FEC
+    pub pause_keepalive: bool, // Native transport: keep telling receivers we're alive while paused
+    // Native transport: at most this many packets back to back, the rest spread out at the
+    // bitrate, see `pacing.rs`. 0 sends them as the encoder writes them.
+    pub pacing_burst: u32,
+    pub pacing_interval_ms: u32, // How often paced packets are released
+    // The native transport also sends every packet out through this interface, e.g.
+    // wlan0 next to Ethernet, so either path may drop out. Empty for one path.
+    pub redundant_interface: String,
+    pub rist_buffer_ms: u32, // RIST recovery buffer; longer survives longer outages but adds as much latency
+    pub dscp: Dscp, // QoS class for outgoing packets, see `qos.rs`
+    pub resume_on_network_change: bool, // Restart on the new route when the network changes, see `netwatch.rs`
+    // Receiver mode jitter buffer: starts at the target depth and adapts within min..max.
+    pub jitter_target_ms: u32,
+    pub jitter_min_ms: u32,
+    pub jitter_max_ms: u32,
+    pub late_packet_policy: LatePacketPolicy,
+    // Multi-room: receivers play each packet this long after the sender stamped it,
+    // so every room using the same value stays in step. 0 plays as soon as buffered.
+    pub sync_playout_ms: u32,
+    // Failover: when the primary target looks gone for this long, the stream moves
+    // to the backup. An empty backup IP disables it.
+    pub backup_target_ip: String,
+    pub backup_target_port: u16,
+    pub failover_after_secs: u32,
+    pub theme: ThemeMode,
+    pub accent_color: [u8; 3], // sRGB
+    pub outputs: Vec<Output>, // Extra destinations besides the target, see `outputs.rs`
+    pub channel_mode: ChannelMode,
+    pub downmix_matrix: DownmixMatrix, // Only used by the downmix channel modes
+    pub remove_vocals: bool, // Karaoke: cancels what's in the centre, on the stream only
+    pub resampler: Resampler,
+    pub resampler_quality: ResamplerQuality,
+    pub dither: Dither,
+    pub sample_format: SampleFormat, // Some receivers glitch on float PCM
+    pub normalize_loudness: bool, // Live loudnorm, plus a two-pass pass over recordings afterwards
+    pub target_lufs: f32,
+    pub noise_suppression: NoiseSuppression, // Applied to microphone sources only
+    pub noise_model: String, // RNNoise model (.rnnn) for voice isolation, e.g. from the rnnoise-models project
+    pub audio_delay_ms: u32, // Held back in the relay for lip sync, see `Relay::set_delay`
+    // How long local monitoring holds the stream back, to hear it when the receiver does:
+    // its buffer, the network and any audio delay. See `monitor.rs`.
+    pub monitor_delay_ms: u32,
+    pub volume_percent: u8, // Of our own capture stream, so the source itself is left alone
+    // Sidechain: while this source (a microphone) peaks above the threshold, the stream
+    // is turned down by the amount, see `ducking.rs`. Empty for none.
+    pub duck_source: String,
+    pub duck_threshold_db: f32, // dBFS
+    pub duck_amount_db: f32,
+    // Apps (by name or program) whose sound is never streamed: while a monitor streams,
+    // they are muted on its sink, see `blocklist.rs`.
+    pub do_not_stream: Vec<String>,
+    pub hooks: Hooks, // Commands run when a stream starts, stops or fails, see `hooks.rs`
+    pub remote_control: bool, // Let native receivers change the volume and codec, see `remote.rs`
+    pub negotiate_codec: bool, // Fit the codec, bitrate and latency to what a native receiver says it plays, see `negotiate.rs`
+    pub receive_max_bitrate_kbps: u32, // Receiver mode: the most we ask senders for; 0 for any
+    pub push_notifications: bool, // Tell native receivers about starting, stopping and tracks, see `notify.rs`
+    pub beacon: Beacon, // Latency beacon; the receiver must use the same setting to detect it
+    pub beacon_interval_secs: u32,
+    pub packet_millis: u32, // Audio per packet for the built-in engine
+    pub rtp_clock: bool, // Plain RTP: wall-clock timestamps and RTCP sender reports, see `rtp.rs`
+    pub battery_saver: bool, // Switch to `power::power_saving` while on battery
+    pub overload_protection: bool, // Restart lighter when ffmpeg can't encode in real time, see `load.rs`
+    pub realtime_priority: bool, // Ask rtkit to raise the capture/encode process, see `priority.rs`
+    pub capture_backend: CaptureBackend,
+    pub jack_connect: Vec<String>, // JACK output ports patched into ours when a stream starts, see `jack.rs`
+    pub source_overrides: SourceOverrides, // Nicknames and hidden sources, by source name
+    pub source_kind_filter: SourceKind, // Which sources the GUI list shows
+    pub running_sources_only: bool,
+    pub auto_follow_source: bool, // Switch to a source when it starts running, see `gui.rs`
+    pub bluetooth_sink: Option<String>, // Address of an A2DP device to play into instead of the network
+    pub opus_fec: bool, // libopus in-band FEC: each packet carries a low-rate copy of the previous one
+    pub opus_expected_loss: u8, // Percent; tells libopus how much redundancy to spend
+    pub opus_dtx: bool, // Near-empty packets during silence
+    pub auto_resume: bool, // Start what was streaming at the last exit without asking, see `resume.rs`
+    pub match_source_spec: bool, // Encode at the source's rate and channels when lower, see `SampleSpec::match_config`
+    pub rendezvous_room: String, // Native transport: when set, the target is a rendezvous helper that pairs us with the receiver in this room
+    pub rendezvous_secret: String, // Shared with the receiver for the room; the helper only pairs peers that have the same
+    pub relay_server_key: String, // When set, the target is an `audio-streamer relay` server with this key, see `bridge.rs`
+    pub listen_token: String, // Required by the HTTP and WebRTC outputs when set, see `access.rs`
+    pub listen_allowlist: Vec<String>, // IPs and subnets those outputs accept; empty for any
+    pub listen_tls: bool, // Serve those outputs over HTTPS with a self-signed certificate, see `tls.rs`
+    pub integrations: Integrations,
+}
+
+// Connections to other software that watches or drives the streamer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Integrations {
+    pub mqtt: MqttSettings, // Home Assistant, see `mqtt.rs`
 }
 
 impl Default for Config {
@@ -22,33 +120,191 @@ impl Default for Config {
             bitrate: "192k".to_string(),
             sample_rate: 48000,
             channels: 2,
-            buffer_size: 1316,
+            mtu: 0,
             low_latency: true,
             preferred_source: None,
+            ffmpeg_path: None,
+            ffmpeg_args_template: None,
+            transport: Transport::Udp,
+            fec_group_size: 8,
+            pacing_burst: 4,
+            pacing_interval_ms: 5,
+            redundant_interface: String::new(),
+            pause_keepalive: true,
+            rist_buffer_ms: 200,
+            dscp: Dscp::Off,
+            resume_on_network_change: true,
+            jitter_target_ms: 60,
+            jitter_min_ms: 20,
+            jitter_max_ms: 250,
+            late_packet_policy: LatePacketPolicy::Drop,
+            sync_playout_ms: 0,
+            backup_target_ip: String::new(),
+            backup_target_port: 1234,
+            failover_after_secs: 5,
+            theme: ThemeMode::Dark,
+            accent_color: DEFAULT_ACCENT,
+            outputs: Vec::new(),
+            channel_mode: ChannelMode::Passthrough,
+            downmix_matrix: DownmixMatrix::Itu,
+            remove_vocals: false,
+            resampler: Resampler::Swr,
+            resampler_quality: ResamplerQuality::Normal,
+            dither: Dither::None,
+            sample_format: SampleFormat::Auto,
+            normalize_loudness: false,
+            target_lufs: -16.0, // Common target for streaming services
+            noise_suppression: NoiseSuppression::Off,
+            noise_model: String::new(),
+            audio_delay_ms: 0,
+            monitor_delay_ms: 100,
+            volume_percent: 100,
+            duck_source: String::new(),
+            duck_threshold_db: -35.0, // Above a quiet room's noise, below speech at arm's length
+            duck_amount_db: 12.0,
+            do_not_stream: Vec::new(),
+            hooks: Hooks::default(),
+            remote_control: false,
+            negotiate_codec: false,
+            receive_max_bitrate_kbps: 0,
+            push_notifications: true,
+            beacon: Beacon::Off,
+            beacon_interval_secs: 5,
+            packet_millis: 5, // Keeps latency low and stays under the MTU for stereo
+            rtp_clock: false,
+            battery_saver: false,
+            overload_protection: true,
+            realtime_priority: false,
+            capture_backend: CaptureBackend::Pulse,
+            jack_connect: Vec::new(),
+            source_overrides: SourceOverrides::new(),
+            source_kind_filter: SourceKind::All,
+            running_sources_only: false,
+            auto_follow_source: false,
+            bluetooth_sink: None,
+            opus_fec: false,
+            opus_expected_loss: 0,
+            opus_dtx: false,
+            auto_resume: false,
+            match_source_spec: true,
+            rendezvous_room: String::new(),
+            rendezvous_secret: String::new(),
+            relay_server_key: String::new(),
+            listen_token: String::new(),
+            listen_allowlist: Vec::new(),
+            listen_tls: false,
+            integrations: Integrations::default(),
         }
     }
 }
 
 impl Config {
+    // As in the config file and profiles, with the secrets in the keyring, see `secrets.rs`.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&secrets::seal(self))?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let mut config: Config = serde_json::from_str(json)?;
+        secrets::unseal(&mut config)?;
+        Ok(config)
+    }
+
+    // "192k", "1.5M" or plain bits per second; None for anything else.
+    pub fn bitrate_bps(&self) -> Option<u64> {
+        let bitrate = self.bitrate.trim();
+        let (number, scale) = match bitrate.char_indices().last()? {
+            (i, 'k' | 'K') => (&bitrate[..i], 1e3),
+            (i, 'm' | 'M') => (&bitrate[..i], 1e6),
+            _ => (bitrate, 1.0),
+        };
+        let bps = number.parse::<f64>().ok()? * scale;
+        (bps.is_finite() && bps >= 1.0).then_some(bps as u64)
+    }
+
+    // These ignore the bitrate, which may be left empty for them.
+    pub fn is_lossless(&self) -> bool {
+        matches!(self.audio_codec.as_str(), "flac" | "pcm_s16be" | "pcm_s24be")
+    }
+
     pub fn is_ip_configured(&self) -> bool {
         !self.target_ip.is_empty() && self.target_ip != "0.0.0.0"
     }
 
-    pub fn build_ffmpeg_command(&self, source: &str) -> Vec<String> {
-        let mut cmd = vec![
-            "-f".to_string(),
-            "pulse".to_string(),
+    pub fn has_backup_target(&self) -> bool {
+        !self.backup_target_ip.is_empty()
+    }
+
+    // The URL a receiver on the phone opens to play the stream, e.g. in VLC.
+    // The native transport can only be played by another audio-streamer.
+    pub fn receiver_url(&self) -> String {
+        match self.transport {
+            Transport::Udp => format!("udp://@:{}", self.target_port),
+            Transport::Native => format!("audio-streamer --receive {}", self.target_port),
+            Transport::Rist => format!("rist://@:{}", self.target_port),
+            Transport::Rtp => sdp::url(self.target_ip.parse().ok().and_then(sdp::local_address), self.target_port),
+        }
+    }
+
+    // Options only libopus understands; other encoders would reject them.
+    fn opus_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.audio_codec != "libopus" {
+            return args;
+        }
+        if self.opus_fec {
+            args.extend(["-fec".to_string(), "1".to_string()]);
+        }
+        if self.opus_expected_loss > 0 {
+            args.extend(["-packet_loss".to_string(), self.opus_expected_loss.min(100).to_string()]);
+        }
+        if self.opus_dtx {
+            args.extend(["-dtx".to_string(), "1".to_string()]);
+        }
+        args
+    }
+
+    // `tag` names the MPEG-TS service; templates are left as written.
+    pub fn build_ffmpeg_command(&self, source: &str, output_url: &str, tag: Option<&StreamTag>) -> Result<Vec<String>> {
+        if let Some(template) = self.ffmpeg_args_template.as_deref().filter(|t| !t.trim().is_empty()) {
+            let values = [
+                ("source", source.to_string()),
+                ("target", output_url.to_string()),
+                ("codec", self.audio_codec.clone()),
+                ("bitrate", self.bitrate.clone()),
+                ("sample_rate", self.sample_rate.to_string()),
+                ("channels", self.channels.to_string()),
+                ("ip", self.target_ip.clone()),
+                ("port", self.target_port.to_string()),
+                ("filters", filter_chain(self).unwrap_or_else(|| "anull".to_string())),
+            ];
+            return expand_template(template, &values);
+        }
+
+        let mut cmd = vec!["-f".to_string(), self.capture_backend.effective().ffmpeg_format().to_string()];
+        if let Some(channels) = capture_channels(self) {
+            cmd.extend(["-channels".to_string(), channels.to_string()]);
+        }
+        cmd.extend([
             "-i".to_string(),
             source.to_string(),
+        ]);
+        if let Some(filters) = filter_chain(self) {
+            cmd.extend(["-af".to_string(), filters]);
+        }
+        cmd.extend([
             "-ac".to_string(),
             self.channels.to_string(),
             "-ar".to_string(),
             self.sample_rate.to_string(),
             "-c:a".to_string(),
             self.audio_codec.clone(),
-            "-b:a".to_string(),
-            self.bitrate.clone(),
-        ];
+        ]);
+        if !self.bitrate.trim().is_empty() {
+            cmd.extend(["-b:a".to_string(), self.bitrate.clone()]);
+        }
+        cmd.extend(self.sample_format.ffmpeg_args());
+        cmd.extend(self.opus_args());
 
         if self.low_latency {
             cmd.extend([
@@ -61,6 +317,23 @@ impl Config {
             ]);
         }
 
+        if let Some(tag) = tag {
+            cmd.extend(tag.ffmpeg_args());
+        }
+
+        // Same relay either way: it forwards RTP packets as plain datagrams too.
+        if self.transport == Transport::Rtp {
+            let mut url = output_url.replacen("udp://", "rtp://", 1);
+            // ffmpeg sends its sender reports to the port above the audio's, which the
+            // relay doesn't listen on; on the audio's own port they go along with it.
+            if self.rtp_clock
+                && let Some((_, port)) = output_url.split('?').next().and_then(|address| address.rsplit_once(':'))
+            {
+                url.push_str(&format!("{}rtcpport={}", if url.contains('?') { '&' } else { '?' }, port));
+            }
+            cmd.extend(["-f".to_string(), "rtp".to_string(), url]);
+            return Ok(cmd);
+        }
         cmd.extend([
             "-f".to_string(),
             "mpegts".to_string(),
@@ -68,12 +341,53 @@ impl Config {
             "0".to_string(),
             "-muxpreload".to_string(),
             "0".to_string(),
-            format!("udp://{}:{}?pkt_size={}", 
-                   self.target_ip, self.target_port, self.buffer_size),
+            output_url.to_string(),
         ]);
 
-        println!("FFmpeg command: ffmpeg {}", cmd.join(" "));
+        Ok(cmd)
+    }
+}
+// Accepts what people tend to paste for a target: "ip", "ip:port", "[v6]:port",
+// "udp://ip:port" or any other URL with an IP host. The port is None when not given.
+pub fn parse_target(input: &str) -> Result<(IpAddr, Option<u16>)> {
+    let mut rest = input.trim();
+    if let Some((_, after)) = rest.split_once("://") {
+        rest = after;
+    }
+    rest = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if let Some((_, host)) = rest.rsplit_once('@') {
+        rest = host; // user@host, or VLC's listening "@:port"
+    }
+    if rest.is_empty() {
+        bail!("Enter the phone's IP address");
+    }
+    // A bare IPv6 address has colons of its own, so it's tried before splitting off a port.
+    if let Ok(ip) = rest.parse::<IpAddr>() {
+        return Ok((ip, None));
+    }
+    let (host, port) = match rest.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, after) = bracketed.split_once(']').context("Missing ']' after the IPv6 address")?;
+            match after {
+                "" => (host, None),
+                _ => (host, Some(after.strip_prefix(':').context("Expected ':port' after ']'")?)),
+            }
+        }
+        None => match rest.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (rest, None),
+        },
+    };
+    if host.is_empty() {
+        bail!("That has a port but no IP address (\"udp://@:port\" is the phone's own listening URL)");
+    }
+    let ip = host.parse::<IpAddr>().map_err(|_| anyhow!("'{}' is not an IP address", host))?;
+    Ok((ip, port.map(parse_port).transpose()?))
+}
 
-        cmd
+pub fn parse_port(input: &str) -> Result<u16> {
+    match input.trim().parse::<u16>() {
+        Ok(0) | Err(_) => bail!("Port must be a number from 1 to 65535, not '{}'", input.trim()),
+        Ok(port) => Ok(port),
     }
-}
\ No newline at end of file
+}
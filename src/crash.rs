@@ -0,0 +1,158 @@
+use crate::{
+    history::{format_utc, unix_now},
+    paths,
+};
+use anyhow::Result;
+use serde_json::Value;
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    panic::PanicHookInfo,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+// What a crash report includes of the log, oldest first.
+const LOG_LINES: usize = 200;
+
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+// `eprintln!` that also keeps the line for a crash report.
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::crash::log(format!($($arg)*))
+    };
+}
+
+pub fn log(line: String) {
+    eprintln!("{}", line);
+    let secs = STARTED.get_or_init(Instant::now).elapsed().as_secs_f64();
+    let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    if log.len() == LOG_LINES {
+        log.pop_front();
+    }
+    log.push_back(format!("[{:9.3}] {}", secs, line));
+}
+
+fn crash_dir(config_path: &Path) -> PathBuf {
+    paths::state_dir(config_path).join("crashes")
+}
+
+// The default hook still prints the panic; a report then goes to `crashes/` in the
+// state directory, see `paths.rs`. The config is read from disk, so it is what was last saved.
+pub fn install(config_path: PathBuf) {
+    STARTED.get_or_init(Instant::now);
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        match write_report(&config_path, info) {
+            Ok(path) => eprintln!("Crash report saved to {}", path.display()),
+            Err(e) => eprintln!("Failed to save a crash report: {:#}", e),
+        }
+    }));
+}
+
+fn write_report(config_path: &Path, info: &PanicHookInfo) -> Result<PathBuf> {
+    let now = unix_now();
+    let mut report = format!("audio-streamer {} crashed at {}\n\n", env!("CARGO_PKG_VERSION"), format_utc(now));
+    writeln!(report, "{}\n", info)?;
+    writeln!(report, "Backtrace:\n{}\n", Backtrace::force_capture())?;
+    writeln!(report, "Last log lines:")?;
+    // The panic may have happened while logging, with the lock held.
+    match LOG.try_lock() {
+        Ok(log) => {
+            for line in log.iter() {
+                writeln!(report, "{}", line)?;
+            }
+        }
+        Err(_) => writeln!(report, "(unavailable)")?,
+    }
+    writeln!(report, "\nConfig ({}):", config_path.display())?;
+    match fs::read_to_string(config_path).ok().and_then(|content| serde_json::from_str::<Value>(&content).ok()) {
+        Some(mut config) => {
+            sanitize(&mut config);
+            writeln!(report, "{}", serde_json::to_string_pretty(&config)?)?;
+        }
+        None => writeln!(report, "(unreadable)")?,
+    }
+    let dir = crash_dir(config_path);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.txt", now));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+// Passwords (e.g. an Icecast output's), tokens, keys and secrets, like the relay server
+// key and the rendezvous secret, never end up in a report that may be shared.
+const REDACTED: [&str; 4] = ["password", "token", "key", "secret"];
+
+fn sanitize(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if REDACTED.iter().any(|word| key.contains(word)) {
+                    *field = Value::String("(removed)".to_string());
+                } else {
+                    sanitize(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(sanitize),
+        _ => {}
+    }
+}
+
+// Reports are named by their Unix time; `seen` holds the newest one already offered.
+fn report_time(path: &Path) -> Option<u64> {
+    path.file_name()?.to_str()?.strip_prefix("crash-")?.strip_suffix(".txt")?.parse().ok()
+}
+
+// The newest report from a crash the user hasn't been told about yet.
+pub fn unseen_report(config_path: &Path) -> Option<PathBuf> {
+    let dir = crash_dir(config_path);
+    let seen: u64 = fs::read_to_string(dir.join("seen")).ok().and_then(|seen| seen.trim().parse().ok()).unwrap_or(0);
+    fs::read_dir(&dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| Some((report_time(&path)?, path)))
+        .filter(|(time, _)| *time > seen)
+        .max()
+        .map(|(_, path)| path)
+}
+
+pub fn mark_seen(report: &Path) -> Result<()> {
+    let (Some(dir), Some(time)) = (report.parent(), report_time(report)) else {
+        return Ok(());
+    };
+    fs::write(dir.join("seen"), time.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, outputs::Output, secrets};
+
+    #[test]
+    fn sanitize_removes_every_secret() {
+        let mut config = Config::default();
+        config.outputs.push(Output::Icecast {
+            server: "example.org:8000".to_string(),
+            mount: "live".to_string(),
+            password: String::new(),
+            name: String::new(),
+            description: String::new(),
+        });
+        for (i, (_, field)) in secrets::fields(&mut config).into_iter().enumerate() {
+            *field = format!("hush-{}", i);
+        }
+        let mut value = serde_json::to_value(&config).unwrap();
+        sanitize(&mut value);
+        assert!(!value.to_string().contains("hush-"), "{}", value);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    access::Subnet,
+    netwatch::{Route, route_to},
+};
+use std::{
+    net::{IpAddr, SocketAddr, UdpSocket},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{process::Command, time::sleep};
+
+// Long enough for an ARP/neighbour discovery round trip, and its retries, on busy Wi-Fi.
+const NEIGHBOUR_WAIT: Duration = Duration::from_millis(1500);
+const DISCARD_PORT: u16 = 9;
+// Interface name prefixes of VPN clients: OpenVPN, WireGuard, Tailscale, PPTP/L2TP,
+// NordVPN, ProtonVPN and ZeroTier.
+const VPN_INTERFACES: [&str; 8] = ["tun", "tap", "wg", "tailscale", "ppp", "nordlynx", "proton", "zt"];
+
+// A likely cause of "nothing arrives", and what to do about it.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub problem: String,
+    pub remedy: String,
+}
+
+fn finding(problem: String, remedy: &str) -> Finding {
+    Finding { problem, remedy: remedy.to_string() }
+}
+
+// Addresses a home or office network hands out, as opposed to somewhere on the internet.
+fn is_local(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => ip.is_unique_local() || ip.is_unicast_link_local(),
+    }
+}
+
+fn is_vpn(interface: &str) -> bool {
+    VPN_INTERFACES.iter().any(|prefix| interface.starts_with(prefix))
+}
+
+// Tailscale's own addresses come from the CGNAT range, so a route to them over tailscale0 is intended.
+fn is_vpn_address(ip: IpAddr) -> bool {
+    Subnet::parse("100.64.0.0/10").is_ok_and(|subnet| subnet.contains(ip)) || Subnet::parse("fd7a:115c:a1e0::/48").is_ok_and(|subnet| subnet.contains(ip))
+}
+
+// The networks on an interface, from `ip -o -4 addr show dev <interface>`: "inet 192.168.1.5/24 brd ...".
+fn interface_networks(interface: &str, ipv4: bool) -> Vec<String> {
+    let family = if ipv4 { "-4" } else { "-6" };
+    let Ok(output) = std::process::Command::new("ip").args(["-o", family, "addr", "show", "dev", interface]).output() else {
+        return Vec::new();
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            words.find(|word| *word == "inet" || *word == "inet6")?;
+            words.next().map(str::to_string)
+        })
+        .collect()
+}
+
+// The kernel's neighbour entry after trying to reach the target: "... lladdr aa:bb:... REACHABLE",
+// or FAILED/INCOMPLETE when nothing answered ARP (IPv4) or neighbour solicitation (IPv6).
+async fn answers_neighbour_discovery(target: IpAddr) -> Option<bool> {
+    let socket = UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).ok()?;
+    // Anything addressed to it makes the kernel resolve it; the discard port ignores the datagram.
+    socket.send_to(b"audio-streamer diagnostics", SocketAddr::new(target, DISCARD_PORT)).ok()?;
+    sleep(NEIGHBOUR_WAIT).await;
+    let output = Command::new("ip").args(["neigh", "show", "to", &target.to_string()]).output().await.ok()?;
+    let entry = String::from_utf8_lossy(&output.stdout).to_string();
+    if entry.trim().is_empty() {
+        return None; // Not resolved yet, or probing isn't possible here
+    }
+    Some(entry.contains("lladdr"))
+}
+
+// None when `ping` isn't installed or may not be used.
+async fn answers_ping(target: IpAddr) -> Option<bool> {
+    let status = Command::new("ping").args(["-c", "2", "-W", "1", "-q", &target.to_string()]).stdout(Stdio::null()).stderr(Stdio::null()).status().await.ok()?;
+    // 1 is "no reply"; anything else is ping itself failing, e.g. without permission.
+    match status.code() {
+        Some(0) => Some(true),
+        Some(1) => Some(false),
+        _ => None,
+    }
+}
+
+fn check_route(target: IpAddr, route: &Route, findings: &mut Vec<Finding>) {
+    if is_vpn(&route.interface) && is_local(target) && !is_vpn_address(target) {
+        findings.push(finding(
+            format!("Packets for {} go out through the VPN interface {}, not the local network.", target, route.interface),
+            "The VPN is capturing all traffic, including the LAN. Turn on its \"allow LAN access\" (split tunnelling) setting, e.g. `tailscale set --exit-node-allow-lan-access` with an exit node, or disconnect it while streaming.",
+        ));
+        return;
+    }
+    if let Some(gateway) = route.gateway
+        && is_local(target)
+    {
+        let ours = interface_networks(&route.interface, target.is_ipv4()).join(", ");
+        let ours = if ours.is_empty() { route.interface.clone() } else { ours };
+        findings.push(finding(
+            format!("{} is not on this machine's network ({}) but reached through the router at {}.", target, ours, gateway),
+            "The two are in different subnets, usually because of a second router (double NAT), a guest network or a mesh system in router mode. Put both on the same Wi-Fi, or switch the second router to access point (bridge) mode. Streaming may still work if the routers forward between the subnets, but discovery and the receiver's replies often won't.",
+        ));
+    }
+}
+
+// Checks the common reasons a stream to `target` doesn't arrive; empty when none apply.
+// Only the target's side of the LAN is probed, so a receiver's firewall can't be ruled out.
+pub async fn diagnose(target: IpAddr, port: u16) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let Some(route) = route_to(target).await else {
+        findings.push(finding(format!("Nothing on this machine routes to {}.", target), "Connect to the network the target is on; check that Wi-Fi or the cable is up."));
+        return findings;
+    };
+    check_route(target, &route, &mut findings);
+
+    let ping = answers_ping(target).await;
+    // Only a target on our own network has a neighbour entry to look at.
+    let neighbour = if route.gateway.is_none() && !is_vpn(&route.interface) { answers_neighbour_discovery(target).await } else { None };
+    match (neighbour, ping) {
+        (Some(false), _) => findings.push(finding(
+            format!("{} doesn't answer on the local network (no ARP reply on {}).", target, route.interface),
+            "Check that the device is on and the IP is right; DHCP may have given it a new one. If it is on Wi-Fi and switched on, the access point probably isolates clients from each other, as guest and public networks do: turn off \"AP isolation\" or \"client isolation\" in the router, or use the main network.",
+        )),
+        (Some(true), Some(false)) => findings.push(finding(
+            format!("{} is on the network but doesn't answer ping.", target),
+            &format!("Its firewall drops ICMP, which is common and harmless, but it may drop the stream too: allow incoming UDP port {} on it.", port),
+        )),
+        (None, Some(false)) => findings.push(finding(
+            format!("{} doesn't answer ping.", target),
+            &format!("It may be off, unreachable, or firewalled. If it is on, allow incoming UDP port {} on it, and check the routers in between forward UDP.", port),
+        )),
+        _ => {}
+    }
+    findings
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// The smallest one-way offset seen in a window is the one least affected by
+// queueing, so fitting a line through window minimums gives the clock skew.
+const WINDOW: Duration = Duration::from_secs(10);
+const WINDOWS_KEPT: usize = 30;
+const MIN_WINDOWS: usize = 3;
+
+#[derive(Debug, Default)]
+pub struct DriftEstimator {
+    started: Option<Instant>,
+    window_start: f64,
+    window_min: Option<f64>,
+    points: VecDeque<(f64, f64)>, // (local seconds since start, min offset in µs)
+}
+
+impl DriftEstimator {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn observe(&mut self, sender_timestamp_us: u64, arrival: Instant) {
+        let started = *self.started.get_or_insert(arrival);
+        let local = (arrival - started).as_secs_f64();
+        let offset = local * 1_000_000.0 - sender_timestamp_us as f64;
+
+        self.window_min = Some(self.window_min.map_or(offset, |min| min.min(offset)));
+        if local - self.window_start >= WINDOW.as_secs_f64() {
+            if let Some(min) = self.window_min.take() {
+                self.points.push_back((local, min));
+            }
+            if self.points.len() > WINDOWS_KEPT {
+                self.points.pop_front();
+            }
+            self.window_start = local;
+        }
+    }
+
+    // Positive when the sender's clock runs fast compared to ours, in parts per million.
+    pub fn skew_ppm(&self) -> Option<f64> {
+        if self.points.len() < MIN_WINDOWS {
+            return None;
+        }
+        let n = self.points.len() as f64;
+        let mean_x = self.points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = self.points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance: f64 = self.points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f64 = self.points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        if variance == 0.0 {
+            return None;
+        }
+        // The offset shrinks by `ppm` µs every second when the sender is fast.
+        Some(-covariance / variance)
+    }
+
+    // Audio the sender has produced beyond what we have played, in µs, since we started.
+    pub fn surplus_us(&self) -> f64 {
+        match (self.skew_ppm(), self.points.back()) {
+            (Some(ppm), Some((elapsed, _))) => ppm * elapsed,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A packet every 20 ms for `secs`, stamped by a sender clock `ppm` fast, arriving
+    // up to 3 ms late from queueing on all but every tenth.
+    fn observed(ppm: f64, secs: u64) -> DriftEstimator {
+        let (mut drift, start) = (DriftEstimator::default(), Instant::now());
+        for i in 0..secs * 50 {
+            let sent_us = i as f64 * 20_000.0;
+            let queueing = if i % 10 == 0 { 0 } else { i * 7919 % 3000 };
+            drift.observe((sent_us * (1.0 + ppm / 1_000_000.0)) as u64, start + Duration::from_micros(sent_us as u64 + queueing));
+        }
+        drift
+    }
+
+    #[test]
+    fn needs_a_few_windows() {
+        assert_eq!(observed(100.0, 25).skew_ppm(), None);
+        assert_eq!(observed(100.0, 25).surplus_us(), 0.0);
+        assert!(observed(100.0, 45).skew_ppm().is_some());
+    }
+
+    #[test]
+    fn finds_a_fast_sender() {
+        let drift = observed(100.0, 120);
+        let ppm = drift.skew_ppm().unwrap();
+        assert!((ppm - 100.0).abs() < 5.0, "{} ppm", ppm);
+        // 100 ppm up to the last whole window, at 110 s, is 11 ms.
+        assert!((drift.surplus_us() - 11_000.0).abs() < 600.0, "{} µs", drift.surplus_us());
+    }
+
+    #[test]
+    fn finds_a_slow_sender() {
+        let ppm = observed(-50.0, 120).skew_ppm().unwrap();
+        assert!((ppm + 50.0).abs() < 5.0, "{} ppm", ppm);
+    }
+
+    #[test]
+    fn matching_clocks_have_no_skew() {
+        let ppm = observed(0.0, 120).skew_ppm().unwrap();
+        assert!(ppm.abs() < 5.0, "{} ppm", ppm);
+    }
+
+    #[test]
+    fn reset_forgets_the_windows() {
+        let mut drift = observed(100.0, 60);
+        drift.reset();
+        assert_eq!(drift.skew_ppm(), None);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{
+    audio::{capture_stream, set_stream_volume},
+    log,
+    meter::LevelMeter,
+};
+use anyhow::Result;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::runtime::Handle;
+
+const TICK: Duration = Duration::from_millis(50);
+// Pauses between words shouldn't bring the music back up.
+const HOLD: Duration = Duration::from_millis(800);
+// Down at once so the first word is heard, back up gently.
+const RELEASE_DB_PER_SEC: f32 = 24.0;
+// Each change runs pactl, so the way back up goes in steps this far apart.
+const RELEASE_STEP: Duration = Duration::from_millis(250);
+
+// PulseAudio volumes are cubic: a percentage p scales the amplitude by (p/100)³.
+fn percent_with_gain(percent: u8, gain_db: f32) -> u8 {
+    let amplitude = 10f32.powf(gain_db / 20.0);
+    (percent as f32 * amplitude.cbrt()).round() as u8
+}
+
+// Follows the microphone until stopped or pactl fails.
+async fn duck(stream: &str, meter: &LevelMeter, threshold_db: f32, amount_db: f32, volume: &AtomicU8, mute: &AtomicBool, stopped: &AtomicBool) {
+    let mut ticks = tokio::time::interval(TICK);
+    let mut heard: Option<Instant> = None;
+    let mut gain_db = 0.0f32;
+    let mut applied: Option<(u8, bool, Instant)> = None;
+    while !stopped.load(Ordering::Relaxed) {
+        ticks.tick().await;
+        if meter.peak_dbfs() >= threshold_db {
+            heard = Some(Instant::now());
+        }
+        let ducked = heard.is_some_and(|heard| heard.elapsed() < HOLD);
+        gain_db = if ducked { -amount_db } else { (gain_db + RELEASE_DB_PER_SEC * TICK.as_secs_f32()).min(0.0) };
+        let (percent, muted) = (percent_with_gain(volume.load(Ordering::Relaxed), gain_db), mute.load(Ordering::Relaxed));
+        let due = match applied {
+            None => true,
+            Some((was, was_muted, _)) if was_muted != muted || percent < was => true,
+            Some((was, _, at)) => percent != was && at.elapsed() >= RELEASE_STEP,
+        };
+        if due {
+            if let Err(e) = set_stream_volume(stream, percent, muted).await {
+                log!("Ducking off: {:#}", e);
+                return;
+            }
+            applied = Some((percent, muted, Instant::now()));
+        }
+    }
+}
+
+// Turns the stream down while the microphone picks something up, so a voice over the
+// music comes through: narration, or a DJ talking over the party. Works on the capture
+// process's own recording stream, like the volume slider, so it needs no restart and
+// suits either engine.
+pub struct Ducker {
+    percent: Arc<AtomicU8>, // The stream's volume when not ducked
+    muted: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>, // The task ends, and stops the meter, on its next tick
+}
+
+impl Ducker {
+    // `pid` records the stream; `mic` is the source that triggers it. Above `threshold_db`
+    // (dBFS peak) the stream goes down by `amount_db`.
+    pub fn start(pid: u32, mic: &str, threshold_db: f32, amount_db: f32, percent: u8, muted: bool, runtime_handle: &Handle) -> Result<Self> {
+        let meter = LevelMeter::start(mic, runtime_handle)?;
+        let (percent, muted, stopped) = (Arc::new(AtomicU8::new(percent)), Arc::new(AtomicBool::new(muted)), Arc::new(AtomicBool::new(false)));
+        let (volume, mute, stop) = (percent.clone(), muted.clone(), stopped.clone());
+        runtime_handle.spawn(async move {
+            match capture_stream(pid).await {
+                Ok(stream) => duck(&stream, &meter, threshold_db, amount_db, &volume, &mute, &stop).await,
+                Err(e) => log!("Ducking off: {:#}", e),
+            }
+            meter.stop();
+        });
+        Ok(Self { percent, muted, stopped })
+    }
+
+    // The volume slider and mute go through here while ducking, which applies them.
+    pub fn set_volume(&self, percent: u8, muted: bool) {
+        self.percent.store(percent, Ordering::Relaxed);
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn stop(self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{
+    audio::AudioSource,
+    bluetooth::BluetoothSink,
+    compare::Comparison,
+    diagnose::Finding,
+    ffmpeg::FfmpegInfo,
+    indicator::Indicator,
+    ipc::{Reply, Request},
+    network::BandwidthReport,
+    rendezvous::NatReport,
+    selftest::SelfTestReport,
+    streams::{StreamEvent, StreamStats},
+    vpn::VpnPeer,
+};
+use tokio::sync::oneshot;
+
+// Everything background work reports to a front end. It all goes over one channel,
+// so the front end applies results in one place instead of draining a receiver (or
+// checking a shared slot) per task.
+#[derive(Debug)]
+pub enum Event {
+    SourceListUpdated(Result<Vec<AudioSource>, String>),
+    BluetoothSinksUpdated(Vec<BluetoothSink>),
+    VpnPeersUpdated(Vec<VpnPeer>),
+    PowerSourceChanged { on_battery: bool },
+    NetworkChanged, // Links, addresses or routes, see `netwatch::watch`
+    BandwidthMeasured(Result<BandwidthReport, String>),
+    NatChecked(Result<NatReport, String>),
+    SelfTestFinished(SelfTestReport),
+    Diagnosed(Vec<Finding>),
+    Compared(Result<Comparison, String>),
+    FfmpegChecked { check: u64, result: Result<FfmpegInfo, String> }, // See `check_ffmpeg`
+    Progress(String), // For the status line, e.g. while normalizing recordings
+    Stream(StreamEvent),
+    StatsTick(Vec<StreamStats>),
+    IndicatorStarted(Result<Indicator, String>),
+    IndicatorActivated, // The tray icon was clicked
+    // From another `audio-streamer` process, see `ipc::Server`; answered on `reply_tx`.
+    Remote { request: Request, reply_tx: oneshot::Sender<Reply> },
+}
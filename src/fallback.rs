@@ -0,0 +1,165 @@
+use crate::{
+    config::Config,
+    flac,
+    mtu::PacketPlan,
+    rtp::{DYNAMIC_PAYLOAD_TYPE, RtpPacketizer},
+    supervisor::Supervisor,
+    tag::StreamTag,
+    transport::Transport,
+    xrun::{AudioClock, Xruns},
+};
+use anyhow::{Context, Result};
+use std::{
+    net::SocketAddr,
+    process::Stdio,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{io::AsyncReadExt, net::UdpSocket, process::Command, runtime::Handle};
+
+// Longer packets are cut down to this so they never need IP fragmentation.
+pub const MAX_PAYLOAD_BYTES: usize = 1440;
+// RFC 3550's minimum RTCP interval.
+const SDES_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    Ffmpeg,
+    BuiltIn,
+}
+
+impl Engine {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Engine::Ffmpeg => "ffmpeg",
+            Engine::BuiltIn => "built-in (PCM over RTP, or FLAC over the native transport)",
+        }
+    }
+}
+
+// The built-in engine compresses to FLAC for our own receiver, which takes it over the
+// native transport; players get PCM over RTP, which they open with the SDP.
+pub fn sends_flac(config: &Config) -> bool {
+    config.audio_codec == "flac" && config.transport == Transport::Native
+}
+
+// What the relay sends on: the built-in engine's RTP must go out as-is.
+pub fn relay_transport(config: &Config, engine: Engine) -> Transport {
+    if engine == Engine::BuiltIn && !sends_flac(config) { Transport::Udp } else { config.transport }
+}
+
+// As sessions and stream tags name it.
+pub fn codec(config: &Config) -> String {
+    if sends_flac(config) { "flac".to_string() } else { format!("pcm_s{}be", config.sample_format.rtp_bits()) }
+}
+
+// parec's big-endian samples, sign-extended.
+fn samples(pcm: &[u8], bits: u8) -> Vec<i32> {
+    let bytes = bits as usize / 8;
+    pcm.chunks_exact(bytes).map(|sample| sample.iter().fold(0i32, |value, &byte| value << 8 | byte as i32) << (32 - bits) >> (32 - bits)).collect()
+}
+
+// `bits` is 16 or 24, for RTP L16 or L24.
+pub fn parec_args(source: &str, sample_rate: u32, channels: u8, bits: u8, packet_millis: u32) -> Vec<String> {
+    vec![
+        format!("--device={}", source),
+        format!("--format=s{}be", bits), // L16/L24 are network byte order
+        format!("--rate={}", sample_rate),
+        format!("--channels={}", channels),
+        format!("--latency-msec={}", packet_millis),
+        "--raw".to_string(),
+    ]
+}
+
+// Minimal pipeline for machines without ffmpeg: `parec` captures raw PCM, which we
+// send uncompressed as RTP L16 (or L24), ~1.5 Mbit/s for 16-bit 48 kHz stereo, or as
+// FLAC frames, one per packet, with `sends_flac`. Other codecs and the bitrate need
+// ffmpeg, and capturing needs PulseAudio or PipeWire.
+pub struct FallbackStreamer {
+    capture: Supervisor,
+}
+
+impl FallbackStreamer {
+    // `packets` keeps each packet within the path MTU, see `mtu::plan`. With RTP, `tag`
+    // goes out as RTCP SDES every few seconds, after a sender report with
+    // `Config::rtp_clock`; the relay announces it otherwise. Audio parec dropped counts in `xruns`.
+    pub fn start(
+        config: &Config,
+        source: &str,
+        destination: SocketAddr,
+        packets: &PacketPlan,
+        tag: StreamTag,
+        xruns: Arc<Xruns>,
+        runtime_handle: &Handle,
+    ) -> Result<Self> {
+        let (sample_rate, channels, bits) = (config.sample_rate, config.channels, config.sample_format.rtp_bits());
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").context("Failed to create UDP socket")?;
+        socket.set_nonblocking(true)?;
+        let mut capture = Supervisor::spawn(
+            "parec",
+            Command::new("parec")
+                .args(parec_args(source, sample_rate, channels, bits, config.packet_millis))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null()),
+            runtime_handle,
+        )?;
+        let mut stdout = capture.take_stdout().context("parec has no stdout")?;
+
+        let frame_bytes = channels as usize * (bits as usize / 8);
+        // A FLAC frame is never much larger than its samples, and has at least 16.
+        let mut encoder = sends_flac(config).then(|| flac::Encoder::new(sample_rate, channels, bits));
+        let (max_payload, min_frames) = match encoder {
+            Some(_) => (packets.ts_size.saturating_sub(flac::MAX_OVERHEAD), 16),
+            None => (packets.rtp_payload.min(MAX_PAYLOAD_BYTES), 1),
+        };
+        let frames_per_packet = (sample_rate * config.packet_millis / 1000).min((max_payload / frame_bytes).max(min_frames) as u32);
+        let packet_bytes = frames_per_packet as usize * frame_bytes;
+        let packet_audio = Duration::from_secs_f64(frames_per_packet as f64 / sample_rate as f64);
+        let wall_clock = config.rtp_clock;
+
+        // Ends when parec exits and its stdout closes.
+        runtime_handle.spawn(async move {
+            let Ok(socket) = UdpSocket::from_std(socket) else {
+                return;
+            };
+            let mut packetizer: Option<RtpPacketizer> = None; // Once the first sample's capture time is known
+            let mut buf = vec![0u8; packet_bytes];
+            let mut described: Option<Instant> = None;
+            let mut clock = AudioClock::default();
+            while stdout.read_exact(&mut buf).await.is_ok() {
+                if let Some(lost) = clock.advance(packet_audio, Instant::now()) {
+                    xruns.overrun(Some(lost));
+                }
+                if let Some(encoder) = &mut encoder {
+                    let _ = socket.send_to(&encoder.encode(&samples(&buf, bits)), destination).await;
+                    continue;
+                }
+                // The read ends as the packet's last sample arrives.
+                let captured = SystemTime::now() - packet_audio;
+                let rtp = packetizer.get_or_insert_with(|| {
+                    if wall_clock { RtpPacketizer::wall_clock(DYNAMIC_PAYLOAD_TYPE, sample_rate, captured) } else { RtpPacketizer::new(DYNAMIC_PAYLOAD_TYPE) }
+                });
+                if described.is_none_or(|at| at.elapsed() >= SDES_INTERVAL) {
+                    let _ = socket.send_to(&rtp.source_description(&tag, wall_clock.then_some(captured)), destination).await;
+                    described = Some(Instant::now());
+                }
+                let packet = rtp.packetize(&buf, frames_per_packet);
+                let _ = socket.send_to(&packet, destination).await;
+            }
+        });
+
+        Ok(Self { capture })
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.capture.pid()
+    }
+
+    pub fn exit_reason(&self) -> Option<String> {
+        self.capture.exit_reason()
+    }
+
+    pub fn stop(self) {
+        self.capture.stop();
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{config::Config, filters::Resampler, transport::Transport};
+use anyhow::{Context, Result, bail};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+use tokio::process::Command;
+
+// Older builds lack options we rely on (e.g. -flush_packets on mpegts).
+const MIN_MAJOR_VERSION: u32 = 4;
+
+pub const INSTALL_HINT: &str = "Install ffmpeg with your package manager (e.g. `sudo pacman -S ffmpeg` or `sudo apt install ffmpeg`), or point `ffmpeg_path` in the config at an existing binary.";
+
+#[derive(Debug, Clone)]
+pub struct FfmpegInfo {
+    pub path: PathBuf,
+    pub version: String,
+    pub encoders: Vec<String>, // By name, so asking about another codec doesn't run ffmpeg again
+}
+
+fn find_in_path(binary: &str) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+}
+
+pub fn locate_ffmpeg(config: &Config) -> Result<PathBuf> {
+    match &config.ffmpeg_path {
+        Some(path) if !path.is_empty() => {
+            let path = PathBuf::from(path);
+            if !path.is_file() {
+                bail!("Configured ffmpeg_path '{}' does not exist", path.display());
+            }
+            Ok(path)
+        }
+        _ => find_in_path("ffmpeg").context("ffmpeg was not found in PATH"),
+    }
+}
+
+async fn run_query(path: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new(path)
+        .arg("-hide_banner")
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run '{}'", path.display()))?;
+    if !output.status.success() {
+        bail!("'{} {}' exited with {}", path.display(), args.join(" "), output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// "ffmpeg version n6.1.1 Copyright ..." or "ffmpeg version 4.4.2-0ubuntu0.22.04.1 ...".
+fn parse_version(output: &str) -> Option<String> {
+    let line = output.lines().next()?;
+    let version = line.strip_prefix("ffmpeg version ")?.split_whitespace().next()?;
+    Some(version.to_string())
+}
+
+fn major_version(version: &str) -> Option<u32> {
+    let digits: String = version
+        .trim_start_matches('n')
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+// Lines in `-formats` look like " D  pulse           Pulse audio input".
+fn has_input_format(formats: &str, name: &str) -> bool {
+    formats.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some(flags), Some(names)) => flags.contains('D') && names.split(',').any(|n| n == name),
+            _ => false,
+        }
+    })
+}
+
+// Lines in `-encoders` look like " A....D aac                  AAC (Advanced Audio Coding)".
+fn has_encoder(encoders: &str, name: &str) -> bool {
+    encoders.lines().any(|line| line.split_whitespace().nth(1) == Some(name))
+}
+
+// What this ffmpeg (and so ffplay, built from the same libraries) can decode, by name.
+pub async fn decoders(config: &Config) -> Result<Vec<String>> {
+    let decoders = run_query(&locate_ffmpeg(config)?, &["-decoders"]).await?;
+    Ok(decoders.lines().filter_map(|line| line.split_whitespace().nth(1)).map(String::from).collect())
+}
+
+// Finds ffmpeg and makes sure it can actually run the pipeline we are about to
+// build, so problems show up as a clear message instead of a failed spawn.
+pub async fn check_ffmpeg(config: &Config) -> Result<FfmpegInfo> {
+    let path = locate_ffmpeg(config)?;
+
+    let version_output = run_query(&path, &["-version"]).await?;
+    let version = parse_version(&version_output).unwrap_or_else(|| "unknown".to_string());
+    // Git snapshots ("N-112345-g…") have no release number; let them through.
+    if let Some(major) = major_version(&version)
+        && major < MIN_MAJOR_VERSION
+    {
+        bail!("ffmpeg {} is too old, version {} or newer is required", version, MIN_MAJOR_VERSION);
+    }
+
+    // soxr is an optional library; `-version` lists the configure flags.
+    if config.resampler == Resampler::Soxr && !version_output.contains("--enable-libsoxr") {
+        bail!("This ffmpeg build has no soxr resampler (libsoxr); choose swr instead");
+    }
+
+    if config.transport == Transport::Rist && !version_output.contains("--enable-librist") {
+        bail!("This ffmpeg build has no RIST support (librist); choose another transport");
+    }
+
+    let formats = run_query(&path, &["-formats"]).await?;
+    let backend = config.capture_backend.effective();
+    if !has_input_format(&formats, backend.ffmpeg_format()) {
+        bail!("This ffmpeg build has no {} input support ({} demuxer)", backend.label(), backend.ffmpeg_format());
+    }
+
+    let encoders = run_query(&path, &["-encoders"]).await?;
+    if !has_encoder(&encoders, &config.audio_codec) {
+        bail!("This ffmpeg build has no '{}' encoder", config.audio_codec);
+    }
+
+    let encoders = encoders.lines().filter_map(|line| line.split_whitespace().nth(1)).map(String::from).collect();
+    Ok(FfmpegInfo { path, version, encoders })
+}
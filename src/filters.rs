@@ -0,0 +1,284 @@
+use crate::{beacon::beacon_filter, config::Config, loudness::loudnorm_filter, outputs::expand_home};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelMode {
+    Passthrough, // What PulseAudio hands us, already stereo
+    Downmix51,
+    Downmix71,
+    Mono,
+    Swap, // Left and right exchanged
+}
+
+impl ChannelMode {
+    pub const ALL: [ChannelMode; 5] = [
+        ChannelMode::Passthrough,
+        ChannelMode::Downmix51,
+        ChannelMode::Downmix71,
+        ChannelMode::Mono,
+        ChannelMode::Swap,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ChannelMode::Passthrough => "As captured",
+            ChannelMode::Downmix51 => "Downmix 5.1 to stereo",
+            ChannelMode::Downmix71 => "Downmix 7.1 to stereo",
+            ChannelMode::Mono => "Mono mix",
+            ChannelMode::Swap => "Swap left/right",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownmixMatrix {
+    Itu,   // ITU-R BS.775: centre and surrounds at -3 dB
+    Lfe,   // As ITU, plus the LFE channel at -6 dB for small speakers without a sub
+    Front, // Front pair and centre only, for dialogue clarity
+}
+
+impl DownmixMatrix {
+    pub const ALL: [DownmixMatrix; 3] = [DownmixMatrix::Itu, DownmixMatrix::Lfe, DownmixMatrix::Front];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DownmixMatrix::Itu => "Standard (ITU)",
+            DownmixMatrix::Lfe => "Standard + LFE",
+            DownmixMatrix::Front => "Front + centre only",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resampler {
+    Swr,  // ffmpeg's own, always available
+    Soxr, // Higher quality, needs ffmpeg built with libsoxr
+}
+
+impl Resampler {
+    pub const ALL: [Resampler; 2] = [Resampler::Swr, Resampler::Soxr];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Resampler::Swr => "swr (built in)",
+            Resampler::Soxr => "soxr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResamplerQuality {
+    Normal,
+    High,
+    VeryHigh,
+}
+
+impl ResamplerQuality {
+    pub const ALL: [ResamplerQuality; 3] = [ResamplerQuality::Normal, ResamplerQuality::High, ResamplerQuality::VeryHigh];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ResamplerQuality::Normal => "Normal",
+            ResamplerQuality::High => "High",
+            ResamplerQuality::VeryHigh => "Very high",
+        }
+    }
+
+    // swr trades CPU for quality through the filter length, soxr through its precision
+    // in bits; Normal is each resampler's own default.
+    fn option(self, resampler: Resampler) -> String {
+        match (resampler, self) {
+            (Resampler::Swr, ResamplerQuality::Normal) => "filter_size=32".to_string(),
+            (Resampler::Swr, ResamplerQuality::High) => "filter_size=64".to_string(),
+            (Resampler::Swr, ResamplerQuality::VeryHigh) => "filter_size=128:cutoff=0.97".to_string(),
+            (Resampler::Soxr, ResamplerQuality::Normal) => "precision=20".to_string(),
+            (Resampler::Soxr, ResamplerQuality::High) => "precision=24".to_string(),
+            (Resampler::Soxr, ResamplerQuality::VeryHigh) => "precision=28".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dither {
+    None,
+    Triangular,
+    Shibata, // Noise-shaped, pushes the noise where it's least audible
+}
+
+impl Dither {
+    pub const ALL: [Dither; 3] = [Dither::None, Dither::Triangular, Dither::Shibata];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Dither::None => "None",
+            Dither::Triangular => "Triangular",
+            Dither::Shibata => "Shibata (noise shaped)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleFormat {
+    Auto, // Whatever the encoder prefers
+    S16,
+    S24,
+    F32,
+}
+
+impl SampleFormat {
+    pub const ALL: [SampleFormat; 4] = [SampleFormat::Auto, SampleFormat::S16, SampleFormat::S24, SampleFormat::F32];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SampleFormat::Auto => "Auto",
+            SampleFormat::S16 => "16-bit integer",
+            SampleFormat::S24 => "24-bit integer",
+            SampleFormat::F32 => "32-bit float",
+        }
+    }
+
+    // Only formats the encoder supports work (aac, for one, is float only).
+    // ffmpeg keeps 24-bit samples in 32-bit containers.
+    pub fn ffmpeg_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            SampleFormat::Auto => &[],
+            SampleFormat::S16 => &["-sample_fmt", "s16"],
+            SampleFormat::S24 => &["-sample_fmt", "s32", "-bits_per_raw_sample", "24"],
+            SampleFormat::F32 => &["-sample_fmt", "flt"],
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    // Bits per sample for the built-in engine's RTP stream. There is no float RTP
+    // payload many receivers play reliably, so float falls back to L16.
+    pub fn rtp_bits(self) -> u8 {
+        if self == SampleFormat::S24 { 24 } else { 16 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseSuppression {
+    Off,
+    Light,   // afftdn, for fans and hum
+    Strong,  // afftdn turned up, at some cost to the voice
+    Rnnoise, // arnndn, a neural net trained on speech; needs a model file
+}
+
+impl NoiseSuppression {
+    pub const ALL: [NoiseSuppression; 4] = [NoiseSuppression::Off, NoiseSuppression::Light, NoiseSuppression::Strong, NoiseSuppression::Rnnoise];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NoiseSuppression::Off => "Off",
+            NoiseSuppression::Light => "Light",
+            NoiseSuppression::Strong => "Strong",
+            NoiseSuppression::Rnnoise => "Voice isolation (RNNoise)",
+        }
+    }
+}
+
+// Only microphones get it: on music it would eat the quiet parts. afftdn tracks the
+// noise floor itself (tn=1), so it adapts when a fan spins up.
+fn noise_filter(config: &Config) -> Option<String> {
+    match config.noise_suppression {
+        NoiseSuppression::Off => None,
+        NoiseSuppression::Light => Some("afftdn=nr=10:nf=-50:tn=1".to_string()),
+        NoiseSuppression::Strong => Some("afftdn=nr=24:nf=-40:tn=1".to_string()),
+        // Inside single quotes only a quote needs escaping, by closing and reopening them.
+        NoiseSuppression::Rnnoise => {
+            let model = expand_home(config.noise_model.trim()).to_string_lossy().replace('\'', "'\\\\''");
+            Some(format!("arnndn=m='{}'", model))
+        }
+    }
+}
+
+fn resample_filter(config: &Config) -> Option<String> {
+    let default = config.resampler == Resampler::Swr
+        && config.resampler_quality == ResamplerQuality::Normal
+        && config.dither == Dither::None;
+    if default {
+        return None;
+    }
+    let resampler = match config.resampler {
+        Resampler::Swr => "swr",
+        Resampler::Soxr => "soxr",
+    };
+    let dither = match config.dither {
+        Dither::None => "none",
+        Dither::Triangular => "triangular",
+        Dither::Shibata => "shibata",
+    };
+    Some(format!(
+        "aresample={}:resampler={}:{}:dither_method={}",
+        config.sample_rate,
+        resampler,
+        config.resampler_quality.option(config.resampler),
+        dither
+    ))
+}
+
+// Channel names follow ffmpeg's default layouts: 5.1 is FL FR FC LFE BL BR,
+// 7.1 adds SL SR. `<` makes pan scale the gains down so the sum can't clip.
+fn downmix_filter(mode: ChannelMode, matrix: DownmixMatrix) -> String {
+    let surround = |side: char| {
+        let mut terms = vec![format!("F{}", side), "0.707*FC".to_string()];
+        if matrix != DownmixMatrix::Front {
+            terms.push(format!("0.707*B{}", side));
+            if mode == ChannelMode::Downmix71 {
+                terms.push(format!("0.707*S{}", side));
+            }
+        }
+        if matrix == DownmixMatrix::Lfe {
+            terms.push("0.5*LFE".to_string());
+        }
+        terms.join("+")
+    };
+    format!("pan=stereo|FL<{}|FR<{}", surround('L'), surround('R'))
+}
+
+// Channels to ask PulseAudio for; by default it already downmixes to stereo for us.
+pub fn capture_channels(config: &Config) -> Option<u8> {
+    match config.channel_mode {
+        ChannelMode::Downmix51 => Some(6),
+        ChannelMode::Downmix71 => Some(8),
+        _ => None,
+    }
+}
+
+// The `-af` chain for the configured processing, or None when there is nothing to do.
+pub fn filter_chain(config: &Config) -> Option<String> {
+    let mut filters = Vec::new();
+    if let mode @ (ChannelMode::Downmix51 | ChannelMode::Downmix71) = config.channel_mode {
+        filters.push(downmix_filter(mode, config.downmix_matrix));
+    }
+    // Vocals are usually mixed dead centre, the same in both channels, so the difference
+    // between them is the song without the singer (and without anything else centred,
+    // like the bass). Both speakers get the same difference: opposite ones would cancel
+    // again in the room, or in a mono mix. After the downmix, which brings the centre
+    // channel into both.
+    if config.remove_vocals {
+        filters.push("pan=stereo|c0<c0-c1|c1<c0-c1".to_string());
+    }
+    match config.channel_mode {
+        // A mono mix still goes out on every configured channel, so both speakers play it.
+        ChannelMode::Mono => filters.push("pan=mono|c0=0.5*c0+0.5*c1".to_string()),
+        ChannelMode::Swap => filters.push("pan=stereo|c0=c1|c1=c0".to_string()),
+        ChannelMode::Passthrough | ChannelMode::Downmix51 | ChannelMode::Downmix71 => {}
+    }
+    // Before normalization, so the noise isn't brought up with the voice.
+    filters.extend(noise_filter(config));
+    if config.normalize_loudness {
+        filters.push(loudnorm_filter(config.target_lufs));
+    }
+    // After normalization, which would otherwise treat the beacon as programme audio.
+    filters.extend(beacon_filter(config));
+    filters.extend(resample_filter(config));
+    (!filters.is_empty()).then(|| filters.join(","))
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result, bail};
+use std::{fs, path::Path, process::Command};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Firewall {
+    Ufw,
+    Firewalld,
+}
+
+impl Firewall {
+    pub fn label(self) -> &'static str {
+        match self {
+            Firewall::Ufw => "ufw",
+            Firewall::Firewalld => "firewalld",
+        }
+    }
+
+    // Commands that let `port` in, run through pkexec so the desktop asks for the password.
+    pub fn allow_commands(self, port: u16) -> Vec<Vec<String>> {
+        let rule = format!("{}/udp", port);
+        match self {
+            Firewall::Ufw => vec![vec!["pkexec".to_string(), "ufw".to_string(), "allow".to_string(), rule]],
+            // Runtime rule for now, permanent one for the next boot.
+            Firewall::Firewalld => vec![
+                vec!["pkexec".to_string(), "firewall-cmd".to_string(), format!("--add-port={}", rule)],
+                vec!["pkexec".to_string(), "firewall-cmd".to_string(), "--permanent".to_string(), format!("--add-port={}", rule)],
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortStatus {
+    NoFirewall,
+    Allowed(Firewall),
+    Blocked(Firewall),
+    Unknown(Firewall, String), // Firewall is on but its rules couldn't be read
+}
+
+fn run(program: &str, args: &[&str]) -> Option<(bool, String)> {
+    let output = Command::new(program).args(args).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout).to_string() + &String::from_utf8_lossy(&output.stderr);
+    Some((output.status.success(), text))
+}
+
+// ufw keeps its on/off switch in a world-readable file; the rules need root.
+fn ufw_enabled() -> bool {
+    fs::read_to_string(Path::new("/etc/ufw/ufw.conf"))
+        .is_ok_and(|conf| conf.lines().any(|line| line.trim() == "ENABLED=yes"))
+}
+
+fn ufw_status(port: u16) -> PortStatus {
+    let Some((true, status)) = run("ufw", &["status", "verbose"]) else {
+        return PortStatus::Unknown(Firewall::Ufw, "ufw rules can only be read as root".to_string());
+    };
+    if status.contains("Status: inactive") {
+        return PortStatus::NoFirewall;
+    }
+    let rule_allows = status.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        let target = fields.next().unwrap_or_default();
+        let allows = line.contains("ALLOW IN") || line.contains(" ALLOW ");
+        allows && (target == port.to_string() || target == format!("{}/udp", port))
+    });
+    if rule_allows || status.contains("allow (incoming)") {
+        PortStatus::Allowed(Firewall::Ufw)
+    } else {
+        PortStatus::Blocked(Firewall::Ufw)
+    }
+}
+
+fn firewalld_status(port: u16) -> Option<PortStatus> {
+    let (running, _) = run("firewall-cmd", &["--state"])?;
+    if !running {
+        return None;
+    }
+    let query = format!("--query-port={}/udp", port);
+    Some(match run("firewall-cmd", &[query.as_str()]) {
+        Some((_, answer)) if answer.trim() == "yes" => PortStatus::Allowed(Firewall::Firewalld),
+        Some((_, answer)) if answer.trim() == "no" => PortStatus::Blocked(Firewall::Firewalld),
+        Some((_, answer)) => PortStatus::Unknown(Firewall::Firewalld, answer.trim().to_string()),
+        None => PortStatus::Unknown(Firewall::Firewalld, "firewall-cmd did not run".to_string()),
+    })
+}
+
+// Whether incoming UDP on `port` gets past the local firewall. Only ufw and
+// firewalld are recognised; hand-written nftables/iptables rules aren't inspected.
+pub fn check_port(port: u16) -> PortStatus {
+    if let Some(status) = firewalld_status(port) {
+        return status;
+    }
+    if ufw_enabled() {
+        return ufw_status(port);
+    }
+    PortStatus::NoFirewall
+}
+
+pub fn allow_port(firewall: Firewall, port: u16) -> Result<()> {
+    for command in firewall.allow_commands(port) {
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .with_context(|| format!("Failed to run {}", command.join(" ")))?;
+        if !status.success() {
+            bail!("'{}' exited with {}", command.join(" "), status);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,457 @@
+// FLAC for the built-in engine, which has no ffmpeg to compress with: fixed predictors
+// and Rice-coded residuals, about half of PCM's bitrate for music and next to nothing
+// for silence. Every frame header has the rate, channels and sample size, so a
+// receiver that joins late builds the stream header from the first frame it gets.
+
+// The 14-bit sync code, then fixed-size blocks.
+const SYNC: [u8; 2] = [0xff, 0xf8];
+// Block size code: the size minus one follows the frame number, in 16 bits.
+const BLOCK_16_BITS: u8 = 7;
+const MAX_ORDER: usize = 4; // The fixed predictors go up to order 4
+const MAX_PARTITION_ORDER: u32 = 6;
+const MAX_RICE_PARAMETER: u32 = 30; // In 5 bits, where 31 is the escape code
+// The most a frame adds to its raw samples: header, subframe headers, padding and CRC.
+pub const MAX_OVERHEAD: usize = 26;
+// Codes in the frame header; rates not listed go in extra header bytes.
+const RATES: [(u32, u8); 11] = [
+    (88200, 1),
+    (176400, 2),
+    (192000, 3),
+    (8000, 4),
+    (16000, 5),
+    (22050, 6),
+    (24000, 7),
+    (32000, 8),
+    (44100, 9),
+    (48000, 10),
+    (96000, 11),
+];
+const SAMPLE_SIZES: [(u8, u8); 5] = [(8, 1), (12, 2), (16, 4), (20, 5), (24, 6)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits: u8,
+    pub block: u32, // Samples per channel in a frame
+}
+
+// MSB first, as FLAC has it.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    len: u32, // Bits in `acc` not yet in `bytes`, under 8
+}
+
+impl BitWriter {
+    // The low `bits` of `value`; up to 32.
+    fn put(&mut self, value: u64, bits: u32) {
+        self.acc = (self.acc << bits) | (value & ((1 << bits) - 1));
+        self.len += bits;
+        while self.len >= 8 {
+            self.len -= 8;
+            self.bytes.push((self.acc >> self.len) as u8);
+        }
+    }
+
+    fn unary(&mut self, mut zeros: u64) {
+        while zeros >= 32 {
+            self.put(0, 32);
+            zeros -= 32;
+        }
+        self.put(1, zeros as u32 + 1);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.put(0, 8 - self.len);
+        }
+        self.bytes
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 })
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 })
+    })
+}
+
+// The "fLaC" marker and a STREAMINFO block, which a decoder needs before the first frame.
+// Frame sizes, the length and the MD5 are left unknown, as for a live stream.
+pub fn stream_header(format: &Format) -> Vec<u8> {
+    let mut info = BitWriter::default();
+    info.put(format.block as u64, 16); // Smallest and largest block
+    info.put(format.block as u64, 16);
+    info.put(0, 24); // Smallest and largest frame
+    info.put(0, 24);
+    info.put(format.sample_rate as u64, 20);
+    info.put(format.channels as u64 - 1, 3);
+    info.put(format.bits as u64 - 1, 5);
+    info.put(0, 4); // Total samples, 36 bits
+    info.put(0, 32);
+    let mut header = b"fLaC".to_vec();
+    header.extend_from_slice(&[0x80, 0, 0, 34]); // The last metadata block: STREAMINFO, 34 bytes
+    header.extend(info.finish());
+    header.extend_from_slice(&[0; 16]);
+    header
+}
+
+// The header fields of a frame, and how long the header is; None for anything else.
+fn parse_header(frame: &[u8]) -> Option<(Format, usize)> {
+    if frame.len() < 6 || frame[..2] != SYNC {
+        return None;
+    }
+    let channels = match frame[3] >> 4 {
+        code @ 0..=7 => code + 1,
+        8..=10 => 2, // Stereo with the channels decorrelated
+        _ => return None,
+    };
+    let size_code = (frame[3] >> 1) & 7;
+    let bits = SAMPLE_SIZES.iter().find(|(_, code)| *code == size_code)?.0;
+    let number_len = match frame[4].leading_ones() {
+        0 => 1,
+        len @ 2..=7 => len as usize,
+        _ => return None,
+    };
+    let mut len = 4 + number_len;
+    let mut extra = |bytes: usize| {
+        let value = frame.get(len..len + bytes)?.iter().fold(0u32, |value, &byte| value << 8 | byte as u32);
+        len += bytes;
+        Some(value)
+    };
+    let block = match frame[2] >> 4 {
+        1 => 192,
+        code @ 2..=5 => 576 << (code - 2),
+        6 => extra(1)? + 1,
+        7 => extra(2)? + 1,
+        code @ 8..=15 => 256 << (code - 8),
+        _ => return None,
+    };
+    let sample_rate = match frame[2] & 0xf {
+        12 => extra(1)? * 1000,
+        13 => extra(2)?,
+        14 => extra(2)? * 10,
+        code => RATES.iter().find(|(_, known)| *known == code)?.0,
+    };
+    (*frame.get(len)? == crc8(&frame[..len])).then_some((Format { sample_rate, channels, bits, block }, len + 1))
+}
+
+// What a frame holds, to tell FLAC from MPEG-TS and start a player on it.
+pub fn frame_format(frame: &[u8]) -> Option<Format> {
+    parse_header(frame).map(|(format, _)| format)
+}
+
+// Bits of zig-zag coded residuals `values` with Rice parameter `k`.
+fn rice_bits(values: &[u64], k: u32) -> u64 {
+    values.iter().map(|value| (value >> k) + 1 + k as u64).sum()
+}
+
+// Around the parameter the mean suggests, which is within one of the best.
+fn best_parameter(values: &[u64]) -> (u32, u64) {
+    let mean = values.iter().sum::<u64>() / values.len().max(1) as u64;
+    let guess = (u64::BITS - mean.leading_zeros()).min(MAX_RICE_PARAMETER);
+    (guess.saturating_sub(1)..=(guess + 1).min(MAX_RICE_PARAMETER))
+        .map(|k| (k, rice_bits(values, k)))
+        .min_by_key(|(_, bits)| *bits)
+        .expect("Never empty")
+}
+
+// The partition order and parameters coding `values` smallest, and their size in bits.
+// `order` warm-up samples come before the first partition.
+fn rice_plan(values: &[u64], order: usize) -> (u32, Vec<u32>, u64) {
+    let block = values.len() + order;
+    (0..=MAX_PARTITION_ORDER)
+        .take_while(|partitions| block.is_multiple_of(1 << partitions) && (block >> partitions) > order)
+        .map(|partitions| {
+            let size = block >> partitions;
+            let mut start = 0;
+            let (mut parameters, mut bits) = (Vec::new(), 6); // Method and partition order
+            for part in 0..1 << partitions {
+                let end = start + size - if part == 0 { order } else { 0 };
+                let (k, part_bits) = best_parameter(&values[start..end]);
+                parameters.push(k);
+                bits += 5 + part_bits;
+                start = end;
+            }
+            (partitions, parameters, bits)
+        })
+        .min_by_key(|(_, _, bits)| *bits)
+        .expect("Partition order 0 always fits")
+}
+
+// What the fixed predictor of `order` misses, after its warm-up samples.
+fn residual(samples: &[i32], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |back: usize| samples[i - back] as i64;
+            let predicted = match order {
+                0 => 0,
+                1 => s(1),
+                2 => 2 * s(1) - s(2),
+                3 => 3 * s(1) - 3 * s(2) + s(3),
+                _ => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+            };
+            samples[i] as i64 - predicted
+        })
+        .collect()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+// One channel: constant, the best fixed predictor, or verbatim when nothing is smaller.
+fn subframe(bits: &mut BitWriter, samples: &[i32], sample_bits: u32) {
+    if samples.iter().all(|sample| *sample == samples[0]) {
+        bits.put(0, 8);
+        bits.put(samples[0] as u64, sample_bits);
+        return;
+    }
+    let verbatim = samples.len() as u64 * sample_bits as u64;
+    let best = (0..=MAX_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let values: Vec<u64> = residual(samples, order).into_iter().map(zigzag).collect();
+            let (partitions, parameters, rice) = rice_plan(&values, order);
+            (order as u64 * sample_bits as u64 + rice, order, values, partitions, parameters)
+        })
+        .min_by_key(|(size, ..)| *size)
+        .filter(|(size, ..)| *size < verbatim);
+    let Some((_, order, values, partitions, parameters)) = best else {
+        bits.put(1 << 1, 8);
+        for sample in samples {
+            bits.put(*sample as u64, sample_bits);
+        }
+        return;
+    };
+    bits.put((8 | order as u64) << 1, 8);
+    for sample in &samples[..order] {
+        bits.put(*sample as u64, sample_bits);
+    }
+    bits.put(1, 2); // Rice with 5-bit parameters
+    bits.put(partitions as u64, 4);
+    let size = samples.len() >> partitions;
+    let mut values = values.iter();
+    for (part, k) in parameters.into_iter().enumerate() {
+        bits.put(k as u64, 5);
+        for value in values.by_ref().take(size - if part == 0 { order } else { 0 }) {
+            bits.unary(value >> k);
+            bits.put(*value, k);
+        }
+    }
+}
+
+pub struct Encoder {
+    sample_rate: u32,
+    channels: u8,
+    bits: u8, // 8 to 24
+    frame: u32, // Numbered from 0, in 31 bits
+}
+
+impl Encoder {
+    pub fn new(sample_rate: u32, channels: u8, bits: u8) -> Self {
+        Self { sample_rate, channels, bits, frame: 0 }
+    }
+
+    // A frame of interleaved samples, 16 per channel at least; all but the last of a
+    // stream should have the same number, which its `Format::block` says.
+    pub fn encode(&mut self, samples: &[i32]) -> Vec<u8> {
+        let channels = self.channels as usize;
+        let block = samples.len() / channels;
+        let (rate_code, rate_bytes) = match RATES.iter().find(|(rate, _)| *rate == self.sample_rate) {
+            Some((_, code)) => (*code, 0),
+            None if self.sample_rate <= u16::MAX as u32 => (13, 2),
+            None => (14, 2), // In tens of Hz
+        };
+        let size_code = SAMPLE_SIZES.iter().find(|(bits, _)| *bits == self.bits).map_or(0, |(_, code)| *code);
+        let mut header = SYNC.to_vec();
+        header.push(BLOCK_16_BITS << 4 | rate_code);
+        header.push((self.channels - 1) << 4 | size_code << 1);
+        // UTF-8's scheme, stretched to 31 bits.
+        let frame = self.frame as u64;
+        match (2..=6u32).find(|bytes| frame >> (5 * bytes + 1) == 0) {
+            _ if frame < 0x80 => header.push(frame as u8),
+            Some(bytes) => {
+                header.push((0xff00u32 >> bytes) as u8 | (frame >> (6 * (bytes - 1))) as u8);
+                header.extend((0..bytes - 1).rev().map(|i| 0x80 | (frame >> (6 * i)) as u8 & 0x3f));
+            }
+            None => unreachable!("The frame number has 31 bits"),
+        }
+        header.extend_from_slice(&(block as u16 - 1).to_be_bytes());
+        let rate = if rate_code == 14 { self.sample_rate / 10 } else { self.sample_rate };
+        header.extend_from_slice(&(rate as u16).to_be_bytes()[2 - rate_bytes..]);
+        header.push(crc8(&header));
+        self.frame = (self.frame + 1) & 0x7fff_ffff;
+
+        let mut bits = BitWriter { bytes: header, ..Default::default() };
+        for channel in 0..channels {
+            let samples: Vec<i32> = samples.iter().skip(channel).step_by(channels).copied().collect();
+            subframe(&mut bits, &samples, self.bits as u32);
+        }
+        let mut frame = bits.finish();
+        frame.extend_from_slice(&crc16(&frame).to_be_bytes());
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        bit: usize,
+    }
+
+    impl BitReader<'_> {
+        fn get(&mut self, bits: u32) -> u64 {
+            (0..bits).fold(0, |value, _| {
+                let bit = self.data[self.bit / 8] >> (7 - self.bit % 8) & 1;
+                self.bit += 1;
+                value << 1 | bit as u64
+            })
+        }
+
+        fn signed(&mut self, bits: u32) -> i64 {
+            let value = self.get(bits) as i64;
+            value << (64 - bits) >> (64 - bits)
+        }
+
+        fn unary(&mut self) -> u64 {
+            let mut zeros = 0;
+            while self.get(1) == 0 {
+                zeros += 1;
+            }
+            zeros
+        }
+    }
+
+    // Interleaved samples back out of a frame, checking what a decoder would.
+    fn decode(frame: &[u8]) -> (Format, Vec<i32>) {
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        assert_eq!(crc16(body).to_be_bytes(), crc);
+        let (format, header_len) = parse_header(frame).expect("a frame header");
+        let (block, bits) = (format.block as usize, format.bits as u32);
+        let mut reader = BitReader { data: &frame[header_len..], bit: 0 };
+        let mut channels = Vec::new();
+        for _ in 0..format.channels {
+            let header = reader.get(8);
+            assert_eq!(header & 0x81, 0, "padding and wasted bits");
+            let samples: Vec<i64> = match header >> 1 {
+                0 => vec![reader.signed(bits); block],
+                1 => (0..block).map(|_| reader.signed(bits)).collect(),
+                kind @ 8..=12 => {
+                    let order = kind as usize - 8;
+                    let mut samples: Vec<i64> = (0..order).map(|_| reader.signed(bits)).collect();
+                    let parameter_bits = if reader.get(2) == 0 { 4 } else { 5 };
+                    let partitions = reader.get(4);
+                    for part in 0..1 << partitions {
+                        let k = reader.get(parameter_bits) as u32;
+                        for _ in 0..(block >> partitions) - if part == 0 { order } else { 0 } {
+                            let value = reader.unary() << k | reader.get(k);
+                            let residual = (value >> 1) as i64 ^ -((value & 1) as i64);
+                            let s = |back: usize| samples[samples.len() - back];
+                            let predicted = match order {
+                                0 => 0,
+                                1 => s(1),
+                                2 => 2 * s(1) - s(2),
+                                3 => 3 * s(1) - 3 * s(2) + s(3),
+                                _ => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+                            };
+                            samples.push(predicted + residual);
+                        }
+                    }
+                    samples
+                }
+                kind => panic!("subframe type {}", kind),
+            };
+            channels.push(samples);
+        }
+        assert_eq!(reader.bit.div_ceil(8), body.len() - header_len, "padding to the CRC");
+        let samples = (0..block).flat_map(|i| channels.iter().map(move |channel| channel[i] as i32)).collect();
+        (format, samples)
+    }
+
+    // A tone in one channel and noise in the other, from a fixed seed.
+    fn music(frames: usize, bits: u8) -> Vec<i32> {
+        let peak = (1 << (bits - 1)) as f64 * 0.7;
+        let mut seed = 1u32;
+        (0..frames)
+            .flat_map(|i| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let tone = (i as f64 * 0.05).sin() * peak;
+                [tone as i32, (seed >> (40 - bits)) as i32 - (1 << (bits - 9))]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_decode_to_their_samples() {
+        for bits in [16, 24] {
+            let mut encoder = Encoder::new(48000, 2, bits);
+            let samples = music(960, bits);
+            let frame = encoder.encode(&samples);
+            let (format, decoded) = decode(&frame);
+            assert_eq!(format, Format { sample_rate: 48000, channels: 2, bits, block: 960 });
+            assert_eq!(decoded, samples);
+            assert!(frame.len() < samples.len() * bits as usize / 8 + MAX_OVERHEAD);
+        }
+    }
+
+    #[test]
+    fn a_tone_compresses_and_silence_is_constant() {
+        let mut encoder = Encoder::new(44100, 1, 16);
+        let tone: Vec<i32> = (0..1024).map(|i| ((i as f64 * 0.03).sin() * 20000.0) as i32).collect();
+        let frame = encoder.encode(&tone);
+        assert_eq!(decode(&frame).1, tone);
+        assert!(frame.len() < tone.len() * 2 / 2, "{} bytes", frame.len());
+        let silence = encoder.encode(&[-5; 1024]);
+        assert_eq!(decode(&silence).1, vec![-5; 1024]);
+        assert!(silence.len() < 16, "{} bytes", silence.len());
+    }
+
+    #[test]
+    fn extremes_survive_the_predictors() {
+        let mut encoder = Encoder::new(48000, 1, 16);
+        let samples: Vec<i32> = (0..64).map(|i| if i % 3 == 0 { i16::MIN as i32 } else { i16::MAX as i32 }).collect();
+        assert_eq!(decode(&encoder.encode(&samples)).1, samples);
+    }
+
+    #[test]
+    fn headers_carry_any_rate_and_frame_number() {
+        for sample_rate in [8000, 11025, 22050, 96000, 352800] {
+            let mut encoder = Encoder::new(sample_rate, 6, 24);
+            encoder.frame = 0x7fff_fffe;
+            for _ in 0..3 {
+                let frame = encoder.encode(&music(96, 24).repeat(3));
+                assert_eq!(frame_format(&frame), Some(Format { sample_rate, channels: 6, bits: 24, block: 96 }));
+            }
+            assert_eq!(encoder.frame, 1);
+        }
+    }
+
+    #[test]
+    fn other_data_is_not_a_frame() {
+        let mut frame = Encoder::new(48000, 2, 16).encode(&music(160, 16));
+        assert!(frame_format(&[0x47; 188]).is_none());
+        frame[3] ^= 0x10; // Another channel count, which the CRC catches
+        assert!(frame_format(&frame).is_none());
+    }
+
+    #[test]
+    fn the_stream_header_describes_the_frames() {
+        let header = stream_header(&Format { sample_rate: 44100, channels: 2, bits: 16, block: 441 });
+        assert_eq!(header.len(), 42);
+        assert_eq!(&header[..8], b"fLaC\x80\x00\x00\x22");
+        assert_eq!(&header[8..12], &[0x01, 0xb9, 0x01, 0xb9]);
+        // 20 bits of rate, 3 of channels minus one and 5 of bits minus one.
+        assert_eq!(&header[18..21], &[0x0a, 0xc4, 0x42]);
+        assert_eq!(header[21] >> 4, 0xf);
+    }
+}
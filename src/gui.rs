@@ -1,234 +1,1508 @@
-use crate::{config::Config, audio::{AudioSource, get_audio_sources, get_best_source_index}};
+use audio_streamer::{access::parse_allowlist, bundle::{self, ImportMode}, compare::{Comparison, DEFAULT_CLIP_SECS, Setting, compare, play}, presets::PRESETS, profiles, sdp, tls::{CERTIFICATE_PATH, TlsIdentity}, config::{Config, parse_port, parse_target}, beacon::Beacon, crash, diagnose::{Finding, diagnose}, events::Event, log, ipc::{self, Reply, Request}, bluetooth::{BluetoothSink, paired_sinks}, pipeline::{RELAY_URL_PLACEHOLDER, describe_pipeline}, template::{PLACEHOLDERS, validate_template}, fallback::{self, Engine}, guide::receiver_guides, indicator::Indicator, inhibit::SleepInhibitor, jack, mix, mqtt::{self, Mqtt, MqttSettings}, ffmpeg::{FfmpegInfo, INSTALL_HINT, check_ffmpeg}, audio::{AudioSource, CaptureBackend, SourceKind, SourceOverrides, get_audio_sources, get_best_source_index}, history::{History, format_utc}, latency::{HISTORY, LatencyMonitor}, live, load::overload_message, negotiate::{self, Capabilities, negotiate}, blocklist::blocked_message, loudness, meter::{LevelMeter, SCOPE_COLUMNS}, power, network::{BandwidthReport, measure_bandwidth}, netwatch, outputs::{Output, expand_home}, qos::Dscp, snapcast, remote::{MAX_VOLUME, REMOTE_CODECS, RemoteCommand}, rendezvous::{DEFAULT_STUN_SERVERS, check_nat}, resume::{self, LastStream}, rollback, streams::{EngineOptions, QUICK_MUTE, Stream, StreamEvent, StreamManager, silence_countdown}, selftest::{SelfTestReport, run_self_test}, signal::{TestSignal, test_signal_args}, filters::{ChannelMode, Dither, DownmixMatrix, NoiseSuppression, Resampler, ResamplerQuality, SampleFormat}, theme::{self, DEFAULT_ACCENT, Palette, ThemeMode}, vpn::{VpnPeer, discover_peers}, transport::Transport, validate::{self, Problem}, xrun};
 use eframe::{egui, CreationContext};
 use egui::{Color32, Stroke};
+use qrcode::{Color as QrColor, QrCode};
 use std::{
+    collections::BTreeMap,
     fs,
     path::PathBuf,
-    process::{Child, Command, Stdio},
-    sync::{Arc, Mutex},
+    sync::mpsc::{self, Receiver, Sender},
     net::{UdpSocket, SocketAddr},
+    time::Instant,
 };
-use tokio::runtime::Handle;
-
-// A function to set up our custom style.
-fn configure_styles(ctx: &egui::Context) {
-    let mut style = (*ctx.style()).clone();
-    let visuals = &mut style.visuals;
-
-    // --- COLOR PALETTE (Dark theme with purple/blue accents) ---
-    let accent_color = Color32::from_rgb(110, 100, 255); // A nice purple
-    let text_color = Color32::from_gray(230);
-    let faint_color = Color32::from_gray(120);
-
-    visuals.window_rounding = 10.0.into();
-    visuals.window_shadow = egui::epaint::Shadow::big_dark();
-    visuals.override_text_color = Some(text_color);
-    visuals.extreme_bg_color = Color32::from_rgba_unmultiplied(10, 10, 15, 255); // For text edit, etc.
-
-    // Widget styling
-    let widget_visuals = &mut visuals.widgets;
-    widget_visuals.noninteractive.bg_fill = Color32::from_gray(27);
-    widget_visuals.noninteractive.bg_stroke = Stroke::new(1.0, Color32::from_gray(200));
-    widget_visuals.noninteractive.fg_stroke = Stroke::new(1.0, faint_color);
-    widget_visuals.noninteractive.rounding = 5.0.into();
-
-    widget_visuals.inactive = widget_visuals.noninteractive;
-    widget_visuals.inactive.bg_fill = Color32::from_white_alpha(10); // Subtle background for buttons
-
-    widget_visuals.hovered.bg_fill = Color32::from_white_alpha(20);
-    widget_visuals.hovered.bg_stroke = Stroke::new(1.0, accent_color);
-
-    widget_visuals.active.bg_fill = accent_color;
-    widget_visuals.active.bg_stroke = Stroke::new(1.0, accent_color);
-    widget_visuals.active.fg_stroke = Stroke::new(1.0, Color32::WHITE);
-
-    // Spacing and padding
-    style.spacing.item_spacing = egui::vec2(10.0, 8.0);
-    style.spacing.window_margin = egui::Margin::same(15.0);
-    style.spacing.button_padding = egui::vec2(12.0, 6.0);
-
-    ctx.set_style(style);
+use tokio::{process::Command, runtime::Handle};
+
+// Past this many visible sources the list is grouped by card and kind.
+const GROUPED_SOURCE_COUNT: usize = 8;
+// How often sources are re-listed while following the best source.
+const SOURCE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3);
+
+// Names an icon-only button for screen readers, which would otherwise read out the glyph.
+fn named_button(response: egui::Response, name: &str) -> egui::Response {
+    response.widget_info(|| egui::WidgetInfo::labeled(egui::WidgetType::Button, name));
+    response
+}
+
+// The allowlist field's entries; commas or spaces between them.
+fn allowlist_entries(text: &str) -> Vec<String> {
+    text.split([',', ' ']).map(str::trim).filter(|entry| !entry.is_empty()).map(String::from).collect()
+}
+
+// App names and JACK ports may have spaces ("ZOOM VoiceEngine"), so only commas separate them.
+fn app_entries(text: &str) -> Vec<String> {
+    text.split(',').map(str::trim).filter(|entry| !entry.is_empty()).map(String::from).collect()
+}
+
+// egui can only paste into a focused text field, so the paste button asks the
+// desktop's clipboard tool directly: Wayland first, then the two common X11 ones.
+fn read_clipboard() -> Option<String> {
+    let tools: [&[&str]; 3] = [&["wl-paste", "--no-newline"], &["xclip", "-selection", "clipboard", "-o"], &["xsel", "--clipboard", "--output"]];
+    tools.iter().find_map(|tool| {
+        let output = std::process::Command::new(tool[0]).args(&tool[1..]).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    })
+}
+
+// Paints a QR code as filled squares, with the quiet zone the spec requires around it.
+fn paint_qr_code(ui: &mut egui::Ui, code: &QrCode, module_size: f32) {
+    let quiet_zone = 4;
+    let width = code.width();
+    let side = (width + quiet_zone * 2) as f32 * module_size;
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(side, side), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 4.0, Color32::WHITE);
+
+    for (i, color) in code.to_colors().iter().enumerate() {
+        if *color != QrColor::Dark {
+            continue;
+        }
+        let (x, y) = (i % width + quiet_zone, i / width + quiet_zone);
+        let min = rect.min + egui::vec2(x as f32 * module_size, y as f32 * module_size);
+        painter.rect_filled(egui::Rect::from_min_size(min, egui::vec2(module_size, module_size)), 0.0, Color32::BLACK);
+    }
+}
+
+// Round trips to the target over the last two minutes, one bar per ping, newest on the
+// right: spikes and lost pings in the warning colour, receiver dropouts as a red tick below.
+fn paint_latency(ui: &mut egui::Ui, monitor: &LatencyMonitor, palette: Palette) {
+    let samples = monitor.samples();
+    if samples.is_empty() {
+        match monitor.error() {
+            Some(e) => ui.small(format!("📈 Round trip: {}", e)),
+            None => ui.small("📈 Round trip: measuring..."),
+        };
+        return;
+    }
+    let median = monitor.median();
+    let spikes = samples.iter().filter(|sample| sample.spike).count();
+    let together = samples.iter().filter(|sample| sample.spike && sample.dropout).count();
+    ui.horizontal(|ui| {
+        let label = match (samples.last().and_then(|sample| sample.rtt), median) {
+            (Some(rtt), Some(median)) => format!("📈 Round trip {:.1} ms (median {:.1} ms)", rtt.as_secs_f64() * 1000.0, median.as_secs_f64() * 1000.0),
+            (None, Some(median)) => format!("📈 Round trip: lost (median {:.1} ms)", median.as_secs_f64() * 1000.0),
+            _ => "📈 Round trip: no answers; the target may ignore ping".to_string(),
+        };
+        ui.small(label);
+        if spikes > 0 {
+            ui.small(format!("· {} spikes, {} with a dropout", spikes, together))
+                .on_hover_text("Dropouts that come with spikes point at the network, e.g. Wi-Fi interference or a busy link; without them, at the receiver");
+        }
+    });
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 40.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 3.0, palette.title_bar);
+    // Scaled to the slowest answer, but never so tight that jitter of a millisecond fills it.
+    let highest = samples.iter().filter_map(|sample| sample.rtt).max().unwrap_or_default().as_secs_f32().max(0.005);
+    let step = rect.width() / HISTORY as f32;
+    let start = rect.right() - samples.len() as f32 * step;
+    let plot_bottom = rect.bottom() - 4.0;
+    for (i, sample) in samples.iter().enumerate() {
+        let x = start + (i as f32 + 0.5) * step;
+        let (height, color) = match sample.rtt {
+            Some(rtt) => (rtt.as_secs_f32() / highest * (plot_bottom - rect.top()), if sample.spike { palette.warning } else { palette.accent }),
+            None => (plot_bottom - rect.top(), palette.warning),
+        };
+        painter.vline(x, (plot_bottom - height.max(1.0))..=plot_bottom, Stroke::new((step - 1.0).max(1.0), color));
+        if sample.dropout {
+            painter.vline(x, plot_bottom..=rect.bottom(), Stroke::new(step.max(2.0), palette.error));
+        }
+    }
+}
+
+// The number of listeners over the session as a step line, from the first one
+// connecting up to now, scaled to the peak.
+fn paint_listener_history(ui: &mut egui::Ui, history: &[(Instant, usize)], peak: usize, palette: Palette) {
+    let Some(&(first, _)) = history.first() else {
+        return;
+    };
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 30.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 3.0, palette.title_bar);
+    let span = first.elapsed().as_secs_f32().max(1.0);
+    let x = |at: Instant| rect.left() + at.duration_since(first).as_secs_f32() / span * rect.width();
+    let y = |count: usize| rect.bottom() - 3.0 - count as f32 / peak.max(1) as f32 * (rect.height() - 6.0);
+    let mut points = Vec::with_capacity(history.len() * 2 + 1);
+    for (i, &(at, count)) in history.iter().enumerate() {
+        if i > 0 {
+            points.push(egui::pos2(x(at), y(history[i - 1].1)));
+        }
+        points.push(egui::pos2(x(at), y(count)));
+    }
+    points.push(egui::pos2(rect.right(), y(history[history.len() - 1].1)));
+    painter.add(egui::Shape::line(points, Stroke::new(1.5, palette.accent)));
 }
 
 pub struct AudioStreamerApp {
     config: Config,
     config_path: PathBuf,
-    sources: Arc<Mutex<Vec<AudioSource>>>,
+    sources: Vec<AudioSource>,
+    // Background tasks report here, so `update()` never waits on pactl, ffmpeg or the network.
+    events_tx: Sender<Event>,
+    events_rx: Receiver<Event>,
     selected_source: usize,
-    streaming: bool,
-    ffmpeg_process: Option<Child>,
+    show_hidden_sources: bool,
+    source_filter: String,
+    scroll_to_selected_source: bool, // Set when the keyboard moved the selection
+    last_source_poll: Instant,
+    source_rename: Option<(String, String)>, // Source name and the nickname being typed
+    streams: StreamManager,
+    inhibitor: Option<SleepInhibitor>, // Held while streaming so the machine doesn't suspend
+    indicator: Option<Indicator>, // The tray icon that shows the stream is live, once the tray took it
+    mqtt: Option<Mqtt>, // Home Assistant's connection, while `integrations.mqtt` asks for one
+    bluetooth_sinks: Vec<BluetoothSink>,
     status_message: String,
     runtime_handle: Handle,
     temp_ip: String,
     temp_port: String,
+    temp_backup_ip: String,
+    temp_backup_port: String,
+    temp_ffmpeg_path: String,
+    temp_args_template: String,
+    temp_allowlist: String, // Comma-separated, like it's typed
+    temp_do_not_stream: String, // Likewise
+    temp_jack_connect: String, // Likewise
+    temp_mqtt: MqttSettings, // Applied when a field loses focus, so typing doesn't reconnect on every key
+    bundle_path: String, // Where settings are exported to and imported from
+    bundle_passphrase: String, // Empty leaves the secrets out of an export
+    template: Option<usize>, // The last applied entry of `PRESETS`, whose instructions are shown
+    ffmpeg_status: Result<FfmpegInfo, String>,
+    ffmpeg_check: u64, // The last check started, so an earlier one finishing late is ignored
+    checking_ffmpeg: bool,
     network_test_result: String,
+    nat_check: Option<String>, // What `rendezvous::check_nat` found, or that it is running
+    command_preview: Option<String>,
+    bandwidth_report: Option<BandwidthReport>, // The last successful measurement
+    measuring_bandwidth: bool,
+    pairing_qr: Option<(String, QrCode)>, // Cached with the URL it encodes
+    certificate_qr: Option<(String, QrCode)>, // Likewise, for the HTTPS certificate's download
+    tls_fingerprint: Option<Result<String, String>>, // Loaded when HTTPS is first shown
+    palette: Palette, // The one currently applied to the egui style
+    history: History,
+    test_signal: TestSignal,
+    test_frequency: u32,
+    test_duration_secs: u32,
+    self_testing: bool,
+    self_test_report: Option<SelfTestReport>,
+    diagnosing: bool,
+    diagnosis: Option<Vec<Finding>>,
+    compare_a: Setting,
+    compare_b: Setting,
+    compare_secs: u32,
+    comparing: bool,
+    comparison: Option<Result<Comparison, String>>,
+    playback: Option<tokio::process::Child>, // One side of the comparison, playing
+    vpn_peers: Vec<VpnPeer>,
+    on_battery: bool,
+    _ipc: Option<ipc::Server>, // Answers other `audio-streamer` processes
+    focus_requested: bool, // Another launch asked for this window
+    last_session: Vec<LastStream>, // Streaming when the app last went away, offered until resumed or dismissed
+    crash_report: Option<PathBuf>, // From a crash since the last start, not offered yet
+    config_problems: Vec<Problem>, // Found when loading the config, offered for reset until dismissed
+    meter: Option<(String, Result<LevelMeter, String>)>, // The waveform's source, while that section is open
 }
 
 impl AudioStreamerApp {
-    pub fn new(config: Config, config_path: PathBuf, runtime_handle: Handle, cc: &CreationContext) -> Self {
+    pub fn new(config: Config, config_problems: Vec<Problem>, config_path: PathBuf, runtime_handle: Handle, cc: &CreationContext) -> Self {
         // Apply the custom style on creation
-        configure_styles(&cc.egui_ctx);
+        let palette = Palette::new(config.theme.is_dark(cc.integration_info.system_theme), config.accent_color);
+        theme::apply(&cc.egui_ctx, &palette);
         
+        let history = History::load(History::path_for(&config_path));
+        let last_session = resume::load(&resume::path_for(&config_path));
+        let crash_report = crash::unseen_report(&config_path);
         let temp_ip = config.target_ip.clone();
         let temp_port = config.target_port.to_string();
+        let temp_backup_ip = config.backup_target_ip.clone();
+        let temp_backup_port = config.backup_target_port.to_string();
+        let temp_ffmpeg_path = config.ffmpeg_path.clone().unwrap_or_default();
+        let temp_args_template = config.ffmpeg_args_template.clone().unwrap_or_default();
+        let temp_allowlist = config.listen_allowlist.join(", ");
+        let temp_do_not_stream = config.do_not_stream.join(", ");
+        let temp_jack_connect = config.jack_connect.join(", ");
+        let temp_mqtt = config.integrations.mqtt.clone();
+        let compare_a = Setting { codec: config.audio_codec.clone(), bitrate: config.bitrate.clone() };
         let status_message = if config.is_ip_configured() {
             "Ready to stream".to_string()
         } else {
             "Please set target IP address".to_string()
         };
 
+        let (events_tx, events_rx) = mpsc::channel();
+        // Without it a network change leaves the stream as it is, like before.
+        if let Err(e) = netwatch::watch(events_tx.clone(), &runtime_handle) {
+            log!("Not watching for network changes: {:#}", e);
+        }
+        // Without it a second launch opens a second window, like before.
+        let ipc = ipc::Server::start(events_tx.clone(), &runtime_handle).map_err(|e| log!("Not accepting commands: {:#}", e)).ok();
+        let indicator_tx = events_tx.clone();
+        runtime_handle.spawn(async move {
+            let result = Indicator::start(indicator_tx.clone()).await.map_err(|e| format!("{:#}", e));
+            let _ = indicator_tx.send(Event::IndicatorStarted(result));
+        });
         let mut app = Self {
             config,
             config_path,
-            sources: Arc::new(Mutex::new(Vec::new())),
+            sources: Vec::new(),
+            events_tx,
+            events_rx,
             selected_source: 0,
-            streaming: false,
-            ffmpeg_process: None,
+            show_hidden_sources: false,
+            source_filter: String::new(),
+            scroll_to_selected_source: false,
+            last_source_poll: Instant::now(),
+            source_rename: None,
+            streams: StreamManager::default(),
+            inhibitor: None,
+            indicator: None,
+            mqtt: None,
+            bluetooth_sinks: Vec::new(),
             status_message,
             runtime_handle,
             temp_ip,
             temp_port,
+            temp_backup_ip,
+            temp_backup_port,
+            temp_ffmpeg_path,
+            temp_args_template,
+            temp_allowlist,
+            temp_do_not_stream,
+            temp_jack_connect,
+            temp_mqtt,
+            bundle_path: "~/audio-streamer-settings.json".to_string(),
+            bundle_passphrase: String::new(),
+            template: None,
+            ffmpeg_status: Err(String::new()),
+            ffmpeg_check: 0,
+            checking_ffmpeg: false,
             network_test_result: String::new(),
+            nat_check: None,
+            command_preview: None,
+            bandwidth_report: None,
+            measuring_bandwidth: false,
+            pairing_qr: None,
+            certificate_qr: None,
+            tls_fingerprint: None,
+            palette,
+            history,
+            test_signal: TestSignal::Sine,
+            test_frequency: 440,
+            test_duration_secs: 5,
+            self_testing: false,
+            self_test_report: None,
+            diagnosing: false,
+            diagnosis: None,
+            compare_a,
+            compare_b: Setting { codec: "libopus".to_string(), bitrate: "96k".to_string() },
+            compare_secs: DEFAULT_CLIP_SECS,
+            comparing: false,
+            comparison: None,
+            playback: None,
+            vpn_peers: Vec::new(),
+            on_battery: false,
+            _ipc: ipc,
+            focus_requested: false,
+            last_session,
+            crash_report,
+            config_problems,
+            meter: None,
         };
 
+        app.recheck_ffmpeg();
+        app.look_up_route();
         app.refresh_sources();
+        app.refresh_vpn_peers();
+        app.refresh_bluetooth_sinks();
+        app.watch_power_source();
         app
     }
 
     // --- LOGIC METHODS (Unchanged from previous version) ---
 
     fn refresh_sources(&self) {
-        let sources_arc = Arc::clone(&self.sources);
+        let (events_tx, backend) = (self.events_tx.clone(), self.config.capture_backend);
+        self.runtime_handle.spawn(async move {
+            let _ = events_tx.send(Event::SourceListUpdated(get_audio_sources(backend).await.map_err(|e| e.to_string())));
+        });
+    }
+
+    fn refresh_vpn_peers(&self) {
+        let events_tx = self.events_tx.clone();
+        self.runtime_handle.spawn(async move {
+            let _ = events_tx.send(Event::VpnPeersUpdated(discover_peers().await));
+        });
+    }
+
+    fn refresh_bluetooth_sinks(&self) {
+        let events_tx = self.events_tx.clone();
+        self.runtime_handle.spawn(async move {
+            let _ = events_tx.send(Event::BluetoothSinksUpdated(paired_sinks().await));
+        });
+    }
+
+    // Reports the power source now and whenever it changes, for the app's lifetime.
+    fn watch_power_source(&self) {
+        let events_tx = self.events_tx.clone();
+        self.runtime_handle.spawn(async move {
+            let mut last = None;
+            loop {
+                let on_battery = tokio::task::spawn_blocking(power::on_battery).await.unwrap_or(false);
+                if last != Some(on_battery) {
+                    if events_tx.send(Event::PowerSourceChanged { on_battery }).is_err() {
+                        return;
+                    }
+                    last = Some(on_battery);
+                }
+                tokio::time::sleep(power::CHECK_INTERVAL).await;
+            }
+        });
+    }
+
+    fn power_saving(&self) -> bool {
+        self.config.battery_saver && self.on_battery
+    }
+
+    // The settings a stream would run with: the configured ones, or the battery profile.
+    fn stream_config(&self) -> Config {
+        if self.power_saving() { power::power_saving(&self.config) } else { self.config.clone() }
+    }
+
+    // A running stream is restarted so it picks up the other profile.
+    fn power_source_changed(&mut self, on_battery: bool) {
+        let was_saving = self.power_saving();
+        self.on_battery = on_battery;
+        if self.config.battery_saver && self.power_saving() != was_saving {
+            let network_streams: Vec<u64> = self.streams.iter().filter(|stream| stream.relay().is_some()).map(|stream| stream.id).collect();
+            for id in network_streams {
+                if let Err(e) = self.restart_stream(id, false, |_| {}) {
+                    self.status_message = format!("Restart for the new power source failed: {}", e);
+                }
+            }
+        }
+    }
+
+    // Applies a finished refresh. The current (or preferred) source stays selected
+    // if it still exists; otherwise the best one is picked.
+    fn receive_sources(&mut self, result: Result<Vec<AudioSource>, String>) {
+        let new_sources = match result {
+            Ok(new_sources) => new_sources,
+            Err(e) => {
+                self.status_message = format!("Failed to refresh sources: {}", e);
+                return;
+            }
+        };
+
+        let keep = self.sources.get(self.selected_source).map(|s| s.name.clone()).or_else(|| self.config.preferred_source.clone());
+        let old_sources = std::mem::replace(&mut self.sources, new_sources);
+        if let Some(index) = keep.and_then(|name| self.sources.iter().position(|s| s.name == name)) {
+            self.selected_source = index;
+            if self.config.auto_follow_source {
+                self.follow_best_source(&old_sources);
+            }
+        } else if !self.sources.is_empty() {
+            self.selected_source = get_best_source_index(&self.sources, &self.config.source_overrides);
+            if self.streams.is_empty() { // Only update status if not actively streaming
+                self.status_message = format!("Auto-selected: {}", self.sources[self.selected_source].label(&self.config.source_overrides));
+            }
+        }
+        // Waits for the first list, since the sources are resumed by name.
+        if self.config.auto_resume && !self.last_session.is_empty() {
+            self.resume_last_session();
+        }
+    }
+
+    // Moves to the best source when it has just started running, e.g. because playback
+    // moved from the speakers to HDMI, restarting the streams that captured the old one.
+    // Sources that were already running don't count, so a deliberate pick isn't undone.
+    fn follow_best_source(&mut self, old_sources: &[AudioSource]) {
+        let best = get_best_source_index(&self.sources, &self.config.source_overrides);
+        let Some(source) = self.sources.get(best).cloned() else {
+            return;
+        };
+        let was_running = old_sources.iter().any(|old| old.name == source.name && old.is_running);
+        if best == self.selected_source || !source.is_running || was_running {
+            return;
+        }
+        let previous = self.sources.get(self.selected_source).map(|previous| previous.name.clone());
+        self.selected_source = best;
+        self.config.preferred_source = Some(source.name.clone());
+        let label = source.label(&self.config.source_overrides).to_string();
+        let following: Vec<u64> = self.streams.iter().filter(|stream| Some(&stream.source.name) == previous.as_ref()).map(|stream| stream.id).collect();
+        for id in following {
+            // In place when it can be, so receivers don't drop out.
+            if let Some(stream) = self.streams.get_mut(id)
+                && stream.switch_source(source.clone(), &self.runtime_handle).is_ok()
+            {
+                continue;
+            }
+            if let Err(e) = self.restart_stream(id, false, |stream| stream.source = source.clone()) {
+                self.status_message = format!("Switching to {} failed: {}", label, e);
+                return;
+            }
+        }
+        self.status_message = format!("Following audio to {}", label);
+    }
+
+    fn test_network_connectivity(&mut self) {
+        if let (Ok(ip), Ok(port)) = (self.temp_ip.parse::<std::net::IpAddr>(), self.temp_port.parse::<u16>()) {
+            match UdpSocket::bind("0.0.0.0:0") {
+                Ok(socket) => {
+                    let target = SocketAddr::new(ip, port);
+                    match socket.send_to(b"audio-streamer-test", target) {
+                        Ok(_) => self.network_test_result = "✅ Network test packet sent successfully".to_string(),
+                        Err(e) => self.network_test_result = format!("❌ Failed to send test packet: {}", e),
+                    }
+                }
+                Err(e) => self.network_test_result = format!("❌ Failed to create UDP socket: {}", e),
+            }
+        } else {
+            self.network_test_result = "❌ Invalid IP or port format".to_string();
+        }
+    }
+
+    // Without a usable ffmpeg we can still stream uncompressed with the built-in engine.
+    fn engine(&self) -> Engine {
+        if self.ffmpeg_status.is_ok() { Engine::Ffmpeg } else { Engine::BuiltIn }
+    }
+
+    // Packet sizing at the start asks `netwatch::route`, which only knows a target once
+    // it has looked it up.
+    fn look_up_route(&self) {
+        if let Ok(ip) = self.config.target_ip.parse() {
+            let _entered = self.runtime_handle.enter();
+            netwatch::route(ip);
+        }
+    }
+
+    // It runs ffmpeg three times, so on the runtime; `Event::FfmpegChecked` brings the result.
+    fn recheck_ffmpeg(&mut self) {
+        self.ffmpeg_check += 1;
+        self.checking_ffmpeg = true;
+        let (config, check, events_tx) = (self.config.clone(), self.ffmpeg_check, self.events_tx.clone());
+        self.runtime_handle.spawn(async move {
+            let result = check_ffmpeg(&config).await.map_err(|e| format!("{:#}", e));
+            let _ = events_tx.send(Event::FfmpegChecked { check, result });
+        });
+    }
+
+    fn ffmpeg_checked(&mut self, check: u64, result: Result<FfmpegInfo, String>) {
+        if check != self.ffmpeg_check {
+            return; // Made for settings that have changed since
+        }
+        self.checking_ffmpeg = false;
+        self.ffmpeg_status = result;
+        // The one at startup leaves "Ready to stream" alone.
+        if check > 1 && let Ok(info) = &self.ffmpeg_status {
+            self.status_message = format!("Using ffmpeg {} at {}", info.version, info.path.display());
+        }
+    }
+
+    // Falls back to a PATH lookup so a failed check still produces the OS error on spawn.
+    fn ffmpeg_command(&self) -> Command {
+        match &self.ffmpeg_status {
+            Ok(info) => Command::new(&info.path),
+            Err(_) => Command::new("ffmpeg"),
+        }
+    }
+
+    // What Start would run with the template currently being edited, without running it.
+    fn template_preview(&self) -> Result<String, String> {
+        let mut config = self.config.clone();
+        config.ffmpeg_args_template = Some(self.temp_args_template.clone());
+        let source = self.sources.get(self.selected_source).map(|s| s.name.clone()).unwrap_or_else(|| "<source>".to_string());
+        config
+            .build_ffmpeg_command(&source, RELAY_URL_PLACEHOLDER, None)
+            .map(|args| format!("ffmpeg {}", args.join(" ")))
+            .map_err(|e| e.to_string())
+    }
+
+    fn preview_command(&mut self) {
+        let source = self.sources.get(self.selected_source).map(|s| s.name.clone());
+        let Some(source) = source else {
+            self.status_message = "No audio source selected".to_string();
+            return;
+        };
+        let ffmpeg_path = self.ffmpeg_status.as_ref().ok().map(|info| info.path.as_path());
+        self.command_preview = Some(match describe_pipeline(&self.stream_config(), &source, self.engine(), ffmpeg_path) {
+            Ok(lines) => lines.join("\n"),
+            Err(e) => format!("❌ {}", e),
+        });
+    }
+
+    fn start_bandwidth_measurement(&mut self) {
+        let (Ok(ip), Ok(port)) = (self.temp_ip.parse::<std::net::IpAddr>(), self.temp_port.parse::<u16>()) else {
+            self.network_test_result = "❌ Invalid IP or port format".to_string();
+            return;
+        };
+
+        self.bandwidth_report = None;
+        self.measuring_bandwidth = true;
+        self.network_test_result = "Measuring bandwidth...".to_string();
+
+        let events_tx = self.events_tx.clone();
+        self.runtime_handle.spawn(async move {
+            let result = measure_bandwidth(ip, port).await.map_err(|e| e.to_string());
+            let _ = events_tx.send(Event::BandwidthMeasured(result));
+        });
+    }
+
+    fn bandwidth_measured(&mut self, result: Result<BandwidthReport, String>) {
+        self.measuring_bandwidth = false;
+        match result {
+            Ok(report) => {
+                self.network_test_result = format!("📶 {}", report.summary());
+                self.bandwidth_report = Some(report);
+            }
+            Err(e) => self.network_test_result = format!("❌ Bandwidth test failed: {}", e),
+        }
+    }
+
+    fn start_nat_check(&mut self) {
+        self.nat_check = Some("Asking STUN servers...".to_string());
+        let events_tx = self.events_tx.clone();
+        self.runtime_handle.spawn(async move {
+            let result = check_nat(&DEFAULT_STUN_SERVERS).await.map_err(|e| format!("{:#}", e));
+            let _ = events_tx.send(Event::NatChecked(result));
+        });
+    }
+
+    fn start_diagnosis(&mut self) {
+        let Ok(ip) = self.config.target_ip.parse() else {
+            self.status_message = "Set a target IP to diagnose".to_string();
+            return;
+        };
+        let (port, events_tx) = (self.config.target_port, self.events_tx.clone());
+        self.runtime_handle.spawn(async move {
+            let _ = events_tx.send(Event::Diagnosed(diagnose(ip, port).await));
+        });
+        self.diagnosing = true;
+        self.diagnosis = None;
+    }
+
+    fn start_comparison(&mut self) {
+        let Some(source) = self.sources.get(self.selected_source).map(|s| s.name.clone()) else {
+            self.status_message = "No audio source selected".to_string();
+            return;
+        };
+        let Ok(info) = &self.ffmpeg_status else {
+            self.status_message = "Comparing codecs needs ffmpeg".to_string();
+            return;
+        };
+        let (ffmpeg, a, b, seconds, events_tx) = (info.path.clone(), self.compare_a.clone(), self.compare_b.clone(), self.compare_secs, self.events_tx.clone());
+        self.runtime_handle.spawn(async move {
+            let result = compare(&ffmpeg, &source, &a, &b, seconds).await.map_err(|e| format!("{:#}", e));
+            let _ = events_tx.send(Event::Compared(result));
+        });
+        self.playback = None;
+        self.comparing = true;
+        self.comparison = None;
+    }
+
+    fn start_self_test(&mut self) {
+        let Some(source) = self.sources.get(self.selected_source).map(|s| s.name.clone()) else {
+            self.status_message = "No audio source selected".to_string();
+            return;
+        };
+        let events_tx = self.events_tx.clone();
+        let config = self.config.clone();
+        let engine = self.engine();
+        let ffmpeg_path = self.ffmpeg_status.as_ref().ok().map(|info| info.path.clone());
         let runtime_handle = self.runtime_handle.clone();
+        self.runtime_handle.spawn(async move {
+            let _ = events_tx.send(Event::SelfTestFinished(run_self_test(config, source, engine, ffmpeg_path, runtime_handle).await));
+        });
+        self.self_testing = true;
+        self.self_test_report = None;
+    }
 
-        runtime_handle.spawn(async move {
-            match get_audio_sources().await {
-                Ok(new_sources) => {
-                    if let Ok(mut sources) = sources_arc.lock() {
-                        *sources = new_sources;
+    fn pairing_qr_code(&mut self) -> Option<&QrCode> {
+        let url = self.config.receiver_url();
+        let stale = self.pairing_qr.as_ref().is_none_or(|(cached_url, _)| *cached_url != url);
+        if stale {
+            self.pairing_qr = QrCode::new(url.as_bytes()).ok().map(|code| (url, code));
+        }
+        self.pairing_qr.as_ref().map(|(_, code)| code)
+    }
+
+    // Where a phone downloads the HTTPS certificate: the first listening output, at our
+    // address on the route to the target. None until both are known.
+    fn certificate_url(&self) -> Option<String> {
+        let port = self.config.outputs.iter().find_map(|output| match output {
+            Output::Http { port } | Output::WebRtc { port, .. } => Some(*port),
+            _ => None,
+        })?;
+        let local = sdp::local_address(self.config.target_ip.parse().ok()?)?;
+        Some(format!("https://{}{}", SocketAddr::new(local, port), CERTIFICATE_PATH))
+    }
+
+    fn certificate_qr_code(&mut self, url: &str) -> Option<&QrCode> {
+        let stale = self.certificate_qr.as_ref().is_none_or(|(cached_url, _)| cached_url != url);
+        if stale {
+            self.certificate_qr = QrCode::new(url.as_bytes()).ok().map(|code| (url.to_string(), code));
+        }
+        self.certificate_qr.as_ref().map(|(_, code)| code)
+    }
+
+    // Starts the selected source to the configured target, next to any streams already running.
+    fn start_streaming(&mut self) -> anyhow::Result<()> {
+        if self.config.bluetooth_sink.is_none() && !self.config.is_ip_configured() {
+            self.status_message = "Please set target IP first".to_string();
+            return Ok(());
+        }
+        let source = self.sources.get(self.selected_source).cloned().ok_or_else(|| anyhow::anyhow!("No audio source selected"))?;
+        self.status_message = self.start_stream(source, self.config.clone())?;
+        Ok(())
+    }
+
+    // Returns the status line for it.
+    fn start_stream(&mut self, source: AudioSource, config: Config) -> anyhow::Result<String> {
+        if self.checking_ffmpeg {
+            anyhow::bail!("Still checking ffmpeg; try again in a moment");
+        }
+        let (id, warning) = self.streams.start(source, config, &self.engine_options(), &self.runtime_handle)?;
+        let stream = self.streams.get(id).expect("just started");
+        Ok(match &stream.config.bluetooth_sink {
+            Some(address) => format!(
+                "Connecting to {}...",
+                self.bluetooth_sinks.iter().find(|sink| &sink.address == address).map_or(address.as_str(), |sink| sink.name.as_str())
+            ),
+            None => format!(
+                "Streaming {} to {}{}{}",
+                stream.source.label(&self.config.source_overrides),
+                stream.target(),
+                if self.power_saving() { " (battery saver)" } else { "" },
+                warning.map(|warning| format!(" ⚠ {}", warning)).unwrap_or_default()
+            ),
+        })
+    }
+
+    // From `audio-streamer stream` and friends while this window is open.
+    fn remote_request(&mut self, request: Request) -> Reply {
+        let result = match request {
+            Request::Show => {
+                self.focus_requested = true;
+                Ok("Audio Streamer is already running".to_string())
+            }
+            Request::Stream { source, target, codec } => self.remote_stream(source, target, codec),
+            Request::Stop => self.stop_streaming().map(|_| "Streaming stopped".to_string()),
+            Request::Status if self.streams.is_empty() => Ok("Not streaming".to_string()),
+            Request::Status => Ok(self
+                .streams
+                .iter()
+                .map(|stream| {
+                    let state = if stream.is_waiting_for_server() { " (waiting for the sound server)" } else if stream.is_paused() { " (paused)" } else { "" };
+                    format!("{} → {} · {}{}", stream.source.label(&self.config.source_overrides), stream.target(), stream.codec(), state)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+        };
+        match result {
+            Ok(message) => Reply { ok: true, message },
+            Err(e) => Reply { ok: false, message: format!("{:#}", e) },
+        }
+    }
+
+    // Like the Start button, with the CLI's --source, --target and --codec on top.
+    fn remote_stream(&mut self, source: Option<String>, target: Option<String>, codec: Option<String>) -> anyhow::Result<String> {
+        let mut config = self.config.clone();
+        if let Some(target) = target {
+            let (ip, port) = parse_target(&target)?;
+            config.target_ip = ip.to_string();
+            if let Some(port) = port {
+                config.target_port = port;
+            }
+            config.bluetooth_sink = None;
+        }
+        if let Some(codec) = codec {
+            config.audio_codec = codec;
+        }
+        if config.bluetooth_sink.is_none() && !config.is_ip_configured() {
+            anyhow::bail!("No target set; pass --target");
+        }
+        let source = match source {
+            Some(name) => self.sources.iter().find(|source| source.name == name).cloned().ok_or_else(|| anyhow::anyhow!("No audio source named '{}'", name))?,
+            None => self.sources.get(self.selected_source).cloned().ok_or_else(|| anyhow::anyhow!("No audio source selected"))?,
+        };
+        let message = self.start_stream(source, config)?;
+        self.status_message = format!("🖧 {}", message);
+        Ok(message)
+    }
+
+    fn engine_options(&self) -> EngineOptions {
+        EngineOptions {
+            engine: self.engine(),
+            // Falls back to a PATH lookup so a failed check still produces the OS error on spawn.
+            ffmpeg: self.ffmpeg_status.as_ref().map_or_else(|_| PathBuf::from("ffmpeg"), |info| info.path.clone()),
+            power_saving: self.power_saving(),
+        }
+    }
+
+    // Held while any stream runs, so the machine doesn't suspend.
+    fn update_inhibitor(&mut self) {
+        if self.streams.is_empty() {
+            if let Some(inhibitor) = self.inhibitor.take() {
+                inhibitor.release();
+            }
+        } else if self.inhibitor.is_none() {
+            // Streaming still works without it; only the indicator stays off.
+            self.inhibitor = SleepInhibitor::acquire(&self.runtime_handle)
+                .map_err(|e| log!("Could not inhibit suspend: {:#}", e))
+                .ok();
+        }
+    }
+
+    // Tells the tray what is streaming where.
+    fn update_indicator(&self) {
+        let Some(indicator) = self.indicator.clone() else {
+            return;
+        };
+        let streaming = (!self.streams.is_empty()).then(|| {
+            let streams: Vec<String> = self.streams.iter().map(|stream| format!("{} → {}", stream.source.label(&self.config.source_overrides), stream.target())).collect();
+            streams.join("\n")
+        });
+        self.runtime_handle.spawn(async move {
+            if let Err(e) = indicator.set(streaming).await {
+                log!("Could not update the streaming indicator: {:#}", e);
+            }
+        });
+    }
+
+    // Connects to the broker when the settings ask for it, again when they change, and
+    // tells Home Assistant what streams.
+    fn sync_mqtt(&mut self) {
+        let wanted = &self.config.integrations.mqtt;
+        let enabled = wanted.enabled && !wanted.broker.trim().is_empty();
+        if let Some(mqtt) = self.mqtt.take_if(|mqtt| !enabled || mqtt.settings() != wanted) {
+            mqtt.stop();
+        }
+        if enabled && self.mqtt.is_none() {
+            self.mqtt = Some(Mqtt::start(wanted.clone(), self.events_tx.clone(), &self.runtime_handle));
+        }
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.update(mqtt::State {
+                streaming: !self.streams.is_empty(),
+                bytes_sent: self.streams.iter().filter_map(Stream::relay).map(|relay| relay.bytes_sent()).sum(),
+                listeners: self.streams.iter().filter_map(Stream::listeners).map(|listeners| listeners.list().len()).sum(),
+            });
+        }
+    }
+
+    // Applies what the streams and the background tasks have reported since the last frame.
+    fn poll_events(&mut self) {
+        let stream_events = self.streams.poll(&self.engine_options(), &self.runtime_handle);
+        let events: Vec<Event> = stream_events.into_iter().map(Event::Stream).chain(self.events_rx.try_iter()).collect();
+        for event in events {
+            match event {
+                Event::SourceListUpdated(result) => self.receive_sources(result),
+                Event::BluetoothSinksUpdated(sinks) => self.bluetooth_sinks = sinks,
+                Event::VpnPeersUpdated(peers) => self.vpn_peers = peers,
+                Event::PowerSourceChanged { on_battery } => self.power_source_changed(on_battery),
+                Event::NetworkChanged => self.network_changed(),
+                Event::BandwidthMeasured(result) => self.bandwidth_measured(result),
+                Event::NatChecked(result) => {
+                    self.nat_check = Some(result.map_or_else(|e| format!("❌ {}", e), |report| report.describe()));
+                }
+                Event::SelfTestFinished(report) => {
+                    self.self_testing = false;
+                    self.self_test_report = Some(report);
+                }
+                Event::Diagnosed(findings) => {
+                    self.diagnosing = false;
+                    self.diagnosis = Some(findings);
+                }
+                Event::Compared(result) => {
+                    self.comparing = false;
+                    self.comparison = Some(result);
+                }
+                Event::FfmpegChecked { check, result } => self.ffmpeg_checked(check, result),
+                Event::Progress(message) => self.status_message = message,
+                Event::Stream(event) => self.stream_event(event),
+                Event::StatsTick(_) => {} // The stream cards read the relays every frame instead
+                Event::IndicatorStarted(Ok(indicator)) => {
+                    self.indicator = Some(indicator);
+                    self.update_indicator();
+                }
+                // Without it the window is the only sign, like before.
+                Event::IndicatorStarted(Err(e)) => log!("No streaming indicator: {}", e),
+                Event::IndicatorActivated => self.focus_requested = true,
+                Event::Remote { request, reply_tx } => {
+                    let _ = reply_tx.send(self.remote_request(request));
+                }
+            }
+        }
+    }
+
+    // Exits, failovers, resuming receivers and Bluetooth connections, and the sessions of streams that ended.
+    fn stream_event(&mut self, event: StreamEvent) {
+        match event {
+            StreamEvent::Started { .. } => {
+                self.last_session.clear(); // Superseded by what runs now
+                self.save_last_session();
+                self.update_inhibitor();
+                self.update_indicator();
+            }
+            StreamEvent::Connected { device, .. } => {
+                self.status_message = format!("Playing on {} over Bluetooth", device);
+                self.refresh_bluetooth_sinks(); // It may have just connected
+            }
+            StreamEvent::FailedOver { id } => {
+                let Some(stream) = self.streams.get(id) else {
+                    return;
+                };
+                self.status_message = format!(
+                    "⚠ {}:{} stopped responding, switched to backup {}:{}",
+                    stream.config.target_ip,
+                    stream.config.target_port,
+                    stream.config.backup_target_ip,
+                    stream.config.backup_target_port
+                );
+            }
+            StreamEvent::ReceiverResumed { receiver, resumption, .. } => {
+                self.status_message = resumption.describe(receiver.as_deref());
+                log!("{}", self.status_message);
+            }
+            StreamEvent::SourceSwitched { id, restarted } => {
+                let Some(stream) = self.streams.get(id) else {
+                    return;
+                };
+                let source = stream.source.label(&self.config.source_overrides);
+                self.status_message = if restarted { format!("Restarted the stream on {}", source) } else { format!("Switched the stream to {}", source) };
+                self.save_last_session();
+                self.update_indicator();
+            }
+            StreamEvent::Unsilenced => self.status_message = "🔊 The privacy mute ran out; the stream is heard again".to_string(),
+            StreamEvent::MuteFailed { reason, .. } => {
+                self.status_message = format!("⚠ Muting through the sound server failed ({}); the stream is held back instead", reason);
+                log!("{}", self.status_message);
+            }
+            StreamEvent::AppBlocked { app, .. } => {
+                self.status_message = blocked_message(&app);
+                log!("{}", self.status_message);
+            }
+            StreamEvent::SoundServerLost { .. } => self.status_message = "⚠ The sound server went away; the stream resumes when it is back".to_string(),
+            StreamEvent::SoundServerBack { .. } => {
+                self.status_message = "The sound server is back; streaming again".to_string();
+                self.update_indicator();
+            }
+            StreamEvent::Overloaded { speed, lighter, .. } => self.status_message = overload_message(speed, lighter.as_deref()),
+            StreamEvent::SessionEnded { session, recordings, .. } => {
+                if let Some(session) = session
+                    && let Err(e) = self.history.record(session)
+                {
+                    log!("Failed to save session history: {}", e);
+                }
+                if let Some(config) = recordings {
+                    self.normalize_recordings(&config);
+                }
+            }
+            StreamEvent::Error { reason, .. } => self.status_message = format!("Streaming stopped unexpectedly: {}", reason),
+            StreamEvent::Stopped { .. } => {
+                self.save_last_session();
+                self.update_inhibitor();
+                self.update_indicator();
+            }
+        }
+    }
+
+    // Kept current on every start and stop, so it is right however the app ends.
+    fn save_last_session(&self) {
+        let streams: Vec<LastStream> = self.streams.iter().map(LastStream::of).collect();
+        if let Err(e) = resume::save(&resume::path_for(&self.config_path), &streams) {
+            log!("Failed to save the last session: {}", e);
+        }
+    }
+
+    // Starts each stream from the last session again, with the current settings.
+    fn resume_last_session(&mut self) {
+        for last in std::mem::take(&mut self.last_session) {
+            let Some(source) = self.sources.iter().find(|source| source.name == last.source).cloned() else {
+                self.status_message = format!("Can't resume streaming to {}: source {} is gone", last.target(), last.source);
+                continue;
+            };
+            self.status_message = match self.start_stream(source, last.apply(&self.config)) {
+                Ok(message) => message,
+                Err(e) => format!("Failed to resume streaming to {}: {}", last.target(), e),
+            };
+        }
+    }
+
+    // Keeps the meter on the selected source. A failed start is kept too, so it isn't retried every frame.
+    fn follow_selection_with_meter(&mut self) {
+        let name = self.sources.get(self.selected_source).map(|source| source.name.clone());
+        if self.meter.as_ref().map(|(metered, _)| metered) == name.as_ref() {
+            return;
+        }
+        self.stop_meter();
+        let Some(name) = name else {
+            return;
+        };
+        let meter = LevelMeter::start(&name, &self.runtime_handle).map_err(|e| format!("No level meter: {:#}", e));
+        if let Ok(meter) = &meter {
+            meter.set_gain(self.config.volume_percent, &self.runtime_handle);
+        }
+        self.meter = Some((name, meter));
+    }
+
+    fn stop_meter(&mut self) {
+        if let Some((_, Ok(meter))) = self.meter.take() {
+            meter.stop();
+        }
+    }
+
+    // A rolling trace of the last 3 s as the encoder gets it, with clipped columns in red.
+    fn waveform_ui(&mut self, ui: &mut egui::Ui, palette: Palette) {
+        self.follow_selection_with_meter();
+        let meter = match &self.meter {
+            Some((_, Ok(meter))) => meter,
+            Some((_, Err(e))) => {
+                ui.colored_label(palette.error, e);
+                return;
+            }
+            None => {
+                ui.label("No source selected");
+                return;
+            }
+        };
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 80.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 4.0, palette.title_bar);
+        let (middle, half) = (rect.center().y, rect.height() / 2.0);
+        painter.hline(rect.x_range(), middle, Stroke::new(1.0, palette.border));
+        let scope = meter.scope();
+        let step = rect.width() / SCOPE_COLUMNS as f32;
+        // Newest on the right, so the trace scrolls left.
+        let start = rect.right() - scope.len() as f32 * step;
+        for (i, column) in scope.iter().enumerate() {
+            let color = if column.clipped { palette.error } else { palette.accent };
+            let top = middle - column.high.min(1.0) * half;
+            let bottom = middle - column.low.max(-1.0) * half;
+            painter.vline(start + (i as f32 + 0.5) * step, top..=bottom.max(top + 1.0), Stroke::new(step.max(1.0), color));
+        }
+        ui.horizontal(|ui| {
+            ui.label(format!("Peak: {:.0} dBFS", meter.peak_dbfs()));
+            ui.label(format!("Clipped: {} samples", meter.clipped_samples()));
+            if ui.small_button("Reset").clicked() {
+                meter.reset_clipped();
+            }
+        });
+        // Phase correlation, -1 on the left to +1 on the right, filled from the middle.
+        let correlation = meter.correlation();
+        ui.horizontal(|ui| {
+            ui.label(match correlation {
+                Some(value) => format!("Phase: {:+.2}", value),
+                None => "Phase: –".to_string(),
+            });
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width().min(240.0), 12.0), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            painter.rect_filled(rect, 3.0, palette.title_bar);
+            if let Some(value) = correlation {
+                let x = rect.center().x + value * rect.width() / 2.0;
+                let color = if value < 0.0 { palette.error } else if value < 0.3 { palette.warning } else { palette.success };
+                painter.rect_filled(egui::Rect::from_x_y_ranges(x.min(rect.center().x)..=x.max(rect.center().x), rect.y_range()), 3.0, color);
+            }
+            painter.vline(rect.center().x, rect.y_range(), Stroke::new(1.0, palette.border));
+        });
+        if correlation.is_some_and(|value| value < 0.0) {
+            ui.colored_label(palette.warning, "⚠ Left and right are out of phase: on a mono phone speaker much of this cancels out. The Mono mix channel mode plays what such a speaker would.");
+        }
+        if meter.is_clipping() {
+            let hint = if self.config.volume_percent > 100 {
+                "⚠ Clipping: reduce the stream's volume to 100 % or less"
+            } else {
+                "⚠ Clipping: reduce the gain at the source, e.g. the player's or the sink's volume"
+            };
+            ui.colored_label(palette.warning, hint);
+        }
+        ui.ctx().request_repaint_after(std::time::Duration::from_millis(50));
+    }
+
+    // Offered once: opening or dismissing it marks it seen.
+    fn close_crash_report(&mut self, open: bool) {
+        let Some(report) = self.crash_report.take() else {
+            return;
+        };
+        if open && let Err(e) = std::process::Command::new("xdg-open").arg(&report).spawn() {
+            self.status_message = format!("Failed to open {}: {}", report.display(), e);
+        }
+        if let Err(e) = crash::mark_seen(&report) {
+            log!("Failed to mark the crash report seen: {:#}", e);
+        }
+    }
+
+    fn restore_previous_config(&mut self) {
+        match rollback::restore_previous(&self.config_path) {
+            Ok((config, replaced)) => {
+                self.config = config;
+                self.reset_temp_fields();
+                self.recheck_ffmpeg();
+                self.status_message = format!("Restored the config as it was before the save at {}", format_utc(replaced));
+            }
+            Err(e) => self.status_message = format!("Restore failed: {:#}", e),
+        }
+    }
+
+    // Puts the broken settings back to their defaults and saves, keeping everything else.
+    fn reset_config_problems(&mut self) {
+        match validate::reset(&self.config, &self.config_problems) {
+            Ok(config) => {
+                self.config = config;
+                self.reset_temp_fields();
+                self.config_problems.clear();
+                if let Err(e) = self.save_config() {
+                    self.status_message = format!("Reset, but saving the config failed: {:#}", e);
+                }
+            }
+            Err(e) => self.status_message = format!("Could not reset the settings: {:#}", e),
+        }
+    }
+
+    fn dismiss_last_session(&mut self) {
+        self.last_session.clear();
+        self.save_last_session();
+    }
+
+    fn stop_streaming(&mut self) -> anyhow::Result<()> {
+        self.streams.stop_all(&self.runtime_handle);
+        self.status_message = "Streaming stopped".to_string();
+        Ok(())
+    }
+
+    // Stops a stream and starts it again in the same place, after `change` (e.g. a new
+    // source or codec). With `resume` it stays one session in the history.
+    fn restart_stream(&mut self, id: u64, resume: bool, change: impl FnOnce(&mut Stream)) -> anyhow::Result<Option<String>> {
+        self.streams.restart(id, resume, change, &self.engine_options(), &self.runtime_handle)
+    }
+
+    // Recordings are only complete once the stream ends, which is what two-pass needs.
+    fn normalize_recordings(&self, config: &Config) {
+        let Ok(info) = &self.ffmpeg_status else {
+            return;
+        };
+        if !config.normalize_loudness {
+            return;
+        }
+        for recording in loudness::recordings(config) {
+            let (ffmpeg, config, events_tx) = (info.path.clone(), config.clone(), self.events_tx.clone());
+            self.runtime_handle.spawn(async move {
+                let _ = events_tx.send(Event::Progress(format!("Normalizing {}...", recording.display())));
+                let message = match loudness::normalize_recording(&ffmpeg, &recording, &config).await {
+                    Ok(normalized) => format!("Normalized recording saved to {}", normalized.display()),
+                    Err(e) => format!("Loudness normalization failed: {:#}", e),
+                };
+                let _ = events_tx.send(Event::Progress(message));
+            });
+        }
+    }
+
+    // Requests from native receivers, e.g. the volume keys on the phone. The stream's
+    // controls show the result, and the status line says who changed what.
+    fn poll_remote_commands(&mut self) {
+        let mut codec_requests = Vec::new();
+        let mut offers = Vec::new();
+        for stream in self.streams.iter_mut() {
+            let Some(relay) = stream.relay() else {
+                continue;
+            };
+            let commands = relay.remote_commands();
+            if commands.is_empty() {
+                continue;
+            }
+            let who = relay.receiver().and_then(|receiver| receiver.name).unwrap_or_else(|| "The receiver".to_string());
+            for command in commands {
+                match command {
+                    _ if !self.config.remote_control => {}
+                    // Says what it plays rather than asking for anything, so it also has a setting of its own.
+                    RemoteCommand::Capabilities(capabilities) => {
+                        if self.config.negotiate_codec {
+                            offers.push((stream.id, who.clone(), capabilities));
+                        }
+                    }
+                    RemoteCommand::SetVolume(percent) => {
+                        stream.config.volume_percent = percent;
+                        stream.apply_volume(&self.runtime_handle);
+                        self.status_message = format!("📱 {} set the volume to {} %", who, percent);
                     }
+                    RemoteCommand::SetMuted(muted) => {
+                        stream.muted = muted;
+                        stream.apply_volume(&self.runtime_handle);
+                        self.status_message = format!("📱 {} {} the stream", who, if muted { "muted" } else { "unmuted" });
+                    }
+                    RemoteCommand::RequestCodec(codec) => codec_requests.push((stream.id, who.clone(), codec)),
                 }
-                Err(e) => {
-                    eprintln!("Failed to refresh sources: {}", e);
+            }
+        }
+        for (id, who, codec) in codec_requests {
+            self.switch_codec(id, &who, codec);
+        }
+        for (id, who, capabilities) in offers {
+            self.negotiate_with(id, &who, &capabilities);
+        }
+    }
+
+    // Fits the stream to what its receiver plays, restarting it only when that changes
+    // something; the receiver repeats its offer, and again after each restart.
+    fn negotiate_with(&mut self, id: u64, who: &str, capabilities: &Capabilities) {
+        let Some(stream) = self.streams.get(id) else {
+            return;
+        };
+        // The built-in engine sends PCM, which any receiver plays.
+        let Ok(info) = &self.ffmpeg_status else {
+            return;
+        };
+        let encodes = |codec: &str| info.encoders.iter().any(|name| name == codec);
+        // From what it was started with, which a restart starts from again.
+        let Some(agreed) = negotiate(&stream.base, capabilities, encodes) else {
+            self.status_message = format!("🤝 {} plays none of the codecs this ffmpeg can encode ({})", who, capabilities.codecs.join(", "));
+            return;
+        };
+        let summary = format!("{}: {}", who, negotiate::describe(&agreed));
+        let base = &stream.base;
+        if (&agreed.audio_codec, &agreed.bitrate, agreed.low_latency) == (&base.audio_codec, &base.bitrate, base.low_latency) {
+            if let Some(stream) = self.streams.get_mut(id) {
+                stream.negotiated = Some(summary);
+            }
+            return;
+        }
+        self.status_message = match self.restart_stream(id, true, |stream| stream.base = agreed) {
+            Ok(_) => {
+                if let Some(stream) = self.streams.get_mut(id) {
+                    stream.negotiated = Some(summary.clone());
                 }
+                format!("🤝 Negotiated with {}", summary)
             }
-        });
+            Err(e) => format!("Restart for {}'s settings failed: {}", who, e),
+        };
     }
 
-    fn update_selected_source(&mut self) {
-        let mut sources = self.sources.lock().unwrap();
-        if sources.is_empty() {
+    // A codec change needs a new encoder, so the stream restarts like it does for a new source.
+    fn switch_codec(&mut self, id: u64, who: &str, codec: String) {
+        let Some(stream) = self.streams.get(id) else {
+            return;
+        };
+        if codec == stream.config.audio_codec {
             return;
         }
+        if !REMOTE_CODECS.contains(&codec.as_str()) || self.engine() == Engine::BuiltIn {
+            self.status_message = format!("📱 Ignored {}'s request for codec '{}'", who, codec);
+            return;
+        }
+        // The check already listed the encoders, so this needn't run ffmpeg again.
+        if let Ok(info) = &self.ffmpeg_status
+            && !info.encoders.contains(&codec)
+        {
+            self.status_message = format!("📱 {} asked for {}, which can't be used: this ffmpeg build has no '{}' encoder", who, codec, codec);
+            return;
+        }
+        self.status_message = match self.restart_stream(id, false, |stream| stream.base.audio_codec = codec.clone()) {
+            Ok(_) => format!("📱 {} switched the codec to {}", who, codec),
+            Err(e) => format!("Restart with {} failed: {}", codec, e),
+        };
+    }
 
-        let new_index = get_best_source_index(&sources);
-
-        if new_index != self.selected_source && new_index < sources.len() {
-            self.selected_source = new_index;
-            if let Some(source) = sources.get(new_index) {
-                if !self.streaming { // Only update status if not actively streaming
-                    self.status_message = format!("Auto-selected: {}", source.description);
-                }
+    // After switching from Ethernet to Wi-Fi (or getting a new address) a stream would
+    // keep going out of the old interface, or nowhere. It restarts on the new route
+    // instead, and stays one session in the history.
+    fn network_changed(&mut self) {
+        if !self.config.resume_on_network_change {
+            return;
+        }
+        let mut moved = Vec::new();
+        for stream in self.streams.iter().filter(|stream| stream.relay().is_some()) {
+            let Ok(ip) = stream.config.target_ip.parse::<std::net::IpAddr>() else {
+                continue;
+            };
+            // `netwatch::watch` renewed the routes before the event came.
+            match netwatch::route(ip) {
+                None => self.status_message = format!("⚠ No route to {}; waiting for the network to come back", ip),
+                Some(route) if stream.route.as_ref() != Some(&route) => moved.push((stream.id, route)),
+                Some(_) => {}
             }
         }
+        for (id, route) in moved {
+            self.status_message = match self.restart_stream(id, true, |_| {}) {
+                Ok(_) => {
+                    let address = route.source.map(|source| format!(" as {}", source)).unwrap_or_default();
+                    format!("🔀 Network changed, stream resumed via {}{}", route.interface, address)
+                }
+                Err(e) => format!("Resume after network change failed: {}", e),
+            };
+        }
     }
 
-    fn test_network_connectivity(&mut self) {
-        if let (Ok(ip), Ok(port)) = (self.temp_ip.parse::<std::net::IpAddr>(), self.temp_port.parse::<u16>()) {
-            match UdpSocket::bind("0.0.0.0:0") {
-                Ok(socket) => {
-                    let target = SocketAddr::new(ip, port);
-                    match socket.send_to(b"audio-streamer-test", target) {
-                        Ok(_) => self.network_test_result = "✅ Network test packet sent successfully".to_string(),
-                        Err(e) => self.network_test_result = format!("❌ Failed to send test packet: {}", e),
-                    }
-                }
-                Err(e) => self.network_test_result = format!("❌ Failed to create UDP socket: {}", e),
-            }
-        } else {
-            self.network_test_result = "❌ Invalid IP or port format".to_string();
+    fn toggle_streaming(&mut self) {
+        self.update_config_from_temp();
+        if !self.streams.is_empty() {
+            if let Err(e) = self.stop_streaming() { self.status_message = format!("Stop failed: {}", e); }
+        } else if let Err(e) = self.start_streaming() {
+            self.status_message = format!("Start failed: {}", e);
         }
     }
 
-    fn start_streaming(&mut self) -> anyhow::Result<()> {
-        if !self.config.is_ip_configured() {
-            self.status_message = "Please set target IP first".to_string();
-            return Ok(());
+    // For a moment of privacy: the streams keep running on silence until it runs out.
+    fn toggle_quick_mute(&mut self) {
+        if self.streams.silenced_for().is_some() {
+            self.streams.unsilence(&self.runtime_handle);
+            self.status_message = "🔊 Unmuted".to_string();
+        } else if !self.streams.is_empty() {
+            self.streams.silence_for(QUICK_MUTE, &self.runtime_handle);
+            self.status_message = format!("🔇 Muted for {} minutes", QUICK_MUTE.as_secs() / 60);
         }
+    }
 
-        let sources = self.sources.lock().unwrap();
-        if let Some(source) = sources.get(self.selected_source) {
-            let args = self.config.build_ffmpeg_command(&source.name);
-            
-            let child = Command::new("ffmpeg")
-                .args(&args)
-                .stdout(Stdio::null()) // Keep these null to avoid blocking
-                .stderr(Stdio::null())
-                .spawn()?;
-
-            self.ffmpeg_process = Some(child);
-            self.streaming = true;
-            self.status_message = format!(
-                "Streaming {} to {}:{}",
-                source.description,
-                self.config.target_ip,
-                self.config.target_port
-            );
+    // Tab/Shift+Tab and Space come from egui. On top of that, Enter starts or stops
+    // streaming and the arrow keys move through the source list, as long as no widget
+    // has focus that would want those keys itself. Ctrl+M, the quick mute, works anywhere.
+    fn handle_keyboard(&mut self, ctx: &egui::Context) {
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::M)) {
+            self.toggle_quick_mute();
         }
-        Ok(())
+        let nothing_focused = ctx.memory(|m| m.focus().is_none());
+        if nothing_focused && ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+            self.toggle_streaming();
+        }
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let step: isize = ctx.input(|i| {
+            if i.key_pressed(egui::Key::ArrowDown) { 1 } else if i.key_pressed(egui::Key::ArrowUp) { -1 } else { 0 }
+        });
+        if step == 0 {
+            return;
+        }
+        let visible = self.visible_sources();
+        let Some(position) = visible.iter().position(|&i| i == self.selected_source) else {
+            return;
+        };
+        let Some(&next) = visible.get(position.saturating_add_signed(step)) else {
+            return;
+        };
+        let source = &self.sources[next];
+        self.selected_source = next;
+        self.config.preferred_source = Some(source.name.clone());
+        self.status_message = format!("Selected: {}", source.label(&self.config.source_overrides));
+        self.scroll_to_selected_source = true;
     }
 
-    fn stop_streaming(&mut self) -> anyhow::Result<()> {
-        if let Some(mut process) = self.ffmpeg_process.take() {
-            process.kill()?;
-            process.wait()?;
+    // The encoder keeps running while paused, so resuming is instant.
+    fn toggle_pause(&mut self, id: u64) {
+        let Some(stream) = self.streams.get(id) else {
+            return;
+        };
+        if let Some(relay) = stream.relay() {
+            let paused = !relay.is_paused();
+            relay.set_paused(paused);
+            self.status_message = if paused {
+                "Paused (encoder still running)".to_string()
+            } else {
+                format!("Resumed streaming to {}", stream.target())
+            };
         }
-        self.streaming = false;
-        self.status_message = "Streaming stopped".to_string();
+    }
+
+    // Puts the config's values back into the fields that edit a copy, after it was replaced.
+    fn reset_temp_fields(&mut self) {
+        self.temp_ip = self.config.target_ip.clone();
+        self.temp_port = self.config.target_port.to_string();
+        self.temp_backup_ip = self.config.backup_target_ip.clone();
+        self.temp_backup_port = self.config.backup_target_port.to_string();
+        self.temp_ffmpeg_path = self.config.ffmpeg_path.clone().unwrap_or_default();
+        self.temp_args_template = self.config.ffmpeg_args_template.clone().unwrap_or_default();
+        self.temp_allowlist = self.config.listen_allowlist.join(", ");
+        self.temp_do_not_stream = self.config.do_not_stream.join(", ");
+        self.temp_jack_connect = self.config.jack_connect.join(", ");
+        self.temp_mqtt = self.config.integrations.mqtt.clone();
+    }
+
+    fn export_bundle(&mut self) -> anyhow::Result<()> {
+        self.update_config_from_temp();
+        let passphrase = Some(self.bundle_passphrase.as_str()).filter(|passphrase| !passphrase.is_empty());
+        let bundle = bundle::export(&self.config_path, &self.config, passphrase)?;
+        let path = expand_home(self.bundle_path.trim());
+        fs::write(&path, bundle)?;
+        self.status_message = format!(
+            "Exported the settings and {} profiles to {}{}",
+            profiles::list(&self.config_path)?.len(),
+            path.display(),
+            if passphrase.is_some() { ", secrets encrypted" } else { ", without secrets" }
+        );
+        Ok(())
+    }
+
+    fn import_bundle(&mut self, mode: ImportMode) -> anyhow::Result<()> {
+        let path = expand_home(self.bundle_path.trim());
+        let json = fs::read_to_string(&path)?;
+        let passphrase = Some(self.bundle_passphrase.as_str()).filter(|passphrase| !passphrase.is_empty());
+        let summary = bundle::import(&self.config_path, &self.config, &json, passphrase, mode)?;
+        self.config = summary.config;
+        self.reset_temp_fields();
+        self.recheck_ffmpeg();
+        self.status_message = format!(
+            "Imported the settings and {} profiles{}",
+            summary.profiles,
+            if summary.secrets { "" } else { "; enter the Icecast passwords and access token again" }
+        );
         Ok(())
     }
 
     fn save_config(&mut self) -> anyhow::Result<()> {
-        let json = serde_json::to_string_pretty(&self.config)?;
-        fs::write(&self.config_path, json)?;
+        rollback::save(&self.config_path, &self.config)?;
         self.status_message = "Configuration saved".to_string();
         Ok(())
     }
 
+    // Splits a pasted "ip:port" or URL into the two fields.
+    fn apply_target_input(&mut self) -> anyhow::Result<()> {
+        let (ip, port) = parse_target(&self.temp_ip)?;
+        self.temp_ip = ip.to_string();
+        if let Some(port) = port {
+            self.temp_port = port.to_string();
+        }
+        Ok(())
+    }
+
+    fn paste_target(&mut self) {
+        match read_clipboard() {
+            Some(text) if !text.is_empty() => {
+                self.temp_ip = text;
+                if self.apply_target_input().is_ok() {
+                    self.update_config_from_temp();
+                }
+            }
+            _ => self.status_message = "Nothing to paste (needs wl-paste, xclip or xsel)".to_string(),
+        }
+    }
+
     fn update_config_from_temp(&mut self) {
-        if !self.temp_ip.is_empty() && self.temp_ip != self.config.target_ip {
+        // An invalid target stays in the field with its error shown, rather than failing at start.
+        if self.apply_target_input().is_ok() && self.temp_ip != self.config.target_ip {
             self.config.target_ip = self.temp_ip.clone();
+            self.look_up_route();
         }
         
-        if let Ok(port) = self.temp_port.parse::<u16>() {
-            if port != self.config.target_port {
-                self.config.target_port = port;
-            }
-        } else {
-             self.temp_port = self.config.target_port.to_string(); // Revert if invalid
+        let ffmpeg_path = Some(self.temp_ffmpeg_path.trim().to_string()).filter(|p| !p.is_empty());
+        if ffmpeg_path != self.config.ffmpeg_path {
+            self.config.ffmpeg_path = ffmpeg_path;
+            self.recheck_ffmpeg();
+        }
+
+        // An invalid template is kept out of the config so it can't break the next start.
+        let template = self.temp_args_template.trim();
+        if template.is_empty() {
+            self.config.ffmpeg_args_template = None;
+        } else if validate_template(template).is_ok() {
+            self.config.ffmpeg_args_template = Some(template.to_string());
+        }
+
+        // Like the template, a list with a typo is kept out of the config.
+        let allowlist = allowlist_entries(&self.temp_allowlist);
+        if parse_allowlist(&allowlist).is_ok() {
+            self.config.listen_allowlist = allowlist;
+        }
+
+        self.config.do_not_stream = app_entries(&self.temp_do_not_stream);
+        self.config.jack_connect = app_entries(&self.temp_jack_connect);
+        self.config.integrations.mqtt = self.temp_mqtt.clone();
+
+        // Unlike the primary, the backup may be cleared to turn failover off.
+        self.config.backup_target_ip = self.temp_backup_ip.trim().to_string();
+        match self.temp_backup_port.parse::<u16>() {
+            Ok(port) => self.config.backup_target_port = port,
+            Err(_) => self.temp_backup_port = self.config.backup_target_port.to_string(),
+        }
+
+        if let Ok(port) = parse_port(&self.temp_port) {
+            self.config.target_port = port;
         }
     }
 
-    fn generate_test_tone(&mut self) -> anyhow::Result<()> {
+    fn generate_test_signal(&mut self) -> anyhow::Result<()> {
         if !self.config.is_ip_configured() {
             self.status_message = "Please set target IP first".to_string();
             return Ok(());
         }
         let target = format!("udp://{}:{}", self.config.target_ip, self.config.target_port);
-        let args = vec!["-f", "lavfi", "-i", "sine=frequency=440:duration=5", "-c:a", "aac", "-f", "mpegts", &target];
-        Command::new("ffmpeg").args(&args).spawn()?;
-        self.status_message = "Sending 5-second test tone (440Hz)...".to_string();
+        let args = test_signal_args(self.test_signal, self.test_frequency, self.test_duration_secs, &self.config, &target);
+        let _runtime = self.runtime_handle.enter(); // tokio spawns need it; the signal is reaped in the background
+        self.ffmpeg_command().args(&args).spawn()?;
+        self.status_message = format!("Sending {}-second test signal: {}...", self.test_duration_secs, self.test_signal.label());
         Ok(())
     }
 
-    fn format_source_display(&self, source: &AudioSource) -> String {
+    // Indices of the sources the list shows, in their usual order. The selected source
+    // stays visible even when hidden or filtered out, so the selection never disappears.
+    fn visible_sources(&self) -> Vec<usize> {
+        let filter = self.source_filter.trim().to_lowercase();
+        let overrides = &self.config.source_overrides;
+        self.sources
+            .iter()
+            .enumerate()
+            .filter(|(i, source)| {
+                let shown = (self.show_hidden_sources || !source.is_hidden(overrides))
+                    && self.config.source_kind_filter.matches(source)
+                    && (!self.config.running_sources_only || source.is_running);
+                shown || *i == self.selected_source
+            })
+            .filter(|(_, source)| {
+                filter.is_empty()
+                    || [source.label(overrides), source.description.as_str(), source.name.as_str()]
+                        .iter()
+                        .any(|text| text.to_lowercase().contains(&filter))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // One selectable entry, with a context menu for renaming and hiding. True when clicked.
+    fn source_row(&mut self, ui: &mut egui::Ui, i: usize) -> bool {
+        let source = &self.sources[i];
+        let overrides = &mut self.config.source_overrides;
+        let text = Self::format_source_display(source, overrides);
+        let hint = match &source.spec {
+            Some(spec) => format!("{}\nRight-click to rename or hide", spec.label()),
+            None => "Right-click to rename or hide".to_string(),
+        };
+        let response = ui.selectable_label(i == self.selected_source, text).on_hover_text(hint);
+        let clicked = response.clicked();
+        if i == self.selected_source && self.scroll_to_selected_source {
+            response.scroll_to_me(None);
+            self.scroll_to_selected_source = false;
+        }
+        response.context_menu(|ui| {
+            if self.source_rename.as_ref().is_none_or(|(name, _)| *name != source.name) {
+                self.source_rename = Some((source.name.clone(), source.label(overrides).to_string()));
+            }
+            if let Some((_, draft)) = &mut self.source_rename {
+                ui.horizontal(|ui| {
+                    let edit = ui.text_edit_singleline(draft);
+                    let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Rename").clicked() || entered {
+                        let nickname = Some(draft.trim().to_string()).filter(|n| !n.is_empty() && *n != source.description);
+                        overrides.entry(source.name.clone()).or_default().nickname = nickname;
+                        ui.close_menu();
+                    }
+                });
+            }
+            let hidden = source.is_hidden(overrides);
+            if ui.button(if hidden { "Show in list" } else { "Hide from list" }).clicked() {
+                overrides.entry(source.name.clone()).or_default().hidden = !hidden;
+                ui.close_menu();
+            }
+            if overrides.contains_key(&source.name) && ui.button("Reset name and visibility").clicked() {
+                overrides.remove(&source.name);
+                ui.close_menu();
+            }
+        });
+        clicked
+    }
+
+    fn format_source_display(source: &AudioSource, overrides: &SourceOverrides) -> String {
         let icon = if source.is_monitor { "🔊" } else { "🎤" };
         let status_indicators = format!(
-            "{}{}",
+            "{}{}{}",
             if source.is_running { " ⚡" } else { "" },
             if source.is_default { " ⭐" } else { "" },
+            if source.is_hidden(overrides) { " (hidden)" } else { "" },
         );
-        format!("{} {}{}", icon, source.description, status_indicators)
+        format!("{} {}{}", icon, source.label(overrides), status_indicators)
     }
 }
 
@@ -236,24 +1510,33 @@ impl AudioStreamerApp {
 
 impl eframe::App for AudioStreamerApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        // --- Follow theme changes, from the Appearance section or the desktop ---
+        let palette = Palette::new(self.config.theme.is_dark(frame.info().system_theme), self.config.accent_color);
+        if palette != self.palette {
+            theme::apply(ctx, &palette);
+            self.palette = palette;
+        }
+        let palette = self.palette;
+
         // --- Process background logic ---
-        if self.streaming {
-            if let Some(process) = &mut self.ffmpeg_process {
-                if process.try_wait().ok().flatten().is_some() {
-                    self.streaming = false;
-                    self.ffmpeg_process = None;
-                    self.status_message = "Streaming stopped unexpectedly".to_string();
-                }
-            }
+        self.poll_events();
+        if std::mem::take(&mut self.focus_requested) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
         }
-        self.update_selected_source();
+        if self.config.auto_follow_source && self.last_source_poll.elapsed() >= SOURCE_POLL_INTERVAL {
+            self.last_source_poll = Instant::now();
+            self.refresh_sources();
+        }
+        self.poll_remote_commands();
+        self.sync_mqtt();
+        self.handle_keyboard(ctx);
         
         let main_frame = egui::Frame {
-            fill: Color32::from_rgba_unmultiplied(30, 30, 45, 255),
+            fill: palette.background,
             inner_margin: egui::Margin::same(0.0),
             outer_margin: egui::Margin::same(0.0),
             rounding: 10.0.into(),
-            stroke: Stroke::new(1.0, Color32::WHITE),
+            stroke: Stroke::new(1.0, palette.border),
             ..Default::default()
         };
 
@@ -271,18 +1554,18 @@ impl eframe::App for AudioStreamerApp {
 
             // Draw title bar content
             let painter = ui.painter();
-            painter.rect_filled(title_bar_rect, 5.0, Color32::from_rgb(50, 50, 65));
+            painter.rect_filled(title_bar_rect, 5.0, palette.title_bar);
             
             ui.allocate_ui_at_rect(title_bar_rect, |ui| {
                 ui.horizontal_centered(|ui| {
                     ui.add_space(8.0);
                     ui.label(egui::RichText::new("🎵 Audio Streamer").strong());
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        if ui.button(egui::RichText::new("❌").color(Color32::LIGHT_RED)).on_hover_text("Close").clicked() { ctx.send_viewport_cmd(egui::ViewportCommand::Close); }
-                        if ui.button(egui::RichText::new("🗗").strong()).on_hover_text("Maximize").clicked() { 
+                        if named_button(ui.button(egui::RichText::new("❌").color(palette.error)), "Close").on_hover_text("Close").clicked() { ctx.send_viewport_cmd(egui::ViewportCommand::Close); }
+                        if named_button(ui.button(egui::RichText::new("🗗").strong()), "Maximize").on_hover_text("Maximize").clicked() { 
                             ctx.send_viewport_cmd(egui::ViewportCommand::Maximized(!ctx.input(|i| i.viewport().maximized.unwrap_or(false))));
                         }
-                        if ui.button(egui::RichText::new("🗕").strong()).on_hover_text("Minimize").clicked() { ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true)); }
+                        if named_button(ui.button(egui::RichText::new("🗕").strong()), "Minimize").on_hover_text("Minimize").clicked() { ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true)); }
                     });
                 });
             });
@@ -293,21 +1576,523 @@ impl eframe::App for AudioStreamerApp {
                 ui.scope(|ui| {
                     ui.style_mut().spacing.window_margin = egui::Margin::same(15.0); // Apply padding for content
                     
+                    // --- ffmpeg problems block streaming, so show them first ---
+                    if !self.checking_ffmpeg && let Err(e) = &self.ffmpeg_status {
+                        let e = e.clone();
+                        ui.group(|ui| {
+                            ui.colored_label(palette.error, format!("❌ {}", e));
+                            ui.label(INSTALL_HINT);
+                            ui.label("Until then streaming uses the built-in engine: uncompressed PCM over RTP, receivable with an SDP file.");
+                            if ui.button("🔄 Check Again").clicked() { self.recheck_ffmpeg(); }
+                        });
+                    }
+
+                    // --- The last run crashed ---
+                    if let Some(report) = &self.crash_report {
+                        let report = report.display().to_string();
+                        ui.group(|ui| {
+                            ui.colored_label(palette.warning, "⚠ Audio Streamer crashed last time");
+                            ui.label(format!("A report was saved to {}. Attaching it to a bug report helps fix the crash.", report));
+                            ui.horizontal(|ui| {
+                                if ui.button("📄 Open Report").clicked() { self.close_crash_report(true); }
+                                if ui.button("Dismiss").clicked() { self.close_crash_report(false); }
+                            });
+                        });
+                    }
+
+                    // --- Settings in the config file that can't be used as they are ---
+                    if !self.config_problems.is_empty() {
+                        ui.group(|ui| {
+                            ui.colored_label(palette.warning, format!("⚠ {} has settings that can't be used:", self.config_path.display()));
+                            for problem in &self.config_problems {
+                                ui.label(format!("• {}", problem.describe()));
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.button("↺ Reset These to Defaults").on_hover_text("Saves the config with only these settings changed").clicked() { self.reset_config_problems(); }
+                                if ui.button("Dismiss").clicked() { self.config_problems.clear(); }
+                            });
+                        });
+                    }
+
+                    // --- What was streaming when the app last closed, crashed or the machine rebooted ---
+                    if !self.last_session.is_empty() {
+                        let targets: Vec<String> = self.last_session.iter().map(LastStream::target).collect();
+                        ui.group(|ui| {
+                            ui.label(format!("Resume streaming to {}?", targets.join(", ")));
+                            ui.horizontal(|ui| {
+                                // Sources are resumed by name, so it waits for the first list.
+                                if ui.add_enabled(!self.sources.is_empty(), egui::Button::new("▶ Resume")).clicked() { self.resume_last_session(); }
+                                if ui.button("Dismiss").clicked() { self.dismiss_last_session(); }
+                            });
+                        });
+                    }
+
                     // --- Configuration section ---
                     ui.collapsing(egui::RichText::new("⚙ Configuration").size(16.0), |ui| {
+                        ui.horizontal(|ui| {
+                            let label = ui.label("Templates:");
+                            let selected = self.template.map_or("Choose a receiver…", |i| PRESETS[i].name);
+                            let mut chosen = None;
+                            egui::ComboBox::from_id_source("template_combo").selected_text(selected).show_ui(ui, |ui| {
+                                for (i, preset) in PRESETS.iter().enumerate() {
+                                    if ui.selectable_label(self.template == Some(i), preset.name).on_hover_text(preset.summary).clicked() {
+                                        chosen = Some(i);
+                                    }
+                                }
+                            }).response.labelled_by(label.id).on_hover_text("Sets the transport, codec and outputs for a common receiver; the target stays as it is");
+                            if let Some(i) = chosen {
+                                PRESETS[i].apply(&mut self.config);
+                                self.template = Some(i);
+                                self.status_message = format!("Applied the {} template", PRESETS[i].name);
+                            }
+                        });
+                        if let Some(i) = self.template {
+                            ui.horizontal_wrapped(|ui| {
+                                ui.small(format!("📖 {}", PRESETS[i].instructions(&self.config)));
+                                if ui.small_button("✖").on_hover_text("Hide the instructions").clicked() { self.template = None; }
+                            });
+                        }
                         egui::Grid::new("config_grid").num_columns(2).spacing([10.0, 10.0]).show(ui, |ui| {
-                            ui.label("Target IP:");
-                            ui.text_edit_singleline(&mut self.temp_ip);
+                            // A paired A2DP device replaces the network target, e.g. where there's no Wi-Fi.
+                            let label = ui.label("Play on:");
+                            ui.horizontal(|ui| {
+                                let selected = match &self.config.bluetooth_sink {
+                                    None => "📡 Network target".to_string(),
+                                    Some(address) => self.bluetooth_sinks.iter().find(|sink| &sink.address == address)
+                                        .map_or_else(|| format!("🎧 {}", address), BluetoothSink::label),
+                                };
+                                egui::ComboBox::from_id_source("play_on").selected_text(selected).show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.bluetooth_sink, None, "📡 Network target");
+                                    for sink in &self.bluetooth_sinks {
+                                        ui.selectable_value(&mut self.config.bluetooth_sink, Some(sink.address.clone()), sink.label());
+                                    }
+                                }).response.labelled_by(label.id);
+                                if named_button(ui.button("🔄"), "Refresh Bluetooth devices").on_hover_text("List paired Bluetooth audio devices again").clicked() {
+                                    self.refresh_bluetooth_sinks();
+                                }
+                            });
+                            ui.end_row();
+                            let label = ui.label("Target IP:");
+                            let mut ip_focused = false;
+                            ui.horizontal(|ui| {
+                                let response = ui.add(egui::TextEdit::singleline(&mut self.temp_ip).hint_text("ip, ip:port or udp://…")).labelled_by(label.id);
+                                ip_focused = response.has_focus();
+                                if response.lost_focus() {
+                                    let _ = self.apply_target_input();
+                                }
+                                if named_button(ui.button("📋"), "Paste target").on_hover_text("Paste an IP, ip:port or stream URL").clicked() {
+                                    self.paste_target();
+                                }
+                                // Many phones are reached over a VPN mesh rather than the LAN.
+                                ui.menu_button("🔗 VPN", |ui| {
+                                    if self.vpn_peers.is_empty() {
+                                        ui.label("No Tailscale or WireGuard peers found");
+                                    }
+                                    for peer in &self.vpn_peers {
+                                        if ui.button(peer.label()).clicked() {
+                                            self.temp_ip = peer.ip.to_string();
+                                            ui.close_menu();
+                                        }
+                                    }
+                                    if ui.button("🔄 Refresh").clicked() { self.refresh_vpn_peers(); }
+                                });
+                            });
+                            ui.end_row();
+                            // Checked while typing pauses, so half an address isn't flagged.
+                            if !ip_focused && !self.temp_ip.trim().is_empty() && let Err(e) = parse_target(&self.temp_ip) {
+                                ui.label("");
+                                ui.colored_label(palette.error, format!("⚠ {}", e));
+                                ui.end_row();
+                            }
+                            let label = ui.label("Target Port:");
+                            ui.text_edit_singleline(&mut self.temp_port).labelled_by(label.id);
+                            ui.end_row();
+                            if let Err(e) = parse_port(&self.temp_port) {
+                                ui.label("");
+                                ui.colored_label(palette.error, format!("⚠ {}", e));
+                                ui.end_row();
+                            }
+                            let label = ui.label("Backup IP:");
+                            ui.add(egui::TextEdit::singleline(&mut self.temp_backup_ip).hint_text("none")).labelled_by(label.id);
+                            ui.end_row();
+                            if !self.temp_backup_ip.trim().is_empty() {
+                                let label = ui.label("Backup Port:");
+                                ui.text_edit_singleline(&mut self.temp_backup_port).labelled_by(label.id);
+                                ui.end_row();
+                                let label = ui.label("Fail over after:");
+                                ui.add(egui::Slider::new(&mut self.config.failover_after_secs, 2..=30).suffix(" s"))
+                                    .labelled_by(label.id)
+                                    .on_hover_text("How long the primary must look unreachable before switching");
+                                ui.end_row();
+                            }
+                            let label = ui.label("ffmpeg Path:");
+                            ui.add(egui::TextEdit::singleline(&mut self.temp_ffmpeg_path).hint_text("search PATH")).labelled_by(label.id);
+                            ui.end_row();
+                            ui.label("Transport:");
+                            egui::ComboBox::from_id_source("transport_combo")
+                                .selected_text(self.config.transport.label())
+                                .show_ui(ui, |ui| {
+                                    for transport in Transport::ALL {
+                                        ui.selectable_value(&mut self.config.transport, transport, transport.label());
+                                    }
+                                });
+                            ui.end_row();
+                            if self.config.transport != Transport::Rist {
+                                let label = ui.label("Relay server key:");
+                                ui.add(egui::TextEdit::singleline(&mut self.config.relay_server_key).password(true).hint_text("not a relay server").desired_width(120.0))
+                                    .labelled_by(label.id)
+                                    .on_hover_text("When the target is an `audio-streamer relay` server: the key it printed when it started, which it takes the stream with");
+                                ui.end_row();
+                            }
+                            if self.config.transport == Transport::Native {
+                                let label = ui.label("FEC group:");
+                                ui.add(egui::Slider::new(&mut self.config.fec_group_size, 0..=20))
+                                    .labelled_by(label.id)
+                                    .on_hover_text("One parity packet per N data packets; 0 disables FEC");
+                                ui.end_row();
+                                let label = ui.label("Pacing burst:");
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::Slider::new(&mut self.config.pacing_burst, 0..=32).suffix(" packets"))
+                                        .labelled_by(label.id)
+                                        .on_hover_text("Packets that may go out back to back before the rest are spread at the bitrate; 0 sends them as the encoder writes them");
+                                    if self.config.pacing_burst > 0 {
+                                        ui.add(egui::DragValue::new(&mut self.config.pacing_interval_ms).clamp_range(1..=50).suffix(" ms"))
+                                            .on_hover_text("How often paced packets are released");
+                                    }
+                                });
+                                ui.end_row();
+                                if self.config.pacing_burst > 0 && self.config.bitrate_bps().is_none() {
+                                    ui.label("");
+                                    ui.small(format!("Not paced: '{}' isn't a bitrate to pace at", self.config.bitrate));
+                                    ui.end_row();
+                                }
+                                let label = ui.label("Second path:");
+                                let selected = if self.config.redundant_interface.is_empty() { "Off".to_string() } else { self.config.redundant_interface.clone() };
+                                egui::ComboBox::from_id_source("second_path_combo")
+                                    .selected_text(selected)
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(&mut self.config.redundant_interface, String::new(), "Off");
+                                        for interface in netwatch::interfaces() {
+                                            ui.selectable_value(&mut self.config.redundant_interface, interface.clone(), interface);
+                                        }
+                                    })
+                                    .response
+                                    .labelled_by(label.id)
+                                    .on_hover_text("Also send every packet through this interface, e.g. Wi-Fi next to Ethernet; the receiver drops the second copy, so either path may fail without a gap. Doubles the bandwidth");
+                                ui.end_row();
+                                ui.label("While paused:");
+                                ui.checkbox(&mut self.config.pause_keepalive, "Send keepalives");
+                                ui.end_row();
+                                ui.label("Remote control:");
+                                ui.checkbox(&mut self.config.remote_control, "Receivers may change volume and codec");
+                                ui.end_row();
+                                ui.label("Negotiation:");
+                                ui.add_enabled(self.config.remote_control, egui::Checkbox::new(&mut self.config.negotiate_codec, "Fit the codec to what the receiver plays"))
+                                    .on_hover_text("audio-streamer --receive says which codecs it decodes, the highest bitrate it wants and how much it buffers; the stream restarts with a codec, bitrate and latency mode both ends agree on when they differ")
+                                    .on_disabled_hover_text("Needs remote control, as the receiver's offer comes in with its commands");
+                                ui.end_row();
+                                ui.label("Notifications:");
+                                ui.checkbox(&mut self.config.push_notifications, "Tell receivers about starts, stops and tracks")
+                                    .on_hover_text("So a companion app can show what plays while it is in the background; the track comes from the media player, through playerctl");
+                                ui.end_row();
+                                let label = ui.label("Rendezvous room:");
+                                ui.add(egui::TextEdit::singleline(&mut self.config.rendezvous_room).hint_text("off"))
+                                    .labelled_by(label.id)
+                                    .on_hover_text("For a receiver behind another router: the target is then a rendezvous helper, which introduces both sides so the stream can punch through NAT, or passes it on when that fails");
+                                ui.end_row();
+                                if !self.config.rendezvous_room.trim().is_empty() {
+                                    let label = ui.label("Room secret:");
+                                    ui.add(egui::TextEdit::singleline(&mut self.config.rendezvous_secret).password(true).hint_text("required").desired_width(120.0))
+                                        .labelled_by(label.id)
+                                        .on_hover_text("The receiver passes the same with --room-secret; the helper only pairs the two when they match, and keeps anyone else out of the room");
+                                    ui.end_row();
+                                }
+                            }
+                            let label = ui.label("MTU:");
+                            ui.add(egui::DragValue::new(&mut self.config.mtu).clamp_range(0..=9000)
+                                .custom_formatter(|mtu, _| if mtu == 0.0 { "auto".to_string() } else { format!("{}", mtu) }))
+                                .labelled_by(label.id)
+                                .on_hover_text("Largest packet the network carries unfragmented; auto asks the route to the target. Lower it for VPNs or PPPoE that aren't detected.");
+                            ui.end_row();
+                            ui.label("QoS marking:");
+                            egui::ComboBox::from_id_source("dscp_combo")
+                                .selected_text(self.config.dscp.label())
+                                .show_ui(ui, |ui| {
+                                    for dscp in Dscp::ALL {
+                                        ui.selectable_value(&mut self.config.dscp, dscp, dscp.label());
+                                    }
+                                })
+                                .response
+                                .on_hover_text("DSCP class on outgoing packets, for routers that prioritize by it. Not applied to the RIST gateway's packets.");
                             ui.end_row();
-                            ui.label("Target Port:");
-                            ui.text_edit_singleline(&mut self.temp_port);
+                            ui.label("Network change:");
+                            ui.checkbox(&mut self.config.resume_on_network_change, "Resume on the new route")
+                                .on_hover_text("Restart the stream on the new interface or address, e.g. after moving from Ethernet to Wi-Fi");
                             ui.end_row();
+                            ui.label("At launch:");
+                            ui.checkbox(&mut self.config.auto_resume, "Resume the last session without asking")
+                                .on_hover_text("Start streaming again to wherever it was streaming when the app last closed, e.g. after a reboot");
+                            ui.end_row();
+                            if sdp::applies(&self.config, self.engine()) {
+                                ui.label("Clock:");
+                                ui.checkbox(&mut self.config.rtp_clock, "Wall-clock timestamps and sender reports")
+                                    .on_hover_text("RTCP sender reports relate the RTP timestamps to this machine's clock, so receivers and sync tools can line the stream up with video or other senders. The built-in engine also counts its timestamps from the NTP epoch (RFC 7273). Needs the clock synced by NTP.");
+                                ui.end_row();
+                            }
+                            if self.config.transport == Transport::Rist {
+                                let label = ui.label("Recovery buffer:");
+                                ui.add(egui::Slider::new(&mut self.config.rist_buffer_ms, 50..=2000).suffix(" ms"))
+                                    .labelled_by(label.id)
+                                    .on_hover_text("How long lost packets can still be resent. Use the same on the receiver; it adds as much latency.");
+                                ui.end_row();
+                            }
+                        });
+                        if self.config.transport == Transport::Native && !self.config.rendezvous_room.trim().is_empty() {
+                            ui.group(|ui| {
+                                ui.label("Reaching a receiver behind another router:");
+                                ui.small("1. On a server with a public IP, run:");
+                                ui.monospace("audio-streamer rendezvous --listen 0.0.0.0:3478");
+                                ui.small("2. Set the target above to that server's IP and port 3478.");
+                                ui.small("3. On the receiver, run the \"through the helper\" command under 📱 Receiver.");
+                                ui.small("Both sides then punch through their NATs to each other; if a NAT won't allow it, the helper passes the stream on, with its bandwidth and latency.");
+                                ui.horizontal(|ui| {
+                                    if ui.button("🔍 Check NAT").on_hover_text("Asks public STUN servers how this network maps UDP ports").clicked() {
+                                        self.start_nat_check();
+                                    }
+                                    if let Some(result) = &self.nat_check {
+                                        ui.small(result);
+                                    }
+                                });
+                            });
+                        }
+                        ui.collapsing("Extra outputs", |ui| {
+                            ui.small("Sent alongside the main target (ffmpeg engine only, not with the RTP transport)");
+                            let mut removed = None;
+                            for (i, output) in self.config.outputs.iter_mut().enumerate() {
+                                ui.horizontal(|ui| {
+                                    match output {
+                                        Output::Udp { address } => { ui.label("UDP"); ui.add(egui::TextEdit::singleline(address).hint_text("ip:port")); }
+                                        Output::Http { port } => { ui.label("HTTP port"); ui.add(egui::DragValue::new(port).clamp_range(1024..=65535)); }
+                                        Output::File { path } => { ui.label("Record to"); ui.add(egui::TextEdit::singleline(path).hint_text("~/stream.ts")); }
+                                        Output::Snapcast { target, control, stream } => {
+                                            ui.label("Snapcast");
+                                            ui.add(egui::TextEdit::singleline(target).hint_text("tcp://server:4953 or FIFO").desired_width(150.0))
+                                                .on_hover_text(format!("A snapserver tcp source in server mode, or a pipe source's FIFO, set to sampleformat {}", snapcast::SAMPLE_FORMAT));
+                                            ui.add(egui::TextEdit::singleline(control).hint_text("server:1705").desired_width(90.0))
+                                                .on_hover_text("snapserver's control port, to switch all groups to the stream below (optional)");
+                                            ui.add(egui::TextEdit::singleline(stream).hint_text("stream id").desired_width(70.0));
+                                        }
+                                        Output::WebRtc { port, stun } => {
+                                            ui.label("Browser (WebRTC) port");
+                                            ui.add(egui::DragValue::new(port).clamp_range(1024..=65535));
+                                            ui.add(egui::TextEdit::singleline(stun).hint_text("LAN only").desired_width(120.0))
+                                                .on_hover_text("A STUN server (host:port) to reach phones outside the LAN; empty keeps it to the local network");
+                                        }
+                                        Output::Sink { name } => {
+                                            ui.label("Mix sink");
+                                            ui.add(egui::TextEdit::singleline(name).hint_text(mix::DEFAULT_NAME).desired_width(150.0))
+                                                .on_hover_text("A sink with what is streamed, after the filters and the codec. In OBS, add an Audio Output Capture of it");
+                                        }
+                                        Output::Icecast { server, mount, password, name, description } => {
+                                            ui.label("Icecast");
+                                            ui.add(egui::TextEdit::singleline(server).hint_text("host:8000").desired_width(110.0));
+                                            ui.add(egui::TextEdit::singleline(mount).hint_text("/mount").desired_width(60.0));
+                                            ui.add(egui::TextEdit::singleline(password).password(true).hint_text("password").desired_width(70.0));
+                                            ui.add(egui::TextEdit::singleline(name).hint_text("station name").desired_width(80.0));
+                                            ui.add(egui::TextEdit::singleline(description).hint_text("description").desired_width(80.0));
+                                        }
+                                    }
+                                    if named_button(ui.small_button("🗑"), "Remove output").clicked() { removed = Some(i); }
+                                });
+                            }
+                            if let Some(i) = removed { self.config.outputs.remove(i); }
+                            if self.config.outputs.iter().any(|output| matches!(output, Output::Http { .. } | Output::WebRtc { .. })) {
+                                ui.horizontal(|ui| {
+                                    let label = ui.label("Access token:");
+                                    ui.add(egui::TextEdit::singleline(&mut self.config.listen_token).password(true).hint_text("none").desired_width(120.0))
+                                        .labelled_by(label.id)
+                                        .on_hover_text("Required by the HTTP and browser outputs: open http://host:port/?token=TOKEN, or give it as the password when asked (any user name)");
+                                });
+                                ui.horizontal(|ui| {
+                                    let label = ui.label("Allowed clients:");
+                                    ui.add(egui::TextEdit::singleline(&mut self.temp_allowlist).hint_text("anyone, or e.g. 192.168.1.0/24, 10.0.0.5"))
+                                        .labelled_by(label.id);
+                                    if let Err(e) = parse_allowlist(&allowlist_entries(&self.temp_allowlist)) {
+                                        ui.colored_label(palette.error, format!("{:#}", e));
+                                    }
+                                });
+                                ui.checkbox(&mut self.config.listen_tls, "🔒 HTTPS")
+                                    .on_hover_text("Serves them over TLS with a certificate made for this machine, so the stream and the token aren't readable on shared Wi-Fi");
+                                if self.config.listen_tls {
+                                    let fingerprint = self.tls_fingerprint
+                                        .get_or_insert_with(|| TlsIdentity::load_or_create().map(|identity| identity.fingerprint).map_err(|e| format!("{:#}", e)))
+                                        .clone();
+                                    match fingerprint {
+                                        Ok(fingerprint) => {
+                                            ui.small("Browsers and players warn about the self-signed certificate; trust it only if it shows this SHA-256 fingerprint:");
+                                            ui.horizontal(|ui| {
+                                                ui.monospace(&fingerprint);
+                                                if ui.small_button("📋 Copy").clicked() {
+                                                    ui.output_mut(|o| o.copied_text = fingerprint.clone());
+                                                }
+                                            });
+                                            if let Some(url) = self.certificate_url() {
+                                                ui.collapsing("Install it on the phone", |ui| {
+                                                    ui.small("Scan to download the certificate, then install it as a trusted CA certificate in the phone's security settings. The warning then goes away.");
+                                                    if let Some(code) = self.certificate_qr_code(&url) {
+                                                        paint_qr_code(ui, code, 3.0);
+                                                    }
+                                                    ui.monospace(&url);
+                                                });
+                                            }
+                                        }
+                                        Err(e) => { ui.colored_label(palette.error, format!("No certificate: {}", e)); }
+                                    }
+                                }
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.button("+ UDP").clicked() { self.config.outputs.push(Output::Udp { address: String::new() }); }
+                                if ui.button("+ HTTP").clicked() { self.config.outputs.push(Output::Http { port: 8080 }); }
+                                if ui.button("+ Recording").clicked() { self.config.outputs.push(Output::File { path: "~/audio-streamer.ts".to_string() }); }
+                                if ui.button("+ Browser").on_hover_text("A page any phone browser can play, with WebRTC latency").clicked() {
+                                    self.config.outputs.push(Output::WebRtc { port: 8081, stun: String::new() });
+                                }
+                                if ui.button("+ Icecast").clicked() {
+                                    self.config.outputs.push(Output::Icecast {
+                                        server: String::new(),
+                                        mount: "/live".to_string(),
+                                        password: String::new(),
+                                        name: "audio-streamer".to_string(),
+                                        description: String::new(),
+                                    });
+                                }
+                                if ui.button("+ Snapcast").clicked() {
+                                    self.config.outputs.push(Output::Snapcast { target: "/tmp/snapfifo".to_string(), control: String::new(), stream: String::new() });
+                                }
+                                if ui.button("+ Mix for OBS").on_hover_text("A virtual sink that OBS and other local apps can record the stream from").clicked() {
+                                    self.config.outputs.push(Output::Sink { name: mix::DEFAULT_NAME.to_string() });
+                                }
+                            });
+                        });
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.config.battery_saver, "🔋 Battery saver")
+                                .on_hover_text("On battery: lower sample rate and bitrate, larger packets and fewer redraws. Switches back on mains power.");
+                            if self.config.battery_saver {
+                                ui.small(if self.on_battery { "on battery, active" } else { "on mains power" });
+                            }
+                        });
+                        ui.collapsing("Advanced: ffmpeg argument template", |ui| {
+                            ui.small(format!("Replaces the generated arguments. Placeholders: {}", PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(" ")));
+                            ui.add(egui::TextEdit::multiline(&mut self.temp_args_template)
+                                .hint_text("-f pulse -i {source} -c:a {codec} -b:a {bitrate} -f mpegts {target}")
+                                .desired_rows(2)
+                                .code_editor());
+                            if !self.temp_args_template.trim().is_empty() {
+                                match self.template_preview() {
+                                    Ok(preview) => { ui.colored_label(palette.success, "✅ Template is valid"); ui.monospace(preview); }
+                                    Err(e) => { ui.colored_label(palette.error, format!("❌ {}", e)); }
+                                }
+                            }
+                        });
+                        ui.collapsing("Hooks: commands on stream events", |ui| {
+                            ui.small("Run with sh -c. AUDIO_STREAMER_EVENT, _SOURCE, _TARGET, _CODEC, _BITRATE and, once it ends, _DURATION_SECS, _BYTES_SENT and _ERROR describe the stream.");
+                            let hooks = &mut self.config.hooks;
+                            egui::Grid::new("hooks").num_columns(2).show(ui, |ui| {
+                                for (name, command, hint) in [
+                                    ("Before start:", &mut hooks.pre_start, "e.g. curl -X POST http://plug.local/on"),
+                                    ("After start:", &mut hooks.post_start, "e.g. makoctl mode -a do-not-disturb"),
+                                    ("On stop:", &mut hooks.on_stop, "e.g. makoctl mode -r do-not-disturb"),
+                                    ("On error:", &mut hooks.on_error, "e.g. notify-send \"Stream failed\" \"$AUDIO_STREAMER_ERROR\""),
+                                ] {
+                                    let label = ui.label(name);
+                                    ui.add(egui::TextEdit::singleline(command).hint_text(hint).desired_width(320.0).code_editor()).labelled_by(label.id);
+                                    ui.end_row();
+                                }
+                            });
+                            ui.small("Before start is waited for, up to 3 s. A failing hook is logged and never stops the stream.");
+                        });
+                        ui.collapsing("🏠 Home Assistant (MQTT)", |ui| {
+                            ui.small("Adds a streaming switch and bitrate and listener sensors to Home Assistant through MQTT discovery. The switch starts the selected source like the Start button.");
+                            if ui.checkbox(&mut self.temp_mqtt.enabled, "Connect to the broker").changed() {
+                                self.config.integrations.mqtt = self.temp_mqtt.clone();
+                            }
+                            let mqtt = &mut self.temp_mqtt;
+                            let mut apply = false;
+                            egui::Grid::new("mqtt").num_columns(2).show(ui, |ui| {
+                                for (name, value, hint, password) in [
+                                    ("Broker:", &mut mqtt.broker, "e.g. homeassistant.local:1883", false),
+                                    ("Username:", &mut mqtt.username, "none", false),
+                                    ("Password:", &mut mqtt.password, "none", true),
+                                    ("Discovery prefix:", &mut mqtt.discovery_prefix, "homeassistant", false),
+                                    ("Device ID:", &mut mqtt.node_id, "this machine's host name", false),
+                                ] {
+                                    let label = ui.label(name);
+                                    apply |= ui.add(egui::TextEdit::singleline(value).hint_text(hint).password(password).desired_width(220.0)).labelled_by(label.id).lost_focus();
+                                    ui.end_row();
+                                }
+                            });
+                            if apply {
+                                self.config.integrations.mqtt = self.temp_mqtt.clone();
+                            }
                         });
                         ui.add_space(5.0);
                         ui.horizontal(|ui| {
                             if ui.button("Apply Settings").clicked() { self.update_config_from_temp(); self.status_message = "Settings updated".to_string(); }
                             if ui.button("💾 Save").clicked() { self.update_config_from_temp(); if let Err(e) = self.save_config() { self.status_message = format!("Save failed: {}", e); } }
+                            if ui.button("↶ Restore Previous Config").on_hover_text("Goes back to the config as it was before the last save; the current one is kept as a backup, so this can be undone").clicked() { self.restore_previous_config(); }
                         });
+                        ui.collapsing("📦 Move settings to another machine", |ui| {
+                            ui.small("One file with these settings and every profile. Secrets are left out unless a passphrase is given, which encrypts them.");
+                            ui.horizontal(|ui| {
+                                let label = ui.label("File:");
+                                ui.add(egui::TextEdit::singleline(&mut self.bundle_path).hint_text("~/audio-streamer-settings.json")).labelled_by(label.id);
+                            });
+                            ui.horizontal(|ui| {
+                                let label = ui.label("Passphrase:");
+                                ui.add(egui::TextEdit::singleline(&mut self.bundle_passphrase).password(true).hint_text("none")).labelled_by(label.id);
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("📤 Export").clicked() && let Err(e) = self.export_bundle() {
+                                    self.status_message = format!("Export failed: {:#}", e);
+                                }
+                                if ui.button("📥 Import and merge")
+                                    .on_hover_text("Keeps this machine's source, ffmpeg path, hooks, Bluetooth sink and source nicknames, and profiles the file doesn't have")
+                                    .clicked()
+                                    && let Err(e) = self.import_bundle(ImportMode::Merge)
+                                {
+                                    self.status_message = format!("Import failed: {:#}", e);
+                                }
+                                if ui.button("📥 Import and replace")
+                                    .on_hover_text("The settings and profiles become the file's, but for this machine's ffmpeg path and hooks; other profiles are deleted")
+                                    .clicked()
+                                    && let Err(e) = self.import_bundle(ImportMode::Replace)
+                                {
+                                    self.status_message = format!("Import failed: {:#}", e);
+                                }
+                            });
+                        });
+                    });
+
+                    // --- Appearance section ---
+                    ui.collapsing(egui::RichText::new("🎨 Appearance").size(16.0), |ui| {
+                        egui::Grid::new("appearance_grid").num_columns(2).spacing([10.0, 10.0]).show(ui, |ui| {
+                            ui.label("Theme:");
+                            egui::ComboBox::from_id_source("theme_combo")
+                                .selected_text(self.config.theme.label())
+                                .show_ui(ui, |ui| {
+                                    for mode in ThemeMode::ALL {
+                                        ui.selectable_value(&mut self.config.theme, mode, mode.label());
+                                    }
+                                });
+                            ui.end_row();
+                            ui.label("Accent:");
+                            ui.horizontal(|ui| {
+                                ui.color_edit_button_srgb(&mut self.config.accent_color);
+                                if ui.add_enabled(self.config.accent_color != DEFAULT_ACCENT, egui::Button::new("Reset")).clicked() { self.config.accent_color = DEFAULT_ACCENT; }
+                            });
+                            ui.end_row();
+                        });
+                        if ui.button("💾 Save").clicked() && let Err(e) = self.save_config() { self.status_message = format!("Save failed: {}", e); }
                     });
 
                     // --- Audio Source Section ---
@@ -316,23 +2101,456 @@ impl eframe::App for AudioStreamerApp {
                             ui.label("Select audio source:");
                             if ui.button("🔄 Refresh").clicked() { self.refresh_sources(); self.status_message = "Refreshing...".to_string(); }
                         });
+                        ui.horizontal(|ui| {
+                            ui.label("Capture from:");
+                            for backend in CaptureBackend::ALL {
+                                if ui.selectable_value(&mut self.config.capture_backend, backend, backend.label()).changed() {
+                                    self.refresh_sources();
+                                }
+                            }
+                        });
+                        if self.config.capture_backend == CaptureBackend::Jack {
+                            ui.small(format!("Streams what is patched into the {}-1:input_N ports (the second stream is {}-2, and so on), e.g. from a DAW in qjackctl or Carla. Volume and ducking need PulseAudio.", jack::CLIENT, jack::CLIENT));
+                            ui.horizontal(|ui| {
+                                let label = ui.label("Patch on start:");
+                                let response = ui.add(egui::TextEdit::singleline(&mut self.temp_jack_connect).hint_text("e.g. Ardour:Master/audio_out 1, Ardour:Master/audio_out 2").desired_width(320.0))
+                                    .labelled_by(label.id)
+                                    .on_hover_text("JACK output ports to connect to our inputs in turn when a stream starts");
+                                if response.lost_focus() {
+                                    self.config.jack_connect = app_entries(&self.temp_jack_connect);
+                                }
+                            });
+                        }
+                        match (self.config.capture_backend, self.config.capture_backend.effective()) {
+                            (CaptureBackend::Alsa, _) => { ui.small("Straight from the sound card, for systems without PulseAudio or PipeWire. Other apps can't record from the card while it streams, and muting pauses the stream."); }
+                            (CaptureBackend::Pulse, CaptureBackend::Alsa) => { ui.small("No PulseAudio or PipeWire is running, so the ALSA devices are listed instead."); }
+                            _ => {}
+                        }
                         ui.horizontal(|ui| {
                             ui.label("Legend:");
                             ui.colored_label(ui.visuals().widgets.active.bg_fill, "⚡=Active");
                             ui.colored_label(ui.visuals().widgets.active.bg_fill, "⭐=Default");
                         });
 
-                        let sources = self.sources.lock().unwrap().clone();
-                        let current_selected = self.selected_source;
-                        egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
-                            for (i, source) in sources.iter().enumerate() {
-                                let text = self.format_source_display(source);
-                                let response = ui.selectable_label(i == current_selected, text);
-                                if response.clicked() { self.selected_source = i; self.config.preferred_source = Some(source.name.clone()); self.status_message = format!("Selected: {}", source.description); }
+                        ui.horizontal(|ui| {
+                            for kind in SourceKind::ALL {
+                                ui.selectable_value(&mut self.config.source_kind_filter, kind, kind.label());
+                            }
+                            ui.toggle_value(&mut self.config.running_sources_only, "⚡ Running only");
+                        });
+                        ui.checkbox(&mut self.config.auto_follow_source, "Follow the best source")
+                            .on_hover_text("Switch (and restart the stream) when another source starts playing, e.g. audio moving from speakers to HDMI");
+                        ui.checkbox(&mut self.config.match_source_spec, "Match the source's sample rate")
+                            .on_hover_text("Encode at the source's own rate and channel count when they are lower than configured, instead of resampling");
+                        ui.horizontal(|ui| {
+                            let label = ui.label("🔍 Filter:");
+                            ui.add(egui::TextEdit::singleline(&mut self.source_filter).hint_text("name or description")).labelled_by(label.id);
+                            if !self.source_filter.is_empty() && named_button(ui.small_button("✖"), "Clear filter").clicked() { self.source_filter.clear(); }
+                        });
+                        let visible = self.visible_sources();
+                        let hidden_count = self.sources.iter().filter(|s| s.is_hidden(&self.config.source_overrides)).count();
+                        let mut clicked = None;
+                        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                            if visible.len() < GROUPED_SOURCE_COUNT {
+                                for &i in &visible {
+                                    if self.source_row(ui, i) { clicked = Some(i); }
+                                }
+                                return;
+                            }
+                            let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+                            for &i in &visible {
+                                groups.entry(self.sources[i].group()).or_default().push(i);
+                            }
+                            for (group, members) in groups {
+                                egui::CollapsingHeader::new(format!("{} ({})", group, members.len()))
+                                    .id_source(("source_group", &group))
+                                    .default_open(true)
+                                    .show(ui, |ui| {
+                                        for i in members {
+                                            if self.source_row(ui, i) { clicked = Some(i); }
+                                        }
+                                    });
+                            }
+                        });
+                        if visible.is_empty() && !self.sources.is_empty() {
+                            ui.small("No sources match the filter.");
+                        }
+                        // Entries with nothing left to override are dropped rather than saved empty.
+                        self.config.source_overrides.retain(|_, o| *o != Default::default());
+                        if hidden_count > 0 {
+                            ui.checkbox(&mut self.show_hidden_sources, format!("Show hidden sources ({})", hidden_count));
+                        }
+                        if let Some(i) = clicked {
+                            let source = &self.sources[i];
+                            self.selected_source = i;
+                            self.config.preferred_source = Some(source.name.clone());
+                            self.status_message = format!("Selected: {}", source.label(&self.config.source_overrides));
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.label("Channels:");
+                            egui::ComboBox::from_id_source("channel_mode_combo")
+                                .selected_text(self.config.channel_mode.label())
+                                .show_ui(ui, |ui| {
+                                    for mode in ChannelMode::ALL {
+                                        ui.selectable_value(&mut self.config.channel_mode, mode, mode.label());
+                                    }
+                                });
+                            if matches!(self.config.channel_mode, ChannelMode::Downmix51 | ChannelMode::Downmix71) {
+                                egui::ComboBox::from_id_source("downmix_matrix_combo")
+                                    .selected_text(self.config.downmix_matrix.label())
+                                    .show_ui(ui, |ui| {
+                                        for matrix in DownmixMatrix::ALL {
+                                            ui.selectable_value(&mut self.config.downmix_matrix, matrix, matrix.label());
+                                        }
+                                    });
+                            }
+                        });
+                        ui.checkbox(&mut self.config.remove_vocals, "Remove vocals")
+                            .on_hover_text("Karaoke: cancels whatever is mixed in the centre, usually the singer. Only the stream changes, local playback keeps the vocals");
+                        if (self.config.channel_mode != ChannelMode::Passthrough || self.config.remove_vocals) && self.engine() == Engine::BuiltIn {
+                            ui.small("Channel processing needs ffmpeg; the built-in engine sends audio as captured.");
+                        }
+
+                        let previous_resampler = self.config.resampler;
+                        ui.horizontal(|ui| {
+                            ui.label("Resampler:");
+                            egui::ComboBox::from_id_source("resampler_combo")
+                                .selected_text(self.config.resampler.label())
+                                .show_ui(ui, |ui| {
+                                    for resampler in Resampler::ALL {
+                                        ui.selectable_value(&mut self.config.resampler, resampler, resampler.label());
+                                    }
+                                });
+                            egui::ComboBox::from_id_source("resampler_quality_combo")
+                                .selected_text(self.config.resampler_quality.label())
+                                .show_ui(ui, |ui| {
+                                    for quality in ResamplerQuality::ALL {
+                                        ui.selectable_value(&mut self.config.resampler_quality, quality, quality.label());
+                                    }
+                                });
+                        });
+                        // Picking soxr may make the current ffmpeg unusable, so say so right away.
+                        if self.config.resampler != previous_resampler {
+                            self.recheck_ffmpeg();
+                        }
+                        ui.horizontal(|ui| {
+                            ui.label("Dither:");
+                            egui::ComboBox::from_id_source("dither_combo")
+                                .selected_text(self.config.dither.label())
+                                .show_ui(ui, |ui| {
+                                    for dither in Dither::ALL {
+                                        ui.selectable_value(&mut self.config.dither, dither, dither.label());
+                                    }
+                                });
+                            ui.label("Samples:");
+                            egui::ComboBox::from_id_source("sample_format_combo")
+                                .selected_text(self.config.sample_format.label())
+                                .show_ui(ui, |ui| {
+                                    for format in SampleFormat::ALL {
+                                        ui.selectable_value(&mut self.config.sample_format, format, format.label());
+                                    }
+                                });
+                        });
+                        if self.engine() == Engine::BuiltIn && self.config.sample_format == SampleFormat::F32 {
+                            ui.small("The built-in engine sends 16-bit samples instead of float.");
+                        }
+
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.config.normalize_loudness, "Normalize loudness")
+                                .on_hover_text("Evens out volume between apps; recordings get an exact two-pass correction when the stream stops");
+                            if self.config.normalize_loudness {
+                                ui.add(egui::Slider::new(&mut self.config.target_lufs, -30.0..=-10.0).step_by(0.5).suffix(" LUFS"));
+                            }
+                        });
+                        if self.config.normalize_loudness && self.engine() == Engine::BuiltIn {
+                            ui.small("Loudness normalization needs ffmpeg.");
+                        }
+                        ui.horizontal(|ui| {
+                            let label = ui.label("Noise suppression:");
+                            egui::ComboBox::from_id_source("noise_suppression_combo")
+                                .selected_text(self.config.noise_suppression.label())
+                                .show_ui(ui, |ui| {
+                                    for suppression in NoiseSuppression::ALL {
+                                        ui.selectable_value(&mut self.config.noise_suppression, suppression, suppression.label());
+                                    }
+                                })
+                                .response
+                                .labelled_by(label.id)
+                                .on_hover_text("Takes out fans, hum and keyboard noise when streaming a microphone; output monitors are sent untouched");
+                            if self.config.noise_suppression == NoiseSuppression::Rnnoise {
+                                ui.add(egui::TextEdit::singleline(&mut self.config.noise_model).hint_text("~/models/sh.rnnn").desired_width(180.0))
+                                    .on_hover_text("An RNNoise model for ffmpeg's arnndn filter, e.g. from the rnnoise-models project");
+                            }
+                        });
+                        if self.config.noise_suppression != NoiseSuppression::Off && self.engine() == Engine::BuiltIn {
+                            ui.small("Noise suppression needs ffmpeg.");
+                        }
+                        ui.horizontal(|ui| {
+                            let label = ui.label("Duck under:");
+                            let selected = self.sources.iter()
+                                .find(|source| source.name == self.config.duck_source)
+                                .map_or_else(|| if self.config.duck_source.is_empty() { "Off".to_string() } else { self.config.duck_source.clone() }, |source| source.label(&self.config.source_overrides).to_string());
+                            egui::ComboBox::from_id_source("duck_source_combo")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut self.config.duck_source, String::new(), "Off");
+                                    for source in self.sources.iter().filter(|source| !source.is_monitor) {
+                                        ui.selectable_value(&mut self.config.duck_source, source.name.clone(), source.label(&self.config.source_overrides));
+                                    }
+                                })
+                                .response
+                                .labelled_by(label.id)
+                                .on_hover_text("While this microphone picks something up, the stream is turned down so the voice comes through, e.g. for narration or a DJ talking over the music");
+                            if !self.config.duck_source.is_empty() {
+                                ui.add(egui::Slider::new(&mut self.config.duck_threshold_db, -60.0..=-10.0).step_by(1.0).prefix("above ").suffix(" dBFS"));
+                                ui.add(egui::Slider::new(&mut self.config.duck_amount_db, 3.0..=30.0).step_by(1.0).prefix("by ").suffix(" dB"));
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            let label = ui.label("Never stream:");
+                            let response = ui.add(egui::TextEdit::singleline(&mut self.temp_do_not_stream).hint_text("e.g. zoom, KeePassXC").desired_width(220.0))
+                                .labelled_by(label.id)
+                                .on_hover_text("Apps, by name or program, whose sound must never reach the stream, e.g. password manager alerts or video calls. While an output monitor streams they are muted, here too, and get their sound back when it stops");
+                            // Straight away, so running streams pick it up too.
+                            if response.lost_focus() {
+                                self.config.do_not_stream = app_entries(&self.temp_do_not_stream);
                             }
                         });
+                        ui.checkbox(&mut self.config.realtime_priority, "Realtime priority")
+                            .on_hover_text("Asks rtkit to run the capture and encoder ahead of other programs, against dropouts while the machine is busy; falls back to a high nice level when realtime is refused");
+                        ui.checkbox(&mut self.config.overload_protection, "Go lighter when overloaded")
+                            .on_hover_text("When ffmpeg can't encode in real time for a few seconds, restart the stream with AAC at a lower rate and bitrate instead of letting the latency grow");
+
+                        if self.config.audio_codec == "libopus" {
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut self.config.opus_fec, "Opus FEC")
+                                    .on_hover_text("Each packet also carries a low-bitrate copy of the one before, so a single lost packet can be rebuilt");
+                                let label = ui.label("Expected loss:");
+                                ui.add(egui::Slider::new(&mut self.config.opus_expected_loss, 0..=30).suffix(" %"))
+                                    .labelled_by(label.id)
+                                    .on_hover_text("How much of the bitrate the encoder spends on redundancy; Wi-Fi typically loses 1–5 %");
+                                ui.checkbox(&mut self.config.opus_dtx, "DTX")
+                                    .on_hover_text("Send almost nothing during silence, to save bandwidth and battery");
+                            });
+                            if self.config.opus_fec && self.config.opus_expected_loss == 0 {
+                                ui.small("FEC only takes effect with an expected loss above 0 %.");
+                            }
+                        }
                     }));
 
+                    // --- Waveform of the selected source, only captured while shown ---
+                    let waveform = ui.collapsing(egui::RichText::new("📈 Waveform").size(16.0), |ui| self.waveform_ui(ui, palette));
+                    if waveform.body_returned.is_none() {
+                        self.stop_meter();
+                    }
+
+                    // --- Session history ---
+                    ui.collapsing(egui::RichText::new("📜 History").size(16.0), |ui| {
+                        if self.history.sessions().is_empty() {
+                            ui.label("No sessions yet");
+                            return;
+                        }
+                        egui::ScrollArea::vertical().id_source("history_scroll").max_height(180.0).show(ui, |ui| {
+                            egui::Grid::new("history_grid").num_columns(6).striped(true).spacing([12.0, 4.0]).show(ui, |ui| {
+                                for header in ["Started", "Duration", "Target", "Codec", "Sent", "Ended"] {
+                                    ui.strong(header);
+                                }
+                                ui.end_row();
+                                for session in self.history.sessions().iter().rev() {
+                                    ui.label(format_utc(session.started_at));
+                                    ui.label(format!("{}:{:02}", session.duration_secs / 60, session.duration_secs % 60));
+                                    ui.label(&session.target);
+                                    ui.label(&session.codec);
+                                    ui.label(format!("{:.1} MB, {} kbps", session.bytes_sent as f64 / 1_000_000.0, session.average_kbps()));
+                                    match &session.end_reason {
+                                        Some(reason) => ui.colored_label(palette.error, "crashed").on_hover_text(reason),
+                                        None => ui.label("stopped"),
+                                    };
+                                    ui.end_row();
+                                }
+                            });
+                        });
+                        if ui.button("🗑 Clear History").clicked() && let Err(e) = self.history.clear() { self.status_message = format!("Failed to clear history: {}", e); }
+                    });
+
+                    // --- Network tools ---
+                    ui.collapsing(egui::RichText::new("🌐 Network").size(16.0), |ui| {
+                        ui.horizontal(|ui| {
+                            if ui.button("📡 Test Packet").clicked() { self.test_network_connectivity(); }
+                            if ui.add_enabled(!self.measuring_bandwidth, egui::Button::new("📶 Measure Bandwidth")).clicked() { self.start_bandwidth_measurement(); }
+                            if ui.add_enabled(!self.self_testing, egui::Button::new("🩺 Self Test"))
+                                .on_hover_text("Streams the selected source to a receiver on this machine and checks the packets")
+                                .clicked() { self.update_config_from_temp(); self.start_self_test(); }
+                            if ui.add_enabled(!self.diagnosing, egui::Button::new("🔎 Diagnose"))
+                                .on_hover_text("Looks for the usual reasons nothing arrives: different subnets, Wi-Fi client isolation, a VPN taking the LAN traffic")
+                                .clicked() { self.update_config_from_temp(); self.start_diagnosis(); }
+                        });
+                        if self.diagnosing {
+                            ui.horizontal(|ui| { ui.spinner(); ui.label("Probing the target..."); });
+                        }
+                        match &self.diagnosis {
+                            Some(findings) if findings.is_empty() => { ui.colored_label(palette.success, "✅ No common network problems found"); }
+                            Some(findings) => {
+                                for finding in findings {
+                                    ui.colored_label(palette.warning, format!("⚠ {}", finding.problem));
+                                    ui.small(&finding.remedy);
+                                }
+                            }
+                            None => {}
+                        }
+                        if self.self_testing {
+                            ui.horizontal(|ui| { ui.spinner(); ui.label("Running self test..."); });
+                        }
+                        if let Some(report) = &self.self_test_report {
+                            let (color, verdict) = if report.passed { (palette.success, "Self test passed") } else { (palette.error, "Self test failed") };
+                            ui.colored_label(color, egui::RichText::new(verdict).strong());
+                            for line in &report.lines {
+                                ui.label(line);
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source("test_signal_combo")
+                                .selected_text(self.test_signal.label())
+                                .show_ui(ui, |ui| {
+                                    for signal in TestSignal::ALL {
+                                        ui.selectable_value(&mut self.test_signal, signal, signal.label()).on_hover_text(signal.description());
+                                    }
+                                });
+                            if self.test_signal == TestSignal::Sine {
+                                ui.add(egui::DragValue::new(&mut self.test_frequency).clamp_range(20..=20000).suffix(" Hz"));
+                            }
+                            ui.add(egui::DragValue::new(&mut self.test_duration_secs).clamp_range(1..=60).suffix(" s"));
+                            if ui.button("🎵 Send Test Signal").clicked() && let Err(e) = self.generate_test_signal() { self.status_message = format!("Test signal failed: {}", e); }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Latency beacon:");
+                            egui::ComboBox::from_id_source("beacon_combo")
+                                .selected_text(self.config.beacon.label())
+                                .show_ui(ui, |ui| {
+                                    for beacon in Beacon::ALL {
+                                        ui.selectable_value(&mut self.config.beacon, beacon, beacon.label());
+                                    }
+                                });
+                            if self.config.beacon != Beacon::Off {
+                                ui.label("every");
+                                ui.add(egui::DragValue::new(&mut self.config.beacon_interval_secs).clamp_range(2..=30).suffix(" s"));
+                            }
+                        }).response.on_hover_text("A receiver in --receive mode with the same setting shows the end-to-end latency it measures.");
+                        if self.config.beacon != Beacon::Off && (self.engine() == Engine::BuiltIn || self.config.transport != Transport::Native) {
+                            ui.small("The beacon needs ffmpeg and the native transport.");
+                        }
+                        if !self.network_test_result.is_empty() {
+                            ui.label(&self.network_test_result);
+                        }
+                        if let Some(report) = self.bandwidth_report.clone() {
+                            if !report.has_feedback() {
+                                ui.small("Run a receiver on the target for loss and jitter figures.");
+                            }
+                            if report.recommended_bitrate != self.config.bitrate
+                                && ui.button(format!("Apply {} bitrate", report.recommended_bitrate)).clicked()
+                            {
+                                self.config.bitrate = report.recommended_bitrate.clone();
+                                self.status_message = format!("Bitrate set to {}", self.config.bitrate);
+                            }
+                        }
+                    });
+
+                    // --- A/B codec comparison ---
+                    ui.collapsing(egui::RichText::new("🆚 Compare Codecs").size(16.0), |ui| {
+                        ui.small("Encodes one clip of the selected source with both settings, so you can hear the difference here before trying them on the receiver.");
+                        egui::Grid::new("compare_grid").num_columns(3).spacing([8.0, 6.0]).show(ui, |ui| {
+                            for (side, setting) in [("A", &mut self.compare_a), ("B", &mut self.compare_b)] {
+                                ui.label(format!("{}:", side));
+                                egui::ComboBox::from_id_source(("compare_codec", side))
+                                    .selected_text(setting.codec.clone())
+                                    .show_ui(ui, |ui| {
+                                        for codec in REMOTE_CODECS.iter().chain(&["flac"]) {
+                                            ui.selectable_value(&mut setting.codec, codec.to_string(), *codec);
+                                        }
+                                    });
+                                ui.add(egui::TextEdit::singleline(&mut setting.bitrate).desired_width(60.0).hint_text("192k"));
+                                ui.end_row();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut self.compare_secs).clamp_range(1..=60).suffix(" s"));
+                            if ui.add_enabled(!self.comparing, egui::Button::new("🆚 Compare")).clicked() {
+                                self.start_comparison();
+                            }
+                        });
+                        if self.comparing {
+                            ui.horizontal(|ui| { ui.spinner(); ui.label(format!("Capturing {} s and encoding...", self.compare_secs)); });
+                        }
+                        let mut play_path = None;
+                        let mut chosen = None;
+                        match &self.comparison {
+                            Some(Ok(comparison)) => {
+                                for (side, encoded) in [("A", &comparison.a), ("B", &comparison.b)] {
+                                    ui.horizontal(|ui| {
+                                        if ui.button(format!("▶ {}", side)).clicked() {
+                                            play_path = Some(encoded.path.clone());
+                                        }
+                                        if ui.small_button("Use").on_hover_text("Stream with this codec and bitrate from now on").clicked() {
+                                            chosen = Some(encoded.setting.clone());
+                                        }
+                                        ui.label(encoded.describe());
+                                    });
+                                    if encoded.speed < 1.0 {
+                                        ui.colored_label(palette.warning, "⚠ Encodes slower than real time on this machine, so it can't keep up live");
+                                    }
+                                }
+                                ui.small("The SNR is only a rough guide: codecs hide their noise where it is least heard, so trust your ears.");
+                            }
+                            Some(Err(e)) => { ui.colored_label(palette.error, format!("❌ {}", e)); }
+                            None => {}
+                        }
+                        if let Some(path) = play_path {
+                            let _runtime = self.runtime_handle.enter(); // tokio spawns need it
+                            match play(&path) {
+                                Ok(child) => self.playback = Some(child), // Stops the one playing before
+                                Err(e) => self.status_message = format!("{:#}", e),
+                            }
+                        }
+                        if let Some(setting) = chosen {
+                            self.status_message = format!("Codec set to {}", setting.label());
+                            self.config.audio_codec = setting.codec;
+                            if !setting.bitrate.is_empty() {
+                                self.config.bitrate = setting.bitrate;
+                            }
+                        }
+                    });
+
+                    // --- Receiver pairing ---
+                    ui.collapsing(egui::RichText::new("📱 Receiver").size(16.0), |ui| {
+                        ui.label("Scan with your phone to open the stream:");
+                        let url = self.config.receiver_url();
+                        match self.pairing_qr_code() {
+                            Some(code) => paint_qr_code(ui, code, 4.0),
+                            None => { ui.label("❌ Could not generate QR code"); }
+                        }
+                        ui.monospace(url);
+
+                        ui.add_space(5.0);
+                        ui.label("Or play it with one of these:");
+                        for (n, guide) in receiver_guides(&self.config, self.engine()).into_iter().enumerate() {
+                            egui::CollapsingHeader::new(guide.player).id_source(("receiver_guide", n)).show(ui, |ui| {
+                                for (i, step) in guide.steps.iter().enumerate() {
+                                    ui.label(format!("{}. {}", i + 1, step));
+                                }
+                                if let Some(text) = &guide.copy {
+                                    ui.horizontal(|ui| {
+                                        ui.monospace(text);
+                                        if ui.small_button("📋 Copy").clicked() {
+                                            ui.output_mut(|o| o.copied_text = text.clone());
+                                            self.status_message = format!("Copied {}", text);
+                                        }
+                                    });
+                                }
+                            });
+                        }
+                    });
+
                     // --- Control & Status ---
                     egui::Frame {
                         inner_margin: egui::Margin::same(8.0),
@@ -342,19 +2560,274 @@ impl eframe::App for AudioStreamerApp {
                         ..Default::default()
                     }.show(ui, |ui| {
                         ui.vertical_centered(|ui| {
-                            let stream_button_text = if self.streaming { "⏹ Stop Streaming" } else { "▶ Start Streaming" };
-                            let stream_button_color = if self.streaming { Color32::from_rgb(200, 70, 70) } else { Color32::from_rgb(70, 170, 70) };
+                            let streaming = !self.streams.is_empty();
+                            let stream_button_text = if streaming { "⏹ Stop All Streams" } else { "▶ Start Streaming" };
+                            let stream_button_color = if streaming { palette.stop_button } else { palette.start_button };
                             let stream_button = egui::Button::new(stream_button_text).fill(stream_button_color).min_size(egui::vec2(200.0, 40.0));
                             
                             if ui.add_enabled(self.config.is_ip_configured(), stream_button).clicked() {
+                                self.toggle_streaming();
+                            }
+                            if streaming {
+                                let (text, fill) = match self.streams.silenced_for() {
+                                    Some(left) => (format!("🔊 Unmute Now ({}:{:02} left)", left.as_secs() / 60, left.as_secs() % 60), palette.warning),
+                                    None => (format!("🔇 Mute Stream for {} Minutes", QUICK_MUTE.as_secs() / 60), palette.stop_button),
+                                };
+                                let button = egui::Button::new(egui::RichText::new(text).color(egui::Color32::WHITE)).fill(fill).min_size(egui::vec2(200.0, 32.0));
+                                if ui.add(button).on_hover_text("Sends silence without stopping, so receivers stay connected, and unmutes on its own (Ctrl+M)").clicked() {
+                                    self.toggle_quick_mute();
+                                }
+                            }
+                            if streaming
+                                && ui.button("➕ Start Another Stream")
+                                    .on_hover_text("Streams the selected source to the target above as well, e.g. the microphone to a second device")
+                                    .clicked()
+                            {
+                                self.update_config_from_temp();
+                                if let Err(e) = self.start_streaming() {
+                                    self.status_message = format!("Start failed: {}", e);
+                                }
+                            }
+
+                            if !streaming && ui.button("🔍 Preview Command").clicked() {
                                 self.update_config_from_temp();
-                                if self.streaming { if let Err(e) = self.stop_streaming() { self.status_message = format!("Stop failed: {}", e); }}
-                                else { if let Err(e) = self.start_streaming() { self.status_message = format!("Start failed: {}", e); }}
+                                self.preview_command();
+                            }
+                            if let Some(preview) = self.command_preview.clone() {
+                                ui.horizontal(|ui| {
+                                    ui.monospace(&preview);
+                                    if ui.small_button("📋 Copy").clicked() { ui.output_mut(|o| o.copied_text = preview.clone()); }
+                                    if named_button(ui.small_button("✖"), "Close preview").clicked() { self.command_preview = None; }
+                                });
+                            }
+
+                            // One card per stream, each with its own controls.
+                            let (mut paused_id, mut stopped_id, mut restart_id) = (None, None, None);
+                            for stream in self.streams.iter_mut() {
+                                let changes = live::changes(&stream.requested, &self.config);
+                                if !changes.live.is_empty() {
+                                    stream.apply_live(&self.config, &self.runtime_handle);
+                                }
+                                ui.group(|ui| {
+                                    ui.label(egui::RichText::new(format!(
+                                        "{} → {} · {}",
+                                        stream.source.label(&self.config.source_overrides),
+                                        stream.target(),
+                                        stream.codec()
+                                    )).strong()).on_hover_text(stream.tag.as_ref().map_or_else(String::new, |tag| format!("Receivers see this as {}", tag.describe())));
+                                    if !changes.restart.is_empty() {
+                                        ui.horizontal(|ui| {
+                                            ui.colored_label(palette.warning, format!("⚠ Needs a restart to apply: {}", changes.describe_restart()));
+                                            if ui.small_button("⟳ Restart")
+                                                .on_hover_text("Restarts the encoder with the new settings; receivers drop out for a moment, the session carries on in the history")
+                                                .clicked()
+                                            {
+                                                restart_id = Some(stream.id);
+                                            }
+                                        });
+                                    }
+                                    if stream.is_waiting_for_server() {
+                                        ui.colored_label(palette.warning, "⏳ Waiting for the sound server to come back")
+                                            .on_hover_text("PulseAudio or PipeWire went away under the capture. The stream resumes on the same source once it is back, or stops after a minute.");
+                                    }
+                                    if let Some(relay) = stream.relay() {
+                                        if let Some(path) = relay.peer_path() {
+                                            ui.label(format!("🔀 Rendezvous: {}", path));
+                                        }
+                                        match relay.receiver() {
+                                            Some(receiver) => ui.colored_label(palette.success, format!("📶 {}", receiver.describe())),
+                                            None => ui.colored_label(palette.warning, "📵 No receiver detected")
+                                                .on_hover_text("Nothing has reported back for a few seconds. audio-streamer --receive does; plain players like VLC never do, so this is expected with them."),
+                                        };
+                                        if let Some(negotiated) = &stream.negotiated {
+                                            ui.small(format!("🤝 Negotiated with {}", negotiated));
+                                        }
+                                    }
+                                    if let Some(sdp) = stream.sdp() {
+                                        ui.horizontal(|ui| {
+                                            ui.label("📄 SDP:").on_hover_text(format!("Open this in VLC or ffplay on the receiver instead of the plain port. Also saved to {}", sdp.path.display()));
+                                            match &sdp.url {
+                                                Some(url) => {
+                                                    ui.monospace(url);
+                                                    if ui.small_button("📋 Copy URL").clicked() {
+                                                        ui.output_mut(|o| o.copied_text = url.clone());
+                                                    }
+                                                }
+                                                None => { ui.monospace(sdp.path.display().to_string()); }
+                                            }
+                                            if let Some(description) = sdp.description()
+                                                && ui.small_button("📋 Copy SDP").clicked()
+                                            {
+                                                ui.output_mut(|o| o.copied_text = description);
+                                            }
+                                        });
+                                    }
+                                    if let Some(monitor) = stream.latency() {
+                                        paint_latency(ui, monitor, palette);
+                                    }
+                                    if let Some(load) = stream.load()
+                                        && let Some(speed) = load.speed()
+                                    {
+                                        let cpu = load.cpu_percent().map_or_else(String::new, |cpu| format!("{:.0} % CPU · ", cpu));
+                                        let text = format!("⚙ Encoder: {}{:.2}× real time", cpu, speed);
+                                        let color = if speed < 1.0 { palette.warning } else { ui.visuals().text_color() };
+                                        ui.colored_label(color, text).on_hover_text("ffmpeg's share of one core, and how fast it encodes compared to the audio coming in; below 1× the latency grows");
+                                    }
+                                    if let Some(xruns) = stream.xruns()
+                                        && let Some(text) = xrun::summary(xruns.overruns(), xruns.underruns())
+                                    {
+                                        ui.colored_label(palette.warning, text)
+                                            .on_hover_text("Overruns are captured audio lost before it was encoded, underruns gaps in what went out; the log says which settings give them more room");
+                                    }
+                                    if let Some(listeners) = stream.listeners()
+                                        && stream.config.outputs.iter().any(Output::takes_listeners)
+                                    {
+                                        let connected = listeners.list();
+                                        let total: f64 = connected.iter().map(|listener| listener.bitrate_bps()).sum();
+                                        ui.horizontal(|ui| {
+                                            ui.label(format!("👥 {} listening", connected.len()));
+                                            if !connected.is_empty() {
+                                                ui.small(format!("· {:.0} kbit/s in all", total / 1000.0));
+                                            }
+                                            let peak = listeners.peak();
+                                            if peak > 0 {
+                                                ui.small(format!("· peak {} this session", peak));
+                                            }
+                                        });
+                                        paint_listener_history(ui, &listeners.history(), listeners.peak(), palette);
+                                        for listener in connected {
+                                            ui.horizontal(|ui| {
+                                                ui.monospace(listener.address.to_string());
+                                                ui.small(format!(
+                                                    "{} · {} min · {:.0} kbit/s · {:.1} MB",
+                                                    listener.output,
+                                                    listener.since.elapsed().as_secs() / 60,
+                                                    listener.bitrate_bps() / 1000.0,
+                                                    listener.bytes_sent as f64 / 1_000_000.0
+                                                ));
+                                                if ui.small_button("Kick")
+                                                    .on_hover_text("Disconnects this address and keeps it out until the stream restarts")
+                                                    .clicked()
+                                                {
+                                                    listeners.kick(listener.id);
+                                                }
+                                            });
+                                        }
+                                    }
+                                    ui.horizontal(|ui| {
+                                        if stream.relay().is_some() {
+                                            let pause_text = if stream.is_paused() { "▶ Resume" } else { "⏸ Pause" };
+                                            if ui.button(pause_text).clicked() { paused_id = Some(stream.id); }
+                                        }
+                                        if ui.button("⏹ Stop").clicked() { stopped_id = Some(stream.id); }
+                                        if stream.relay().is_some()
+                                            && let Some(selected) = self.sources.get(self.selected_source).filter(|selected| selected.name != stream.source.name)
+                                            && ui.button(format!("🔀 Switch to {}", selected.label(&self.config.source_overrides)))
+                                                .on_hover_text("Moves this stream to the selected source without restarting it, so the receiver keeps playing through a short fade")
+                                                .clicked()
+                                            && let Err(e) = stream.switch_source(selected.clone(), &self.runtime_handle)
+                                        {
+                                            self.status_message = format!("Could not switch: {:#}", e);
+                                        }
+                                        if stream.can_monitor() {
+                                            let monitoring = stream.monitor().is_some();
+                                            let clicked = ui.selectable_label(monitoring, "🎧 Monitor locally")
+                                                .on_hover_text("Plays the outgoing stream here with ffplay or mpv, decoded as the receiver decodes it")
+                                                .clicked();
+                                            if clicked && monitoring {
+                                                stream.stop_monitor();
+                                            } else if clicked {
+                                                self.status_message = match stream.start_monitor(&self.runtime_handle) {
+                                                    Ok(player) => format!("Monitoring {} locally with {}", stream.target(), player),
+                                                    Err(e) => format!("Could not monitor: {:#}", e),
+                                                };
+                                            }
+                                        }
+                                    });
+                                    if stream.monitor().is_some() {
+                                        ui.horizontal(|ui| {
+                                            let label = ui.label("Monitor delay:");
+                                            let slider = egui::Slider::new(&mut stream.config.monitor_delay_ms, 0..=3000).suffix(" ms");
+                                            let changed = ui.add(slider)
+                                                .labelled_by(label.id)
+                                                .on_hover_text("Holds the local playback back to when the receiver plays it: its buffer, the network and the audio delay")
+                                                .changed();
+                                            if changed && let Some(monitor) = stream.monitor() {
+                                                monitor.set_delay(std::time::Duration::from_millis(stream.config.monitor_delay_ms as u64));
+                                                self.config.monitor_delay_ms = stream.config.monitor_delay_ms;
+                                            }
+                                        });
+                                    }
+                                    if stream.relay().is_none() {
+                                        return;
+                                    }
+
+                                    // Adjustable while streaming, to line the audio up with video on the receiving device.
+                                    // Both controls also become the starting values for new streams.
+                                    ui.horizontal(|ui| {
+                                        let label = ui.label("Audio delay:");
+                                        let slider = egui::Slider::new(&mut stream.config.audio_delay_ms, 0..=2000).suffix(" ms");
+                                        let changed = ui.add(slider)
+                                            .labelled_by(label.id)
+                                            .on_hover_text("Holds the audio back when it is ahead of the picture. Audio that lags can't be sped up here; lower the receiver's buffer instead.")
+                                            .changed();
+                                        if changed && let Some(relay) = stream.relay() {
+                                            relay.set_delay(std::time::Duration::from_millis(stream.config.audio_delay_ms as u64));
+                                            self.config.audio_delay_ms = stream.config.audio_delay_ms;
+                                        }
+                                    });
+                                    ui.horizontal(|ui| {
+                                        let label = ui.label("Volume:");
+                                        let has_volume = stream.has_volume();
+                                        let slider = egui::Slider::new(&mut stream.config.volume_percent, 0..=MAX_VOLUME).suffix(" %");
+                                        let changed = ui.add_enabled(!stream.muted && has_volume, slider)
+                                            .labelled_by(label.id)
+                                            .on_hover_text("Only what is streamed; the source keeps its own volume. Above 100 % may clip.")
+                                            .on_disabled_hover_text(if has_volume { "Unmute to change the volume" } else { "JACK and ALSA captures have no volume here, only mute" })
+                                            .changed();
+                                        if ui.checkbox(&mut stream.muted, "Mute").changed() || changed {
+                                            stream.apply_volume(&self.runtime_handle);
+                                            self.config.volume_percent = stream.config.volume_percent;
+                                            if let Some((_, Ok(meter))) = &self.meter {
+                                                meter.set_gain(self.config.volume_percent, &self.runtime_handle);
+                                            }
+                                        }
+                                    });
+                                });
+                            }
+                            if let Some(id) = paused_id {
+                                self.toggle_pause(id);
+                            }
+                            if let Some(id) = stopped_id {
+                                self.streams.stop(id, &self.runtime_handle);
+                                self.status_message = "Stream stopped".to_string();
+                            }
+                            if let Some(id) = restart_id {
+                                let config = self.config.clone();
+                                self.status_message = match self.restart_stream(id, true, |stream| {
+                                    stream.requested = live::merged(&stream.config, &config);
+                                    stream.base = stream.requested.clone();
+                                }) {
+                                    Ok(_) => "Restarted with the new settings".to_string(),
+                                    Err(e) => format!("Restart failed: {:#}", e),
+                                };
                             }
 
                             ui.separator();
-                            let status_color = if self.streaming { Color32::from_rgb(76, 175, 80) } else if !self.config.is_ip_configured() { Color32::from_rgb(244, 67, 54) } else { Color32::from_rgb(255, 152, 0) };
+                            let paused = self.streams.iter().any(Stream::is_paused);
+                            let status_color = if paused { palette.warning } else if !self.streams.is_empty() { palette.success } else if !self.config.is_ip_configured() { palette.error } else { palette.warning };
                             ui.label(egui::RichText::new(format!("Status: {}", self.status_message)).color(status_color));
+                            if let Some(left) = self.streams.silenced_for() {
+                                ui.label(egui::RichText::new(silence_countdown(left)).color(palette.warning).strong());
+                            }
+                            ui.small(format!("Engine: {}", self.engine().label()));
+                            if self.engine() == Engine::BuiltIn && !fallback::sends_flac(&self.config) {
+                                ui.small("With the flac codec and the native transport it sends FLAC instead, for audio-streamer --receive.");
+                            }
+                            ui.small("Keys: Enter starts/stops, Ctrl+M mutes for a while, ↑/↓ pick the source, Tab moves between controls");
+                            if self.inhibitor.as_ref().is_some_and(SleepInhibitor::is_active) {
+                                ui.small("☕ Suspend inhibited while streaming");
+                            }
                         });
                     });
 
@@ -362,6 +2835,7 @@ impl eframe::App for AudioStreamerApp {
             }); // End of content area allocation
         });
 
-        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        let repaint_interval = if self.power_saving() { power::BATTERY_REPAINT_INTERVAL } else { std::time::Duration::from_secs(1) };
+        ctx.request_repaint_after(repaint_interval);
     }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use crate::{config::Config, fallback::Engine, outputs::Output, sdp, transport::Transport};
+use std::net::IpAddr;
+
+// How to play the stream with one player, for the GUI's receiver panel.
+pub struct ReceiverGuide {
+    pub player: &'static str,
+    pub steps: Vec<String>,
+    pub copy: Option<String>, // The URL or command the steps refer to
+}
+
+impl ReceiverGuide {
+    fn new(player: &'static str, steps: &[&str], copy: Option<String>) -> Self {
+        Self { player, steps: steps.iter().map(|step| step.to_string()).collect(), copy }
+    }
+
+    fn note(mut self, note: Option<String>) -> Self {
+        self.steps.extend(note);
+        self
+    }
+}
+
+// "<this machine>" until the route to the target is known.
+fn host(config: &Config) -> String {
+    match config.target_ip.parse().ok().and_then(sdp::local_address) {
+        Some(IpAddr::V6(ip)) => format!("[{}]", ip),
+        Some(ip) => ip.to_string(),
+        None => "<this machine>".to_string(),
+    }
+}
+
+// Codecs that players commonly have trouble with in MPEG-TS.
+fn codec_note(config: &Config) -> Option<String> {
+    match config.audio_codec.as_str() {
+        "libopus" => Some("Opus in MPEG-TS needs VLC 3.0 or newer; older ones stay silent.".to_string()),
+        "flac" | "pcm_s16be" => Some(format!("Not every player decodes {} in MPEG-TS; AAC works everywhere.", config.audio_codec)),
+        _ => None,
+    }
+}
+
+// Step by step, for the transport, codec and engine in use, plus one guide per listening output.
+pub fn receiver_guides(config: &Config, engine: Engine) -> Vec<ReceiverGuide> {
+    let port = config.target_port;
+    let firewall = format!("If nothing plays, allow incoming UDP port {} in the receiver's firewall.", port);
+    let mut guides = Vec::new();
+    if sdp::applies(config, engine) {
+        let url = sdp::url(config.target_ip.parse().ok().and_then(sdp::local_address), port);
+        let steps = ["Desktop: Media → Open Network Stream… (Ctrl+N). Android: More → New stream.", "Enter the SDP address below and press Play.", "Start the stream here first; the SDP is only served while it runs."];
+        guides.push(ReceiverGuide::new("VLC", &steps, Some(url.clone())).note(Some(firewall.clone())));
+        guides.push(ReceiverGuide::new("ffplay", &["Run this in a terminal on the receiver:"], Some(format!("ffplay -nodisp -protocol_whitelist http,tcp,udp,rtp -i {}", url))));
+    } else {
+        match config.transport {
+            Transport::Native if !config.rendezvous_room.trim().is_empty() => {
+                // The target is the helper; the receiver can listen on any port.
+                let command = format!("audio-streamer --receive 5000 --rendezvous {}:{} --room {} --room-secret SECRET", config.target_ip, port, config.rendezvous_room.trim());
+                let steps = ["Install audio-streamer on the receiver.", "Run this there, with the room secret set here; it finds this machine through the helper:"];
+                guides.push(ReceiverGuide::new("audio-streamer", &steps, Some(command)));
+            }
+            Transport::Native => {
+                let steps = ["Install audio-streamer on the receiver.", "Run this there, with its IP as the target above:"];
+                guides.push(ReceiverGuide::new("audio-streamer", &steps, Some(config.receiver_url())).note(Some(firewall.clone())));
+                let steps = ["For a receiver behind another router, run this instead; it asks the router to forward the port:"];
+                guides.push(ReceiverGuide::new("over the internet", &steps, Some(format!("{} --upnp", config.receiver_url()))));
+            }
+            Transport::Rist => {
+                let steps = ["Needs VLC 3.0.14 or newer.", "Desktop: Media → Open Network Stream… (Ctrl+N). Android: More → New stream.", "Enter the address below and press Play."];
+                guides.push(ReceiverGuide::new("VLC", &steps, Some(config.receiver_url())).note(Some(firewall.clone())));
+                let command = format!("ffplay -nodisp -fflags nobuffer rist://@:{}?buffer_size={}", port, config.rist_buffer_ms);
+                guides.push(ReceiverGuide::new("ffplay", &["Run this in a terminal on the receiver:"], Some(command)));
+            }
+            Transport::Udp | Transport::Rtp => { // RTP always has an SDP, above
+                let steps = [
+                    "Desktop: Media → Open Network Stream… (Ctrl+N). Android: More → New stream.",
+                    "Enter the address below and press Play.",
+                    "For less delay, lower Tools → Preferences → Input / Codecs → Network caching.",
+                ];
+                guides.push(ReceiverGuide::new("VLC", &steps, Some(config.receiver_url())).note(codec_note(config)).note(Some(firewall.clone())));
+                let command = format!("mpv --profile=low-latency --no-cache udp://0.0.0.0:{}", port);
+                guides.push(ReceiverGuide::new("mpv", &["Run this in a terminal on the receiver:"], Some(command)));
+                let command = format!("ffplay -nodisp -fflags nobuffer -flags low_delay udp://0.0.0.0:{}", port);
+                guides.push(ReceiverGuide::new("ffplay", &["Run this in a terminal on the receiver:"], Some(command)));
+            }
+        }
+    }
+
+    let scheme = if config.listen_tls { "https" } else { "http" };
+    let token = Some(config.listen_token.trim()).filter(|token| !token.is_empty()).map_or_else(String::new, |token| format!("?token={}", token));
+    let tls_note = config.listen_tls.then(|| "Accept the certificate warning if its fingerprint matches the one shown under Extra outputs.".to_string());
+    for output in &config.outputs {
+        match output {
+            Output::WebRtc { port, .. } => {
+                let url = format!("{}://{}:{}/{}", scheme, host(config), port, token);
+                let steps = ["Open the address below in any browser on the same network.", "Tap ▶ Play; browsers only start audio after a tap."];
+                guides.push(ReceiverGuide::new("Browser", &steps, Some(url)).note(tls_note.clone()));
+            }
+            Output::Http { port } => {
+                let url = format!("{}://{}:{}/{}", scheme, host(config), port, token);
+                let steps = ["In VLC, mpv or any player that opens network streams, open the address below."];
+                guides.push(ReceiverGuide::new("HTTP player", &steps, Some(url)).note(tls_note.clone()));
+            }
+            _ => {}
+        }
+    }
+    guides
+}
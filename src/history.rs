@@ -0,0 +1,95 @@
+use crate::{log, paths};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Oldest sessions are dropped beyond this, so the file stays small.
+const MAX_SESSIONS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub started_at: u64, // Unix seconds
+    pub duration_secs: u64,
+    pub target: String,
+    pub codec: String,
+    pub bytes_sent: u64,
+    pub end_reason: Option<String>, // Why it stopped on its own; None when stopped by the user
+}
+
+impl Session {
+    pub fn average_kbps(&self) -> u64 {
+        if self.duration_secs == 0 {
+            return 0;
+        }
+        self.bytes_sent * 8 / 1000 / self.duration_secs
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// "2024-05-01 18:30 UTC"; days-to-date conversion from Howard Hinnant's civil_from_days.
+pub fn format_utc(unix_secs: u64) -> String {
+    let days = (unix_secs / 86400) as i64;
+    let minutes_of_day = unix_secs % 86400 / 60;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes_of_day / 60, minutes_of_day % 60)
+}
+
+// Past streaming sessions, kept next to the config file as JSON.
+pub struct History {
+    path: PathBuf,
+    sessions: Vec<Session>,
+}
+
+impl History {
+    pub fn path_for(config_path: &Path) -> PathBuf {
+        paths::state_dir(config_path).join("history.json")
+    }
+
+    // A missing or unreadable file just means an empty history.
+    pub fn load(path: PathBuf) -> Self {
+        let sessions = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log!("Ignoring unreadable history file {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self { path, sessions }
+    }
+
+    pub fn sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+
+    pub fn record(&mut self, session: Session) -> Result<()> {
+        self.sessions.push(session);
+        let excess = self.sessions.len().saturating_sub(MAX_SESSIONS);
+        self.sessions.drain(..excess);
+        self.save()
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.sessions.clear();
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(&self.sessions)?)?;
+        Ok(())
+    }
+}
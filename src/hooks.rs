@@ -0,0 +1,123 @@
+use crate::{audio::AudioSource, config::Config, history::Session, log};
+use serde::{Deserialize, Serialize};
+use std::{process::Stdio, time::Duration};
+use tokio::{process::Command, runtime::Handle, task::JoinHandle, time::timeout};
+
+// Enough to switch on a smart plug or an amplifier before the first note, without
+// holding the window up for long; the stream starts anyway after this.
+const PRE_START_WAIT: Duration = Duration::from_secs(3);
+
+// Shell commands run on stream events, e.g. `curl` to a smart plug or `makoctl mode -a dnd`.
+// Each is run with `sh -c`, with the `AUDIO_STREAMER_*` variables from `environment`.
+// Empty for none.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    pub pre_start: String, // Waited for, up to a few seconds
+    pub post_start: String,
+    pub on_stop: String,  // Whenever a stream ends, after `on_error` if it failed
+    pub on_error: String, // When it stopped on its own or failed to start
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    PreStart,
+    PostStart,
+    Stop,
+    Error,
+}
+
+impl HookEvent {
+    // AUDIO_STREAMER_EVENT
+    fn name(self) -> &'static str {
+        match self {
+            HookEvent::PreStart => "pre-start",
+            HookEvent::PostStart => "post-start",
+            HookEvent::Stop => "stop",
+            HookEvent::Error => "error",
+        }
+    }
+
+    fn command(self, hooks: &Hooks) -> &str {
+        match self {
+            HookEvent::PreStart => &hooks.pre_start,
+            HookEvent::PostStart => &hooks.post_start,
+            HookEvent::Stop => &hooks.on_stop,
+            HookEvent::Error => &hooks.on_error,
+        }
+    }
+}
+
+// What a hook is told about the stream. `session` adds how long it ran and what it
+// sent, for `Stop` and `Error`, and `error` why it ended.
+pub fn environment(id: u64, source: &AudioSource, config: &Config, session: Option<&Session>, error: Option<&str>) -> Vec<(&'static str, String)> {
+    let target = match &config.bluetooth_sink {
+        Some(address) => address.clone(),
+        None => format!("{}:{}", config.target_ip, config.target_port),
+    };
+    let mut environment = vec![
+        ("AUDIO_STREAMER_STREAM_ID", id.to_string()),
+        ("AUDIO_STREAMER_SOURCE", source.name.clone()),
+        ("AUDIO_STREAMER_SOURCE_DESCRIPTION", source.description.clone()),
+        ("AUDIO_STREAMER_TARGET", target),
+        ("AUDIO_STREAMER_TRANSPORT", format!("{:?}", config.transport).to_lowercase()),
+        ("AUDIO_STREAMER_CODEC", config.audio_codec.clone()),
+        ("AUDIO_STREAMER_BITRATE", config.bitrate.clone()),
+    ];
+    if let Some(session) = session {
+        environment.push(("AUDIO_STREAMER_STARTED_AT", session.started_at.to_string()));
+        environment.push(("AUDIO_STREAMER_DURATION_SECS", session.duration_secs.to_string()));
+        environment.push(("AUDIO_STREAMER_BYTES_SENT", session.bytes_sent.to_string()));
+    }
+    if let Some(error) = error {
+        environment.push(("AUDIO_STREAMER_ERROR", error.to_string()));
+    }
+    environment
+}
+
+// Runs the hook for `event`, if one is set, on `runtime_handle`. The handle finishes
+// with the hook, or for `PreStart`, which may have to get something ready, after at
+// most `PRE_START_WAIT`. Failures are only logged: a broken hook shouldn't keep anyone
+// from streaming.
+pub fn run(hooks: &Hooks, event: HookEvent, environment: Vec<(&'static str, String)>, runtime_handle: &Handle) -> Option<JoinHandle<()>> {
+    let command = event.command(hooks).trim();
+    if command.is_empty() {
+        return None;
+    }
+    let spawned = {
+        // Spawning registers the child with the runtime's process driver.
+        let _runtime = runtime_handle.enter();
+        Command::new("sh")
+            .args(["-c", command])
+            .env("AUDIO_STREAMER_EVENT", event.name())
+            .envs(environment)
+            .stdin(Stdio::null())
+            .spawn()
+    };
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            log!("Could not run the {} hook: {}", event.name(), e);
+            return None;
+        }
+    };
+    let name = event.name();
+    Some(runtime_handle.spawn(async move {
+        let waited = match event {
+            HookEvent::PreStart => timeout(PRE_START_WAIT, child.wait()).await,
+            _ => Ok(child.wait().await),
+        };
+        match waited {
+            Ok(Ok(status)) if !status.success() => log!("The {} hook failed: {}", name, status),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => log!("The {} hook failed: {}", name, e),
+            Err(_) => {
+                log!("The pre-start hook is still running after {} s; starting anyway", PRE_START_WAIT.as_secs());
+                // Reaped in the background so it doesn't linger as a zombie.
+                tokio::spawn(async move {
+                    let _ = child.wait().await;
+                });
+            }
+        }
+    }))
+}
@@ -0,0 +1,53 @@
+use crate::config::Config;
+use anyhow::{Context, Result, bail};
+use std::{path::Path, process::Stdio};
+use tokio::process::{Child, Command};
+
+// Internet radio players want a plain audio stream rather than MPEG-TS, so the audio
+// is remuxed into the container that goes with the codec. Anything else (e.g. PCM)
+// is re-encoded to AAC at the configured bitrate.
+fn container(codec: &str) -> Option<(&'static str, &'static str)> {
+    match codec {
+        "aac" | "libfdk_aac" => Some(("adts", "audio/aac")),
+        "libmp3lame" | "mp3" => Some(("mp3", "audio/mpeg")),
+        "libopus" | "libvorbis" | "flac" => Some(("ogg", "audio/ogg")),
+        _ => None,
+    }
+}
+
+// "radio.example.org:8000" and "/live" become icecast://source@radio.example.org:8000/live.
+fn source_url(server: &str, mount: &str) -> Result<String> {
+    let server = server.trim().trim_start_matches("http://").trim_end_matches('/');
+    if server.is_empty() {
+        bail!("Icecast output needs a server address");
+    }
+    let port = if server.contains(':') { "" } else { ":8000" }; // Icecast's default
+    Ok(format!("icecast://source@{}{}/{}", server, port, mount.trim().trim_start_matches('/')))
+}
+
+// Connects as a source client through ffmpeg's icecast protocol; the process reads
+// the MPEG-TS on stdin and ends the mount when it exits.
+pub fn start_source(ffmpeg: &Path, config: &Config, server: &str, mount: &str, password: &str, name: &str, description: &str) -> Result<Child> {
+    let url = source_url(server, mount)?;
+    let mut command = Command::new(ffmpeg);
+    command.args(["-loglevel", "error", "-fflags", "nobuffer", "-f", "mpegts", "-i", "pipe:0"]);
+    let content_type = match container(&config.audio_codec) {
+        Some((format, content_type)) => {
+            command.args(["-c:a", "copy", "-f", format]);
+            content_type
+        }
+        None => {
+            command.args(["-c:a", "aac", "-b:a", config.bitrate.as_str(), "-f", "adts"]);
+            "audio/aac"
+        }
+    };
+    command
+        .args(["-content_type", content_type, "-password", password])
+        .args(["-ice_name", name, "-ice_description", description])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to start the Icecast source client")
+}
@@ -0,0 +1,121 @@
+use crate::events::Event;
+use anyhow::{Context, Result};
+use std::sync::mpsc::Sender;
+use zbus::{Connection, connection, interface, object_server::SignalContext};
+
+const PATH: &str = "/StatusNotifierItem";
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+// From the freedesktop icon theme, so every desktop has them.
+const LIVE_ICON: &str = "media-record";
+const IDLE_ICON: &str = "audio-card";
+
+// Width, height and ARGB32 data; the icon comes from the theme instead.
+type Pixmap = (i32, i32, Vec<u8>);
+
+// The StatusNotifierItem itself. Hosts show Passive items only when asked to, so the
+// icon is there while streaming.
+struct Item {
+    streaming: Option<String>, // What goes where, for the tooltip
+    events_tx: Sender<Event>,
+}
+
+#[interface(name = "org.kde.StatusNotifierItem")]
+impl Item {
+    #[zbus(property)]
+    fn category(&self) -> &str {
+        "ApplicationStatus"
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> &str {
+        "audio-streamer"
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> &str {
+        "Audio Streamer"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        if self.streaming.is_some() { "Active" } else { "Passive" }
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> &str {
+        if self.streaming.is_some() { LIVE_ICON } else { IDLE_ICON }
+    }
+
+    // Icon name, pixmaps (none), title and text.
+    #[zbus(property)]
+    fn tool_tip(&self) -> (String, Vec<Pixmap>, String, String) {
+        let text = self.streaming.clone().unwrap_or_else(|| "Not streaming".to_string());
+        (self.icon_name().to_string(), Vec::new(), "Audio Streamer".to_string(), text)
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        false
+    }
+
+    // A click brings the window forward.
+    fn activate(&self, _x: i32, _y: i32) {
+        let _ = self.events_tx.send(Event::IndicatorActivated);
+    }
+
+    fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    fn context_menu(&self, _x: i32, _y: i32) {}
+
+    fn scroll(&self, _delta: i32, _orientation: &str) {}
+
+    #[zbus(signal)]
+    async fn new_status(context: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_icon(context: &SignalContext<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_tool_tip(context: &SignalContext<'_>) -> zbus::Result<()>;
+}
+
+// A tray icon that says the stream is live, through the StatusNotifier spec that KDE,
+// most other panels and GNOME's AppIndicator extension host.
+#[derive(Debug, Clone)]
+pub struct Indicator {
+    connection: Connection,
+}
+
+impl Indicator {
+    // Fails without a session bus or a tray to show it in.
+    pub async fn start(events_tx: Sender<Event>) -> Result<Self> {
+        let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+        let connection = connection::Builder::session()?
+            .name(name.as_str())?
+            .serve_at(PATH, Item { streaming: None, events_tx })?
+            .build()
+            .await
+            .context("No session bus")?;
+        connection
+            .call_method(Some(WATCHER), "/StatusNotifierWatcher", Some(WATCHER), "RegisterStatusNotifierItem", &(name.as_str(),))
+            .await
+            .context("No tray to show the streaming indicator in")?;
+        Ok(Self { connection })
+    }
+
+    // `streaming` is what the tooltip says, e.g. "Monitor of Speakers → 10.0.0.5:1234";
+    // None when nothing streams.
+    pub async fn set(&self, streaming: Option<String>) -> Result<()> {
+        let item = self.connection.object_server().interface::<_, Item>(PATH).await?;
+        let status = {
+            let mut item = item.get_mut().await;
+            item.streaming = streaming;
+            item.status().to_string()
+        };
+        let context = item.signal_context();
+        Item::new_status(context, &status).await?;
+        Item::new_icon(context).await?;
+        Item::new_tool_tip(context).await?;
+        Ok(())
+    }
+}
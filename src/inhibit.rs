@@ -0,0 +1,36 @@
+use crate::supervisor::Supervisor;
+use anyhow::Result;
+use std::process::Stdio;
+use tokio::{process::Command, runtime::Handle};
+
+// Keeps the machine awake while streaming, through a systemd-logind inhibitor lock.
+// `systemd-inhibit` holds the lock for as long as its child runs; the child is a
+// `cat` on a pipe we own, so even if systemd-inhibit is killed first, `cat` sees
+// EOF and exits rather than keeping the lock.
+pub struct SleepInhibitor {
+    process: Supervisor,
+}
+
+impl SleepInhibitor {
+    pub fn acquire(runtime_handle: &Handle) -> Result<Self> {
+        let process = Supervisor::spawn(
+            "systemd-inhibit",
+            Command::new("systemd-inhibit")
+                .args(["--what=sleep:idle", "--who=audio-streamer", "--why=Streaming audio", "--mode=block", "cat"])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null()),
+            runtime_handle,
+        )?;
+        Ok(Self { process })
+    }
+
+    // False once systemd-inhibit has exited, e.g. because logind isn't running.
+    pub fn is_active(&self) -> bool {
+        self.process.exit_reason().is_none()
+    }
+
+    pub fn release(self) {
+        self.process.stop();
+    }
+}
@@ -0,0 +1,108 @@
+use crate::events::Event;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, sync::mpsc::Sender, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    runtime::Handle,
+    sync::oneshot,
+    task::JoinHandle,
+    time::timeout,
+};
+
+// The GUI only looks at requests when it repaints, which is at least every few seconds.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What a second `audio-streamer` asks the running one for, as one JSON line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", tag = "command")]
+pub enum Request {
+    Show, // Another GUI launch: bring this one forward instead
+    Stream { source: Option<String>, target: Option<String>, codec: Option<String> },
+    Stop,
+    Status,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reply {
+    pub ok: bool,
+    pub message: String,
+}
+
+// In $XDG_RUNTIME_DIR, which is per user and cleared on logout.
+pub fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("audio-streamer.sock"),
+        None => std::env::temp_dir().join(format!("audio-streamer-{}.sock", std::env::var("USER").unwrap_or_default())),
+    }
+}
+
+// The running instance's reply, or None when there is no running instance.
+pub async fn send(request: &Request) -> Result<Option<Reply>> {
+    let Ok(stream) = UnixStream::connect(socket_path()).await else {
+        return Ok(None);
+    };
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(format!("{}\n", serde_json::to_string(request)?).as_bytes()).await?;
+    let mut line = String::new();
+    timeout(REPLY_TIMEOUT, BufReader::new(reader).read_line(&mut line))
+        .await
+        .context("The running instance did not answer")??;
+    Ok(Some(serde_json::from_str(&line).context("The running instance sent an invalid reply")?))
+}
+
+// Accepts requests for as long as it lives and hands each to the front end as
+// `Event::Remote`, which answers on the enclosed channel.
+pub struct Server {
+    path: PathBuf,
+    accept: JoinHandle<()>,
+}
+
+impl Server {
+    pub fn start(events_tx: Sender<Event>, runtime_handle: &Handle) -> Result<Self> {
+        let path = socket_path();
+        let _runtime = runtime_handle.enter(); // tokio sockets need it
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            // Left behind by an instance that crashed, unless someone still answers on it.
+            Err(_) if std::os::unix::net::UnixStream::connect(&path).is_err() => {
+                fs::remove_file(&path)?;
+                UnixListener::bind(&path).with_context(|| format!("Failed to listen on {}", path.display()))?
+            }
+            Err(_) => bail!("Another instance is listening on {}", path.display()),
+        };
+        let accept = runtime_handle.spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let events_tx = events_tx.clone();
+                tokio::spawn(async move {
+                    let _ = serve(stream, events_tx).await;
+                });
+            }
+        });
+        Ok(Self { path, accept })
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.accept.abort();
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+async fn serve(stream: UnixStream, events_tx: Sender<Event>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    let reply = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            events_tx.send(Event::Remote { request, reply_tx })?;
+            timeout(REPLY_TIMEOUT, reply_rx).await.ok().and_then(Result::ok).unwrap_or(Reply { ok: false, message: "No answer".to_string() })
+        }
+        Err(e) => Reply { ok: false, message: format!("Invalid request: {}", e) },
+    };
+    writer.write_all(format!("{}\n", serde_json::to_string(&reply)?).as_bytes()).await?;
+    Ok(())
+}
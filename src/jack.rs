@@ -0,0 +1,124 @@
+use crate::{audio::AudioSource, log};
+use anyhow::{Context, Result, bail};
+use std::{sync::Arc, time::Duration};
+use tokio::{process::Command, sync::Mutex, time::sleep};
+
+// ffmpeg's JACK input registers a client by the name it is given, with ports input_1,
+// input_2, … for the channels it captures. JACK refuses a second client by the same
+// name, so each stream gets its own, see `client`.
+pub const CLIENT: &str = "audio-streamer";
+// ffmpeg opens its ports soon after starting, but only once the encoder is ready.
+const PORT_ATTEMPTS: usize = 20; // 5 s at 250 ms
+
+// The only source JACK has: our own inputs, which the user patches from there.
+pub fn source() -> AudioSource {
+    AudioSource {
+        name: CLIENT.to_string(),
+        description: format!("JACK inputs ({}-N:input_1, …, one client per stream)", CLIENT),
+        is_monitor: false,
+        is_running: true,
+        is_default: true,
+        card: Some("JACK".to_string()),
+        spec: None,
+    }
+}
+
+// The client stream `id` captures as, e.g. audio-streamer-1; a restart keeps it.
+pub fn client(id: u64) -> String {
+    format!("{}-{}", CLIENT, id)
+}
+
+async fn ports() -> Option<Vec<String>> {
+    let output = Command::new("jack_lsp").output().await.ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+// Patches `ports` (e.g. "Ardour:Master/audio_out 1") into our inputs in turn, once
+// `client` has registered them, wrapping around after the last one. Uses jack_lsp and
+// jack_connect from the JACK tools; without them the ports are patched by hand, e.g.
+// in qjackctl or Carla.
+pub async fn connect(client: String, ports_to_connect: Vec<String>) {
+    let first = format!("{}:input_1", client);
+    let mut inputs = Vec::new();
+    for _ in 0..PORT_ATTEMPTS {
+        let Some(ports) = ports().await else {
+            log!("Could not list JACK ports to connect; is jack_lsp installed?");
+            return;
+        };
+        if ports.contains(&first) {
+            inputs = ports.into_iter().filter(|port| port.starts_with(&format!("{}:input_", client))).collect();
+            break;
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+    if inputs.is_empty() {
+        log!("{} did not show up in JACK; its ports are left unconnected", first);
+        return;
+    }
+    for (port, input) in ports_to_connect.iter().zip(inputs.iter().cycle()) {
+        match Command::new("jack_connect").args([port, input]).status().await {
+            Ok(status) if status.success() => log!("Connected {} to {}", port, input),
+            _ => log!("Could not connect {} to {}", port, input),
+        }
+    }
+}
+
+// What is patched into `client`'s inputs, as (from, to), from `jack_lsp -c`, which
+// lists each port with the ones connected to it indented below.
+async fn connections(client: &str) -> Result<Vec<(String, String)>> {
+    let output = Command::new("jack_lsp").arg("-c").output().await.context("Failed to run jack_lsp")?;
+    if !output.status.success() {
+        bail!("jack_lsp failed; is the JACK server running?");
+    }
+    let prefix = format!("{}:input_", client);
+    let mut connections = Vec::new();
+    let mut port: Option<String> = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        match line.strip_prefix("   ") {
+            Some(from) => {
+                if let Some(to) = &port {
+                    connections.push((from.to_string(), to.clone()));
+                }
+            }
+            None => port = line.starts_with(&prefix).then(|| line.to_string()),
+        }
+    }
+    Ok(connections)
+}
+
+async fn patch(connect: bool, from: &str, to: &str) -> Result<()> {
+    let tool = if connect { "jack_connect" } else { "jack_disconnect" };
+    let status = Command::new(tool).args([from, to]).status().await.with_context(|| format!("Failed to run {}", tool))?;
+    if !status.success() {
+        bail!("'{} {} {}' failed", tool, from, to);
+    }
+    Ok(())
+}
+
+// Muting without PulseAudio's stream controls: the inputs are unpatched, so only
+// silence reaches the encoder, and patched back as they were afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct Mute {
+    cut: Arc<Mutex<Vec<(String, String)>>>, // Unpatched while muted
+}
+
+impl Mute {
+    pub async fn set(&self, client: &str, muted: bool) -> Result<()> {
+        let mut cut = self.cut.lock().await;
+        if muted {
+            for (from, to) in connections(client).await? {
+                patch(false, &from, &to).await?;
+                cut.push((from, to));
+            }
+            return Ok(());
+        }
+        // All of them, even after one fails, e.g. because its client has quit since.
+        let mut result = Ok(());
+        for (from, to) in std::mem::take(&mut *cut) {
+            if let Err(e) = patch(true, &from, &to).await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
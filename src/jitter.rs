@@ -0,0 +1,428 @@
+use crate::{
+    drift::DriftEstimator,
+    presence::Resumption,
+    sync::SyncClock,
+    transport::{Packet, PacketKind, recover_from_parity, seq_lt},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+// Growing on every late packet and shrinking only after a quiet period keeps the
+// buffer deep enough for bursty Wi-Fi without holding latency high forever.
+const GROW_STEP: Duration = Duration::from_millis(10);
+const SHRINK_STEP: Duration = Duration::from_millis(2);
+const QUIET_BEFORE_SHRINK: Duration = Duration::from_secs(10);
+const SHRINK_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_PACKET_DURATION_US: f64 = 100_000.0;
+// Nothing for this long while the sender's sequence moved on is a dropout on our side,
+// e.g. Wi-Fi roaming; shorter gaps are left to the usual loss handling.
+const RESUME_GAP: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatePacketPolicy {
+    // Skip a missing packet as soon as its playout time passes; it's dropped if it shows up later.
+    Drop,
+    // Hold playout for a missing packet up to the maximum depth before giving up on it.
+    Wait,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReceiveStats {
+    pub received: u64,
+    pub recovered: u64,
+    pub lost: u64,
+    pub late: u64,
+    pub drift_drops: u64,
+    pub restarts: u64,
+    pub duplicates: u64, // Second copies, e.g. from a sender sending over two paths
+    pub resumes: u64,
+}
+
+// Re-orders native packets, fills single gaps per FEC group, and releases payloads
+// once they have spent the current buffer depth waiting.
+pub struct JitterBuffer {
+    min_depth: Duration,
+    max_depth: Duration,
+    depth: Duration,
+    policy: LatePacketPolicy,
+    sync_delay: Option<Duration>, // Multi-room: play at sender time + this delay instead of arrival + depth
+    next_seq: Option<u32>,
+    session: Option<u32>, // The sender's, once a packet carried one
+    resumed: Option<Resumption>, // Until `take_resumed`
+    pending: BTreeMap<u32, (Instant, u64, Vec<u8>)>, // (arrival, sender timestamp, payload)
+    released: BTreeMap<u32, Vec<u8>>, // Already played, kept for parity groups still open
+    parity: BTreeMap<u32, (u8, Vec<u8>)>,
+    last_late: Option<Instant>,
+    last_shrink: Option<Instant>,
+    last_arrival: Option<Instant>,
+    mean_interval: f64, // Seconds, smoothed
+    arrival_jitter: f64, // Seconds, smoothed deviation from the mean interval
+    drift: DriftEstimator,
+    drift_dropped_us: f64,
+    packet_duration_us: f64, // Smoothed sender timestamp step per packet
+    last_timestamp: Option<(u32, u64)>,
+    pub stats: ReceiveStats,
+}
+
+impl JitterBuffer {
+    pub fn new(target: Duration, min: Duration, max: Duration, policy: LatePacketPolicy, sync_delay: Option<Duration>) -> Self {
+        let max = max.max(min);
+        Self {
+            min_depth: min,
+            max_depth: max,
+            depth: target.clamp(min, max),
+            policy,
+            sync_delay,
+            next_seq: None,
+            session: None,
+            resumed: None,
+            pending: BTreeMap::new(),
+            released: BTreeMap::new(),
+            parity: BTreeMap::new(),
+            last_late: None,
+            last_shrink: None,
+            last_arrival: None,
+            mean_interval: 0.0,
+            arrival_jitter: 0.0,
+            drift: DriftEstimator::default(),
+            drift_dropped_us: 0.0,
+            packet_duration_us: 0.0,
+            last_timestamp: None,
+            stats: ReceiveStats::default(),
+        }
+    }
+
+    pub fn depth(&self) -> Duration {
+        self.depth
+    }
+
+    pub fn skew_ppm(&self) -> Option<f64> {
+        self.drift.skew_ppm()
+    }
+
+    // Number of packets waiting and how long the oldest of them has been waiting.
+    pub fn occupancy(&self, now: Instant) -> (usize, Duration) {
+        let oldest = self.pending.values().map(|(arrived, _, _)| *arrived).min();
+        (self.pending.len(), oldest.map(|arrived| now - arrived).unwrap_or_default())
+    }
+
+    // The dropout picked up again since the last call, to tell the sender about.
+    pub fn take_resumed(&mut self) -> Option<Resumption> {
+        self.resumed.take()
+    }
+
+    pub fn push(&mut self, packet: Packet, now: Instant) {
+        let next = *self.next_seq.get_or_insert(packet.seq);
+        // A new session means the sender restarted; so does, from senders without one,
+        // a sequence far behind what we expect.
+        let new_session = packet.session != 0 && self.session.is_some_and(|session| session != packet.session);
+        if packet.session != 0 {
+            self.session = Some(packet.session);
+        }
+        if new_session || (seq_lt(packet.seq, next) && next.wrapping_sub(packet.seq) > 1000) {
+            self.pending.clear();
+            self.released.clear();
+            self.parity.clear();
+            self.next_seq = Some(packet.seq);
+            self.drift.reset();
+            self.drift_dropped_us = 0.0;
+            self.last_timestamp = None;
+            self.stats.restarts += 1;
+        }
+        let next = self.next_seq.unwrap_or(packet.seq);
+
+        match packet.kind {
+            PacketKind::Data if self.pending.contains_key(&packet.seq) || self.released.contains_key(&packet.seq) => {
+                self.stats.duplicates += 1;
+            }
+            PacketKind::Data if seq_lt(packet.seq, next) => {
+                self.stats.late += 1;
+                self.depth = (self.depth + GROW_STEP).min(self.max_depth);
+                self.last_late = Some(now);
+            }
+            PacketKind::Data => {
+                // Same session, and the sender kept going while nothing got through: carry on
+                // from here with the decoder and clock sync as they are, instead of counting
+                // every packet missed as lost.
+                if packet.session != 0
+                    && seq_lt(next, packet.seq)
+                    && self.pending.is_empty()
+                    && let Some(last) = self.last_arrival.filter(|last| now - *last >= RESUME_GAP)
+                {
+                    self.resumed = Some(Resumption { gap: now - last, skipped: packet.seq.wrapping_sub(next) });
+                    self.next_seq = Some(packet.seq);
+                    self.last_arrival = None; // The gap says nothing about arrival jitter
+                    self.stats.resumes += 1;
+                }
+                if let Some(last) = self.last_arrival {
+                    let interval = (now - last).as_secs_f64();
+                    self.mean_interval += (interval - self.mean_interval) / 16.0;
+                    self.arrival_jitter += ((interval - self.mean_interval).abs() - self.arrival_jitter) / 16.0;
+                }
+                self.last_arrival = Some(now);
+                self.drift.observe(packet.timestamp_us, now);
+                if let Some((seq, timestamp)) = self.last_timestamp
+                    && seq_lt(seq, packet.seq)
+                    && packet.timestamp_us >= timestamp
+                {
+                    let step = (packet.timestamp_us - timestamp) as f64 / packet.seq.wrapping_sub(seq) as f64;
+                    // A pause shows up as one huge step that says nothing about packet duration.
+                    if step < MAX_PACKET_DURATION_US {
+                        self.packet_duration_us += (step - self.packet_duration_us) / 16.0;
+                    }
+                }
+                self.last_timestamp = Some((packet.seq, packet.timestamp_us));
+                self.stats.received += 1;
+                self.pending.insert(packet.seq, (now, packet.timestamp_us, packet.payload));
+            }
+            PacketKind::Parity => {
+                self.parity.insert(packet.seq, (packet.fec_group, packet.payload));
+            }
+            PacketKind::TimeRequest | PacketKind::TimeReply | PacketKind::Keepalive | PacketKind::Control | PacketKind::Announce | PacketKind::Resumed | PacketKind::Notify => {}
+        }
+    }
+
+    // When a buffered packet is due: on the shared sender clock in sync mode (once it
+    // is known), otherwise after waiting the current depth since it arrived.
+    fn playout_time(&self, arrived: Instant, timestamp_us: u64, clock: Option<&SyncClock>) -> Instant {
+        match (self.sync_delay, clock.and_then(|clock| clock.sender_to_local(timestamp_us))) {
+            (Some(delay), Some(sender_time)) => sender_time + delay,
+            _ => arrived + self.depth,
+        }
+    }
+
+    // Returns the payloads whose playout time has come, in order.
+    pub fn poll(&mut self, now: Instant, clock: Option<&SyncClock>) -> Vec<Vec<u8>> {
+        let mut ready = Vec::new();
+        let Some(mut next) = self.next_seq else {
+            return ready;
+        };
+
+        loop {
+            if let Some((arrived, timestamp, _)) = self.pending.get(&next) {
+                if now < self.playout_time(*arrived, *timestamp, clock) {
+                    break;
+                }
+                if let Some((_, _, payload)) = self.pending.remove(&next) {
+                    self.released.insert(next, payload.clone());
+                    if self.drift_surplus_exceeds_packet() {
+                        // Skipping one packet's worth of audio keeps a fast sender from piling up latency.
+                        self.drift_dropped_us += self.packet_duration_us;
+                        self.stats.drift_drops += 1;
+                    } else {
+                        ready.push(payload);
+                    }
+                }
+            } else if let Some(payload) = self.try_recover(next) {
+                self.released.insert(next, payload.clone());
+                ready.push(payload);
+            } else {
+                // Give up on the gap once the packet after it has waited long enough; the
+                // earliest by offset, as after a wrap key order puts 0 first.
+                let Some((_, (arrived, timestamp, _))) = self.pending.iter().min_by_key(|(seq, _)| seq.wrapping_sub(next)) else {
+                    break;
+                };
+                let give_up_at = match self.policy {
+                    LatePacketPolicy::Drop => self.playout_time(*arrived, *timestamp, clock),
+                    LatePacketPolicy::Wait => *arrived + self.max_depth,
+                };
+                if now < give_up_at {
+                    break;
+                }
+                self.stats.lost += 1;
+            }
+            next = next.wrapping_add(1);
+        }
+
+        self.next_seq = Some(next);
+        // Parity groups whose packets have all been released are no longer useful.
+        self.parity.retain(|start, (group, _)| seq_lt(next, start.wrapping_add(*group as u32)));
+        // Keep enough history for the largest possible group whose parity is still in flight.
+        self.released.retain(|seq, _| next.wrapping_sub(*seq) <= u8::MAX as u32);
+
+        self.maybe_shrink(now);
+        ready
+    }
+
+    // In sync mode the sender clock paces playout, so skipping audio would only put
+    // this room out of step with the others.
+    fn drift_surplus_exceeds_packet(&self) -> bool {
+        self.sync_delay.is_none()
+            && self.packet_duration_us > 0.0
+            && self.drift.surplus_us() - self.drift_dropped_us >= self.packet_duration_us
+    }
+
+    // Never shrinks below a few multiples of the jitter we are actually seeing.
+    fn maybe_shrink(&mut self, now: Instant) {
+        let floor = Duration::from_secs_f64(self.arrival_jitter * 3.0).clamp(self.min_depth, self.max_depth);
+        let quiet = self.last_late.is_none_or(|late| now - late >= QUIET_BEFORE_SHRINK);
+        let due = self.last_shrink.is_none_or(|shrink| now - shrink >= SHRINK_INTERVAL);
+        if quiet && due && self.depth > floor {
+            self.depth = self.depth.saturating_sub(SHRINK_STEP).max(floor);
+            self.last_shrink = Some(now);
+        }
+    }
+
+    fn try_recover(&mut self, seq: u32) -> Option<Vec<u8>> {
+        // By offset rather than key order, for a group that spans the wrap to 0.
+        let (&start, (group, parity)) = self.parity.iter().find(|(start, (group, _))| seq.wrapping_sub(**start) < *group as u32)?;
+        let others: Vec<&[u8]> = (0..*group as u32)
+            .map(|offset| start.wrapping_add(offset))
+            .filter(|s| *s != seq)
+            .filter_map(|s| {
+                self.pending
+                    .get(&s)
+                    .map(|(_, _, payload)| payload)
+                    .or_else(|| self.released.get(&s))
+                    .map(Vec::as_slice)
+            })
+            .collect();
+        if others.len() + 1 != *group as usize {
+            return None;
+        }
+        let payload = recover_from_parity(parity, others.into_iter())?;
+        self.stats.recovered += 1;
+        Some(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::xor_parity;
+
+    const SESSION: u32 = 7;
+
+    fn buffer() -> JitterBuffer {
+        JitterBuffer::new(Duration::from_millis(20), Duration::from_millis(10), Duration::from_millis(100), LatePacketPolicy::Drop, None)
+    }
+
+    fn data(seq: u32) -> Packet {
+        Packet { kind: PacketKind::Data, seq, fec_group: 0, session: SESSION, timestamp_us: 0, payload: seq.to_be_bytes().to_vec() }
+    }
+
+    fn parity(start: u32, group: u8) -> Packet {
+        let payloads: Vec<Vec<u8>> = (0..group as u32).map(|offset| start.wrapping_add(offset).to_be_bytes().to_vec()).collect();
+        let payload = xor_parity(payloads.iter().map(Vec::as_slice));
+        Packet { kind: PacketKind::Parity, seq: start, fec_group: group, session: SESSION, timestamp_us: 0, payload }
+    }
+
+    fn played(buffer: &mut JitterBuffer, at: Instant) -> Vec<u32> {
+        buffer.poll(at, None).into_iter().map(|payload| u32::from_be_bytes(payload.try_into().unwrap())).collect()
+    }
+
+    #[test]
+    fn reorders_packets() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        for seq in [10, 13, 11, 12] {
+            buffer.push(data(seq), now);
+        }
+        assert!(played(&mut buffer, now).is_empty(), "released before the buffer depth");
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [10, 11, 12, 13]);
+        assert_eq!((buffer.stats.received, buffer.stats.lost), (4, 0));
+    }
+
+    #[test]
+    fn reorders_across_the_sequence_wrap() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        for seq in [u32::MAX - 1, 0, u32::MAX, 1] {
+            buffer.push(data(seq), now);
+        }
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [u32::MAX - 1, u32::MAX, 0, 1]);
+        assert_eq!(buffer.stats.late, 0);
+    }
+
+    #[test]
+    fn skips_a_lost_packet_without_parity() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        for seq in [1, 2, 4] {
+            buffer.push(data(seq), now);
+        }
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [1, 2, 4]);
+        assert_eq!(buffer.stats.lost, 1);
+    }
+
+    #[test]
+    fn recovers_one_lost_packet_per_group() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        // Groups of four from 100: 101 and 106 are lost, one in each.
+        for seq in [100, 102, 103, 104, 105, 107] {
+            buffer.push(data(seq), now);
+        }
+        buffer.push(parity(100, 4), now);
+        buffer.push(parity(104, 4), now);
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), (100..108).collect::<Vec<_>>());
+        assert_eq!((buffer.stats.recovered, buffer.stats.lost), (2, 0));
+    }
+
+    #[test]
+    fn recovers_in_a_group_spanning_the_wrap() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        let start = u32::MAX - 1;
+        for seq in [start, u32::MAX, 1] {
+            buffer.push(data(seq), now);
+        }
+        buffer.push(parity(start, 4), now);
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [start, u32::MAX, 0, 1]);
+        assert_eq!(buffer.stats.recovered, 1);
+    }
+
+    #[test]
+    fn two_losses_in_a_group_are_not_recovered() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        for seq in [0, 3] {
+            buffer.push(data(seq), now);
+        }
+        buffer.push(parity(0, 4), now);
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [0, 3]);
+        assert_eq!((buffer.stats.recovered, buffer.stats.lost), (0, 2));
+    }
+
+    #[test]
+    fn counts_duplicates_once() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        buffer.push(data(5), now);
+        buffer.push(data(5), now);
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [5]);
+        // Still a duplicate once played, e.g. over a slower second path.
+        buffer.push(data(5), now + Duration::from_secs(1));
+        assert_eq!((buffer.stats.received, buffer.stats.duplicates), (1, 2));
+    }
+
+    #[test]
+    fn drops_a_late_packet_and_grows() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        buffer.push(data(1), now);
+        buffer.push(data(3), now);
+        let later = now + Duration::from_secs(1);
+        assert_eq!(played(&mut buffer, later), [1, 3]);
+        let depth = buffer.depth();
+        buffer.push(data(2), later);
+        assert!(played(&mut buffer, later + Duration::from_secs(1)).is_empty());
+        assert_eq!(buffer.stats.late, 1);
+        assert!(buffer.depth() > depth);
+    }
+
+    #[test]
+    fn a_late_packet_before_the_wrap_is_late() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        buffer.push(data(u32::MAX), now);
+        buffer.push(data(1), now);
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [u32::MAX, 1]);
+        buffer.push(data(0), now + Duration::from_secs(1));
+        assert_eq!((buffer.stats.late, buffer.stats.restarts), (1, 0));
+    }
+
+    #[test]
+    fn a_new_session_restarts_the_buffer() {
+        let (mut buffer, now) = (buffer(), Instant::now());
+        buffer.push(data(500), now);
+        buffer.push(Packet { session: SESSION + 1, ..data(3) }, now);
+        assert_eq!(buffer.stats.restarts, 1);
+        assert_eq!(played(&mut buffer, now + Duration::from_secs(1)), [3]);
+    }
+}
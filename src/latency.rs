@@ -0,0 +1,137 @@
+use crate::relay::ReceiverWatch;
+use anyhow::Context;
+use std::{
+    collections::VecDeque,
+    net::IpAddr,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    runtime::Handle,
+    task::JoinHandle,
+};
+
+// One ping a second, so this is the last two minutes.
+pub const HISTORY: usize = 120;
+// A round trip this much above the recent median, and at least `SPIKE_MIN` above it, is a spike.
+const SPIKE_FACTOR: f64 = 3.0;
+const SPIKE_MIN: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sample {
+    pub rtt: Option<Duration>, // None when the ping got no answer within a second
+    pub spike: bool,
+    // The receiver's jitter buffer grew, which it does after dropping late packets, or it
+    // stopped reporting back. Only native receivers report, so plain players never show one.
+    pub dropout: bool,
+}
+
+#[derive(Default)]
+struct History {
+    samples: VecDeque<Sample>,
+    error: Option<String>,
+    last_buffer: Option<Duration>,
+    receiver_seen: bool,
+}
+
+impl History {
+    fn median(&self) -> Option<Duration> {
+        let mut rtts: Vec<Duration> = self.samples.iter().filter_map(|sample| sample.rtt).collect();
+        rtts.sort();
+        rtts.get(rtts.len() / 2).copied()
+    }
+
+    fn record(&mut self, rtt: Option<Duration>, receiver: &ReceiverWatch) {
+        let spike = match (rtt, self.median()) {
+            (Some(rtt), Some(median)) => rtt.as_secs_f64() > median.as_secs_f64() * SPIKE_FACTOR && rtt > median + SPIKE_MIN,
+            (None, _) => true, // A lost ping is the worst spike
+            (Some(_), None) => false,
+        };
+        let status = receiver.get();
+        let buffer = status.as_ref().and_then(|status| status.buffer);
+        let grew = matches!((self.last_buffer, buffer), (Some(before), Some(now)) if now > before);
+        let dropout = grew || (self.receiver_seen && status.is_none());
+        if status.is_some() {
+            self.last_buffer = buffer;
+        }
+        self.receiver_seen = status.is_some();
+        if self.samples.len() == HISTORY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample { rtt, spike, dropout });
+    }
+}
+
+// Pings the target once a second while a stream runs, using the system's `ping`, which
+// may open ICMP sockets where we can't. Some phones don't answer ping at all; then every
+// sample is lost and the graph says so.
+pub struct LatencyMonitor {
+    history: Arc<Mutex<History>>,
+    task: JoinHandle<()>,
+}
+
+// "64 bytes from 192.168.1.20: icmp_seq=3 ttl=64 time=4.21 ms", or with -O,
+// "no answer yet for icmp_seq=4".
+fn parse_line(line: &str) -> Option<Option<Duration>> {
+    if line.starts_with("no answer yet") {
+        return Some(None);
+    }
+    let millis: f64 = line.split_once("time=")?.1.split_whitespace().next()?.parse().ok()?;
+    Some(Some(Duration::from_secs_f64(millis / 1000.0)))
+}
+
+impl LatencyMonitor {
+    pub fn start(target: IpAddr, receiver: ReceiverWatch, runtime_handle: &Handle) -> Self {
+        let history = Arc::new(Mutex::new(History::default()));
+        let shared = history.clone();
+        let task = runtime_handle.spawn(async move {
+            let spawned = Command::new("ping")
+                .args(["-n", "-O", "-i", "1", "-W", "1", &target.to_string()])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+                .context("Needs the ping command");
+            let mut child = match spawned {
+                Ok(child) => child,
+                Err(e) => {
+                    shared.lock().unwrap().error = Some(format!("{:#}", e));
+                    return;
+                }
+            };
+            let Some(stdout) = child.stdout.take() else {
+                return;
+            };
+            let mut lines = BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if let Some(rtt) = parse_line(&line) {
+                    shared.lock().unwrap().record(rtt, &receiver);
+                }
+            }
+            // ping exits early when it can't work at all, e.g. without permission for ICMP.
+            let status = child.wait().await.ok().and_then(|status| status.code());
+            shared.lock().unwrap().error = Some(format!("ping stopped (exit code {})", status.map_or_else(|| "none".to_string(), |code| code.to_string())));
+        });
+        Self { history, task }
+    }
+
+    pub fn samples(&self) -> Vec<Sample> {
+        self.history.lock().unwrap().samples.iter().copied().collect()
+    }
+
+    pub fn median(&self) -> Option<Duration> {
+        self.history.lock().unwrap().median()
+    }
+
+    // Why there are no samples, when ping couldn't run.
+    pub fn error(&self) -> Option<String> {
+        self.history.lock().unwrap().error.clone()
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
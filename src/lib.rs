@@ -0,0 +1,84 @@
+// The streaming engine without the egui front end: capture, encode and transport,
+// driven through `streamer::Streamer` (async) or `streams::StreamManager` (polled).
+
+pub mod config;
+pub mod audio;
+pub mod access;
+pub mod alsa;
+pub mod beacon;
+pub mod blocklist;
+pub mod bluetooth;
+pub mod bridge;
+pub mod bundle;
+pub mod browser;
+pub mod compare;
+pub mod crash;
+pub mod diagnose;
+pub mod drift;
+pub mod ducking;
+pub mod events;
+pub mod fallback;
+pub mod ffmpeg;
+pub mod filters;
+pub mod flac;
+pub mod firewall;
+pub mod guide;
+pub mod history;
+pub mod hooks;
+pub mod icecast;
+pub mod indicator;
+pub mod inhibit;
+pub mod ipc;
+pub mod jack;
+pub mod jitter;
+pub mod latency;
+pub mod live;
+pub mod load;
+pub mod loudness;
+pub mod meter;
+pub mod mix;
+pub mod monitor;
+pub mod mqtt;
+pub mod mtu;
+pub mod negotiate;
+pub mod netwatch;
+pub mod network;
+pub mod notify;
+pub mod outputs;
+pub mod pacing;
+pub mod paths;
+pub mod pipeline;
+pub mod power;
+pub mod presence;
+pub mod presets;
+pub mod priority;
+pub mod profiles;
+pub mod qos;
+pub mod receiver;
+pub mod recovery;
+pub mod relay;
+pub mod remote;
+pub mod rendezvous;
+pub mod resume;
+pub mod rollback;
+pub mod rist;
+pub mod rtp;
+pub mod sdp;
+pub mod secrets;
+pub mod selftest;
+pub mod signal;
+pub mod snapcast;
+pub mod streams;
+pub mod streamer;
+pub mod supervisor;
+pub mod sync;
+pub mod tag;
+pub mod template;
+pub mod theme;
+pub mod tls;
+pub mod transport;
+pub mod upnp;
+pub mod validate;
+pub mod vpn;
+pub mod watchdog;
+pub mod xrun;
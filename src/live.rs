@@ -0,0 +1,95 @@
+use crate::config::Config;
+use serde_json::Value;
+
+// Chosen for each stream when it starts, and adjusted on its own card afterwards, so
+// the settings in the main window don't carry over to the streams already running.
+const PER_STREAM: [&str; 9] = [
+    "capture_backend", // Goes with the source
+    "target_ip",
+    "target_port",
+    "backup_target_ip",
+    "backup_target_port",
+    "bluetooth_sink",
+    "volume_percent",
+    "audio_delay_ms",
+    "monitor_delay_ms",
+];
+// Not used by a running stream, or read from the app's settings whenever needed.
+const APP_ONLY: [&str; 20] = [
+    "preferred_source",
+    "theme",
+    "accent_color",
+    "source_overrides",
+    "source_kind_filter",
+    "running_sources_only",
+    "auto_follow_source",
+    "auto_resume",
+    "battery_saver",
+    "resume_on_network_change",
+    "remote_control",
+    "negotiate_codec",
+    "receive_max_bitrate_kbps",
+    "jitter_target_ms",
+    "jitter_min_ms",
+    "jitter_max_ms",
+    "late_packet_policy",
+    "sync_playout_ms",
+    "integrations",
+    "jack_connect", // Patched once when a stream starts
+];
+// What `Stream::apply_live` changes on a running stream. The bitrate and the filters
+// aren't among them: ffmpeg can't change an encoder's bitrate while it runs, and
+// editing a running filter graph needs a build with ZeroMQ, so those restart the
+// stream. The volume and the delays are live too, on each stream's card.
+const LIVE: [&str; 6] = ["duck_source", "duck_threshold_db", "duck_amount_db", "overload_protection", "do_not_stream", "hooks"];
+
+// How the settings differ from what a stream runs with, by field name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Changes {
+    pub live: Vec<String>,    // Applied without interrupting it
+    pub restart: Vec<String>, // Only take effect when it restarts: the encoder, filters and transport are fixed once ffmpeg runs
+}
+
+impl Changes {
+    // "bitrate, channel mode", for telling the user what a restart would change.
+    pub fn describe_restart(&self) -> String {
+        self.restart.iter().map(|field| field.replace('_', " ")).collect::<Vec<_>>().join(", ")
+    }
+}
+
+// `requested` is what the stream was started with from the main window, not what it
+// runs with after the load monitor or its receiver adjusted it; those would otherwise
+// show as changes, and restarting would undo them.
+pub fn changes(requested: &Config, wanted: &Config) -> Changes {
+    let (Ok(Value::Object(running)), Ok(Value::Object(wanted))) = (serde_json::to_value(requested), serde_json::to_value(wanted)) else {
+        return Changes::default();
+    };
+    let mut changes = Changes::default();
+    for (field, value) in wanted {
+        if running.get(field.as_str()) == Some(&value) || PER_STREAM.contains(&field.as_str()) || APP_ONLY.contains(&field.as_str()) {
+            continue;
+        }
+        if LIVE.contains(&field.as_str()) {
+            changes.live.push(field);
+        } else {
+            changes.restart.push(field);
+        }
+    }
+    changes
+}
+
+// The settings a stream restarts with: `wanted`, but still to its own target and
+// at its own volume and delays, from the same backend.
+pub fn merged(running: &Config, wanted: &Config) -> Config {
+    let mut merged = wanted.clone();
+    merged.target_ip = running.target_ip.clone();
+    merged.target_port = running.target_port;
+    merged.capture_backend = running.capture_backend;
+    merged.backup_target_ip = running.backup_target_ip.clone();
+    merged.backup_target_port = running.backup_target_port;
+    merged.bluetooth_sink = running.bluetooth_sink.clone();
+    merged.volume_percent = running.volume_percent;
+    merged.audio_delay_ms = running.audio_delay_ms;
+    merged.monitor_delay_ms = running.monitor_delay_ms;
+    merged
+}
@@ -0,0 +1,141 @@
+use crate::{
+    config::Config,
+    filters::{Resampler, ResamplerQuality},
+    power,
+    xrun::Xruns,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::ChildStderr,
+    runtime::Handle,
+    task::JoinHandle,
+};
+
+// Passed to ffmpeg in front of its other arguments: key=value progress on stderr
+// twice a second, instead of the usual status line.
+pub const PROGRESS_ARGS: [&str; 3] = ["-progress", "pipe:2", "-nostats"];
+// ffmpeg's speed wanders at start-up while the capture buffer fills.
+const WARMUP: Duration = Duration::from_secs(5);
+// Below this, the capture buffer grows and so does the latency; ffmpeg itself
+// hovers just under 1.0× when it keeps up.
+const SLOW_SPEED: f32 = 0.95;
+const OVERLOAD_AFTER: Duration = Duration::from_secs(5);
+// What the stream falls back to: ffmpeg's own AAC encoder at the battery profile's
+// rate and bitrate, which every receiver plays.
+const LIGHT_CODEC: &str = "aac";
+const CLOCK_TICKS_PER_SEC: f64 = 100.0; // USER_HZ, the unit of /proc/<pid>/stat on Linux
+
+#[derive(Debug, Default)]
+struct State {
+    cpu_percent: Option<f32>, // Of one core, so it can go above 100 with threaded encoders
+    speed: Option<f32>,       // Times real time
+    slow_since: Option<Instant>,
+}
+
+// Watches ffmpeg's CPU time and how fast it encodes compared to real time.
+pub struct EncoderLoad {
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+// User plus system time in clock ticks, from /proc/<pid>/stat. The command name in
+// parentheses may have spaces, so the fields are counted after it.
+fn cpu_ticks(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let user: u64 = fields.next()?.parse().ok()?;
+    let system: u64 = fields.next()?.parse().ok()?;
+    Some(user + system)
+}
+
+// What ffmpeg prints when its capture buffer fills up, e.g. "Thread message queue
+// blocking; consider raising the thread_queue_size option", or ALSA's "buffer xrun".
+fn is_overrun(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    line.contains("thread message queue blocking") || line.contains("xrun")
+}
+
+impl EncoderLoad {
+    // ffmpeg's own warnings on stderr count as capture overruns in `xruns`.
+    pub fn start(pid: u32, stderr: ChildStderr, xruns: Arc<Xruns>, runtime_handle: &Handle) -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        // Reads to the end, also so ffmpeg never blocks on a full stderr.
+        let task = runtime_handle.spawn(async move {
+            let started = Instant::now();
+            let mut last = cpu_ticks(pid).map(|ticks| (Instant::now(), ticks));
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if is_overrun(&line) {
+                    xruns.overrun(None);
+                }
+                // "speed=0.998x", or "speed=N/A" before the first frame.
+                if let Some(speed) = line.strip_prefix("speed=") {
+                    let speed = speed.trim().trim_end_matches('x').parse::<f32>().ok();
+                    let mut state = shared.lock().unwrap();
+                    state.speed = speed;
+                    let slow = speed.is_some_and(|speed| speed < SLOW_SPEED) && started.elapsed() >= WARMUP;
+                    state.slow_since = if slow { state.slow_since.or(Some(Instant::now())) } else { None };
+                }
+                // Each report ends with "progress=continue".
+                if line.starts_with("progress=")
+                    && let Some(ticks) = cpu_ticks(pid)
+                {
+                    let now = Instant::now();
+                    if let Some((then, before)) = last {
+                        let wall = now.duration_since(then).as_secs_f64();
+                        if wall > 0.0 {
+                            let cpu = ticks.saturating_sub(before) as f64 / CLOCK_TICKS_PER_SEC / wall * 100.0;
+                            shared.lock().unwrap().cpu_percent = Some(cpu as f32);
+                        }
+                    }
+                    last = Some((now, ticks));
+                }
+            }
+        });
+        Self { state, task }
+    }
+
+    pub fn cpu_percent(&self) -> Option<f32> {
+        self.state.lock().unwrap().cpu_percent
+    }
+
+    pub fn speed(&self) -> Option<f32> {
+        self.state.lock().unwrap().speed
+    }
+
+    // Encoding has stayed slower than real time for a while, so the latency keeps growing.
+    pub fn is_overloaded(&self) -> bool {
+        self.state.lock().unwrap().slow_since.is_some_and(|since| since.elapsed() >= OVERLOAD_AFTER)
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+// For `StreamEvent::Overloaded`, with what the stream switched to, if anything.
+pub fn overload_message(speed: f32, lighter: Option<&str>) -> String {
+    match lighter {
+        Some(lighter) => format!("⚠ ffmpeg was encoding at {:.2}× real time, so the stream switched to {} to keep the latency down", speed, lighter),
+        None => format!("⚠ ffmpeg can't keep up ({:.2}× real time), so the latency grows; close other programs or pick a lighter codec or lower bitrate", speed),
+    }
+}
+
+// The config with less for the encoder to do, or None when it can't get any lighter.
+pub fn lighter(config: &Config) -> Option<Config> {
+    let mut lighter = power::power_saving(config);
+    lighter.audio_codec = LIGHT_CODEC.to_string();
+    lighter.resampler = Resampler::Swr;
+    lighter.resampler_quality = ResamplerQuality::Normal;
+    let unchanged = lighter.audio_codec == config.audio_codec
+        && lighter.bitrate == config.bitrate
+        && lighter.sample_rate == config.sample_rate
+        && lighter.resampler == config.resampler
+        && lighter.resampler_quality == config.resampler_quality;
+    (!unchanged).then_some(lighter)
+}
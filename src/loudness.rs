@@ -0,0 +1,108 @@
+use crate::{config::Config, outputs::{Output, expand_home}};
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::process::Command;
+
+// EBU R128 defaults, apart from the target which is configurable.
+const TRUE_PEAK_DB: f32 = -1.5;
+const LOUDNESS_RANGE: f32 = 11.0;
+
+// On its own this is the live, single-pass mode: loudnorm looks a few seconds ahead
+// and adjusts the gain dynamically, which is all a live stream allows.
+pub fn loudnorm_filter(target_lufs: f32) -> String {
+    format!("loudnorm=I={}:TP={}:LRA={}", target_lufs, TRUE_PEAK_DB, LOUDNESS_RANGE)
+}
+
+// What the first pass measured; the names match loudnorm's options for the second.
+struct Measurement {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+// loudnorm prints its measurement as a JSON object at the end of stderr.
+fn parse_measurement(stderr: &str) -> Option<Measurement> {
+    let json: Value = serde_json::from_str(&stderr[stderr.rfind('{')?..]).ok()?;
+    let field = |name: &str| json[name].as_str().map(str::to_string);
+    Some(Measurement {
+        input_i: field("input_i")?,
+        input_tp: field("input_tp")?,
+        input_lra: field("input_lra")?,
+        input_thresh: field("input_thresh")?,
+        target_offset: field("target_offset")?,
+    })
+}
+
+async fn measure(ffmpeg: &Path, recording: &Path, target_lufs: f32) -> Result<Measurement> {
+    let filter = format!("{}:print_format=json", loudnorm_filter(target_lufs));
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(recording)
+        .args(["-af", filter.as_str(), "-f", "null", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context("Failed to run ffmpeg")?;
+    parse_measurement(&String::from_utf8_lossy(&output.stderr))
+        .with_context(|| format!("ffmpeg could not measure the loudness of {}", recording.display()))
+}
+
+// "stream.ts" becomes "stream.normalized.ts", next to the original.
+fn normalized_path(recording: &Path) -> PathBuf {
+    let stem = recording.file_stem().unwrap_or_default().to_string_lossy();
+    match recording.extension() {
+        Some(extension) => recording.with_file_name(format!("{}.normalized.{}", stem, extension.to_string_lossy())),
+        None => recording.with_file_name(format!("{}.normalized", stem)),
+    }
+}
+
+// Two-pass normalization: measure the whole recording, then apply one linear gain
+// so it lands on the target without the pumping the live filter can cause.
+pub async fn normalize_recording(ffmpeg: &Path, recording: &Path, config: &Config) -> Result<PathBuf> {
+    let measured = measure(ffmpeg, recording, config.target_lufs).await?;
+    let filter = format!(
+        "{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        loudnorm_filter(config.target_lufs),
+        measured.input_i,
+        measured.input_tp,
+        measured.input_lra,
+        measured.input_thresh,
+        measured.target_offset
+    );
+    let destination = normalized_path(recording);
+    // loudnorm resamples to 192 kHz internally, so the rate is set explicitly.
+    let status = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y", "-i"])
+        .arg(recording)
+        .args(["-af", filter.as_str(), "-ar"])
+        .arg(config.sample_rate.to_string())
+        .args(["-c:a", config.audio_codec.as_str()])
+        .args(if config.bitrate.trim().is_empty() { Vec::new() } else { vec!["-b:a", config.bitrate.as_str()] })
+        .arg(&destination)
+        .stdin(Stdio::null())
+        .status()
+        .await
+        .context("Failed to run ffmpeg")?;
+    if !status.success() {
+        bail!("ffmpeg exited with {} while normalizing {}", status, recording.display());
+    }
+    Ok(destination)
+}
+
+// The recordings the configured outputs wrote, for normalizing once a stream ends.
+pub fn recordings(config: &Config) -> Vec<PathBuf> {
+    config
+        .outputs
+        .iter()
+        .filter_map(|output| match output {
+            Output::File { path } => Some(expand_home(path)),
+            _ => None,
+        })
+        .collect()
+}
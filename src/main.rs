@@ -1,19 +1,85 @@
 use anyhow::{Context, Result};
-use clap::{Arg, Command};
+use clap::{Arg, ArgMatches, Command};
+use clap_complete::Shell;
 use eframe::egui;
 use std::{path::PathBuf, fs};
 
-mod config;
-mod audio;
 mod gui;
+mod cli;
+mod tui;
 
-use config::Config;
+use audio_streamer::{bridge, config::Config, crash, fallback, ffmpeg, ipc::{self, Request}, log, paths, pipeline, profiles, netwatch, receiver, rendezvous, streams, upnp, validate::{self, Problem}};
 use gui::AudioStreamerApp;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // clap argument parsing remains the same...
-    let matches = Command::new("Audio Streamer")
+fn source_arg() -> Arg {
+    Arg::new("source")
+        .long("source")
+        .value_name("NAME")
+        .help("PulseAudio source to capture, as in list-sources; defaults to the preferred one")
+}
+
+fn target_args() -> [Arg; 2] {
+    [
+        Arg::new("target")
+            .long("target")
+            .value_name("IP[:PORT]")
+            .help("Send here instead of the configured target"),
+        Arg::new("codec")
+            .long("codec")
+            .value_name("ENCODER")
+            .help("ffmpeg encoder to use instead of the configured one, e.g. libopus"),
+    ]
+}
+
+fn json_arg() -> Arg {
+    Arg::new("json")
+        .long("json")
+        .action(clap::ArgAction::SetTrue)
+        .help("Print machine-readable JSON instead of text")
+}
+
+fn upnp_arg() -> Arg {
+    Arg::new("upnp")
+        .long("upnp")
+        .action(clap::ArgAction::SetTrue)
+        .help("Ask the router (UPnP IGD) to forward the receive port, for senders on the internet")
+}
+
+fn rendezvous_args() -> [Arg; 3] {
+    [
+        Arg::new("rendezvous")
+            .long("rendezvous")
+            .value_name("HOST[:PORT]")
+            .requires("room")
+            .requires("room-secret")
+            .help("Meet the sender at this rendezvous helper and punch through NAT to it, for senders behind another router"),
+        Arg::new("room")
+            .long("room")
+            .value_name("NAME")
+            .requires("rendezvous")
+            .help("Room name the sender also uses at the rendezvous helper"),
+        Arg::new("room-secret")
+            .long("room-secret")
+            .value_name("SECRET")
+            .requires("rendezvous")
+            .help("The room's secret, as set on the sender"),
+    ]
+}
+
+fn relay_args() -> [Arg; 2] {
+    [
+        Arg::new("relay")
+            .long("relay")
+            .value_name("HOST[:PORT]")
+            .conflicts_with("rendezvous")
+            .requires("relay-key")
+            .help("Pull the stream from an `audio-streamer relay` server the sender streams to"),
+        Arg::new("relay-key").long("relay-key").value_name("KEY").requires("relay").help("The key the relay server printed when it started"),
+    ]
+}
+
+fn command() -> Command {
+    Command::new("audio-streamer")
         .version("0.1.0")
         .about("Stream system audio to phone via UDP")
         .arg(
@@ -21,17 +87,227 @@ async fn main() -> Result<()> {
                 .short('c')
                 .long("config")
                 .value_name("FILE")
+                .global(true)
                 .help("Use custom config file")
         )
-        .get_matches();
+        .arg(
+            Arg::new("portable")
+                .long("portable")
+                .action(clap::ArgAction::SetTrue)
+                .global(true)
+                .help("Keep config, state and cache in audio-streamer-data next to the executable, e.g. on a USB stick")
+        )
+        .arg(
+            Arg::new("profile")
+                .short('p')
+                .long("profile")
+                .value_name("NAME")
+                .global(true)
+                .help("Use a saved profile instead of the config file, see `profiles`")
+        )
+        .arg(
+            Arg::new("receive")
+                .long("receive")
+                .value_name("PORT")
+                .value_parser(clap::value_parser!(u16))
+                .help("Run as a receiver for the native transport instead of opening the GUI")
+        )
+        .arg(upnp_arg().requires("receive"))
+        .args(rendezvous_args().map(|arg| arg.requires("receive")))
+        .args(relay_args().map(|arg| arg.requires("receive")))
+        .arg(
+            Arg::new("tui")
+                .long("tui")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("receive")
+                .help("Use the terminal interface instead of the GUI, e.g. over SSH")
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(clap::ArgAction::SetTrue)
+                .help("Print the pipeline that streaming would start, without starting it")
+        )
+        .subcommand(
+            Command::new("stream")
+                .about("Stream without a UI until Ctrl+C")
+                .arg(source_arg())
+                .args(target_args())
+        )
+        .subcommand(Command::new("stop").about("Stop streaming in the running instance"))
+        .subcommand(Command::new("list-sources").about("List the audio sources that can be streamed").arg(json_arg()))
+        .subcommand(Command::new("status").about("Show the target, source and engine streaming would use, and the last session").arg(json_arg()))
+        .subcommand(
+            Command::new("test")
+                .about("Run the self test: stream to a receiver on this machine and check the packets")
+                .arg(source_arg())
+                .args(target_args())
+                .arg(json_arg())
+        )
+        .subcommand(
+            Command::new("diagnose")
+                .about("Look for the usual network reasons a stream doesn't arrive at the target")
+                .args(target_args().into_iter().take(1))
+                .arg(json_arg())
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Encode one short clip of the source with two codec settings, report how they differ and play them back")
+                .arg(source_arg())
+                .arg(Arg::new("a").long("a").value_name("CODEC[:BITRATE]").help("The first setting, e.g. aac:192k; the configured codec and bitrate when left out"))
+                .arg(Arg::new("b").long("b").value_name("CODEC[:BITRATE]").required(true).help("The second setting, e.g. libopus:96k"))
+                .arg(Arg::new("seconds").long("seconds").value_name("SECS").default_value("5").value_parser(clap::value_parser!(u32).range(1..=60)))
+                .arg(Arg::new("play").long("play").action(clap::ArgAction::SetTrue).help("Play A, then B, on this machine"))
+                .arg(json_arg())
+        )
+        .subcommand(
+            Command::new("receive")
+                .about("Run as a receiver for the native transport")
+                .arg(Arg::new("port").value_name("PORT").required(true).value_parser(clap::value_parser!(u16)))
+                .arg(upnp_arg())
+                .args(rendezvous_args())
+                .args(relay_args())
+        )
+        .subcommand(
+            Command::new("rendezvous")
+                .about("Run a rendezvous helper on a server with a public IP, which pairs senders and receivers behind NAT")
+                .arg(
+                    Arg::new("listen")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .default_value("0.0.0.0:3478")
+                        .value_parser(clap::value_parser!(std::net::SocketAddr))
+                        .help("UDP address to listen on")
+                )
+        )
+        .subcommand(
+            Command::new("relay")
+                .about("Run a relay server on a machine both sides can reach, which takes the sender's stream and re-serves it to receivers over UDP and HTTP")
+                .arg(
+                    Arg::new("listen")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .default_value("0.0.0.0:9000")
+                        .value_parser(clap::value_parser!(std::net::SocketAddr))
+                        .help("UDP and TCP address to listen on")
+                )
+                .arg(Arg::new("key").long("key").value_name("KEY").help("What senders and receivers must give; a random one is made and printed without it"))
+        )
+        .subcommand(
+            Command::new("profiles")
+                .about("List, save, switch to or delete named configs")
+                .subcommand(Command::new("list").about("List the saved profiles"))
+                .subcommand(Command::new("save").about("Save the current settings as a profile").arg(Arg::new("name").required(true)))
+                .subcommand(Command::new("use").about("Make a profile the config the GUI opens with").arg(Arg::new("name").required(true)))
+                .subcommand(Command::new("delete").about("Delete a profile").arg(Arg::new("name").required(true)))
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script, e.g. `audio-streamer completions bash > /etc/bash_completion.d/audio-streamer`")
+                .arg(Arg::new("shell").required(true).value_parser(clap::value_parser!(Shell)))
+        )
+}
+
+// `matches` has --upnp, --rendezvous and --relay with its key, from the top level or the `receive` subcommand.
+async fn receive(port: u16, matches: &ArgMatches, config: &Config) -> Result<()> {
+    let rendezvous = match (matches.get_one::<String>("rendezvous"), matches.get_one::<String>("room"), matches.get_one::<String>("room-secret")) {
+        (Some(server), Some(room), Some(secret)) => Some((rendezvous::resolve(server).await?, rendezvous::Room::new(room, secret)?)),
+        _ => None,
+    };
+    let relay = match (matches.get_one::<String>("relay"), matches.get_one::<String>("relay-key")) {
+        (Some(server), Some(key)) => {
+            bridge::check_key(key)?;
+            Some((bridge::resolve(server).await?, key.clone()))
+        }
+        _ => None,
+    };
+    if matches.get_flag("upnp") {
+        return upnp::with_port_forwarded(port, receiver::run_receiver(port, config, rendezvous, relay)).await;
+    }
+    receiver::run_receiver(port, config, rendezvous, relay).await
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let matches = command().get_matches();
+
+    // Before the config is loaded, so these don't create one.
+    if let Some(("completions", sub)) = matches.subcommand() {
+        let shell = *sub.get_one::<Shell>("shell").expect("required");
+        clap_complete::generate(shell, &mut command(), "audio-streamer", &mut std::io::stdout());
+        return Ok(());
+    }
+    if let Some(("rendezvous", sub)) = matches.subcommand() {
+        return rendezvous::run_server(*sub.get_one::<std::net::SocketAddr>("listen").expect("has a default")).await;
+    }
+    if let Some(("relay", sub)) = matches.subcommand() {
+        return bridge::run_server(*sub.get_one::<std::net::SocketAddr>("listen").expect("has a default"), sub.get_one::<String>("key").cloned()).await;
+    }
 
+    if matches.get_flag("portable") {
+        paths::set_portable()?;
+    }
     let config_path = if let Some(config_file) = matches.get_one::<String>("config") {
         PathBuf::from(config_file)
     } else {
-        get_default_config_path()?
+        paths::default_config_path()?
+    };
+    paths::prepare(&config_path);
+    // The GUI offers the report on its next start.
+    crash::install(config_path.clone());
+
+    let (config, problems) = match matches.get_one::<String>("profile") {
+        Some(name) => {
+            let config = profiles::load(&config_path, name)?;
+            let problems = validate::check(&config);
+            (config, problems)
+        }
+        None => load_or_create_config(&config_path).await?,
     };
+    // The GUI lists them and offers to reset them; everything else just warns.
+    for problem in &problems {
+        log!("⚠ Config {}", problem.describe());
+    }
+
+    match matches.subcommand() {
+        Some(("stream", sub)) => return cli::stream(config, &config_path, sub).await,
+        Some(("stop", _)) => {
+            if !cli::forward(&Request::Stop).await? {
+                anyhow::bail!("Audio Streamer is not running");
+            }
+            return Ok(());
+        }
+        Some(("list-sources", sub)) => return cli::list_sources(&config, sub).await,
+        Some(("status", sub)) => return cli::status(&config, &config_path, sub).await,
+        Some(("test", sub)) => return cli::self_test(config, sub).await,
+        Some(("diagnose", sub)) => return cli::diagnose(config, sub).await,
+        Some(("compare", sub)) => return cli::compare(&config, sub).await,
+        Some(("receive", sub)) => return receive(*sub.get_one::<u16>("port").expect("required"), sub, &config).await,
+        Some(("profiles", sub)) => return cli::profiles(&config_path, &config, sub),
+        _ => {}
+    }
+
+    if let Some(port) = matches.get_one::<u16>("receive") {
+        return receive(*port, &matches, &config).await;
+    }
+
+    if matches.get_flag("dry-run") {
+        return print_dry_run(&config).await;
+    }
+
+    if matches.get_flag("tui") {
+        let options = streams::EngineOptions::detect(&config).await;
+        if let Ok(ip) = config.target_ip.parse() {
+            netwatch::resolve(ip).await;
+        }
+        return tui::run(config, config_path, options, tokio::runtime::Handle::current());
+    }
 
-    let config = load_or_create_config(&config_path).await?;
+    // One window is enough; a second launch brings the first one forward.
+    if let Some(reply) = ipc::send(&Request::Show).await? {
+        println!("{}", reply.message);
+        return Ok(());
+    }
 
     // --- KEY CHANGE: Set up a transparent, borderless window ---
     let options = eframe::NativeOptions {
@@ -51,7 +327,7 @@ async fn main() -> Result<()> {
         options,
         // Pass the creation context to the app so we can apply styles
         Box::new(move |cc| {
-            let app = AudioStreamerApp::new(config, config_path, rt, cc);
+            let app = AudioStreamerApp::new(config, problems, config_path, rt, cc);
             Box::new(app)
         }),
     ).map_err(|e| anyhow::anyhow!("Failed to run GUI: {}", e))?;
@@ -59,24 +335,35 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn get_default_config_path() -> Result<PathBuf> {
-    let config_dir = dirs::config_dir()
-        .context("Could not find config directory")?
-        .join("audio-streamer");
-    
-    std::fs::create_dir_all(&config_dir)?;
-    Ok(config_dir.join("config.json"))
+async fn print_dry_run(config: &Config) -> Result<()> {
+    let source = cli::resolve_source(config, None).await?;
+    if let Ok(ip) = config.target_ip.parse() {
+        netwatch::resolve(ip).await;
+    }
+
+    let (engine, ffmpeg_path) = match ffmpeg::check_ffmpeg(config).await {
+        Ok(info) => (fallback::Engine::Ffmpeg, Some(info.path)),
+        Err(e) => {
+            log!("ffmpeg unavailable ({:#}), using the built-in engine", e);
+            (fallback::Engine::BuiltIn, None)
+        }
+    };
+
+    println!("# engine: {}", engine.label());
+    for line in pipeline::describe_pipeline(config, &source, engine, ffmpeg_path.as_deref())? {
+        println!("{}", line);
+    }
+    Ok(())
 }
 
-async fn load_or_create_config(path: &PathBuf) -> Result<Config> {
+async fn load_or_create_config(path: &PathBuf) -> Result<(Config, Vec<Problem>)> {
     if path.exists() {
         let content = fs::read_to_string(path)?;
-        let config: Config = serde_json::from_str(&content)?;
-        Ok(config)
+        validate::parse(&content).with_context(|| format!("Could not load {}", path.display()))
     } else {
         let config = Config::default();
-        let json = serde_json::to_string_pretty(&config)?;
+        let json = config.to_json()?;
         fs::write(path, json)?;
-        Ok(config)
+        Ok((config, Vec::new()))
     }
 }
\ No newline at end of file
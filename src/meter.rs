@@ -0,0 +1,166 @@
+use crate::{audio::set_capture_volume, log, supervisor::Supervisor};
+use anyhow::{Context, Result};
+use std::{
+    collections::VecDeque,
+    process::Stdio,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
+};
+use tokio::{io::AsyncReadExt, process::Command, runtime::Handle};
+
+// 8 kHz is plenty to see the level, and costs next to nothing. Both channels, so a
+// clip on one side isn't averaged away, and as float, so overs aren't clamped.
+const METER_RATE: u32 = 8000;
+const METER_CHANNELS: usize = 2;
+const SAMPLE_BYTES: usize = 4;
+// 25 ms of samples per reading.
+const CHUNK_BYTES: usize = (METER_RATE as usize / 40) * METER_CHANNELS * SAMPLE_BYTES;
+// 5 ms per scope column; 600 of them are the last 3 s.
+const COLUMN_BYTES: usize = (METER_RATE as usize / 200) * METER_CHANNELS * SAMPLE_BYTES;
+pub const SCOPE_COLUMNS: usize = 600;
+// Just under full scale, since a clipped 16-bit source tops out at 32767/32768.
+const CLIP_LEVEL: f32 = 0.999;
+const RECENT_COLUMNS: usize = 200; // 1 s, for `is_clipping`
+// Share of the correlation sums kept from one reading to the next, about a quarter second's memory.
+const CORRELATION_DECAY: f32 = 0.9;
+// The sums settle at about 2000 frames' worth; below -60 dBFS there is nothing to correlate.
+const SILENCE_ENERGY: f32 = 2000.0 * 1e-6;
+
+// One column of the scope: the lowest and highest sample in it, -1.0 to 1.0 unless clipping.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Column {
+    pub low: f32,
+    pub high: f32,
+    pub clipped: bool,
+}
+
+// Peak level and waveform of a source, from a separate low-rate `parec` so it works
+// before and alongside streaming and with either engine.
+pub struct LevelMeter {
+    capture: Supervisor,
+    peak: Arc<AtomicU32>, // f32 bits, 0.0 to 1.0
+    scope: Arc<Mutex<VecDeque<Column>>>, // Oldest first
+    clipped: Arc<AtomicU64>, // Samples at or above full scale since it started
+    correlation: Arc<AtomicU32>, // f32 bits, -1.0 to 1.0; NaN during silence
+}
+
+impl LevelMeter {
+    pub fn start(source: &str, runtime_handle: &Handle) -> Result<Self> {
+        let mut capture = Supervisor::spawn(
+            "parec",
+            Command::new("parec")
+                .args([
+                    &format!("--device={}", source),
+                    "--format=float32le",
+                    &format!("--rate={}", METER_RATE),
+                    &format!("--channels={}", METER_CHANNELS),
+                    "--latency-msec=25",
+                    "--raw",
+                ])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null()),
+            runtime_handle,
+        )?;
+        let mut stdout = capture.take_stdout().context("parec has no stdout")?;
+        let peak = Arc::new(AtomicU32::new(0));
+        let scope = Arc::new(Mutex::new(VecDeque::with_capacity(SCOPE_COLUMNS)));
+        let clipped = Arc::new(AtomicU64::new(0));
+        let correlation = Arc::new(AtomicU32::new(f32::NAN.to_bits()));
+        let (level, columns, clips, phase) = (peak.clone(), scope.clone(), clipped.clone(), correlation.clone());
+        // Ends when parec exits and its stdout closes.
+        runtime_handle.spawn(async move {
+            let mut buf = [0u8; CHUNK_BYTES];
+            let (mut left_right, mut left_energy, mut right_energy) = (0.0f32, 0.0f32, 0.0f32);
+            while stdout.read_exact(&mut buf).await.is_ok() {
+                let mut loudest = 0.0f32;
+                let mut columns = columns.lock().unwrap();
+                for column in buf.chunks_exact(COLUMN_BYTES) {
+                    let samples = column.chunks_exact(SAMPLE_BYTES).map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]));
+                    let (mut low, mut high, mut clipped) = (0.0f32, 0.0f32, 0);
+                    for sample in samples {
+                        low = low.min(sample);
+                        high = high.max(sample);
+                        if sample.abs() >= CLIP_LEVEL {
+                            clipped += 1;
+                        }
+                    }
+                    loudest = loudest.max(high).max(-low);
+                    clips.fetch_add(clipped, Ordering::Relaxed);
+                    if columns.len() == SCOPE_COLUMNS {
+                        columns.pop_front();
+                    }
+                    columns.push_back(Column { low, high, clipped: clipped > 0 });
+                }
+                level.store(loudest.min(1.0).to_bits(), Ordering::Relaxed);
+                // Pearson correlation of left and right, over a decaying window.
+                left_right *= CORRELATION_DECAY;
+                left_energy *= CORRELATION_DECAY;
+                right_energy *= CORRELATION_DECAY;
+                for frame in buf.chunks_exact(METER_CHANNELS * SAMPLE_BYTES) {
+                    let left = f32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+                    let right = f32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+                    left_right += left * right;
+                    left_energy += left * left;
+                    right_energy += right * right;
+                }
+                let energy = (left_energy * right_energy).sqrt();
+                let value = if energy < SILENCE_ENERGY { f32::NAN } else { (left_right / energy).clamp(-1.0, 1.0) };
+                phase.store(value.to_bits(), Ordering::Relaxed);
+            }
+        });
+        Ok(Self { capture, peak, scope, clipped, correlation })
+    }
+
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    // -60 dBFS stands in for silence.
+    pub fn peak_dbfs(&self) -> f32 {
+        (20.0 * self.peak().log10()).max(-60.0)
+    }
+
+    // The last 3 s, oldest first.
+    pub fn scope(&self) -> Vec<Column> {
+        self.scope.lock().unwrap().iter().copied().collect()
+    }
+
+    pub fn clipped_samples(&self) -> u64 {
+        self.clipped.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_clipped(&self) {
+        self.clipped.store(0, Ordering::Relaxed);
+    }
+
+    // Clipped within the last second.
+    pub fn is_clipping(&self) -> bool {
+        self.scope.lock().unwrap().iter().rev().take(RECENT_COLUMNS).any(|column| column.clipped)
+    }
+
+    // +1 is the same signal on both sides, 0 unrelated ones, -1 one side inverted, which a
+    // mono phone speaker sums to near silence. None while the source is silent.
+    pub fn correlation(&self) -> Option<f32> {
+        let value = f32::from_bits(self.correlation.load(Ordering::Relaxed));
+        (!value.is_nan()).then_some(value)
+    }
+
+    // Records at the stream's volume, so the meter shows the level the encoder gets,
+    // including clipping from a volume above 100 %.
+    pub fn set_gain(&self, percent: u8, runtime_handle: &Handle) {
+        let Some(pid) = self.capture.pid() else {
+            return;
+        };
+        runtime_handle.spawn(async move {
+            if let Err(e) = set_capture_volume(pid, percent, false).await {
+                log!("Could not set the meter's volume: {:#}", e);
+            }
+        });
+    }
+
+    pub fn stop(self) {
+        self.capture.stop();
+    }
+}
@@ -0,0 +1,119 @@
+use crate::log;
+use anyhow::{Context, Result, bail};
+use std::{path::Path, process::Stdio, sync::Mutex};
+use tokio::{
+    process::{Child, Command},
+    runtime::Handle,
+};
+
+// What OBS and the sound settings list it as, unless the output names it otherwise.
+pub const DEFAULT_NAME: &str = "Audio Streamer Mix";
+const SINK_PREFIX: &str = "audio_streamer_";
+
+// Sinks our streams play into now, by sink name; any other of ours the sound server
+// still has is stale, e.g. left behind by a crash.
+static IN_USE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Sink names can't have spaces, so "Audio Streamer Mix" is audio_streamer_mix.
+pub fn sink_name(name: &str) -> String {
+    let name = if name.trim().is_empty() { DEFAULT_NAME } else { name.trim() };
+    let slug: String = name.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if slug.starts_with(SINK_PREFIX) { slug } else { format!("{}{}", SINK_PREFIX, slug) }
+}
+
+// Streaming the mix's own monitor with the mix output on would play the stream back
+// into itself.
+pub fn feeds_back(source: &str, name: &str) -> bool {
+    source == format!("{}.monitor", sink_name(name))
+}
+
+// A null sink that only our decoder plays into: apps record its monitor, e.g. OBS
+// with an "Audio Output Capture" of it, and hear exactly what is streamed, after
+// every filter and the codec.
+pub struct MixSink {
+    module: String,
+    claim: Claim,
+    runtime: Handle,
+}
+
+// A sink name taken in `IN_USE`, so a second stream into it fails before any pactl
+// runs; given back when dropped.
+pub struct Claim {
+    name: String,
+    sink: String,
+}
+
+impl Claim {
+    pub fn new(name: &str) -> Result<Self> {
+        let name = if name.trim().is_empty() { DEFAULT_NAME } else { name.trim() };
+        let sink = sink_name(name);
+        let mut in_use = IN_USE.lock().unwrap();
+        if in_use.contains(&sink) {
+            bail!("Another stream already plays into the '{}' sink; give this one another name", name);
+        }
+        in_use.push(sink.clone());
+        Ok(Self { name: name.to_string(), sink })
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        IN_USE.lock().unwrap().retain(|sink| *sink != self.sink);
+    }
+}
+
+// The module that made the sink `sink`, from `pactl list short modules`.
+async fn loaded_module(sink: &str) -> Option<String> {
+    let output = Command::new("pactl").args(["list", "short", "modules"]).output().await.ok()?;
+    let argument = format!("sink_name={}", sink);
+    String::from_utf8_lossy(&output.stdout).lines().find_map(|line| {
+        let mut fields = line.split('\t');
+        let (module, kind, arguments) = (fields.next()?, fields.next()?, fields.next().unwrap_or(""));
+        (kind == "module-null-sink" && arguments.split_whitespace().any(|word| word == argument)).then(|| module.to_string())
+    })
+}
+
+impl MixSink {
+    pub async fn create(claim: Claim) -> Result<Self> {
+        let Claim { name, sink } = &claim;
+        if let Some(stale) = loaded_module(sink).await {
+            log!("Replacing the '{}' sink the sound server still had, e.g. after a crash", name);
+            let _ = Command::new("pactl").args(["unload-module", stale.as_str()]).status().await;
+        }
+        let output = Command::new("pactl")
+            .args(["load-module", "module-null-sink"])
+            .arg(format!("sink_name={}", sink))
+            .arg(format!("sink_properties=device.description=\"{}\"", name.replace('"', "")))
+            .output()
+            .await
+            .context("Failed to run pactl")?;
+        if !output.status.success() {
+            bail!("Could not create the '{}' sink: {}", name, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(Self { module: String::from_utf8_lossy(&output.stdout).trim().to_string(), claim, runtime: Handle::current() })
+    }
+
+    // Decodes the MPEG-TS into the sink, like a receiver would.
+    pub fn start_decoder(&self, ffmpeg: &Path) -> Result<Child> {
+        Command::new(ffmpeg)
+            .args(["-loglevel", "error", "-fflags", "nobuffer", "-flags", "low_delay", "-f", "mpegts", "-i", "pipe:0"])
+            .args(["-f", "pulse", "-device", &self.claim.sink, "Audio Streamer"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .kill_on_drop(true) // The output task owns it; aborting the task ends the decoder
+            .spawn()
+            .context("Failed to start the mix decoder")
+    }
+}
+
+// The module belongs to the sound server, so it outlives the stream unless unloaded;
+// the output task holds it, so this runs when the task is aborted, and unloads it on
+// the runtime rather than blocking there. The claim goes with it.
+impl Drop for MixSink {
+    fn drop(&mut self) {
+        let module = std::mem::take(&mut self.module);
+        self.runtime.spawn(async move {
+            let _ = Command::new("pactl").args(["unload-module", module.as_str()]).status().await;
+        });
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{log, outputs::next_chunk};
+use anyhow::{Context, Result};
+use std::{
+    collections::VecDeque,
+    process::Stdio,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    runtime::Handle,
+    sync::broadcast::Receiver,
+    task::JoinHandle,
+    time::sleep_until,
+};
+
+// Tried in order; both read MPEG-TS from stdin and play it without a window.
+const PLAYERS: [(&str, &[&str]); 2] = [
+    ("ffplay", &["-nodisp", "-loglevel", "error", "-fflags", "nobuffer", "-flags", "low_delay", "-f", "mpegts", "-i", "pipe:0"]),
+    ("mpv", &["--no-video", "--really-quiet", "--profile=low-latency", "--cache=no", "--demuxer-lavf-format=mpegts", "-"]),
+];
+
+// Plays the outgoing stream on this machine, decoded from the same MPEG-TS a receiver
+// gets, so the codec and bitrate can be judged without walking over to the phone. The
+// delay holds it back to about when the receiver plays it, after its buffer and the network.
+pub struct Monitor {
+    pub player: &'static str,
+    delay_ms: Arc<AtomicU32>,
+    task: JoinHandle<()>,
+}
+
+impl Monitor {
+    pub fn start(mut chunks: Receiver<Arc<[u8]>>, delay: Duration, runtime_handle: &Handle) -> Result<Self> {
+        let _runtime = runtime_handle.enter(); // tokio spawns need it
+        let (player, mut child) = PLAYERS
+            .iter()
+            .find_map(|(player, args)| {
+                let child = Command::new(player).args(*args).stdin(Stdio::piped()).stdout(Stdio::null()).kill_on_drop(true).spawn().ok()?;
+                Some((*player, child))
+            })
+            .context("Monitoring needs ffplay or mpv")?;
+        let mut input = child.stdin.take().context("The player has no stdin")?;
+        let delay_ms = Arc::new(AtomicU32::new(delay.as_millis() as u32));
+        let shared = delay_ms.clone();
+        let task = runtime_handle.spawn(async move {
+            let _child = child; // Killed when the task ends
+            let mut queue: VecDeque<(Instant, Arc<[u8]>)> = VecDeque::new(); // Chunks with their arrival time
+            loop {
+                let delay = Duration::from_millis(shared.load(Ordering::Relaxed) as u64);
+                let next_due = queue.front().map(|(arrived, _)| *arrived + delay);
+                tokio::select! {
+                    chunk = next_chunk(&mut chunks) => {
+                        let Some(chunk) = chunk else {
+                            return;
+                        };
+                        queue.push_back((Instant::now(), chunk));
+                    }
+                    _ = sleep_until(next_due.unwrap_or_else(Instant::now).into()), if next_due.is_some() => {
+                        let now = Instant::now();
+                        while let Some((arrived, _)) = queue.front()
+                            && *arrived + delay <= now
+                        {
+                            let (_, chunk) = queue.pop_front().expect("front was just checked");
+                            if input.write_all(&chunk).await.is_err() {
+                                log!("Monitor stopped: {} exited", player);
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Self { player, delay_ms, task })
+    }
+
+    // Applies to what is already queued, so lowering it skips ahead.
+    pub fn set_delay(&self, delay: Duration) {
+        self.delay_ms.store(delay.as_millis() as u32, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
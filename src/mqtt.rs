@@ -0,0 +1,507 @@
+use crate::{
+    events::Event,
+    ipc::{Reply, Request},
+    log,
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, tcp::OwnedWriteHalf},
+    runtime::Handle,
+    sync::{oneshot, watch},
+    time::{interval, sleep, timeout},
+};
+
+const DEFAULT_PORT: u16 = 1883;
+const KEEP_ALIVE_SECS: u16 = 60;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Between reconnects while the broker is away.
+const RETRY: Duration = Duration::from_secs(10);
+// How often the bitrate is sent while streaming, and a ping goes out otherwise.
+const REFRESH: Duration = Duration::from_secs(15);
+
+// Packet types, in the high nibble of the first byte.
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82; // With the reserved bits the spec asks for
+const PINGREQ: u8 = 0xc0;
+const DISCONNECT: u8 = 0xe0;
+
+// Home Assistant over MQTT: a switch that starts and stops streaming, and sensors for
+// the bitrate and the listeners, announced through its discovery topics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttSettings {
+    pub enabled: bool,
+    pub broker: String, // host or host:port, 1883 by default
+    pub username: String, // Empty for an anonymous broker
+    pub password: String,
+    pub discovery_prefix: String, // Home Assistant's, "homeassistant" unless changed there
+    pub node_id: String, // Tells machines apart in Home Assistant; empty for the host name
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            broker: String::new(),
+            username: String::new(),
+            password: String::new(),
+            discovery_prefix: "homeassistant".to_string(),
+            node_id: String::new(),
+        }
+    }
+}
+
+impl MqttSettings {
+    // Discovery only takes letters, digits, '_' and '-' in an ID.
+    fn node(&self) -> String {
+        let name = match self.node_id.trim() {
+            "" => std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default(),
+            id => id.to_string(),
+        };
+        let node: String = name.trim().chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+        if node.is_empty() { "audio_streamer".to_string() } else { node }
+    }
+
+    fn address(&self) -> String {
+        let broker = self.broker.trim();
+        if broker.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+            broker.to_string()
+        } else {
+            format!("{}:{}", broker, DEFAULT_PORT)
+        }
+    }
+}
+
+// What Home Assistant is shown; the front end keeps it current with `Mqtt::update`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct State {
+    pub streaming: bool,
+    pub bytes_sent: u64, // By every stream so far, for the bitrate
+    pub listeners: usize, // On the HTTP and WebRTC outputs
+}
+
+struct Topics {
+    node: String,
+    state: String,
+    command: String,
+    availability: String,
+}
+
+impl Topics {
+    fn new(node: String) -> Self {
+        let base = format!("audio-streamer/{}", node);
+        Self { state: format!("{}/state", base), command: format!("{}/streaming/set", base), availability: format!("{}/availability", base), node }
+    }
+
+    // (topic, payload) for each entity, retained so Home Assistant finds them after it restarts.
+    fn discovery(&self, prefix: &str) -> Vec<(String, Value)> {
+        let device = json!({
+            "identifiers": [format!("audio_streamer_{}", self.node)],
+            "name": format!("Audio Streamer ({})", self.node),
+            "sw_version": env!("CARGO_PKG_VERSION"),
+        });
+        let entity = |component: &str, object: &str, name: &str, value: &str, extra: Value| {
+            let mut config = json!({
+                "name": name,
+                "unique_id": format!("audio_streamer_{}_{}", self.node, object),
+                "state_topic": self.state,
+                "value_template": format!("{{{{ value_json.{} }}}}", value),
+                "availability_topic": self.availability,
+                "device": device,
+            });
+            if let (Value::Object(config), Value::Object(extra)) = (&mut config, extra) {
+                config.extend(extra);
+            }
+            (format!("{}/{}/{}/{}/config", prefix, component, self.node, object), config)
+        };
+        vec![
+            entity("switch", "streaming", "Streaming", "streaming", json!({"command_topic": self.command, "icon": "mdi:cast-audio"})),
+            entity("sensor", "bitrate", "Bitrate", "bitrate", json!({"unit_of_measurement": "kbit/s", "device_class": "data_rate", "state_class": "measurement"})),
+            entity("sensor", "listeners", "Listeners", "listeners", json!({"state_class": "measurement", "icon": "mdi:account-multiple"})),
+        ]
+    }
+}
+
+fn put_string(packet: &mut Vec<u8>, value: &[u8]) {
+    packet.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    packet.extend_from_slice(value);
+}
+
+// The fixed header: type and flags, then the remaining length 7 bits at a time.
+fn frame(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        packet.push(if length > 0 { byte | 0x80 } else { byte });
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+// Clean session, with a last will that marks the entities unavailable if we vanish.
+fn connect_packet(settings: &MqttSettings, topics: &Topics) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, b"MQTT");
+    body.push(4); // 3.1.1
+    let mut flags = 0x02 | 0x04 | 0x20; // Clean session, will, retained will
+    if !settings.username.is_empty() {
+        flags |= 0x80;
+        if !settings.password.is_empty() {
+            flags |= 0x40;
+        }
+    }
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    put_string(&mut body, format!("audio-streamer-{}", topics.node).as_bytes());
+    put_string(&mut body, topics.availability.as_bytes());
+    put_string(&mut body, b"offline");
+    if !settings.username.is_empty() {
+        put_string(&mut body, settings.username.as_bytes());
+        if !settings.password.is_empty() {
+            put_string(&mut body, settings.password.as_bytes());
+        }
+    }
+    frame(CONNECT, &body)
+}
+
+// QoS 0: fire and forget, which is plenty on a LAN and needs no acknowledgements.
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    frame(PUBLISH | u8::from(retain), &body)
+}
+
+fn subscribe_packet(topic: &str) -> Vec<u8> {
+    let mut body = 1u16.to_be_bytes().to_vec(); // Packet ID
+    put_string(&mut body, topic.as_bytes());
+    body.push(0); // QoS 0
+    frame(SUBSCRIBE, &body)
+}
+
+// The first whole packet in `buffer` as its header byte and body, removed from it.
+fn take_packet(buffer: &mut Vec<u8>) -> Result<Option<(u8, Vec<u8>)>> {
+    let (mut length, mut shift, mut used) = (0usize, 0, 1);
+    loop {
+        let Some(&byte) = buffer.get(used) else {
+            return Ok(None);
+        };
+        length |= usize::from(byte & 0x7f) << shift;
+        used += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 21 {
+            bail!("The broker sent a malformed packet");
+        }
+    }
+    if buffer.len() < used + length {
+        return Ok(None);
+    }
+    let header = buffer[0];
+    let body = buffer[used..used + length].to_vec();
+    buffer.drain(..used + length);
+    Ok(Some((header, body)))
+}
+
+// Topic and payload of a PUBLISH from the broker.
+fn parse_publish(header: u8, body: &[u8]) -> Option<(String, Vec<u8>)> {
+    let length = usize::from(u16::from_be_bytes([*body.first()?, *body.get(1)?]));
+    let topic = String::from_utf8(body.get(2..2 + length)?.to_vec()).ok()?;
+    // QoS 1 and 2 come with a packet ID, though we only ask for 0.
+    let start = 2 + length + if header & 0x06 != 0 { 2 } else { 0 };
+    Some((topic, body.get(start..)?.to_vec()))
+}
+
+fn connack_error(code: u8) -> &'static str {
+    match code {
+        1 => "it doesn't speak MQTT 3.1.1",
+        2 => "it refused the client ID",
+        3 => "it is unavailable",
+        4 => "the username or password is wrong",
+        5 => "we are not authorized",
+        _ => "it refused the connection",
+    }
+}
+
+struct Session {
+    writer: OwnedWriteHalf,
+    topics: Topics,
+    published: Option<State>,
+    bitrate_kbps: u64,
+    last_sample: (Instant, u64), // When the bitrate was last worked out, and the bytes then
+}
+
+impl Session {
+    // Over the time since the last sample, so it is steady however often the state goes out.
+    fn sample_bitrate(&mut self, state: State) {
+        let (at, bytes) = self.last_sample;
+        let secs = at.elapsed().as_secs_f64();
+        self.bitrate_kbps = if state.streaming && secs > 0.0 { (state.bytes_sent.saturating_sub(bytes) as f64 * 8.0 / 1000.0 / secs).round() as u64 } else { 0 };
+        self.last_sample = (Instant::now(), state.bytes_sent);
+    }
+
+    async fn publish_state(&mut self, state: State) -> Result<()> {
+        if !state.streaming {
+            self.bitrate_kbps = 0;
+        }
+        self.published = Some(state);
+        let payload = json!({"streaming": if state.streaming { "ON" } else { "OFF" }, "bitrate": self.bitrate_kbps, "listeners": state.listeners});
+        self.writer.write_all(&publish_packet(&self.topics.state, payload.to_string().as_bytes(), true)).await?;
+        Ok(())
+    }
+}
+
+// The switch asks the front end the same way a second `audio-streamer` process does.
+fn command(payload: &[u8], events_tx: &Sender<Event>, runtime_handle: &Handle) {
+    let request = match payload {
+        b"ON" => Request::Stream { source: None, target: None, codec: None },
+        b"OFF" => Request::Stop,
+        _ => return,
+    };
+    let (reply_tx, reply_rx) = oneshot::channel();
+    if events_tx.send(Event::Remote { request, reply_tx }).is_err() {
+        return;
+    }
+    runtime_handle.spawn(async move {
+        if let Ok(Reply { ok: false, message }) = reply_rx.await {
+            log!("Home Assistant switch: {}", message);
+        }
+    });
+}
+
+// One connection, until the broker goes away or `stop_rx` fires.
+async fn run(settings: &MqttSettings, state_rx: &mut watch::Receiver<State>, stop_rx: &mut oneshot::Receiver<()>, events_tx: &Sender<Event>) -> Result<()> {
+    let address = settings.address();
+    let stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(&address))
+        .await
+        .with_context(|| format!("{} did not answer", address))?
+        .with_context(|| format!("Could not connect to {}", address))?;
+    let (mut reader, writer) = stream.into_split();
+    let mut session = Session { writer, topics: Topics::new(settings.node()), published: None, bitrate_kbps: 0, last_sample: (Instant::now(), 0) };
+    session.writer.write_all(&connect_packet(settings, &session.topics)).await?;
+
+    let mut buffer = Vec::new();
+    let connack = timeout(CONNECT_TIMEOUT, async {
+        loop {
+            if let Some(packet) = take_packet(&mut buffer)? {
+                return anyhow::Ok(packet);
+            }
+            let mut chunk = [0; 512];
+            match reader.read(&mut chunk).await? {
+                0 => bail!("{} closed the connection", address),
+                read => buffer.extend_from_slice(&chunk[..read]),
+            }
+        }
+    });
+    match connack.await.with_context(|| format!("{} did not accept the connection", address))?? {
+        (CONNACK, body) if body.get(1) == Some(&0) => {}
+        (CONNACK, body) => bail!("{} refused us: {}", address, connack_error(body.get(1).copied().unwrap_or(0xff))),
+        _ => bail!("{} is not an MQTT broker", address),
+    }
+    log!("Connected to the MQTT broker at {}", address);
+
+    for (topic, config) in session.topics.discovery(&settings.discovery_prefix) {
+        session.writer.write_all(&publish_packet(&topic, config.to_string().as_bytes(), true)).await?;
+    }
+    session.writer.write_all(&publish_packet(&session.topics.availability, b"online", true)).await?;
+    session.writer.write_all(&subscribe_packet(&session.topics.command)).await?;
+    let state = *state_rx.borrow_and_update();
+    session.last_sample = (Instant::now(), state.bytes_sent);
+    session.publish_state(state).await?;
+
+    let runtime_handle = Handle::current();
+    let mut refresh = interval(REFRESH);
+    refresh.reset();
+    let mut chunk = [0; 4096];
+    loop {
+        tokio::select! {
+            read = reader.read(&mut chunk) => {
+                match read? {
+                    0 => bail!("{} closed the connection", address),
+                    read => buffer.extend_from_slice(&chunk[..read]),
+                }
+                while let Some((header, body)) = take_packet(&mut buffer)? {
+                    if header & 0xf0 == PUBLISH
+                        && let Some((topic, payload)) = parse_publish(header, &body)
+                        && topic == session.topics.command
+                    {
+                        command(payload.trim_ascii(), events_tx, &runtime_handle);
+                    }
+                }
+            }
+            changed = state_rx.changed() => {
+                if changed.is_err() {
+                    return Ok(()); // The front end is gone
+                }
+                // The bytes alone wait for the next refresh, or they would go out every frame.
+                let state = *state_rx.borrow_and_update();
+                if session.published.is_none_or(|published| (published.streaming, published.listeners) != (state.streaming, state.listeners)) {
+                    session.publish_state(state).await?;
+                }
+            }
+            _ = refresh.tick() => {
+                let state = *state_rx.borrow();
+                session.sample_bitrate(state);
+                if state.streaming || session.published != Some(state) {
+                    session.publish_state(state).await?;
+                } else {
+                    session.writer.write_all(&[PINGREQ, 0]).await?;
+                }
+            }
+            _ = &mut *stop_rx => {
+                // Gone on purpose, so say so now rather than after the keep-alive runs out.
+                // Ok however that goes: `stop_rx` has fired and must not be polled again.
+                let _ = session.writer.write_all(&publish_packet(&session.topics.availability, b"offline", true)).await;
+                let _ = session.writer.write_all(&[DISCONNECT, 0]).await;
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Keeps a connection to the broker for as long as it lives, reconnecting when it
+// drops. Switch commands arrive as `Event::Remote` requests.
+pub struct Mqtt {
+    settings: MqttSettings,
+    state_tx: watch::Sender<State>,
+    stop_tx: oneshot::Sender<()>,
+}
+
+impl Mqtt {
+    pub fn start(settings: MqttSettings, events_tx: Sender<Event>, runtime_handle: &Handle) -> Self {
+        let (state_tx, mut state_rx) = watch::channel(State::default());
+        let (stop_tx, mut stop_rx) = oneshot::channel();
+        let task_settings = settings.clone();
+        runtime_handle.spawn(async move {
+            let mut last_error = String::new();
+            loop {
+                match run(&task_settings, &mut state_rx, &mut stop_rx, &events_tx).await {
+                    Ok(()) => return,
+                    // Once per distinct problem, not every retry while the broker is down.
+                    Err(e) => {
+                        let error = format!("{:#}", e);
+                        if error != last_error {
+                            log!("MQTT: {}; retrying every {} s", error, RETRY.as_secs());
+                            last_error = error;
+                        }
+                    }
+                }
+                tokio::select! {
+                    _ = sleep(RETRY) => {}
+                    _ = &mut stop_rx => return,
+                }
+            }
+        });
+        Self { settings, state_tx, stop_tx }
+    }
+
+    pub fn settings(&self) -> &MqttSettings {
+        &self.settings
+    }
+
+    pub fn update(&self, state: State) {
+        self.state_tx.send_if_modified(|current| std::mem::replace(current, state) != state);
+    }
+
+    pub fn stop(self) {
+        let _ = self.stop_tx.send(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remaining_length(packet: &[u8]) -> (usize, usize) {
+        let mut buffer = packet.to_vec();
+        let (_, body) = take_packet(&mut buffer).unwrap().unwrap();
+        (body.len(), packet.len() - body.len() - 1)
+    }
+
+    #[test]
+    fn remaining_length_takes_one_to_three_bytes() {
+        for (length, bytes) in [(0, 1), (127, 1), (128, 2), (16_383, 2), (16_384, 3), (2_097_151, 3)] {
+            assert_eq!(remaining_length(&frame(PUBLISH, &vec![0; length])), (length, bytes), "length {}", length);
+        }
+        assert_eq!(&frame(PUBLISH, &[0; 321])[..3], &[PUBLISH, 0xc1, 0x02]);
+    }
+
+    #[test]
+    fn take_packet_waits_for_whole_packets() {
+        let mut buffer = frame(PUBLISH, &[1, 2, 3]);
+        buffer.extend_from_slice(&frame(PINGREQ, &[]));
+        let tail = buffer.split_off(buffer.len() - 1);
+        assert_eq!(take_packet(&mut buffer).unwrap(), Some((PUBLISH, vec![1, 2, 3])));
+        assert_eq!(take_packet(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(&tail);
+        assert_eq!(take_packet(&mut buffer).unwrap(), Some((PINGREQ, vec![])));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn take_packet_rejects_a_fifth_length_byte() {
+        let mut buffer = vec![PUBLISH, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(take_packet(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn connect_packet_framing() {
+        let settings = MqttSettings { username: "ha".to_string(), password: "pw".to_string(), ..Default::default() };
+        let topics = Topics::new("den".to_string());
+        let mut buffer = connect_packet(&settings, &topics);
+        let (header, body) = take_packet(&mut buffer).unwrap().unwrap();
+        assert_eq!(header, CONNECT);
+        let mut expected = vec![0, 4, b'M', b'Q', b'T', b'T', 4, 0xe6, 0, 60];
+        for field in ["audio-streamer-den", "audio-streamer/den/availability", "offline", "ha", "pw"] {
+            expected.extend_from_slice(&(field.len() as u16).to_be_bytes());
+            expected.extend_from_slice(field.as_bytes());
+        }
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn connect_packet_without_credentials() {
+        let topics = Topics::new("den".to_string());
+        let mut buffer = connect_packet(&MqttSettings::default(), &topics);
+        let (_, body) = take_packet(&mut buffer).unwrap().unwrap();
+        assert_eq!(body[7], 0x26);
+        assert!(body.ends_with(b"\0\x07offline"));
+    }
+
+    #[test]
+    fn publish_packet_framing() {
+        assert_eq!(publish_packet("a/b", b"ON", true), vec![PUBLISH | 1, 7, 0, 3, b'a', b'/', b'b', b'O', b'N']);
+        assert_eq!(publish_packet("a/b", b"ON", false)[0], PUBLISH);
+    }
+
+    #[test]
+    fn parse_publish_skips_the_packet_id_above_qos_0() {
+        let mut buffer = publish_packet("a/b", b"OFF", false);
+        let (header, body) = take_packet(&mut buffer).unwrap().unwrap();
+        assert_eq!(parse_publish(header, &body), Some(("a/b".to_string(), b"OFF".to_vec())));
+        let body = [&[0, 3][..], b"a/b", &[0, 9], b"ON"].concat();
+        assert_eq!(parse_publish(PUBLISH | 0x02, &body), Some(("a/b".to_string(), b"ON".to_vec())));
+        assert_eq!(parse_publish(PUBLISH, &[0, 9, b'a']), None);
+    }
+
+    #[test]
+    fn subscribe_packet_framing() {
+        assert_eq!(subscribe_packet("c"), vec![SUBSCRIBE, 6, 0, 1, 0, 1, b'c', 0]);
+    }
+}
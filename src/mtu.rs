@@ -0,0 +1,73 @@
+use crate::{config::Config, fallback::MAX_PAYLOAD_BYTES, netwatch, rtp::RTP_HEADER_LEN, transport::{HEADER_LEN, Transport}};
+use std::{fs, net::IpAddr};
+
+pub const DEFAULT_MTU: u32 = 1500; // Ethernet and Wi-Fi
+const UDP_HEADER: u32 = 8;
+const TS_PACKET: usize = 188;
+// Seven TS packets (1316 bytes) is the size players are used to; more only adds latency at low bitrates.
+const MAX_TS_PACKETS: usize = 7;
+// Native: our header, plus the length prefix in parity payloads. RIST: GRE with key and sequence, then RTP.
+const NATIVE_OVERHEAD: usize = HEADER_LEN + 2;
+const RIST_OVERHEAD: usize = 24;
+
+// How large each datagram of the stream is, and how that was decided.
+#[derive(Debug, Clone)]
+pub struct PacketPlan {
+    pub mtu: u32,
+    pub interface: Option<String>, // The one the route to the target leaves through
+    pub ts_size: usize,            // ffmpeg's pkt_size; always whole TS packets
+    pub rtp_payload: usize,        // Largest PCM payload for the built-in engine
+    pub warning: Option<String>,
+}
+
+impl PacketPlan {
+    pub fn describe(&self) -> String {
+        let from = match &self.interface {
+            Some(interface) => format!("{}'s MTU {}", interface, self.mtu),
+            None => format!("MTU {}", self.mtu),
+        };
+        format!("{}-byte packets for {}", self.ts_size, from)
+    }
+}
+
+// A learned path MTU wins (see `Route::path_mtu`); otherwise the outgoing interface's own MTU applies.
+// From `netwatch::route`, so a target it doesn't know yet gets the default.
+fn route_mtu(target: IpAddr) -> Option<(String, u32)> {
+    let route = netwatch::route(target)?;
+    let mtu = route.path_mtu.or_else(|| fs::read_to_string(format!("/sys/class/net/{}/mtu", route.interface)).ok()?.trim().parse().ok())?;
+    Some((route.interface, mtu))
+}
+
+fn overhead(transport: Transport) -> usize {
+    match transport {
+        Transport::Udp | Transport::Rtp => 0,
+        Transport::Native => NATIVE_OVERHEAD,
+        Transport::Rist => RIST_OVERHEAD,
+    }
+}
+
+// A manual `mtu` in the config wins; 0 means ask the routing table.
+pub fn plan(config: &Config, target: IpAddr, transport: Transport) -> PacketPlan {
+    let probed = route_mtu(target);
+    let mtu = match (config.mtu, &probed) {
+        (0, Some((_, mtu))) => *mtu,
+        (0, None) => DEFAULT_MTU,
+        (manual, _) => manual,
+    };
+    let ip_header = if target.is_ipv4() { 20 } else { 40 };
+    let budget = mtu.saturating_sub(ip_header + UDP_HEADER) as usize;
+    let ts_packets = (budget.saturating_sub(overhead(transport)) / TS_PACKET).clamp(1, MAX_TS_PACKETS);
+    let ts_size = ts_packets * TS_PACKET;
+    let rtp_payload = budget.saturating_sub(RTP_HEADER_LEN).min(MAX_PAYLOAD_BYTES);
+
+    let warning = match &probed {
+        Some((interface, interface_mtu)) if config.mtu > *interface_mtu => Some(format!(
+            "MTU {} is above {}'s {}; larger packets will be fragmented",
+            config.mtu, interface, interface_mtu
+        )),
+        _ if ts_size + overhead(transport) > budget => Some(format!("MTU {} is too small for even one MPEG-TS packet; packets will be fragmented", mtu)),
+        _ => None,
+    };
+    let interface = probed.filter(|_| config.mtu == 0).map(|(interface, _)| interface);
+    PacketPlan { mtu, interface, ts_size, rtp_payload, warning }
+}
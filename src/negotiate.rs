@@ -0,0 +1,90 @@
+use crate::{config::Config, ffmpeg, remote::REMOTE_CODECS};
+
+// A receiver this close to real time wants small packets and no encoder lookahead.
+const LOW_LATENCY_MS: u32 = 80;
+// The receivers' preference where they can decode several: the most quality per bit first.
+const PREFERENCE: [&str; 4] = ["libopus", "aac", "ac3", "libmp3lame"];
+
+// What a receiver can play and would like, sent as `RemoteCommand::Capabilities` when a
+// stream (or a run of it) reaches it. Payload layout: max bitrate in kbit/s (4) |
+// preferred latency in ms (2) | encoder names, comma-separated, best first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub codecs: Vec<String>,   // As the sender's ffmpeg encoders are called, e.g. "libopus"
+    pub max_bitrate_kbps: u32, // 0 for any
+    pub latency_ms: u32,       // Its playout buffer target; 0 for no preference
+}
+
+impl Capabilities {
+    // Ours as a receiver: what the local ffmpeg decodes, or every codec when it can't say.
+    pub async fn of_receiver(config: &Config) -> Self {
+        let decoders = ffmpeg::decoders(config).await.ok();
+        let codecs = PREFERENCE
+            .iter()
+            .filter(|codec| {
+                let decoder = match **codec {
+                    "libopus" => "opus",
+                    "libmp3lame" => "mp3",
+                    codec => codec,
+                };
+                // Some come in a fixed and a float flavour, e.g. mp3 and mp3float.
+                decoders.as_ref().is_none_or(|decoders| decoders.iter().any(|name| name.strip_suffix("float").unwrap_or(name) == decoder))
+            })
+            .map(|codec| codec.to_string())
+            .collect();
+        Self { codecs, max_bitrate_kbps: config.receive_max_bitrate_kbps, latency_ms: config.jitter_target_ms }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let latency_ms = self.latency_ms.min(u16::MAX as u32) as u16;
+        let mut payload = self.max_bitrate_kbps.to_be_bytes().to_vec();
+        payload.extend_from_slice(&latency_ms.to_be_bytes());
+        payload.extend_from_slice(self.codecs.join(",").as_bytes());
+        payload
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let (max_bitrate_kbps, rest) = payload.split_first_chunk::<4>()?;
+        let (latency_ms, codecs) = rest.split_first_chunk::<2>()?;
+        let codecs = String::from_utf8_lossy(codecs).split(',').map(str::trim).filter(|codec| !codec.is_empty()).map(String::from).collect();
+        Some(Self { codecs, max_bitrate_kbps: u32::from_be_bytes(*max_bitrate_kbps), latency_ms: u16::from_be_bytes(*latency_ms) as u32 })
+    }
+}
+
+// The least each encoder accepts at our sample rates; below it ffmpeg refuses to start,
+// so a receiver asking for less gets this.
+fn min_bitrate_kbps(codec: &str) -> u32 {
+    match codec {
+        "libopus" => 6,
+        "libmp3lame" => 8,
+        _ => 32,
+    }
+}
+
+// The settings both ends agree on, from what the stream runs with and what the receiver
+// said. The sender's codec stays when the receiver can play it; otherwise it is the
+// receiver's favourite of those `encodes` says the sender has. None when nothing fits.
+// Negotiating the result again gives the same, so a restart doesn't start it over.
+pub fn negotiate(config: &Config, capabilities: &Capabilities, encodes: impl Fn(&str) -> bool) -> Option<Config> {
+    let mut agreed = config.clone();
+    if !capabilities.codecs.contains(&config.audio_codec) {
+        agreed.audio_codec = capabilities
+            .codecs
+            .iter()
+            .find(|codec| REMOTE_CODECS.contains(&codec.as_str()) && encodes(codec))?
+            .clone();
+    }
+    let max_bps = capabilities.max_bitrate_kbps as u64 * 1000;
+    if max_bps > 0 && config.bitrate_bps().is_none_or(|bps| bps > max_bps) {
+        agreed.bitrate = format!("{}k", capabilities.max_bitrate_kbps.max(min_bitrate_kbps(&agreed.audio_codec)));
+    }
+    if capabilities.latency_ms > 0 {
+        agreed.low_latency = capabilities.latency_ms <= LOW_LATENCY_MS;
+    }
+    Some(agreed)
+}
+
+// "libopus at 128k, low latency", for showing what was agreed.
+pub fn describe(config: &Config) -> String {
+    format!("{} at {}{}", config.audio_codec, config.bitrate, if config.low_latency { ", low latency" } else { "" })
+}
@@ -0,0 +1,117 @@
+use crate::events::Event;
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    process::Stdio,
+    sync::{Mutex, mpsc::Sender},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    runtime::Handle,
+    time::timeout,
+};
+
+// Plugging in a cable or joining Wi-Fi comes as a burst of link, address and route
+// events over a second or two; the change is reported once they stop.
+const SETTLE: Duration = Duration::from_millis(1500);
+// How long `route` trusts a route it found; `watch` renews them as soon as the network changes.
+const ROUTE_TTL: Duration = Duration::from_secs(5);
+
+// The routes to every target asked about, and when they were looked up, so `route`
+// answers on the GUI thread without waiting for `ip route get`.
+static ROUTES: Mutex<BTreeMap<IpAddr, (Instant, Option<Route>)>> = Mutex::new(BTreeMap::new());
+
+// How packets to a target leave this machine, from `ip route get`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub interface: String,
+    pub source: Option<IpAddr>, // Our address on that interface
+    pub gateway: Option<IpAddr>, // None when the target is on that interface's own network
+    pub path_mtu: Option<u32>,  // Only when the kernel has learned one, e.g. from ICMP "fragmentation needed"
+}
+
+// None when nothing routes there, e.g. with every interface down.
+pub async fn route_to(target: IpAddr) -> Option<Route> {
+    let output = Command::new("ip").args(["route", "get", &target.to_string()]).output().await.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let route = String::from_utf8_lossy(&output.stdout).to_string();
+    let mut words = route.split_whitespace();
+    let (mut interface, mut source, mut gateway, mut path_mtu) = (None, None, None, None);
+    while let Some(word) = words.next() {
+        match word {
+            "dev" => interface = words.next().map(str::to_string),
+            "src" => source = words.next().and_then(|source| source.parse().ok()),
+            "via" => gateway = words.next().and_then(|gateway| gateway.parse().ok()),
+            "mtu" => path_mtu = words.next().and_then(|mtu| mtu.parse().ok()),
+            _ => {}
+        }
+    }
+    Some(Route { interface: interface?, source, gateway, path_mtu })
+}
+
+// Looks the route up now, and keeps it for `route`.
+pub async fn resolve(target: IpAddr) -> Option<Route> {
+    let route = route_to(target).await;
+    ROUTES.lock().unwrap().insert(target, (Instant::now(), route.clone()));
+    route
+}
+
+// The route last found to `target`. One not known yet, or for too long, is looked up on
+// the runtime for the next call; until the first lookup is back this is None.
+pub fn route(target: IpAddr) -> Option<Route> {
+    let mut routes = ROUTES.lock().unwrap();
+    let known = routes.get(&target).cloned();
+    if known.as_ref().is_none_or(|(at, _)| at.elapsed() >= ROUTE_TTL)
+        && let Ok(runtime) = Handle::try_current()
+    {
+        // Counted as fresh meanwhile, so each frame doesn't start another lookup.
+        routes.insert(target, (Instant::now(), known.as_ref().and_then(|(_, route)| route.clone())));
+        runtime.spawn(resolve(target));
+    }
+    known.and_then(|(_, route)| route)
+}
+
+// Network interfaces to send through, from /sys/class/net, without loopback.
+pub fn interfaces() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir("/sys/class/net") else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries.filter_map(|entry| entry.ok()?.file_name().into_string().ok()).filter(|name| name != "lo").collect();
+    names.sort();
+    names
+}
+
+// Sends `Event::NetworkChanged` whenever the kernel's links, addresses or routes have changed,
+// using netlink through `ip monitor`, once `route` has the new routes. Ends when the
+// receiving side goes away.
+pub fn watch(events_tx: Sender<Event>, runtime_handle: &Handle) -> Result<()> {
+    let _runtime = runtime_handle.enter(); // tokio spawns need it
+    let mut monitor = Command::new("ip")
+        .args(["-o", "monitor", "link", "address", "route"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to run 'ip monitor'")?;
+    let stdout = monitor.stdout.take().context("ip monitor has no stdout")?;
+    runtime_handle.spawn(async move {
+        let _monitor = monitor;
+        let mut events = BufReader::new(stdout).lines();
+        while let Ok(Some(_)) = events.next_line().await {
+            while let Ok(Ok(Some(_))) = timeout(SETTLE, events.next_line()).await {}
+            let targets: Vec<IpAddr> = ROUTES.lock().unwrap().keys().copied().collect();
+            for target in targets {
+                resolve(target).await;
+            }
+            if events_tx.send(Event::NetworkChanged).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
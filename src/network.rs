@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::UdpSocket;
+
+// Probe packets start with this magic so a receiver can tell them apart from stream data.
+pub const PROBE_MAGIC: &[u8; 4] = b"ASBW";
+// A receiver that understands probes answers with a single report packet using this magic.
+pub const REPORT_MAGIC: &[u8; 4] = b"ASBR";
+
+const PROBE_PACKET_COUNT: u32 = 500;
+const PROBE_PACKET_SIZE: usize = 1316; // Same as the default mpegts pkt_size
+const PROBE_HEADER_SIZE: usize = 20;
+const REPORT_WAIT: Duration = Duration::from_millis(1500);
+
+// Bitrates we are willing to recommend, in kbit/s, lowest first.
+const BITRATE_LADDER: [u32; 8] = [48, 64, 96, 128, 160, 192, 256, 320];
+
+#[derive(Debug, Clone)]
+pub struct BandwidthReport {
+    pub packets_sent: u32,
+    pub packets_received: Option<u32>, // None when no receiver answered
+    pub throughput_kbps: f64,
+    pub jitter_ms: Option<f64>,
+    pub recommended_bitrate: String,
+}
+
+impl BandwidthReport {
+    pub fn has_feedback(&self) -> bool {
+        self.packets_received.is_some()
+    }
+
+    pub fn loss_percent(&self) -> Option<f64> {
+        self.packets_received.map(|received| {
+            let lost = self.packets_sent.saturating_sub(received);
+            lost as f64 * 100.0 / self.packets_sent as f64
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        match (self.packets_received, self.jitter_ms) {
+            (Some(_), Some(jitter)) => format!(
+                "~{:.0} kbit/s, {:.1}% loss, {:.1} ms jitter → recommended {}",
+                self.throughput_kbps,
+                self.loss_percent().unwrap_or(0.0),
+                jitter,
+                self.recommended_bitrate
+            ),
+            _ => format!(
+                "~{:.0} kbit/s sent (no receiver feedback, upper bound only) → recommended {}",
+                self.throughput_kbps, self.recommended_bitrate
+            ),
+        }
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+// Probe layout: magic (4) | seq (4) | total (4) | send time in µs (8) | zero padding.
+fn build_probe_packet(seq: u32, total: u32) -> Vec<u8> {
+    let mut packet = vec![0u8; PROBE_PACKET_SIZE];
+    packet[0..4].copy_from_slice(PROBE_MAGIC);
+    packet[4..8].copy_from_slice(&seq.to_be_bytes());
+    packet[8..12].copy_from_slice(&total.to_be_bytes());
+    packet[12..PROBE_HEADER_SIZE].copy_from_slice(&now_micros().to_be_bytes());
+    packet
+}
+
+// Report layout: magic (4) | packets received (4) | bytes received (8) |
+// first-to-last arrival span in µs (8) | interarrival jitter in µs (4).
+fn parse_report_packet(data: &[u8]) -> Option<(u32, u64, u64, u32)> {
+    if data.len() < 28 || &data[0..4] != REPORT_MAGIC {
+        return None;
+    }
+    let received = u32::from_be_bytes(data[4..8].try_into().ok()?);
+    let bytes = u64::from_be_bytes(data[8..16].try_into().ok()?);
+    let span_micros = u64::from_be_bytes(data[16..24].try_into().ok()?);
+    let jitter_micros = u32::from_be_bytes(data[24..28].try_into().ok()?);
+    Some((received, bytes, span_micros, jitter_micros))
+}
+
+// Receiver-side bookkeeping for a probe burst, turned into a report packet once
+// the burst is over.
+#[derive(Debug, Default)]
+pub struct ProbeStats {
+    received: u32,
+    bytes: u64,
+    first_arrival: u64,
+    last_arrival: u64,
+    last_transit: Option<i64>,
+    jitter: f64,
+}
+
+impl ProbeStats {
+    pub fn is_probe(data: &[u8]) -> bool {
+        data.len() >= PROBE_HEADER_SIZE && &data[0..4] == PROBE_MAGIC
+    }
+
+    // Returns true when this was the last packet of the burst.
+    pub fn observe(&mut self, data: &[u8]) -> bool {
+        let arrival = now_micros();
+        let seq = u32::from_be_bytes(data[4..8].try_into().unwrap_or_default());
+        let total = u32::from_be_bytes(data[8..12].try_into().unwrap_or_default());
+        let sent = u64::from_be_bytes(data[12..PROBE_HEADER_SIZE].try_into().unwrap_or_default());
+
+        if self.received == 0 {
+            self.first_arrival = arrival;
+        }
+        self.received += 1;
+        self.bytes += data.len() as u64;
+        self.last_arrival = arrival;
+
+        // RFC 3550 interarrival jitter; the clock offset between hosts cancels out.
+        let transit = arrival as i64 - sent as i64;
+        if let Some(last) = self.last_transit {
+            let d = (transit - last).abs() as f64;
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+
+        seq + 1 >= total
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        Duration::from_micros(now_micros().saturating_sub(self.last_arrival))
+    }
+
+    pub fn report_packet(&self) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(28);
+        packet.extend_from_slice(REPORT_MAGIC);
+        packet.extend_from_slice(&self.received.to_be_bytes());
+        packet.extend_from_slice(&self.bytes.to_be_bytes());
+        packet.extend_from_slice(&(self.last_arrival - self.first_arrival).to_be_bytes());
+        packet.extend_from_slice(&(self.jitter as u32).to_be_bytes());
+        packet
+    }
+}
+
+// Leaves plenty of headroom for mpegts overhead and Wi-Fi bursts: audio should
+// never use more than a quarter of what the link can carry.
+fn recommend_bitrate(throughput_kbps: f64, loss_percent: f64) -> String {
+    let mut budget = throughput_kbps / 4.0;
+    if loss_percent > 1.0 {
+        budget /= 2.0;
+    }
+    let kbps = BITRATE_LADDER
+        .iter()
+        .rev()
+        .find(|&&rate| rate as f64 <= budget)
+        .copied()
+        .unwrap_or(BITRATE_LADDER[0]);
+    format!("{}k", kbps)
+}
+
+// Sends a short burst of padded UDP packets to the target and waits briefly for a
+// receiver report. Without a report the estimate is just our local send rate.
+pub async fn measure_bandwidth(ip: IpAddr, port: u16) -> Result<BandwidthReport> {
+    let bind_addr = if ip.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_addr)
+        .await
+        .context("Failed to create UDP socket")?;
+    let target = SocketAddr::new(ip, port);
+
+    let started = Instant::now();
+    for seq in 0..PROBE_PACKET_COUNT {
+        let packet = build_probe_packet(seq, PROBE_PACKET_COUNT);
+        socket
+            .send_to(&packet, target)
+            .await
+            .context("Failed to send probe packet")?;
+    }
+    let send_elapsed = started.elapsed().as_secs_f64().max(1e-6);
+    let sent_bytes = PROBE_PACKET_COUNT as u64 * PROBE_PACKET_SIZE as u64;
+    let send_rate_kbps = sent_bytes as f64 * 8.0 / send_elapsed / 1000.0;
+
+    let mut buf = [0u8; 64];
+    let report = tokio::time::timeout(REPORT_WAIT, async {
+        loop {
+            match socket.recv_from(&mut buf).await {
+                Ok((len, from)) if from.ip() == ip => {
+                    if let Some(report) = parse_report_packet(&buf[..len]) {
+                        return Some(report);
+                    }
+                }
+                Ok(_) => continue,
+                Err(_) => return None, // e.g. ICMP port unreachable surfaced as an error
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten();
+
+    let result = match report {
+        Some((received, bytes, span_micros, jitter_micros)) => {
+            let span = (span_micros as f64 / 1_000_000.0).max(send_elapsed);
+            let throughput_kbps = bytes as f64 * 8.0 / span / 1000.0;
+            let lost = PROBE_PACKET_COUNT.saturating_sub(received);
+            let loss_percent = lost as f64 * 100.0 / PROBE_PACKET_COUNT as f64;
+            BandwidthReport {
+                packets_sent: PROBE_PACKET_COUNT,
+                packets_received: Some(received),
+                throughput_kbps,
+                jitter_ms: Some(jitter_micros as f64 / 1000.0),
+                recommended_bitrate: recommend_bitrate(throughput_kbps, loss_percent),
+            }
+        }
+        None => BandwidthReport {
+            packets_sent: PROBE_PACKET_COUNT,
+            packets_received: None,
+            throughput_kbps: send_rate_kbps,
+            jitter_ms: None,
+            recommended_bitrate: recommend_bitrate(send_rate_kbps, 0.0),
+        },
+    };
+
+    Ok(result)
+}
@@ -0,0 +1,96 @@
+use crate::transport::{Packet, PacketKind};
+use std::process::Stdio;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+    runtime::Handle,
+    sync::mpsc::UnboundedSender,
+    task::JoinHandle,
+};
+
+// UDP may lose one; receivers drop the repeats by sequence number.
+pub const REPEATS: usize = 3;
+
+// What the sender tells native receivers about, so a companion app can show it while
+// the player is in the background. Separate from the stream itself: a receiver that
+// only plays ignores these.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    Started(String), // Who and what, as `StreamTag::describe` puts it
+    Stopped,
+    Track(String), // "Artist – Title", from the media player
+}
+
+impl Notification {
+    // Payload layout: kind, then the text on the next line.
+    pub fn packet(&self, session: u32, seq: u32) -> Packet {
+        let payload = match self {
+            Notification::Started(what) => format!("started\n{}", what),
+            Notification::Stopped => "stopped\n".to_string(),
+            Notification::Track(title) => format!("track\n{}", title),
+        };
+        Packet { kind: PacketKind::Notify, seq, fec_group: 0, session, timestamp_us: 0, payload: payload.into_bytes() }
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let payload = String::from_utf8_lossy(payload);
+        let (kind, text) = payload.split_once('\n').unwrap_or((&payload, ""));
+        match kind {
+            "started" => Some(Notification::Started(text.to_string())),
+            "stopped" => Some(Notification::Stopped),
+            "track" => Some(Notification::Track(text.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Notification::Started(what) => format!("Streaming started: {}", what),
+            Notification::Stopped => "Streaming stopped".to_string(),
+            Notification::Track(title) => format!("Now playing: {}", title),
+        }
+    }
+}
+
+// Follows the desktop's media player over MPRIS with playerctl, which prints a line
+// whenever the track changes. Without playerctl, or a player, there is nothing to tell.
+pub struct TrackWatcher {
+    task: JoinHandle<()>,
+}
+
+impl TrackWatcher {
+    pub fn start(notifications: UnboundedSender<Notification>, runtime_handle: &Handle) -> Self {
+        let task = runtime_handle.spawn(async move {
+            let Ok(mut playerctl) = Command::new("playerctl")
+                .args(["--follow", "metadata", "--format", "{{artist}} – {{title}}"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+            else {
+                return;
+            };
+            let Some(stdout) = playerctl.stdout.take() else {
+                return;
+            };
+            let mut lines = BufReader::new(stdout).lines();
+            let mut last = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                // Pausing or seeking prints the same track again; no artist leaves a dash.
+                let title = line.trim().trim_start_matches('–').trim().to_string();
+                if title.is_empty() || title == last {
+                    continue;
+                }
+                last = title.clone();
+                if notifications.send(Notification::Track(title)).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { task }
+    }
+
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{
+    access::{AccessPolicy, Listeners},
+    browser::{self, BrowserPlayer},
+    config::Config,
+    ffmpeg::locate_ffmpeg,
+    icecast, log,
+    mix::{self, Claim, MixSink},
+    qos,
+    relay::Relay,
+    snapcast,
+    tls::{CERTIFICATE_PATH, TlsIdentity, certificate_response},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, UdpSocket},
+    process::Child,
+    runtime::Handle,
+    sync::broadcast::{Receiver, error::RecvError},
+    task::JoinHandle,
+};
+
+const HTTP_HEADER: &[u8] = b"HTTP/1.0 200 OK\r\nContent-Type: video/mp2t\r\nCache-Control: no-cache\r\n\r\n";
+
+// Extra destinations for the encoded stream, next to the main target. They get the
+// MPEG-TS exactly as ffmpeg produced it, so they only apply to the ffmpeg engine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Output {
+    Udp { address: String },  // "ip:port", e.g. a second phone
+    Http { port: u16 },       // Players open http://<this machine>:<port>/
+    File { path: String },    // Local recording; "~/" is the home directory
+    // Into a snapserver source; with `control` ("host:1705") and `stream` set, every
+    // Snapcast group is switched to that stream when streaming starts.
+    Snapcast {
+        target: String,
+        #[serde(default)]
+        control: String,
+        #[serde(default)]
+        stream: String,
+    },
+    // As a source client of an Icecast server, for anything that plays internet radio.
+    Icecast {
+        server: String, // "host:port"; port 8000 when left out
+        mount: String,
+        password: String,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        description: String,
+    },
+    // A player page at http://<this machine>:<port>/ that plays over WebRTC in any
+    // browser. LAN only unless a STUN server ("host:port") is given.
+    WebRtc {
+        port: u16,
+        #[serde(default)]
+        stun: String,
+    },
+    // Into a sink on this machine that OBS and other apps can record, see `mix.rs`.
+    Sink {
+        #[serde(default)]
+        name: String, // As apps list it; `mix::DEFAULT_NAME` when empty
+    },
+}
+
+impl Output {
+    // Players connect to it, rather than it sending somewhere; see `access.rs`.
+    pub fn takes_listeners(&self) -> bool {
+        matches!(self, Output::Http { .. } | Output::WebRtc { .. })
+    }
+
+    // `tls` is `Config::listen_tls`, for the listening ones.
+    pub fn describe(&self, tls: bool) -> String {
+        let scheme = if tls { "https" } else { "http" };
+        match self {
+            Output::Udp { address } => format!("udp://{}", address),
+            Output::Http { port } => format!("{}://0.0.0.0:{}/", scheme, port),
+            Output::File { path } => format!("recording to {}", path),
+            Output::Snapcast { target, control, stream } if !control.is_empty() && !stream.is_empty() => {
+                format!("snapcast {} as {}, groups switched to '{}' via {}", target, snapcast::SAMPLE_FORMAT, stream, control)
+            }
+            Output::Snapcast { target, .. } => format!("snapcast {} as {}", target, snapcast::SAMPLE_FORMAT),
+            Output::Icecast { server, mount, .. } => format!("icecast source http://{}/{}", server, mount.trim_start_matches('/')),
+            Output::WebRtc { port, .. } => format!("webrtc player page at {}://0.0.0.0:{}/", scheme, port),
+            Output::Sink { name } => format!("'{}' sink for OBS and other apps", if name.trim().is_empty() { mix::DEFAULT_NAME } else { name.trim() }),
+        }
+    }
+}
+
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+// Running outputs, each a task reading the relay's tap. Slow ones skip ahead
+// rather than holding the others back.
+pub struct Outputs {
+    tasks: Vec<JoinHandle<()>>,
+    listeners: Listeners, // Clients of the HTTP and WebRTC outputs
+}
+
+// The tasks started so far, aborted if a later output fails to start, so none keeps
+// its port or process; taken out once all are running.
+struct Started(Vec<JoinHandle<()>>);
+
+impl Drop for Started {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+impl Outputs {
+    // With `append`, after a restart, recordings carry on at the end of their files
+    // instead of starting them over.
+    pub fn start(config: &Config, relay: &Relay, append: bool, runtime_handle: &Handle) -> Result<Self> {
+        let mut started = Started(Vec::new());
+        let listeners = Listeners::new(AccessPolicy::from_config(config)?);
+        let tls = config.listen_tls.then(TlsIdentity::load_or_create).transpose()?.map(Arc::new);
+        for output in &config.outputs {
+            let chunks = relay.subscribe();
+            let task = match output {
+                Output::Udp { address } => {
+                    let target: SocketAddr = address.parse().with_context(|| format!("Invalid output address '{}'", address))?;
+                    let socket = std::net::UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+                    socket.set_nonblocking(true)?;
+                    qos::apply(&socket, config.dscp)?;
+                    runtime_handle.spawn(forward_udp(socket, target, chunks))
+                }
+                Output::Http { port } => {
+                    let listener = std::net::TcpListener::bind(("0.0.0.0", *port))
+                        .with_context(|| format!("Failed to listen for HTTP on port {}", port))?;
+                    listener.set_nonblocking(true)?;
+                    runtime_handle.spawn(serve_http(listener, chunks, listeners.clone(), format!("HTTP :{}", port), tls.clone()))
+                }
+                Output::File { path } => {
+                    let path = expand_home(path);
+                    let file = std::fs::OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .append(append)
+                        .truncate(!append)
+                        .open(&path)
+                        .with_context(|| format!("Failed to create {}", path.display()))?;
+                    runtime_handle.spawn(record(tokio::fs::File::from_std(file), chunks))
+                }
+                Output::Snapcast { target, control, stream } => {
+                    let decoder = {
+                        let _runtime = runtime_handle.enter(); // tokio spawns need it
+                        snapcast::start_decoder(&locate_ffmpeg(config)?, target)?
+                    };
+                    if !control.is_empty() && !stream.is_empty() {
+                        let (control, stream) = (control.clone(), stream.clone());
+                        runtime_handle.spawn(async move {
+                            if let Err(e) = snapcast::switch_groups(&control, &stream).await {
+                                log!("Could not switch Snapcast groups: {:#}", e);
+                            }
+                        });
+                    }
+                    runtime_handle.spawn(feed_process("Snapcast output", decoder, chunks))
+                }
+                Output::Icecast { server, mount, password, name, description } => {
+                    let source = {
+                        let _runtime = runtime_handle.enter();
+                        icecast::start_source(&locate_ffmpeg(config)?, config, server, mount, password, name, description)?
+                    };
+                    runtime_handle.spawn(feed_process("Icecast output", source, chunks))
+                }
+                Output::Sink { name } => {
+                    // pactl makes the sink on the runtime; only a name in use fails the start.
+                    let claim = Claim::new(name)?;
+                    let ffmpeg = locate_ffmpeg(config)?;
+                    runtime_handle.spawn(async move {
+                        let started = async {
+                            let sink = MixSink::create(claim).await?;
+                            let decoder = sink.start_decoder(&ffmpeg)?;
+                            anyhow::Ok((sink, decoder))
+                        };
+                        match started.await {
+                            // The sink is removed when the output stops
+                            Ok((_sink, decoder)) => feed_process("Mix output", decoder, chunks).await,
+                            Err(e) => log!("Mix output: {:#}", e),
+                        }
+                    })
+                }
+                Output::WebRtc { port, stun } => {
+                    let listener = std::net::TcpListener::bind(("0.0.0.0", *port))
+                        .with_context(|| format!("Failed to listen for the WebRTC page on port {}", port))?;
+                    listener.set_nonblocking(true)?;
+                    let rtp = std::net::UdpSocket::bind("127.0.0.1:0")?;
+                    rtp.set_nonblocking(true)?;
+                    let encoder = {
+                        let _runtime = runtime_handle.enter();
+                        browser::start_encoder(&locate_ffmpeg(config)?, rtp.local_addr()?)?
+                    };
+                    let player = BrowserPlayer::new(stun, listeners.clone(), format!("WebRTC :{}", port), tls.clone())?;
+                    runtime_handle.spawn(async move {
+                        tokio::select! {
+                            _ = feed_process("WebRTC output", encoder, chunks) => {}
+                            result = player.run(listener, rtp) => {
+                                if let Err(e) = result {
+                                    log!("WebRTC output stopped: {:#}", e);
+                                }
+                            }
+                        }
+                    })
+                }
+            };
+            started.0.push(task);
+        }
+        Ok(Self { tasks: std::mem::take(&mut started.0), listeners })
+    }
+
+    pub fn listeners(&self) -> &Listeners {
+        &self.listeners
+    }
+
+    pub fn stop(self) {
+        for task in self.tasks {
+            task.abort();
+        }
+    }
+}
+
+// None once the stream has ended; lagging just loses the skipped chunks.
+pub(crate) async fn next_chunk(chunks: &mut Receiver<Arc<[u8]>>) -> Option<Arc<[u8]>> {
+    loop {
+        match chunks.recv().await {
+            Ok(chunk) => return Some(chunk),
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+async fn forward_udp(socket: std::net::UdpSocket, target: SocketAddr, mut chunks: Receiver<Arc<[u8]>>) {
+    let Ok(socket) = UdpSocket::from_std(socket) else {
+        return;
+    };
+    while let Some(chunk) = next_chunk(&mut chunks).await {
+        let _ = socket.send_to(&chunk, target).await;
+    }
+}
+
+pub(crate) async fn serve_http(
+    listener: std::net::TcpListener,
+    chunks: Receiver<Arc<[u8]>>,
+    listeners: Listeners,
+    name: String,
+    tls: Option<Arc<TlsIdentity>>,
+) {
+    let Ok(listener) = TcpListener::from_std(listener) else {
+        return;
+    };
+    while let Ok((client, address)) = listener.accept().await {
+        let (chunks, listeners, name, tls) = (chunks.resubscribe(), listeners.clone(), name.clone(), tls.clone());
+        tokio::spawn(async move {
+            match tls {
+                Some(tls) => {
+                    if let Ok(client) = tls.accept(client).await {
+                        stream_to_client(client, address, chunks, listeners, &name, Some(&tls.certificate)).await;
+                    }
+                }
+                None => stream_to_client(client, address, chunks, listeners, &name, None).await,
+            }
+        });
+    }
+}
+
+// Whatever was requested, the answer is the live stream, apart from the certificate
+// over HTTPS; it starts mid-stream, which MPEG-TS players handle by waiting for the
+// next keyframe/sync point.
+async fn stream_to_client<S: AsyncRead + AsyncWrite + Unpin>(
+    mut client: S,
+    address: SocketAddr,
+    mut chunks: Receiver<Arc<[u8]>>,
+    listeners: Listeners,
+    name: &str,
+    certificate: Option<&[u8]>,
+) {
+    let mut request = [0u8; 2048];
+    let Ok(read) = client.read(&mut request).await else {
+        return;
+    };
+    let head = String::from_utf8_lossy(&request[..read]);
+    if let Some(certificate) = certificate
+        && head.split_whitespace().nth(1) == Some(CERTIFICATE_PATH)
+    {
+        let _ = client.write_all(&certificate_response(certificate)).await;
+        return;
+    }
+    let admission = match listeners.admit(address, name, &head) {
+        Ok(admission) => admission,
+        Err(refusal) => {
+            let _ = client.write_all(refusal.response().as_bytes()).await;
+            return;
+        }
+    };
+    if client.write_all(HTTP_HEADER).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            chunk = next_chunk(&mut chunks) => {
+                let Some(chunk) = chunk else {
+                    return;
+                };
+                if client.write_all(&chunk).await.is_err() {
+                    return; // Player went away
+                }
+                admission.sent(chunk.len());
+            }
+            _ = admission.kicked() => return,
+        }
+    }
+}
+
+// For outputs that hand the stream to an ffmpeg of their own.
+async fn feed_process(name: &str, mut process: Child, mut chunks: Receiver<Arc<[u8]>>) {
+    let Some(mut input) = process.stdin.take() else {
+        return;
+    };
+    while let Some(chunk) = next_chunk(&mut chunks).await {
+        if input.write_all(&chunk).await.is_err() {
+            log!("{} stopped: its ffmpeg exited", name);
+            return;
+        }
+    }
+}
+
+async fn record(mut file: tokio::fs::File, mut chunks: Receiver<Arc<[u8]>>) {
+    while let Some(chunk) = next_chunk(&mut chunks).await {
+        if let Err(e) = file.write_all(&chunk).await {
+            log!("Recording stopped: {}", e);
+            return;
+        }
+    }
+    let _ = file.flush().await;
+}
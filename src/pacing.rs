@@ -0,0 +1,91 @@
+use crate::config::Config;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Above the nominal bitrate, for MPEG-TS and native headers, FEC parity and a VBR
+// encoder's peaks, so the pacer only ever smooths and never falls behind.
+const HEADROOM: f64 = 1.5;
+// A chunk held this long goes out regardless: the encoder outran the rate (e.g. a wrong
+// bitrate setting), and a burst is better than latency that keeps growing.
+const MAX_HOLD: Duration = Duration::from_millis(200);
+
+// A token bucket: `burst_bytes` may leave back to back, then the rest at `rate`, released
+// every `interval`. ffmpeg writes a muxer's worth of packets at once; Wi-Fi and the
+// receiver's jitter buffer both cope better with them spread out.
+#[derive(Debug, Clone, Copy)]
+pub struct Pacing {
+    pub rate_bytes_per_sec: f64,
+    pub burst_bytes: usize,
+    pub interval: Duration,
+}
+
+impl Pacing {
+    // None when it's off, or there is no bitrate to pace at (FLAC and PCM ignore the setting).
+    pub fn from_config(config: &Config, packet_size: usize) -> Option<Self> {
+        if config.pacing_burst == 0 || config.is_lossless() {
+            return None;
+        }
+        let bits_per_sec = config.bitrate_bps()?;
+        Some(Self {
+            rate_bytes_per_sec: bits_per_sec as f64 / 8.0 * HEADROOM,
+            burst_bytes: config.pacing_burst as usize * packet_size,
+            interval: Duration::from_millis(config.pacing_interval_ms.max(1) as u64),
+        })
+    }
+}
+
+pub struct Pacer {
+    pacing: Option<Pacing>, // None passes chunks straight through
+    tokens: f64,
+    refilled: Instant,
+    queue: VecDeque<(Instant, Vec<u8>)>, // With when they arrived
+}
+
+impl Pacer {
+    pub fn new(pacing: Option<Pacing>) -> Self {
+        let tokens = pacing.map_or(0.0, |pacing| pacing.burst_bytes as f64);
+        Self { pacing, tokens, refilled: Instant::now(), queue: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, chunk: Vec<u8>, now: Instant) {
+        self.queue.push_back((now, chunk));
+    }
+
+    fn refill(&mut self, pacing: Pacing, now: Instant) {
+        let ticks = (now.saturating_duration_since(self.refilled).as_nanos() / pacing.interval.as_nanos().max(1)) as u32;
+        if ticks == 0 {
+            return;
+        }
+        self.refilled += pacing.interval * ticks;
+        let added = pacing.rate_bytes_per_sec * pacing.interval.as_secs_f64() * ticks as f64;
+        self.tokens = (self.tokens + added).min(pacing.burst_bytes as f64);
+    }
+
+    // The next chunk that may be sent now; call until None.
+    pub fn pop(&mut self, now: Instant) -> Option<Vec<u8>> {
+        let Some(pacing) = self.pacing else {
+            return self.queue.pop_front().map(|(_, chunk)| chunk);
+        };
+        self.refill(pacing, now);
+        let (arrived, chunk) = self.queue.front()?;
+        // A chunk bigger than the whole bucket goes once the bucket is full.
+        let cost = chunk.len().min(pacing.burst_bytes) as f64;
+        if self.tokens >= cost {
+            self.tokens -= cost;
+        } else if now.saturating_duration_since(*arrived) >= MAX_HOLD {
+            self.tokens = 0.0;
+        } else {
+            return None;
+        }
+        self.queue.pop_front().map(|(_, chunk)| chunk)
+    }
+
+    // When to call `pop` again; None while nothing is waiting.
+    pub fn next_release(&self) -> Option<Instant> {
+        let pacing = self.pacing?;
+        self.queue.front()?;
+        Some(self.refilled + pacing.interval)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::log;
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+const APP: &str = "audio-streamer";
+// Next to the executable in portable mode, holding the config with state/ and cache/.
+const PORTABLE_DIR: &str = "audio-streamer-data";
+// What used to sit next to the config before it moved to the state directory.
+const STATE_ENTRIES: [&str; 4] = ["history.json", "last-session.json", "crashes", "backups"];
+
+static PORTABLE: OnceLock<PathBuf> = OnceLock::new();
+
+// Keeps everything next to the executable, e.g. on a USB stick, instead of in the
+// user's XDG directories. Must come before anything asks for a directory.
+pub fn set_portable() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Could not find the executable")?;
+    let dir = exe.parent().context("The executable has no directory")?.join(PORTABLE_DIR);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(PORTABLE.get_or_init(|| dir).clone())
+}
+
+fn portable() -> Option<&'static Path> {
+    PORTABLE.get().map(PathBuf::as_path)
+}
+
+// Settings, profiles and the TLS certificate: $XDG_CONFIG_HOME/audio-streamer.
+pub fn config_dir() -> Result<PathBuf> {
+    match portable() {
+        Some(dir) => Ok(dir.to_path_buf()),
+        None => Ok(dirs::config_dir().context("Could not find config directory")?.join(APP)),
+    }
+}
+
+pub fn default_config_path() -> Result<PathBuf> {
+    let dir = config_dir()?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join("config.json"))
+}
+
+// History, the last session, crash reports and config backups, which change on their
+// own: $XDG_STATE_HOME/audio-streamer. A config file elsewhere (--config) keeps them
+// beside it instead, so separate setups don't share a history.
+pub fn state_dir(config_path: &Path) -> PathBuf {
+    let beside = config_path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    if config_dir().ok().as_ref() != Some(&beside) {
+        return beside;
+    }
+    match portable() {
+        Some(dir) => dir.join("state"),
+        None => dirs::state_dir().map_or(beside, |dir| dir.join(APP)), // Linux only
+    }
+}
+
+// Files that can be made again: SDP files and comparison clips. $XDG_CACHE_HOME/audio-streamer.
+pub fn cache_dir() -> PathBuf {
+    match portable() {
+        Some(dir) => dir.join("cache"),
+        None => dirs::cache_dir().unwrap_or_else(std::env::temp_dir).join(APP),
+    }
+}
+
+// Creates the state directory, and moves state an older version left next to the
+// config into it. Anything already there wins.
+pub fn prepare(config_path: &Path) {
+    let state = state_dir(config_path);
+    if let Err(e) = fs::create_dir_all(&state) {
+        log!("Failed to create {}: {}", state.display(), e);
+        return;
+    }
+    for entry in STATE_ENTRIES {
+        let (old, new) = (config_path.with_file_name(entry), state.join(entry));
+        if old == new || !old.exists() || new.exists() {
+            continue;
+        }
+        match fs::rename(&old, &new) {
+            Ok(()) => log!("Moved {} to {}", old.display(), new.display()),
+            Err(e) => log!("Failed to move {} to {}: {}", old.display(), new.display(), e),
+        }
+    }
+}
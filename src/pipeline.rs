@@ -0,0 +1,69 @@
+use crate::{
+    config::Config,
+    fallback::{self, Engine, parec_args},
+    mtu,
+    sdp,
+    transport::Transport,
+};
+use anyhow::Result;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::Path,
+};
+
+// Placeholder for the relay's local port, which is only picked when streaming starts.
+pub const RELAY_URL_PLACEHOLDER: &str = "udp://127.0.0.1:<relay port>";
+
+// Human-readable description of everything Start would launch, for previews,
+// `--dry-run` and bug reports. Nothing is spawned.
+pub fn describe_pipeline(config: &Config, source: &str, engine: Engine, ffmpeg_path: Option<&Path>) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let transport = fallback::relay_transport(config, engine);
+    let target = config.target_ip.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let packets = mtu::plan(config, target, transport);
+    match engine {
+        Engine::Ffmpeg => {
+            let output_url = format!("{}?pkt_size={}", RELAY_URL_PLACEHOLDER, packets.ts_size);
+            let args = config.build_ffmpeg_command(source, &output_url, None)?;
+            let binary = ffmpeg_path.map(|p| p.display().to_string()).unwrap_or_else(|| "ffmpeg".to_string());
+            lines.push(format!("{} {}", binary, args.join(" ")));
+        }
+        Engine::BuiltIn => {
+            let bits = config.sample_format.rtp_bits();
+            let args = parec_args(source, config.sample_rate, config.channels, bits, config.packet_millis);
+            let format = if fallback::sends_flac(config) { "FLAC" } else { "RTP L" };
+            lines.push(format!("parec {} | {}{}/{}/{} → {}", args.join(" "), format, bits, config.sample_rate, config.channels, RELAY_URL_PLACEHOLDER));
+        }
+    }
+
+    let wrapping = match transport {
+        Transport::Udp | Transport::Rtp => "forwarded as-is".to_string(),
+        Transport::Native if config.fec_group_size > 1 => format!("native transport, 1 parity per {} packets", config.fec_group_size),
+        Transport::Native => "native transport, no FEC".to_string(),
+        Transport::Rist => "through the RIST gateway".to_string(),
+    };
+    lines.push(format!("relay {} → {}:{} ({})", RELAY_URL_PLACEHOLDER, config.target_ip, config.target_port, wrapping));
+    if transport == Transport::Rist {
+        lines.push(format!("ffmpeg RIST gateway, {} ms recovery buffer", config.rist_buffer_ms));
+    }
+    lines.push(format!("{}{}", packets.describe(), packets.warning.map(|warning| format!(" ⚠ {}", warning)).unwrap_or_default()));
+    if config.audio_delay_ms > 0 {
+        lines.push(format!("delay {} ms before sending", config.audio_delay_ms));
+    }
+    if sdp::applies(config, engine) {
+        lines.push(format!("SDP served at {}, saved to {}", sdp::url(sdp::local_address(target), config.target_port), sdp::path_for(SocketAddr::new(target, config.target_port)).display()));
+    }
+    // Extra outputs are fed MPEG-TS.
+    if engine == Engine::Ffmpeg && config.transport != Transport::Rtp {
+        for output in &config.outputs {
+            lines.push(format!("tee → {}", output.describe(config.listen_tls)));
+        }
+    }
+    if config.has_backup_target() {
+        lines.push(format!(
+            "failover → {}:{} after {} s unreachable",
+            config.backup_target_ip, config.backup_target_port, config.failover_after_secs
+        ));
+    }
+    Ok(lines)
+}
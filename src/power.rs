@@ -0,0 +1,50 @@
+use crate::config::Config;
+use std::{fs, process::Command, time::Duration};
+
+// How often the GUI checks whether the power source changed.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Redraws while idle on battery; the usual is once a second.
+pub const BATTERY_REPAINT_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_SAMPLE_RATE: u32 = 32000;
+const MIN_BITRATE_KBPS: u32 = 64;
+const PACKET_MILLIS: u32 = 10;
+
+// upower's daemon summary has an "on-battery: yes/no" line; without upower, any
+// mains supply in sysfs reporting offline means we run on battery.
+pub fn on_battery() -> bool {
+    if let Ok(output) = Command::new("upower").args(["-i", "/org/freedesktop/UPower"]).output()
+        && let Some(line) = String::from_utf8_lossy(&output.stdout).lines().find(|line| line.trim_start().starts_with("on-battery:"))
+    {
+        return line.trim_end().ends_with("yes");
+    }
+    let Ok(supplies) = fs::read_dir("/sys/class/power_supply") else {
+        return false;
+    };
+    supplies.flatten().any(|supply| {
+        let path = supply.path();
+        let is_mains = fs::read_to_string(path.join("type")).is_ok_and(|kind| kind.trim() == "Mains");
+        is_mains && fs::read_to_string(path.join("online")).is_ok_and(|online| online.trim() == "0")
+    })
+}
+
+// "192k" becomes "96k"; anything that isn't plain kbit/s is left alone.
+fn reduced_bitrate(bitrate: &str) -> String {
+    match bitrate.strip_suffix('k').and_then(|kbps| kbps.parse::<u32>().ok()) {
+        Some(kbps) => format!("{}k", (kbps / 2).max(MIN_BITRATE_KBPS).min(kbps)),
+        None => bitrate.to_string(),
+    }
+}
+
+// The battery profile: fewer samples to encode, fewer bits to send and fewer, larger
+// packets, which lets the CPU and Wi-Fi radio sleep longer between them.
+pub fn power_saving(config: &Config) -> Config {
+    let mut saving = config.clone();
+    // Opus only encodes at a few fixed rates and resamples to 48 kHz internally anyway.
+    if config.audio_codec != "libopus" {
+        saving.sample_rate = config.sample_rate.min(MAX_SAMPLE_RATE);
+    }
+    saving.bitrate = reduced_bitrate(&config.bitrate);
+    saving.low_latency = false; // ffmpeg then fills each datagram instead of flushing every packet
+    saving.packet_millis = config.packet_millis.max(PACKET_MILLIS);
+    saving
+}
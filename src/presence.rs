@@ -0,0 +1,83 @@
+use crate::transport::{Packet, PacketKind};
+use std::{fs, time::Duration};
+
+// Receivers send a keepalive every second; after this long without one the receiver is gone.
+pub const PRESENCE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// What a receiver says about itself in its keepalives. Payload layout: playout buffer
+// in ms (2) | device name. Receivers that predate it send an empty payload.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReceiverStatus {
+    pub name: Option<String>,
+    pub buffer: Option<Duration>,
+}
+
+impl ReceiverStatus {
+    pub fn encode(&self) -> Vec<u8> {
+        let buffer_ms = self.buffer.map_or(0, |buffer| buffer.as_millis().min(u16::MAX as u128) as u16);
+        let mut payload = buffer_ms.to_be_bytes().to_vec();
+        payload.extend_from_slice(self.name.as_deref().unwrap_or_default().as_bytes());
+        payload
+    }
+
+    pub fn decode(payload: &[u8]) -> Self {
+        let Some((buffer, name)) = payload.split_first_chunk::<2>() else {
+            return Self::default();
+        };
+        let name = String::from_utf8_lossy(name).trim().to_string();
+        Self {
+            name: (!name.is_empty()).then_some(name),
+            buffer: Some(Duration::from_millis(u16::from_be_bytes(*buffer) as u64)),
+        }
+    }
+
+    // "Receiver connected (Pixel 7, buffer 40 ms)"
+    pub fn describe(&self) -> String {
+        let details: Vec<String> = self
+            .name
+            .iter()
+            .cloned()
+            .chain(self.buffer.map(|buffer| format!("buffer {} ms", buffer.as_millis())))
+            .collect();
+        if details.is_empty() {
+            "Receiver connected".to_string()
+        } else {
+            format!("Receiver connected ({})", details.join(", "))
+        }
+    }
+}
+
+// A receiver lost the stream for a while and picked it up again in the same session,
+// keeping its decoder and clock sync. Payload layout: gap in ms (4) | packets skipped (4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resumption {
+    pub gap: Duration,
+    pub skipped: u32, // Sent while nothing got through
+}
+
+impl Resumption {
+    // `seq` is the packet it carried on with.
+    pub fn packet(&self, session: u32, seq: u32) -> Packet {
+        let gap_ms = self.gap.as_millis().min(u32::MAX as u128) as u32;
+        let mut payload = gap_ms.to_be_bytes().to_vec();
+        payload.extend_from_slice(&self.skipped.to_be_bytes());
+        Packet { kind: PacketKind::Resumed, seq, fec_group: 0, session, timestamp_us: 0, payload }
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let (gap_ms, rest) = payload.split_first_chunk::<4>()?;
+        let skipped = rest.first_chunk::<4>()?;
+        Some(Self { gap: Duration::from_millis(u32::from_be_bytes(*gap_ms) as u64), skipped: u32::from_be_bytes(*skipped) })
+    }
+
+    // "Pixel 7 resumed after 1.2 s gap (120 packets skipped)"
+    pub fn describe(&self, receiver: Option<&str>) -> String {
+        format!("{} resumed after {:.1} s gap ({} packets skipped)", receiver.unwrap_or("Receiver"), self.gap.as_secs_f64(), self.skipped)
+    }
+}
+
+// How this machine introduces itself, as a receiver and in stream tags.
+pub fn device_name() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
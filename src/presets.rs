@@ -0,0 +1,111 @@
+use crate::{config::Config, outputs::Output, transport::Transport};
+
+// A built-in starting point for a common receiver: sets the transport, codec and
+// outputs it needs, and says what to do on the other end. The target is left alone.
+pub struct Preset {
+    pub name: &'static str,
+    pub summary: &'static str,
+    apply: fn(&mut Config),
+    instructions: fn(&Config) -> String,
+}
+
+impl Preset {
+    pub fn apply(&self, config: &mut Config) {
+        (self.apply)(config)
+    }
+
+    // For the config after `apply`, so ports and addresses are the ones in use.
+    pub fn instructions(&self, config: &Config) -> String {
+        (self.instructions)(config)
+    }
+}
+
+pub const PRESETS: [Preset; 4] = [
+    Preset {
+        name: "VLC on Android",
+        summary: "Plain MPEG-TS over UDP with AAC, which VLC plays without extra setup",
+        apply: |config| {
+            config.transport = Transport::Udp;
+            config.audio_codec = "aac".to_string();
+            config.bitrate = "192k".to_string();
+        },
+        instructions: |config| {
+            format!(
+                "In VLC, open More → New stream and enter udp://@:{}, with the phone's IP as the target above. Keep the phone awake, or VLC stops when the screen turns off.",
+                config.target_port
+            )
+        },
+    },
+    Preset {
+        name: "mpv low latency",
+        summary: "Opus over UDP with the smallest buffers, for a desktop or laptop receiver",
+        apply: |config| {
+            config.transport = Transport::Udp;
+            config.audio_codec = "libopus".to_string();
+            config.bitrate = "128k".to_string();
+            config.low_latency = true;
+        },
+        instructions: |config| {
+            format!("On the receiver, run: mpv --profile=low-latency --untimed --cache=no udp://@:{}", config.target_port)
+        },
+    },
+    Preset {
+        name: "Sonos via DLNA",
+        summary: "MP3 through an Icecast server, which Sonos plays as an internet radio station",
+        apply: |config| {
+            config.transport = Transport::Udp;
+            config.audio_codec = "libmp3lame".to_string();
+            config.bitrate = "320k".to_string(); // Sonos' limit for MP3
+            if !config.outputs.iter().any(|output| matches!(output, Output::Icecast { .. })) {
+                config.outputs.push(Output::Icecast {
+                    server: String::new(),
+                    mount: "/live.mp3".to_string(),
+                    password: String::new(),
+                    name: "audio-streamer".to_string(),
+                    description: String::new(),
+                });
+            }
+        },
+        instructions: |config| {
+            let url = config
+                .outputs
+                .iter()
+                .find_map(|output| match output {
+                    Output::Icecast { server, mount, .. } if !server.is_empty() => Some(format!("http://{}{}", server, mount)),
+                    _ => None,
+                })
+                .unwrap_or_else(|| "http://<icecast server>:8000/live.mp3".to_string());
+            format!(
+                "Sonos can't be sent to directly, and there is no DLNA server built in, so the stream goes through Icecast: fill in its server and password under Extra outputs. Then, in the Sonos app, add {} as a radio station (Browse → TuneIn → My Radio Stations → Add New Radio Station). Sonos buffers a few seconds, so it won't keep up with video.",
+                url
+            )
+        },
+    },
+    Preset {
+        name: "Browser WebRTC",
+        summary: "A player page that any phone or laptop browser can open, with WebRTC latency",
+        apply: |config| {
+            // The page's peer connection encodes its own Opus; the main target keeps going as UDP.
+            config.transport = Transport::Udp;
+            config.audio_codec = "libopus".to_string();
+            if !config.outputs.iter().any(|output| matches!(output, Output::WebRtc { .. })) {
+                config.outputs.push(Output::WebRtc { port: 8081, stun: String::new() });
+            }
+        },
+        instructions: |config| {
+            let port = config
+                .outputs
+                .iter()
+                .find_map(|output| match output {
+                    Output::WebRtc { port, .. } => Some(*port),
+                    _ => None,
+                })
+                .unwrap_or(8081);
+            let scheme = if config.listen_tls { "https" } else { "http" };
+            format!(
+                "Open {}://<this machine>:{}/ in the browser on the same network and press Play. Browsers only start audio after a tap, so the page waits for one.",
+                scheme, port
+            )
+        },
+    },
+];
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result, bail};
+use std::time::Duration;
+use tokio::{process::Command, time::sleep};
+use zbus::Connection;
+
+// ffmpeg starts its encoder and muxer threads once the input is open; threads started
+// after the boost fall back to normal, since rtkit marks them reset-on-fork.
+const THREADS_SETTLE: Duration = Duration::from_secs(1);
+// Within rtkit's defaults (MaxRealtimePriority 20, RTTimeUSecMax 200 ms): the RLIMIT_RTTIME
+// it insists on caps how long a realtime thread may run without blocking, and the
+// kernel kills the process past it. Only the capture thread gets realtime, since it
+// blocks on every read; an encoder busy for a fifth of a second would not.
+const REALTIME_PRIORITY: u32 = 10;
+const RTTIME_LIMIT_US: u32 = 200_000;
+// The highest rtkit grants by default (MinNiceLevel -15).
+const HIGH_PRIORITY_NICE: i32 = -11;
+const RTKIT_SERVICE: &str = "org.freedesktop.RealtimeKit1";
+const RTKIT_PATH: &str = "/org/freedesktop/RealtimeKit1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boost {
+    Realtime,     // SCHED_RR through rtkit
+    HighPriority, // A negative nice level through rtkit, when realtime is refused
+}
+
+impl Boost {
+    pub fn label(self) -> &'static str {
+        match self {
+            Boost::Realtime => "realtime scheduling for the capture thread",
+            Boost::HighPriority => "high priority (nice -11)",
+        }
+    }
+}
+
+fn threads(pid: u32) -> Vec<u32> {
+    let Ok(tasks) = std::fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return Vec::new();
+    };
+    tasks.filter_map(|task| task.ok()?.file_name().to_str()?.parse().ok()).collect()
+}
+
+// The thread that reads the device: ffmpeg 6.1 and later read each input in a thread
+// named after its demuxer (dmx0:pulse); older ones, and parec, in the main thread.
+fn capture_thread(pid: u32, threads: &[u32]) -> u32 {
+    threads
+        .iter()
+        .copied()
+        .find(|thread| std::fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, thread)).is_ok_and(|name| name.starts_with("dmx")))
+        .unwrap_or(pid)
+}
+
+// One rtkit method per thread, over the system bus.
+async fn boost_threads(connection: &Connection, pid: u32, threads: &[u32], boost: Boost) -> Result<()> {
+    for thread in threads {
+        let (pid, thread) = (pid as u64, *thread as u64);
+        let reply = match boost {
+            Boost::Realtime => connection.call_method(Some(RTKIT_SERVICE), RTKIT_PATH, Some(RTKIT_SERVICE), "MakeThreadRealtimeWithPID", &(pid, thread, REALTIME_PRIORITY)).await,
+            Boost::HighPriority => connection.call_method(Some(RTKIT_SERVICE), RTKIT_PATH, Some(RTKIT_SERVICE), "MakeThreadHighPriorityWithPID", &(pid, thread, HIGH_PRIORITY_NICE)).await,
+        };
+        reply?;
+    }
+    Ok(())
+}
+
+// Raises the capture/encode process `pid` (ffmpeg, or parec for the built-in engine)
+// above everything else competing for the CPU, so a busy machine doesn't cause
+// dropouts. rtkit hands this out to unprivileged desktop processes; without it,
+// or when it refuses, nothing changes.
+pub async fn boost(pid: u32) -> Result<Boost> {
+    sleep(THREADS_SETTLE).await;
+    let threads = threads(pid);
+    if threads.is_empty() {
+        bail!("Process {} has already exited", pid);
+    }
+    let connection = Connection::system().await.context("Could not reach the system bus")?;
+    // rtkit only grants realtime to processes that limit their own CPU time.
+    let limit = format!("--rttime={}", RTTIME_LIMIT_US);
+    let limited = Command::new("prlimit").args(["--pid", &pid.to_string(), &limit]).status().await.is_ok_and(|status| status.success());
+    let capture = capture_thread(pid, &threads);
+    let others: Vec<u32> = threads.iter().copied().filter(|thread| *thread != capture).collect();
+    if limited && boost_threads(&connection, pid, &[capture], Boost::Realtime).await.is_ok() {
+        // Best effort: the capture thread is what keeps up with the device.
+        let _ = boost_threads(&connection, pid, &others, Boost::HighPriority).await;
+        return Ok(Boost::Realtime);
+    }
+    boost_threads(&connection, pid, &threads, Boost::HighPriority).await.context("rtkit refused both realtime and high priority")?;
+    Ok(Boost::HighPriority)
+}
@@ -0,0 +1,66 @@
+use crate::{config::Config, secrets};
+use anyhow::{Context, Result, bail};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Named configs, e.g. "living-room" and "office" with their own targets and codecs,
+// kept as JSON in a `profiles` directory next to the config file.
+fn dir_for(config_path: &Path) -> PathBuf {
+    config_path.with_file_name("profiles")
+}
+
+fn path_for(config_path: &Path, name: &str) -> Result<PathBuf> {
+    // The name becomes a file name, so nothing that could leave the directory.
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        bail!("Profile names may only use letters, digits, '-' and '_', not '{}'", name);
+    }
+    Ok(dir_for(config_path).join(format!("{}.json", name)))
+}
+
+pub fn list(config_path: &Path) -> Result<Vec<String>> {
+    let Ok(entries) = fs::read_dir(dir_for(config_path)) else {
+        return Ok(Vec::new()); // None saved yet
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            (path.extension()? == "json").then(|| path.file_stem()?.to_str().map(str::to_string))?
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+// The text of every profile file, for the keyring entries they refer to.
+pub fn contents(config_path: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir_for(config_path)) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .collect()
+}
+
+pub fn load(config_path: &Path, name: &str) -> Result<Config> {
+    let path = path_for(config_path, name)?;
+    let content = fs::read_to_string(&path).with_context(|| format!("No profile named '{}'", name))?;
+    Config::from_json(&content).with_context(|| format!("Profile '{}' is not a valid config", name))
+}
+
+pub fn save(config_path: &Path, name: &str, config: &Config) -> Result<()> {
+    let path = path_for(config_path, name)?;
+    fs::create_dir_all(dir_for(config_path))?;
+    fs::write(&path, config.to_json()?).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub fn delete(config_path: &Path, name: &str) -> Result<()> {
+    let path = path_for(config_path, name)?;
+    let dropped: Vec<String> = fs::read_to_string(&path).into_iter().collect();
+    fs::remove_file(&path).with_context(|| format!("No profile named '{}'", name))?;
+    secrets::prune(config_path, &dropped);
+    Ok(())
+}
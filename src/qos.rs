@@ -0,0 +1,50 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use socket2::SockRef;
+use std::net::UdpSocket;
+
+// DiffServ class put in the IP header of everything the relay sends, so routers and
+// Wi-Fi access points with QoS (WMM maps EF and CS5 to the voice queue) send it first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dscp {
+    Off,
+    Ef,   // Expedited forwarding, what VoIP uses
+    Cs5,  // Broadcast video/audio
+    Af41, // Interactive media; some routers only honour the AF classes
+}
+
+impl Dscp {
+    pub const ALL: [Dscp; 4] = [Dscp::Off, Dscp::Ef, Dscp::Cs5, Dscp::Af41];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Dscp::Off => "Off (best effort)",
+            Dscp::Ef => "EF (voice)",
+            Dscp::Cs5 => "CS5 (broadcast)",
+            Dscp::Af41 => "AF41 (interactive media)",
+        }
+    }
+
+    fn code_point(&self) -> u32 {
+        match self {
+            Dscp::Off => 0,
+            Dscp::Ef => 46,
+            Dscp::Cs5 => 40,
+            Dscp::Af41 => 34,
+        }
+    }
+}
+
+// DSCP is the top six bits of the IPv4 TOS byte and the IPv6 traffic class; the
+// low two (ECN) stay clear. ffmpeg only ever sends to the relay on loopback, so its
+// own sockets are left alone.
+pub fn apply(socket: &UdpSocket, dscp: Dscp) -> Result<()> {
+    if dscp == Dscp::Off {
+        return Ok(());
+    }
+    let socket = SockRef::from(socket);
+    let tos = dscp.code_point() << 2;
+    let result = if socket.local_addr()?.is_ipv6() { socket.set_tclass_v6(tos) } else { socket.set_tos_v4(tos) };
+    result.with_context(|| format!("Failed to mark packets as {}", dscp.label()))
+}
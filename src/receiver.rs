@@ -0,0 +1,404 @@
+use crate::{
+    beacon::{BeaconDetector, beacon_latency},
+    bridge,
+    config::Config,
+    firewall::{Firewall, PortStatus, allow_port, check_port},
+    flac,
+    jitter::JitterBuffer,
+    negotiate::Capabilities,
+    network::ProbeStats,
+    notify::Notification,
+    presence::{PRESENCE_TIMEOUT, ReceiverStatus, device_name},
+    remote::RemoteCommand,
+    rendezvous::{self, Message, REFRESH_INTERVAL, Role, Room},
+    sync::SyncClock,
+    tag::StreamTag,
+    transport::{Packet, PacketKind, seq_lt},
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write, stdin, stdout},
+    net::SocketAddr,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::UdpSocket,
+    process::{Child, ChildStdin},
+    sync::mpsc,
+};
+
+const PROBE_IDLE_TIMEOUT: Duration = Duration::from_millis(300);
+const PLAYOUT_INTERVAL: Duration = Duration::from_millis(5);
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+const SYNC_INTERVAL: Duration = Duration::from_secs(2);
+// Capabilities go out with this many keepalives of each run of a sender, in case some are lost.
+const CAPABILITY_REPEATS: u8 = 3;
+
+fn offer_to_allow(firewall: Firewall, port: u16) {
+    let commands: Vec<String> = firewall.allow_commands(port).iter().map(|c| c.join(" ")).collect();
+    print!("Run `{}` now? [y/N] ", commands.join(" && "));
+    let _ = stdout().flush();
+    let mut answer = String::new();
+    if stdin().lock().read_line(&mut answer).is_err() || !answer.trim().eq_ignore_ascii_case("y") {
+        return;
+    }
+    match allow_port(firewall, port) {
+        Ok(()) => println!("UDP port {} is now open in {}", port, firewall.label()),
+        Err(e) => println!("Could not open the port: {:#}", e),
+    }
+}
+
+// A blocked port looks exactly like a sender that isn't sending, so say so up front.
+fn check_firewall(port: u16) {
+    match check_port(port) {
+        PortStatus::NoFirewall | PortStatus::Allowed(_) => {}
+        PortStatus::Blocked(firewall) => {
+            println!("⚠ {} is blocking incoming UDP on port {}; the stream will not arrive.", firewall.label(), port);
+            offer_to_allow(firewall, port);
+        }
+        PortStatus::Unknown(firewall, reason) => {
+            println!("⚠ {} is active but its rules could not be checked ({}).", firewall.label(), reason);
+            println!("  If nothing arrives, UDP port {} is probably blocked.", port);
+            offer_to_allow(firewall, port);
+        }
+    }
+}
+
+// Lines typed while receiving, read on their own thread since stdin blocks.
+fn read_commands() -> mpsc::UnboundedReceiver<String> {
+    let (lines_tx, lines) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in stdin().lock().lines().map_while(Result::ok) {
+            if lines_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    lines
+}
+
+// Says who is sending when that changes, and warns when a second sender shares the
+// port, since both streams then garble each other.
+fn announce(announced: &mut HashMap<SocketAddr, (StreamTag, Instant)>, from: SocketAddr, tag: StreamTag) {
+    let now = Instant::now();
+    announced.retain(|_, (_, seen)| now - *seen < PRESENCE_TIMEOUT);
+    let known = announced.get(&from).is_some_and(|(known, _)| *known == tag);
+    if !known {
+        let others = announced.keys().filter(|address| **address != from).count();
+        if others == 0 {
+            println!("\nReceiving from {} at {}", tag.describe(), from);
+        } else {
+            println!("\n⚠ {} at {} is also sending to this port", tag.describe(), from);
+        }
+    }
+    announced.insert(from, (tag, now));
+}
+
+// Shows a sender's notification here, and on the desktop where one runs a notification daemon.
+fn show_notification(notification: &Notification) {
+    let text = notification.describe();
+    println!("\n🔔 {}", text);
+    let shown = Command::new("notify-send")
+        .args(["--app-name=audio-streamer", "audio-streamer", &text])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Ok(mut child) = shown {
+        std::thread::spawn(move || child.wait());
+    }
+}
+
+// ffplay, for MPEG-TS from a sender's ffmpeg or FLAC frames from its built-in engine.
+// FLAC needs a stream header first, which is made from the frames' format.
+struct Player {
+    _process: Child, // Killed when dropped
+    input: ChildStdin,
+    flac: Option<flac::Format>,
+}
+
+impl Player {
+    async fn start(flac: Option<flac::Format>) -> Result<Self> {
+        let format = if flac.is_some() { "flac" } else { "mpegts" };
+        let mut process = tokio::process::Command::new("ffplay")
+            .args(["-nodisp", "-loglevel", "error", "-fflags", "nobuffer", "-flags", "low_delay", "-f", format, "-i", "pipe:0"])
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start ffplay")?;
+        let mut input = process.stdin.take().context("ffplay has no stdin")?;
+        if let Some(format) = &flac {
+            input.write_all(&flac::stream_header(format)).await?;
+        }
+        Ok(Self { _process: process, input, flac })
+    }
+}
+
+// Receiver mode: listens for the native transport, repairs it and plays it with ffplay.
+// `rendezvous` is a helper and room to meet the sender at, for one behind another NAT;
+// `relay` an `audio-streamer relay` server to pull the stream from, and its key.
+pub async fn run_receiver(port: u16, config: &Config, rendezvous: Option<(SocketAddr, Room)>, relay: Option<(SocketAddr, String)>) -> Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))
+        .await
+        .with_context(|| format!("Failed to listen on UDP port {}", port))?;
+
+    // Started on the first audio, once it is clear what the sender sends.
+    let mut player: Option<Player> = None;
+
+    // The beacon is optional; without ffmpeg we still play, just without latency figures.
+    let mut detector = BeaconDetector::start(config).unwrap_or_else(|e| {
+        println!("⚠ Latency beacon detection disabled: {:#}", e);
+        None
+    });
+
+    check_firewall(port);
+    if let Some((server, room)) = &rendezvous {
+        println!("Waiting for the sender at {} in room '{}'", server, room.name);
+        let path = rendezvous::pair(&socket, *server, room, Role::Receiver).await?;
+        println!("Paired: {}", path);
+    }
+    if let Some((relay, _)) = &relay {
+        println!("Pulling the stream from the relay server at {}", relay);
+    }
+    println!("Receiving on UDP port {} (Ctrl+C to stop)", port);
+    println!("Type `volume 80`, `mute`, `unmute` or `codec libopus` and Enter to control the sender");
+    let mut commands = read_commands();
+
+    let mut buffer = JitterBuffer::new(
+        Duration::from_millis(config.jitter_target_ms as u64),
+        Duration::from_millis(config.jitter_min_ms as u64),
+        Duration::from_millis(config.jitter_max_ms as u64),
+        config.late_packet_policy,
+        (config.sync_playout_ms > 0).then(|| Duration::from_millis(config.sync_playout_ms as u64)),
+    );
+    let sync_enabled = config.sync_playout_ms > 0;
+    // Beacon latency is measured on the sender's clock, so it needs the sync exchange too.
+    let clock_requests = sync_enabled || detector.is_some();
+    let mut latency: Option<Duration> = None;
+    let mut clock = SyncClock::new();
+    // The relay server starts sending once we have answered its challenge.
+    let mut sender: Option<SocketAddr> = relay.as_ref().map(|(server, _)| *server);
+    let mut announced: HashMap<SocketAddr, (StreamTag, Instant)> = HashMap::new(); // Senders that said who they are
+    let mut notified: Option<(u32, u32)> = None; // Session and sequence of the last notification shown
+    let mut paths: Vec<SocketAddr> = Vec::new(); // Where the played session's audio comes from, both paths with a second one
+    let name = device_name();
+    let capabilities = Capabilities::of_receiver(config).await;
+    println!("Offering senders {}{}", capabilities.codecs.join(", "), if capabilities.max_bitrate_kbps > 0 { format!(" up to {} kbit/s", capabilities.max_bitrate_kbps) } else { String::new() });
+    let capabilities = RemoteCommand::Capabilities(capabilities);
+    let mut offered: Option<u32> = None; // The sender session they went to
+    let mut offers_left = 0;
+    let mut restarts = 0;
+    let mut probe: Option<(SocketAddr, ProbeStats)> = None;
+    let mut buf = vec![0u8; 65536];
+    let mut stats_timer = tokio::time::interval(STATS_INTERVAL);
+    let mut probe_timer = tokio::time::interval(PROBE_IDLE_TIMEOUT);
+    let mut playout_timer = tokio::time::interval(PLAYOUT_INTERVAL);
+    let mut sync_timer = tokio::time::interval(SYNC_INTERVAL);
+    let mut refresh_timer = tokio::time::interval(REFRESH_INTERVAL);
+    let mut subscribe_timer = tokio::time::interval(bridge::SUBSCRIBE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = received?;
+                let data = &buf[..len];
+
+                // A restarted sender is pairing again, from a new address; open our NAT to it.
+                if let Some((server, _)) = &rendezvous
+                    && from == *server
+                    && let Some(message) = Message::decode(data)
+                {
+                    if let Message::Peer(peer) = message {
+                        for _ in 0..3 {
+                            let _ = socket.send_to(&Message::Punch.encode(), peer).await;
+                        }
+                    }
+                    continue;
+                }
+
+                if let Some((server, key)) = &relay
+                    && from == *server
+                    && let Some(bridge::Message::Challenge { cookie }) = bridge::Message::decode(data)
+                {
+                    let _ = socket.send_to(&bridge::Message::Join { key: key.clone(), cookie }.encode(), server).await;
+                    continue;
+                }
+
+                if ProbeStats::is_probe(data) {
+                    let (_, stats) = probe.get_or_insert_with(|| (from, ProbeStats::default()));
+                    if stats.observe(data) {
+                        let report = stats.report_packet();
+                        socket.send_to(&report, from).await?;
+                        probe = None;
+                    }
+                    continue;
+                }
+
+                let Some(packet) = Packet::decode(data) else {
+                    continue;
+                };
+                if packet.kind == PacketKind::TimeReply {
+                    clock.handle_reply(&packet, Instant::now());
+                    continue;
+                }
+                if packet.kind == PacketKind::Notify {
+                    // Only the one we play may put something on the desktop.
+                    if offered != Some(packet.session) || !paths.contains(&from) {
+                        continue;
+                    }
+                    // Each comes several times, and over both paths with a second one.
+                    let repeat = notified.is_some_and(|(session, seq)| session == packet.session && !seq_lt(seq, packet.seq));
+                    if !repeat && let Some(notification) = Notification::decode(&packet.payload) {
+                        notified = Some((packet.session, packet.seq));
+                        show_notification(&notification);
+                    }
+                    continue;
+                }
+                if packet.kind == PacketKind::Announce {
+                    if let Some(tag) = StreamTag::decode(&packet.payload) {
+                        announce(&mut announced, from, tag);
+                    }
+                    continue;
+                }
+                let (session, seq) = (packet.session, packet.seq);
+                let received = buffer.stats.received;
+                buffer.push(packet, Instant::now());
+                // Only audio the buffer took for the session it plays says where the sender
+                // is; anything else reaching the port could point our replies elsewhere.
+                if buffer.stats.received != received {
+                    sender = Some(from);
+                    if offered != Some(session) {
+                        offered = Some(session);
+                        offers_left = CAPABILITY_REPEATS;
+                        paths.clear();
+                    }
+                    if !paths.contains(&from) {
+                        if paths.len() == 2 {
+                            paths.remove(0);
+                        }
+                        paths.push(from);
+                    }
+                }
+                // Lets the sender show the dropout; it was ours, the stream went on.
+                if let Some(resumed) = buffer.take_resumed() {
+                    println!("\nStream resumed after {:.1} s gap ({} packets skipped)", resumed.gap.as_secs_f64(), resumed.skipped);
+                    let _ = socket.send_to(&resumed.packet(session, seq).encode(), from).await;
+                }
+                // A restarted sender has a new stream clock.
+                if buffer.stats.restarts != restarts {
+                    restarts = buffer.stats.restarts;
+                    clock.reset();
+                }
+            }
+            Some(line) = commands.recv() => {
+                match (RemoteCommand::parse(&line), sender) {
+                    (Some(command), Some(sender)) => {
+                        socket.send_to(&command.packet(offered.unwrap_or(0)).encode(), sender).await?;
+                    }
+                    (Some(_), None) => println!("\nNothing received yet, so there is no sender to control"),
+                    (None, _) if line.trim().is_empty() => {}
+                    (None, _) => println!("\nUnknown command '{}'", line.trim()),
+                }
+            }
+            // Keeps our registration, and the NAT's mapping to the helper, alive.
+            _ = refresh_timer.tick(), if rendezvous.is_some() => {
+                if let Some((server, room)) = &rendezvous {
+                    let _ = socket.send_to(&room.hello(Role::Receiver).encode(), server).await;
+                }
+            }
+            _ = subscribe_timer.tick(), if relay.is_some() => {
+                if let Some((server, key)) = &relay {
+                    let _ = socket.send_to(&bridge::Message::Subscribe { key: key.clone() }.encode(), server).await;
+                }
+            }
+            _ = sync_timer.tick(), if clock_requests => {
+                if let Some(sender) = sender {
+                    let request = clock.request_packet(Instant::now());
+                    socket.send_to(&request.encode(), sender).await?;
+                }
+            }
+            _ = playout_timer.tick() => {
+                let clock = sync_enabled.then_some(&clock);
+                for payload in buffer.poll(Instant::now(), clock) {
+                    let flac = flac::frame_format(&payload);
+                    // A sender restarted with another codec or format needs another player.
+                    let player = match &mut player {
+                        Some(player) if player.flac == flac => player,
+                        _ => player.insert(Player::start(flac).await?),
+                    };
+                    if player.input.write_all(&payload).await.is_err() {
+                        println!("\nPlayer exited, stopping receiver");
+                        return Ok(());
+                    }
+                    // It decodes MPEG-TS.
+                    if flac.is_none()
+                        && let Some(beacon) = &mut detector
+                        && beacon.feed(&payload).is_err()
+                    {
+                        println!("\nBeacon decoder exited, latency is no longer measured");
+                        detector = None;
+                    }
+                }
+            }
+            Some(onset) = async { detector.as_mut()?.onsets.recv().await }, if detector.is_some() => {
+                if let Some(sender_us) = clock.local_to_sender(onset) {
+                    latency = Some(beacon_latency(sender_us, config.beacon_interval_secs));
+                }
+            }
+            _ = probe_timer.tick() => {
+                // The tail of a probe burst may have been lost; answer with what we have.
+                if let Some((from, stats)) = &probe && stats.idle_for() >= PROBE_IDLE_TIMEOUT {
+                    socket.send_to(&stats.report_packet(), *from).await?;
+                    probe = None;
+                }
+            }
+            _ = stats_timer.tick() => {
+                // Tells the sender's watchdog we're still here, and its status bar who we are.
+                if let Some(sender) = sender {
+                    let status = ReceiverStatus { name: name.clone(), buffer: Some(buffer.depth()) };
+                    let keepalive = Packet { kind: PacketKind::Keepalive, seq: 0, fec_group: 0, session: 0, timestamp_us: 0, payload: status.encode() };
+                    let _ = socket.send_to(&keepalive.encode(), sender).await;
+                    if offers_left > 0 {
+                        offers_left -= 1;
+                        let _ = socket.send_to(&capabilities.packet(offered.unwrap_or(0)).encode(), sender).await;
+                    }
+                }
+                let stats = buffer.stats;
+                let (buffered, waiting) = buffer.occupancy(Instant::now());
+                let skew = buffer.skew_ppm().map(|ppm| format!("{:+.0} ppm", ppm)).unwrap_or_else(|| "measuring".to_string());
+                let sync = match (sync_enabled, clock.round_trip()) {
+                    (false, _) => String::new(),
+                    (true, Some(round_trip)) => format!(" | synced, rtt {:.1} ms", round_trip.as_secs_f64() * 1000.0),
+                    (true, None) => " | waiting for clock sync".to_string(),
+                };
+                let latency = match (&detector, latency) {
+                    (None, _) => String::new(),
+                    (Some(_), Some(latency)) => format!(" | latency {} ms", latency.as_millis()),
+                    (Some(_), None) => " | latency measuring".to_string(),
+                };
+                // Only a sender with a second path sends copies.
+                let duplicates = if stats.duplicates > 0 { format!(" dup {}", stats.duplicates) } else { String::new() };
+                let resumes = if stats.resumes > 0 { format!(" resumed {}", stats.resumes) } else { String::new() };
+                print!(
+                    "\rbuffer {:>3} ms / {:>3} ms ({:>3} pkts) | received {} recovered {} late {} lost {}{}{} | skew {} ({} dropped){}{}   ",
+                    waiting.as_millis(),
+                    buffer.depth().as_millis(),
+                    buffered,
+                    stats.received,
+                    stats.recovered,
+                    stats.late,
+                    stats.lost,
+                    duplicates,
+                    resumes,
+                    skew,
+                    stats.drift_drops,
+                    sync,
+                    latency
+                );
+                let _ = stdout().flush();
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{
+    audio::{AudioSource, CaptureBackend, get_audio_sources, pulse_available},
+    log,
+};
+use std::{
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+use tokio::{runtime::Handle, time::sleep};
+
+// PipeWire comes back within a few seconds of `systemctl --user restart`; a server
+// that takes longer than this was stopped on purpose.
+const WAIT: Duration = Duration::from_secs(60);
+const POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub enum Recovery {
+    // The capture ended because the sound server went away.
+    Lost,
+    // The server is back, with the source by the same name; its description may differ.
+    Back(AudioSource),
+    // It wasn't the server, or the server didn't come back; the stream ends with this.
+    Failed(String),
+}
+
+// Which source `name` is now, if the server is up and has one by that name.
+async fn find(name: &str) -> Option<AudioSource> {
+    get_audio_sources(CaptureBackend::Pulse).await.ok()?.into_iter().find(|source| source.name == name)
+}
+
+// After the capture of `source` ended with `reason`: tells a server restart (`pactl
+// info` fails) from the capture failing on its own or the device going away, e.g. an
+// unplugged USB microphone, and in the first case waits for the source to return.
+pub fn start(source: String, reason: String, runtime_handle: &Handle) -> Receiver<Recovery> {
+    let (tx, rx) = mpsc::channel();
+    runtime_handle.spawn(async move {
+        if pulse_available().await {
+            let reason = match find(&source).await {
+                Some(_) => reason,
+                None => format!("{} went away ({})", source, reason),
+            };
+            let _ = tx.send(Recovery::Failed(reason));
+            return;
+        }
+        log!("The sound server lost {} ({}); waiting for it to come back", source, reason);
+        let _ = tx.send(Recovery::Lost);
+        let since = Instant::now();
+        while since.elapsed() < WAIT {
+            sleep(POLL).await;
+            if let Some(found) = find(&source).await {
+                log!("{} is back after {} s; resuming the stream", source, since.elapsed().as_secs());
+                let _ = tx.send(Recovery::Back(found));
+                return;
+            }
+        }
+        let _ = tx.send(Recovery::Failed(format!("{} did not come back within {} s after the sound server went away", source, WAIT.as_secs())));
+    });
+    rx
+}
@@ -0,0 +1,577 @@
+use crate::{
+    bridge::{self, REGISTER_INTERVAL},
+    log,
+    notify::{self, Notification},
+    pacing::{Pacer, Pacing},
+    presence::{PRESENCE_TIMEOUT, ReceiverStatus, Resumption},
+    qos::{self, Dscp},
+    remote::RemoteCommand,
+    rendezvous::{self, Message, PeerPath, REFRESH_INTERVAL, Role, Room},
+    sync::time_reply,
+    tag::{ANNOUNCE_INTERVAL, StreamTag},
+    transport::{Packet, PacketKind, Transport, xor_parity},
+    watchdog::{CHECK_INTERVAL, Watchdog},
+    xrun::{ChunkGaps, Xruns},
+};
+use anyhow::{Context, Result};
+use socket2::SockRef;
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, Sender},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::UdpSocket,
+    runtime::Handle,
+    sync::{
+        broadcast,
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+    },
+    task::JoinHandle,
+    time::{sleep, sleep_until},
+};
+
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+// Chunks an output may fall behind by before it skips ahead; about 10 s at 192 kbit/s.
+const TAP_CAPACITY: usize = 256;
+// Held-back packets this far past due are dropped rather than sent, so lowering the
+// delay skips ahead instead of bursting the backlog at the receiver.
+const DELAY_SLACK: Duration = Duration::from_millis(20);
+// Notifications still queued when the relay stops go out within this.
+const STOP_GRACE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct RelayOptions {
+    pub transport: Transport,
+    pub fec_group: u8,
+    pub keepalive_while_paused: bool,
+    pub failover: Option<Failover>,
+    pub dscp: Dscp,
+    pub tag: Option<StreamTag>, // Announced to native receivers, see `tag.rs`
+    pub rendezvous: Option<Room>, // The target is then a rendezvous helper, see `rendezvous.rs`
+    pub pacing: Option<Pacing>,
+    pub second_path: Option<String>, // An interface every packet is also sent through, e.g. wlan0
+    pub relay_server_key: Option<String>, // The target is a relay server, which only takes the stream from who registers with it
+    pub xruns: Option<Arc<Xruns>>, // Counts the encoder's gaps as underruns, see `xrun.rs`
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Failover {
+    pub backup: SocketAddr,
+    pub after: Duration,
+}
+
+// Sits between ffmpeg and the network: ffmpeg writes MPEG-TS to `local_addr`, and
+// the relay task forwards it to the target, raw or wrapped in the native format.
+// Keeping this hop lets us pause without restarting the encoder.
+pub struct Relay {
+    pub local_addr: SocketAddr,
+    paused: Arc<AtomicBool>,
+    held: Arc<AtomicBool>, // See `hold_until`
+    muted: Arc<AtomicBool>, // See `set_muted`
+    failed_over: Arc<AtomicBool>,
+    bytes_sent: Arc<AtomicU64>,
+    delay_ms: Arc<AtomicU32>,
+    receiver: Arc<Mutex<Option<(Instant, ReceiverStatus)>>>,
+    path: Arc<Mutex<Option<PeerPath>>>,
+    commands: Receiver<RemoteCommand>,
+    resumptions: Receiver<Resumption>,
+    notifications: UnboundedSender<Notification>,
+    tap: broadcast::Sender<Arc<[u8]>>,
+    task: JoinHandle<()>,
+    runtime: Handle,
+}
+
+impl Relay {
+    pub fn start(target: SocketAddr, options: RelayOptions, runtime_handle: &Handle) -> Result<Self> {
+        let input = std::net::UdpSocket::bind("127.0.0.1:0").context("Failed to bind relay input socket")?;
+        let bind_addr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let output = std::net::UdpSocket::bind(bind_addr).context("Failed to bind relay output socket")?;
+        input.set_nonblocking(true)?;
+        output.set_nonblocking(true)?;
+        output.set_broadcast(true)?; // Lets one stream reach several receivers for multi-room playback
+        qos::apply(&output, options.dscp)?;
+        let second = options.second_path.as_deref().map(|interface| second_path(bind_addr, interface, options.dscp)).transpose()?;
+        let local_addr = input.local_addr()?;
+
+        let paused = Arc::new(AtomicBool::new(false));
+        let held = Arc::new(AtomicBool::new(false));
+        let muted = Arc::new(AtomicBool::new(false));
+        let failed_over = Arc::new(AtomicBool::new(false));
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        let delay_ms = Arc::new(AtomicU32::new(0));
+        let receiver = Arc::new(Mutex::new(None));
+        let path = Arc::new(Mutex::new(None));
+        let (commands_tx, commands) = mpsc::channel();
+        let (resumptions_tx, resumptions) = mpsc::channel();
+        let (notifications, notifications_rx) = unbounded_channel();
+        let state = RelayState {
+            paused: Arc::clone(&paused),
+            held: Arc::clone(&held),
+            muted: Arc::clone(&muted),
+            failed_over: Arc::clone(&failed_over),
+            bytes_sent: Arc::clone(&bytes_sent),
+            delay_ms: Arc::clone(&delay_ms),
+            receiver: Arc::clone(&receiver),
+            path: Arc::clone(&path),
+            commands: commands_tx,
+            resumptions: resumptions_tx,
+            notifications: notifications_rx,
+            tap: broadcast::channel(TAP_CAPACITY).0,
+        };
+        let tap = state.tap.clone();
+        let task = runtime_handle.spawn(async move {
+            if let Err(e) = run_relay(input, output, second, target, options, state).await {
+                log!("Relay stopped: {}", e);
+            }
+        });
+
+        let runtime = runtime_handle.clone();
+        Ok(Self { local_addr, paused, held, muted, failed_over, bytes_sent, delay_ms, receiver, path, commands, resumptions, notifications, tap, task, runtime })
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // Sends no audio while set, as while paused but apart from it, so unmuting doesn't
+    // resume a stream the user paused. For captures whose volume can't be turned down.
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    // Drops the encoder's output, as while paused but apart from it, until `ready`
+    // finishes, e.g. a pre-start hook switching on an amplifier.
+    pub fn hold_until(&self, ready: impl Future<Output = ()> + Send + 'static) {
+        let held = Arc::clone(&self.held);
+        held.store(true, Ordering::Relaxed);
+        self.runtime.spawn(async move {
+            ready.await;
+            held.store(false, Ordering::Relaxed);
+        });
+    }
+
+    // Holds the stream back before it goes to the target, for lip sync with video
+    // playing there. Takes effect immediately; extra outputs are not delayed.
+    pub fn set_delay(&self, delay: Duration) {
+        self.delay_ms.store(delay.as_millis() as u32, Ordering::Relaxed);
+    }
+
+    // True once the primary target went away and the stream moved to the backup.
+    pub fn has_failed_over(&self) -> bool {
+        self.failed_over.load(Ordering::Relaxed)
+    }
+
+    // Stream bytes put on the wire so far, including native headers and parity.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    // The receiver on the other end, while it keeps sending keepalives. Plain players
+    // never do, so None means "nothing reported back" rather than "nothing listening".
+    pub fn receiver(&self) -> Option<ReceiverStatus> {
+        self.watch_receiver().get()
+    }
+
+    // The same, for tasks that outlive a borrow of the relay.
+    pub fn watch_receiver(&self) -> ReceiverWatch {
+        ReceiverWatch(Arc::clone(&self.receiver))
+    }
+
+    // Where the stream goes after meeting the receiver at a rendezvous helper; None without one.
+    pub fn peer_path(&self) -> Option<PeerPath> {
+        *self.path.lock().ok()?
+    }
+
+    // Requests from receivers since the last call, oldest first.
+    pub fn remote_commands(&self) -> Vec<RemoteCommand> {
+        self.commands.try_iter().collect()
+    }
+
+    // Receivers that lost this stream for a while and picked it up again, oldest first.
+    pub fn resumptions(&self) -> Vec<Resumption> {
+        self.resumptions.try_iter().collect()
+    }
+
+    // Every chunk the encoder produces while not paused, before any wrapping.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<[u8]>> {
+        self.tap.subscribe()
+    }
+
+    // Sent to native receivers; other transports have nobody to read it.
+    pub fn notify(&self, notification: Notification) {
+        let _ = self.notifications.send(notification); // Fails only once the task has ended
+    }
+
+    // The same, for tasks that outlive a borrow of the relay.
+    pub fn notifier(&self) -> UnboundedSender<Notification> {
+        self.notifications.clone()
+    }
+
+    // The task sends what is still queued, e.g. `Notification::Stopped`, and ends once
+    // every sender is gone; it is cut off after a moment in case one lingers.
+    pub fn stop(self) {
+        let task = self.task.abort_handle();
+        self.runtime.spawn(async move {
+            sleep(STOP_GRACE).await;
+            task.abort();
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct ReceiverWatch(Arc<Mutex<Option<(Instant, ReceiverStatus)>>>);
+
+impl ReceiverWatch {
+    pub fn get(&self) -> Option<ReceiverStatus> {
+        let receiver = self.0.lock().ok()?;
+        let (last_seen, status) = receiver.as_ref()?;
+        (last_seen.elapsed() < PRESENCE_TIMEOUT).then(|| status.clone())
+    }
+}
+
+// Flags shared between the relay task and its `Relay` handle.
+struct RelayState {
+    paused: Arc<AtomicBool>,
+    held: Arc<AtomicBool>,
+    muted: Arc<AtomicBool>,
+    failed_over: Arc<AtomicBool>,
+    bytes_sent: Arc<AtomicU64>,
+    delay_ms: Arc<AtomicU32>,
+    receiver: Arc<Mutex<Option<(Instant, ReceiverStatus)>>>,
+    path: Arc<Mutex<Option<PeerPath>>>,
+    commands: Sender<RemoteCommand>,
+    resumptions: Sender<Resumption>,
+    notifications: UnboundedReceiver<Notification>,
+    tap: broadcast::Sender<Arc<[u8]>>,
+}
+
+// A socket tied to one interface, so its copies take another way than the routing
+// table picks, e.g. Wi-Fi next to Ethernet. Receivers drop whichever copy comes second.
+fn second_path(bind_addr: &str, interface: &str, dscp: Dscp) -> Result<std::net::UdpSocket> {
+    let socket = std::net::UdpSocket::bind(bind_addr).context("Failed to bind second path socket")?;
+    SockRef::from(&socket).bind_device(Some(interface.as_bytes())).with_context(|| format!("Failed to send through {}", interface))?;
+    socket.set_nonblocking(true)?;
+    socket.set_broadcast(true)?;
+    qos::apply(&socket, dscp)?;
+    Ok(socket)
+}
+
+// Puts chunks on the wire, wrapping them when the native transport is in use.
+struct Forwarder {
+    native: bool,
+    second: Option<UdpSocket>, // Sends every packet a second time, see `second_path`
+    fec_group: u8,
+    session: u32, // From the tag; 0 without one
+    seq: u32,
+    group: Vec<Vec<u8>>,
+    started: Instant,
+    bytes_sent: Arc<AtomicU64>,
+}
+
+impl Forwarder {
+    async fn send(&self, output: &UdpSocket, target: SocketAddr, bytes: &[u8]) {
+        for socket in std::iter::once(output).chain(self.second.as_ref()) {
+            if let Ok(sent) = socket.send_to(bytes, target).await {
+                self.bytes_sent.fetch_add(sent as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // A lost send (e.g. ICMP unreachable before the receiver is up) must not end the stream.
+    async fn forward(&mut self, output: &UdpSocket, target: SocketAddr, chunk: Vec<u8>) {
+        if !self.native {
+            self.send(output, target, &chunk).await;
+            return;
+        }
+
+        let fec_group = self.fec_group;
+        let timestamp_us = self.started.elapsed().as_micros() as u64;
+        let packet = Packet { kind: PacketKind::Data, seq: self.seq, fec_group, session: self.session, timestamp_us, payload: chunk };
+        self.send(output, target, &packet.encode()).await;
+
+        if fec_group > 1 {
+            self.group.push(packet.payload);
+            if self.group.len() == fec_group as usize {
+                let parity = Packet {
+                    kind: PacketKind::Parity,
+                    seq: self.seq.wrapping_add(1).wrapping_sub(fec_group as u32), // The group's first, across a wrap too
+                    fec_group,
+                    session: self.session,
+                    timestamp_us,
+                    payload: xor_parity(self.group.iter().map(Vec::as_slice)),
+                };
+                self.send(output, target, &parity.encode()).await;
+                self.group.clear();
+            }
+        }
+        self.seq = self.seq.wrapping_add(1);
+    }
+}
+
+// ICMP errors only reach connected sockets, so the watchdog probes the target from
+// its own connected socket with empty datagrams, which players ignore.
+async fn health_socket(target: SocketAddr) -> Result<UdpSocket> {
+    let bind_addr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_addr).await.context("Failed to bind health check socket")?;
+    socket.connect(target).await.context("Failed to connect health check socket")?;
+    Ok(socket)
+}
+
+// Control packets come back on whichever socket the receiver last heard from.
+async fn readable<'a>(output: &'a UdpSocket, second: Option<&'a UdpSocket>) -> &'a UdpSocket {
+    let Some(second) = second else {
+        let _ = output.readable().await;
+        return output;
+    };
+    tokio::select! {
+        _ = output.readable() => output,
+        _ = second.readable() => second,
+    }
+}
+
+async fn run_relay(
+    input: std::net::UdpSocket,
+    output: std::net::UdpSocket,
+    second: Option<std::net::UdpSocket>,
+    mut target: SocketAddr,
+    options: RelayOptions,
+    state: RelayState,
+) -> Result<()> {
+    let RelayState { paused, held, muted, failed_over, bytes_sent, delay_ms, receiver, path, commands, resumptions, mut notifications, tap } = state;
+    let input = UdpSocket::from_std(input)?;
+    let output = UdpSocket::from_std(output)?;
+    let native = options.transport == Transport::Native;
+    let started = Instant::now();
+    let mut forwarder = Forwarder {
+        native,
+        second: second.map(UdpSocket::from_std).transpose()?,
+        fec_group: options.fec_group,
+        session: options.tag.as_ref().map_or(0, StreamTag::session_id),
+        seq: 0,
+        group: Vec::with_capacity(options.fec_group as usize),
+        started,
+        bytes_sent,
+    };
+    let mut buf = vec![0u8; 65536];
+    let mut delayed: VecDeque<(Instant, Vec<u8>)> = VecDeque::new(); // Chunks with their arrival time
+    let mut pacer = Pacer::new(options.pacing);
+    let mut gaps = ChunkGaps::default();
+    let mut control_buf = [0u8; 512];
+    let mut keepalive_timer = tokio::time::interval(KEEPALIVE_INTERVAL);
+    let mut notified: u32 = 0; // Sequence numbers of notifications, for receivers to drop repeats
+    // With a rendezvous, the target is the helper, and the stream goes wherever pairing leads.
+    let server = target;
+    if let Some(room) = &options.rendezvous {
+        *path.lock().unwrap() = Some(PeerPath::Waiting);
+        let paired = rendezvous::pair(&output, server, room, Role::Sender);
+        tokio::pin!(paired);
+        // The encoder's output is dropped until then, so the receiver doesn't start on a backlog.
+        let found = loop {
+            tokio::select! {
+                found = &mut paired => break found?,
+                _ = input.recv(&mut buf) => {}
+            }
+        };
+        log!("Rendezvous: {}", found);
+        target = found.address().unwrap_or(server);
+        *path.lock().unwrap() = Some(found);
+    }
+    let mut refresh_timer = tokio::time::interval(REFRESH_INTERVAL);
+    let mut failover = options.failover;
+    let mut watchdog = Watchdog::new(failover.map_or(Duration::ZERO, |f| f.after));
+    let mut health = health_socket(target).await?;
+    let mut health_buf = [0u8; 64];
+    let mut health_timer = tokio::time::interval(CHECK_INTERVAL);
+    let mut announce_timer = tokio::time::interval(ANNOUNCE_INTERVAL);
+    let mut register_timer = tokio::time::interval(REGISTER_INTERVAL);
+    let tag = options.tag.clone();
+
+    loop {
+        // Read every time round, so a changed delay applies to what is already queued.
+        let delay = Duration::from_millis(delay_ms.load(Ordering::Relaxed) as u64);
+        let next_due = delayed.front().map_or_else(Instant::now, |(arrived, _)| *arrived + delay);
+        let next_release = pacer.next_release();
+
+        tokio::select! {
+            received = input.recv(&mut buf) => {
+                let len = received?;
+                // While paused ffmpeg keeps encoding; its output is simply discarded.
+                if paused.load(Ordering::Relaxed) || held.load(Ordering::Relaxed) || muted.load(Ordering::Relaxed) {
+                    gaps.reset();
+                    continue;
+                }
+                if let Some(xruns) = &options.xruns
+                    && let Some(gap) = gaps.arrived(Instant::now())
+                {
+                    xruns.underrun(gap);
+                }
+                // Fails only when no output is listening.
+                if tap.receiver_count() > 0 {
+                    let _ = tap.send(Arc::from(&buf[..len]));
+                }
+                // The delay may have changed while we waited for this chunk.
+                if delay_ms.load(Ordering::Relaxed) == 0 && delayed.is_empty() {
+                    pacer.push(buf[..len].to_vec(), Instant::now());
+                    while let Some(chunk) = pacer.pop(Instant::now()) {
+                        forwarder.forward(&output, target, chunk).await;
+                    }
+                } else {
+                    delayed.push_back((Instant::now(), buf[..len].to_vec()));
+                }
+            }
+            _ = sleep_until(next_due.into()), if !delayed.is_empty() => {
+                let now = Instant::now();
+                while let Some((arrived, _)) = delayed.front()
+                    && *arrived + delay <= now
+                {
+                    let (arrived, chunk) = delayed.pop_front().expect("front was just checked");
+                    if arrived + delay + DELAY_SLACK >= now {
+                        pacer.push(chunk, now);
+                    }
+                }
+                while let Some(chunk) = pacer.pop(Instant::now()) {
+                    forwarder.forward(&output, target, chunk).await;
+                }
+            }
+            _ = sleep_until(next_release.unwrap_or_else(Instant::now).into()), if next_release.is_some() => {
+                while let Some(chunk) = pacer.pop(Instant::now()) {
+                    forwarder.forward(&output, target, chunk).await;
+                }
+            }
+            // Receivers ask for our clock and report back on the same socket the stream comes from.
+            socket = readable(&output, forwarder.second.as_ref()) => {
+                let Ok((len, from)) = socket.try_recv_from(&mut control_buf) else {
+                    continue;
+                };
+                // A restarted receiver comes back from a new address. Through the helper,
+                // it keeps up; direct, the stream follows it.
+                if from == server
+                    && let Some(Message::Peer(peer)) = Message::decode(&control_buf[..len])
+                {
+                    let known = *path.lock().unwrap();
+                    if let Some(PeerPath::Direct(known)) = known
+                        && known != peer
+                    {
+                        for _ in 0..3 {
+                            let _ = output.send_to(&Message::Punch.encode(), peer).await;
+                        }
+                        log!("Receiver moved to {}", peer);
+                        target = peer;
+                        *path.lock().unwrap() = Some(PeerPath::Direct(peer));
+                    }
+                    continue;
+                }
+                if let Some(request) = Packet::decode(&control_buf[..len]) {
+                    match request.kind {
+                        PacketKind::TimeRequest => {
+                            let reply = time_reply(&request, started.elapsed().as_micros() as u64);
+                            let _ = socket.send_to(&reply.encode(), from).await;
+                        }
+                        PacketKind::Keepalive => {
+                            // Any receiver counts as present, e.g. one of several on a broadcast address.
+                            if let Ok(mut receiver) = receiver.lock() {
+                                *receiver = Some((Instant::now(), ReceiverStatus::decode(&request.payload)));
+                            }
+                            if from.ip() == target.ip() {
+                                watchdog.feedback(Instant::now());
+                            }
+                        }
+                        // Only the receiver the stream goes to gets a say, about this run of it.
+                        PacketKind::Control if from.ip() == target.ip() && request.session == forwarder.session => {
+                            if let Some(command) = RemoteCommand::decode(&request.payload) {
+                                let _ = commands.send(command); // Only fails once the handle is gone
+                            }
+                        }
+                        // A report about an earlier run of this stream is of no interest.
+                        PacketKind::Resumed if request.session == forwarder.session => {
+                            if let Some(resumption) = Resumption::decode(&request.payload) {
+                                let _ = resumptions.send(resumption);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            health_result = health.recv(&mut health_buf), if failover.is_some() => {
+                if health_result.is_err() {
+                    watchdog.refused(Instant::now());
+                }
+            }
+            _ = health_timer.tick(), if failover.is_some() => {
+                if health.send(&[]).await.is_err() {
+                    watchdog.refused(Instant::now());
+                }
+                if watchdog.check(Instant::now())
+                    && let Some(Failover { backup, .. }) = failover.take()
+                {
+                    log!("Target {} unreachable, failing over to {}", target, backup);
+                    target = backup;
+                    health = health_socket(target).await?;
+                    watchdog.reset();
+                    failed_over.store(true, Ordering::Relaxed);
+                }
+            }
+            // Keeps our registration, and the NAT's mapping to the helper, alive.
+            _ = refresh_timer.tick(), if options.rendezvous.is_some() => {
+                if let Some(room) = &options.rendezvous {
+                    let _ = output.send_to(&room.hello(Role::Sender).encode(), server).await;
+                }
+            }
+            // From every socket the stream leaves on, also while paused.
+            _ = register_timer.tick(), if options.relay_server_key.is_some() => {
+                if let Some(key) = &options.relay_server_key {
+                    let register = bridge::Message::Register { key: key.clone() }.encode();
+                    for socket in std::iter::once(&output).chain(forwarder.second.as_ref()) {
+                        let _ = socket.send_to(&register, target).await;
+                    }
+                }
+            }
+            notification = notifications.recv() => {
+                let Some(notification) = notification else {
+                    // Stopped, see `Relay::stop`. A restart pairs again from a new port.
+                    if let Some(room) = &options.rendezvous {
+                        let _ = output.send_to(&room.leave(Role::Sender).encode(), server).await;
+                    }
+                    return Ok(());
+                };
+                if native {
+                    let packet = notification.packet(forwarder.session, notified).encode();
+                    notified = notified.wrapping_add(1);
+                    for _ in 0..notify::REPEATS {
+                        forwarder.send(&output, target, &packet).await;
+                    }
+                }
+            }
+            // Also while paused, so a receiver can tell who is there.
+            _ = announce_timer.tick(), if native && tag.is_some() => {
+                if let Some(tag) = &tag {
+                    let announce = tag.packet(forwarder.seq, started.elapsed().as_micros() as u64);
+                    let _ = output.send_to(&announce.encode(), target).await;
+                }
+            }
+            _ = keepalive_timer.tick(), if native && options.keepalive_while_paused => {
+                if paused.load(Ordering::Relaxed) || muted.load(Ordering::Relaxed) {
+                    let keepalive = Packet {
+                        kind: PacketKind::Keepalive,
+                        seq: forwarder.seq,
+                        fec_group: 0,
+                        session: forwarder.session,
+                        timestamp_us: started.elapsed().as_micros() as u64,
+                        payload: Vec::new(),
+                    };
+                    let _ = output.send_to(&keepalive.encode(), target).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    negotiate::Capabilities,
+    transport::{Packet, PacketKind},
+};
+
+// Above 100 % PulseAudio amplifies in software, which clips soon after.
+pub const MAX_VOLUME: u8 = 150;
+// Codecs a receiver may switch us to: all of them go into MPEG-TS and decode anywhere.
+pub const REMOTE_CODECS: [&str; 4] = ["aac", "libopus", "libmp3lame", "ac3"];
+
+const TAG_VOLUME: u8 = 1;
+const TAG_MUTE: u8 = 2;
+const TAG_CODEC: u8 = 3;
+const TAG_CAPABILITIES: u8 = 4;
+
+// What a receiver can ask the sender for, sent as `PacketKind::Control` on the socket
+// the stream arrives from. Payload layout: tag (1) | argument, where the argument is
+// the volume in percent (1), 0/1 for unmute/mute (1), the ffmpeg encoder name, or
+// what the receiver can play (see `Capabilities`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteCommand {
+    SetVolume(u8),
+    SetMuted(bool),
+    RequestCodec(String),
+    // Not a request as such: the sender fits the stream to it, see `negotiate.rs`.
+    Capabilities(Capabilities),
+}
+
+impl RemoteCommand {
+    // `session` is the one the receiver plays, see `Packet::session`; senders ignore
+    // commands meant for an earlier run.
+    pub fn packet(&self, session: u32) -> Packet {
+        let payload = match self {
+            RemoteCommand::SetVolume(percent) => vec![TAG_VOLUME, *percent],
+            RemoteCommand::SetMuted(muted) => vec![TAG_MUTE, *muted as u8],
+            RemoteCommand::RequestCodec(codec) => [&[TAG_CODEC], codec.as_bytes()].concat(),
+            RemoteCommand::Capabilities(capabilities) => [&[TAG_CAPABILITIES], capabilities.encode().as_slice()].concat(),
+        };
+        Packet { kind: PacketKind::Control, seq: 0, fec_group: 0, session, timestamp_us: 0, payload }
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let (tag, argument) = payload.split_first()?;
+        match (*tag, argument) {
+            (TAG_VOLUME, [percent]) => Some(RemoteCommand::SetVolume((*percent).min(MAX_VOLUME))),
+            (TAG_MUTE, [muted]) => Some(RemoteCommand::SetMuted(*muted != 0)),
+            (TAG_CODEC, codec) => Some(RemoteCommand::RequestCodec(String::from_utf8_lossy(codec).trim().to_string())),
+            (TAG_CAPABILITIES, capabilities) => Capabilities::decode(capabilities).map(RemoteCommand::Capabilities),
+            _ => None,
+        }
+    }
+
+    // "volume 80", "mute", "unmute" or "codec libopus", as typed into the receiver.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let command = match (words.next()?, words.next()) {
+            ("volume", Some(percent)) => RemoteCommand::SetVolume(percent.trim_end_matches('%').parse::<u8>().ok()?.min(MAX_VOLUME)),
+            ("mute", None) => RemoteCommand::SetMuted(true),
+            ("unmute", None) => RemoteCommand::SetMuted(false),
+            ("codec", Some(codec)) => RemoteCommand::RequestCodec(codec.to_string()),
+            _ => return None,
+        };
+        words.next().is_none().then_some(command)
+    }
+}
@@ -0,0 +1,379 @@
+use crate::log;
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::{UdpSocket, lookup_host},
+    time::{interval, timeout, timeout_at},
+};
+
+pub const DEFAULT_PORT: u16 = 3478;
+pub const DEFAULT_STUN_SERVERS: [&str; 2] = ["stun.l.google.com:19302", "stun1.l.google.com:19302"];
+// Text datagrams that start with this; native packets start "AS" and a version byte.
+const MAGIC: &str = "ASRV";
+const HELLO_INTERVAL: Duration = Duration::from_secs(1);
+// Often enough to keep a NAT's mapping to the helper open; most keep idle UDP for 30 s or more.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(60);
+const PUNCH_INTERVAL: Duration = Duration::from_millis(100);
+const PUNCH_TIMEOUT: Duration = Duration::from_secs(3);
+const STUN_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Sender,
+    Receiver,
+}
+
+impl Role {
+    fn word(self) -> &'static str {
+        match self {
+            Role::Sender => "sender",
+            Role::Receiver => "receiver",
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Role::Sender => Role::Receiver,
+            Role::Receiver => Role::Sender,
+        }
+    }
+}
+
+// A room at the helper, and the secret both sides were given for it. Only a digest of
+// the two goes over the network; peers pair only when theirs are the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Room {
+    pub name: String,
+    secret: String,
+}
+
+impl Room {
+    pub fn new(name: &str, secret: &str) -> Result<Self> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("The rendezvous room must be one word");
+        }
+        if secret.is_empty() {
+            bail!("The rendezvous room needs a secret, the same on both sides");
+        }
+        Ok(Self { name: name.to_string(), secret: secret.to_string() })
+    }
+
+    fn key(&self) -> String {
+        let digest = Sha256::digest(format!("{} {}", self.name, self.secret));
+        digest.iter().take(16).map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn hello(&self, role: Role) -> Message {
+        Message::Hello { role, room: self.name.clone(), key: self.key() }
+    }
+
+    pub fn leave(&self, role: Role) -> Message {
+        Message::Leave { role, room: self.name.clone(), key: self.key() }
+    }
+}
+
+// What peers and the helper say to each other, e.g. "ASRV hello sender living-room 3f9c…".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Hello { role: Role, room: String, key: String }, // Registers, and refreshes the registration
+    Leave { role: Role, room: String, key: String }, // Frees our place at once, for a restart to take it
+    Seen(SocketAddr),                                // The address the helper saw the hello come from
+    Peer(SocketAddr),                                // The other side of the room, to punch through to
+    Punch,                                           // Sent straight to the peer to open our NAT for it
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Message::Hello { role, room, key } => format!("{} hello {} {} {}", MAGIC, role.word(), room, key),
+            Message::Leave { role, room, key } => format!("{} leave {} {} {}", MAGIC, role.word(), room, key),
+            Message::Seen(address) => format!("{} seen {}", MAGIC, address),
+            Message::Peer(address) => format!("{} peer {}", MAGIC, address),
+            Message::Punch => format!("{} punch", MAGIC),
+        }
+        .into_bytes()
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data).ok()?;
+        let mut words = text.strip_prefix(MAGIC)?.split_whitespace();
+        match (words.next()?, words.next()) {
+            (kind @ ("hello" | "leave"), Some(role)) => {
+                let role = match role {
+                    "sender" => Role::Sender,
+                    "receiver" => Role::Receiver,
+                    _ => return None,
+                };
+                let (room, key) = (words.next()?.to_string(), words.next()?.to_string());
+                Some(if kind == "hello" { Message::Hello { role, room, key } } else { Message::Leave { role, room, key } })
+            }
+            ("seen", Some(address)) => Some(Message::Seen(address.parse().ok()?)),
+            ("peer", Some(address)) => Some(Message::Peer(address.parse().ok()?)),
+            ("punch", None) => Some(Message::Punch),
+            _ => None,
+        }
+    }
+}
+
+// How the stream gets from sender to receiver once they have met at the helper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerPath {
+    Waiting,                // For the other side to join the room
+    Direct(SocketAddr),     // Through both NATs, to the peer's public address
+    Relayed(SocketAddr),    // Punching failed, so the helper forwards everything
+}
+
+impl PeerPath {
+    // Where to send: the peer, or the helper that passes it on.
+    pub fn address(&self) -> Option<SocketAddr> {
+        match self {
+            PeerPath::Waiting => None,
+            PeerPath::Direct(address) | PeerPath::Relayed(address) => Some(*address),
+        }
+    }
+}
+
+impl fmt::Display for PeerPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeerPath::Waiting => write!(f, "waiting for the other side to join"),
+            PeerPath::Direct(address) => write!(f, "direct to {} (hole punched)", address),
+            PeerPath::Relayed(address) => write!(f, "relayed through {}", address),
+        }
+    }
+}
+
+// "host" or "host:port"; the helper's port defaults to 3478.
+pub async fn resolve(server: &str) -> Result<SocketAddr> {
+    resolve_with_default(server, DEFAULT_PORT).await
+}
+
+pub async fn resolve_with_default(server: &str, default_port: u16) -> Result<SocketAddr> {
+    let server = server.trim();
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, default_port));
+    }
+    let with_port = if server.contains(':') { server.to_string() } else { format!("{}:{}", server, default_port) };
+    lookup_host(&with_port).await?.next().with_context(|| format!("'{}' did not resolve", server))
+}
+
+// Registers with the helper until the other side is there, then tries to punch through
+// to it. Datagrams from the socket that aren't ours are dropped meanwhile.
+pub async fn pair(socket: &UdpSocket, server: SocketAddr, room: &Room, role: Role) -> Result<PeerPath> {
+    let hello = room.hello(role).encode();
+    let mut hello_timer = interval(HELLO_INTERVAL);
+    let mut buf = [0u8; 2048];
+    let mut public = None;
+    let peer = loop {
+        tokio::select! {
+            _ = hello_timer.tick() => {
+                socket.send_to(&hello, server).await.context("Failed to reach the rendezvous helper")?;
+            }
+            received = socket.recv_from(&mut buf) => {
+                let Ok((len, from)) = received else {
+                    continue; // ICMP unreachable from an earlier hello; the helper may not be up yet
+                };
+                match Message::decode(&buf[..len]) {
+                    Some(Message::Peer(peer)) if from == server => break peer,
+                    Some(Message::Seen(seen)) if from == server && public != Some(seen) => {
+                        log!("Rendezvous helper sees us as {}, waiting for the {} to join '{}'", seen, role.other().word(), room.name);
+                        public = Some(seen);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    };
+    Ok(punch(socket, peer, server).await)
+}
+
+// Both sides send to each other's public address at once, which makes each NAT expect
+// the other's packets. Anything arriving from the peer means the path is open.
+pub async fn punch(socket: &UdpSocket, peer: SocketAddr, server: SocketAddr) -> PeerPath {
+    let deadline = Instant::now() + PUNCH_TIMEOUT;
+    let mut punch_timer = interval(PUNCH_INTERVAL);
+    let mut buf = [0u8; 2048];
+    loop {
+        tokio::select! {
+            _ = punch_timer.tick() => {
+                let _ = socket.send_to(&Message::Punch.encode(), peer).await;
+            }
+            received = timeout_at(deadline.into(), socket.recv_from(&mut buf)) => match received {
+                Ok(Ok((_, from))) if from == peer => break,
+                Ok(_) => {}
+                Err(_) => return PeerPath::Relayed(server),
+            }
+        }
+    }
+    // A few more, in case ours haven't got through to the peer yet.
+    for _ in 0..3 {
+        let _ = socket.send_to(&Message::Punch.encode(), peer).await;
+    }
+    PeerPath::Direct(peer)
+}
+
+struct Registration {
+    role: Role,
+    room: String,
+    key: String,   // See `Room::key`; a room is only the same with the same secret
+    seen: Instant, // Last hello or forwarded datagram
+}
+
+impl Registration {
+    fn is(&self, role: Role, room: &str, key: &str) -> bool {
+        self.role == role && self.room == room && self.key == key
+    }
+}
+
+// The other side of `role`'s room, if it has joined.
+fn peer_of(registered: &HashMap<SocketAddr, Registration>, role: Role, room: &str, key: &str) -> Option<SocketAddr> {
+    registered.iter().find(|(_, peer)| peer.is(role.other(), room, key)).map(|(address, _)| *address)
+}
+
+// `audio-streamer rendezvous`: introduces a sender and a receiver that name the same room
+// with the same secret, and forwards between them when their NATs can't be punched
+// through. Only datagrams from registered peers are forwarded, and only to the other
+// side of their own room. A place is held until its peer leaves or goes quiet, so a
+// second hello for it from elsewhere is turned away rather than taking it over.
+pub async fn run_server(listen: SocketAddr) -> Result<()> {
+    let socket = UdpSocket::bind(listen).await.with_context(|| format!("Failed to listen on UDP {}", listen))?;
+    println!("Rendezvous helper on UDP {} (Ctrl+C to stop)", listen);
+    let mut registered: HashMap<SocketAddr, Registration> = HashMap::new();
+    let mut buf = vec![0u8; 65536];
+    let mut expiry_timer = interval(REGISTRATION_TIMEOUT / 4);
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                let Ok((len, from)) = received else {
+                    continue;
+                };
+                match Message::decode(&buf[..len]) {
+                    Some(Message::Hello { role, room, key }) => {
+                        if registered.iter().any(|(address, known)| *address != from && known.is(role, &room, &key)) {
+                            continue;
+                        }
+                        let new = registered.get(&from).is_none_or(|known| !known.is(role, &room, &key));
+                        if new {
+                            println!("{} {} joined '{}'", role.word(), from, room);
+                        }
+                        let peer = peer_of(&registered, role, &room, &key);
+                        registered.insert(from, Registration { role, room, key, seen: Instant::now() });
+                        let _ = socket.send_to(&Message::Seen(from).encode(), from).await;
+                        if let Some(peer) = peer {
+                            let _ = socket.send_to(&Message::Peer(peer).encode(), from).await;
+                            if new {
+                                let _ = socket.send_to(&Message::Peer(from).encode(), peer).await;
+                            }
+                        }
+                    }
+                    Some(Message::Leave { role, room, key }) => {
+                        if registered.get(&from).is_some_and(|known| known.is(role, &room, &key)) {
+                            registered.remove(&from);
+                            println!("{} {} left '{}'", role.word(), from, room);
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        let Some(known) = registered.get_mut(&from) else {
+                            continue;
+                        };
+                        known.seen = Instant::now();
+                        let (role, room, key) = (known.role, known.room.clone(), known.key.clone());
+                        if let Some(peer) = peer_of(&registered, role, &room, &key) {
+                            let _ = socket.send_to(&buf[..len], peer).await;
+                        }
+                    }
+                }
+            }
+            _ = expiry_timer.tick() => {
+                registered.retain(|address, known| {
+                    let alive = known.seen.elapsed() < REGISTRATION_TIMEOUT;
+                    if !alive {
+                        println!("{} {} left '{}'", known.role.word(), address, known.room);
+                    }
+                    alive
+                });
+            }
+        }
+    }
+}
+
+// What two STUN servers see of one socket. The same public port from both means the NAT
+// maps endpoint-independently, which hole punching needs; "symmetric" NATs that pick a new
+// port per destination can only be relayed.
+#[derive(Debug, Clone)]
+pub struct NatReport {
+    pub public: SocketAddr,
+    pub punchable: Option<bool>, // None when only one server answered
+}
+
+impl NatReport {
+    pub fn describe(&self) -> String {
+        match self.punchable {
+            Some(true) => format!("Public address {}; the NAT keeps ports, so hole punching should work", self.public),
+            Some(false) => format!("Public address {}; the NAT changes ports per destination, so streams will go through the helper", self.public),
+            None => format!("Public address {}; only one STUN server answered, so the NAT type is unknown", self.public),
+        }
+    }
+}
+
+pub async fn check_nat(servers: &[&str]) -> Result<NatReport> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    let mut seen = Vec::new();
+    for server in servers {
+        match stun_mapping(&socket, server).await {
+            Ok(public) => seen.push(public),
+            Err(e) => log!("STUN server {} failed: {:#}", server, e),
+        }
+    }
+    let public = *seen.first().context("No STUN server answered; UDP to the internet may be blocked")?;
+    let punchable = (seen.len() > 1).then(|| seen.iter().all(|address| *address == public));
+    Ok(NatReport { public, punchable })
+}
+
+// An RFC 5389 binding request, and the XOR-MAPPED-ADDRESS (or old MAPPED-ADDRESS) of the reply.
+async fn stun_mapping(socket: &UdpSocket, server: &str) -> Result<SocketAddr> {
+    const MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xa4, 0x42];
+    let server = lookup_host(server).await?.find(SocketAddr::is_ipv4).context("No IPv4 address")?;
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let transaction = &nanos.to_be_bytes()[4..];
+    let mut request = vec![0x00, 0x01, 0x00, 0x00];
+    request.extend_from_slice(&MAGIC_COOKIE);
+    request.extend_from_slice(transaction);
+    socket.send_to(&request, server).await?;
+    let mut buf = [0u8; 1024];
+    let len = loop {
+        let (len, from) = timeout(STUN_TIMEOUT, socket.recv_from(&mut buf)).await.context("No answer")??;
+        if from == server && len >= 20 && buf[8..20] == *transaction {
+            break len;
+        }
+    };
+    let mut attributes = &buf[20..len];
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let size = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let Some(value) = attributes.get(4..4 + size) else {
+            break;
+        };
+        // Family 1 is IPv4: reserved byte, family, port, address.
+        if (kind == 0x0020 || kind == 0x0001) && size >= 8 && value[1] == 1 {
+            let mut port = u16::from_be_bytes([value[2], value[3]]);
+            let mut ip = [value[4], value[5], value[6], value[7]];
+            if kind == 0x0020 {
+                port ^= u16::from_be_bytes([MAGIC_COOKIE[0], MAGIC_COOKIE[1]]);
+                ip.iter_mut().zip(MAGIC_COOKIE).for_each(|(byte, cookie)| *byte ^= cookie);
+            }
+            return Ok(SocketAddr::from((ip, port)));
+        }
+        attributes = &attributes[(4 + size).next_multiple_of(4).min(attributes.len())..];
+    }
+    bail!("The answer has no mapped address")
+}
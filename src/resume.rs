@@ -0,0 +1,59 @@
+use crate::{config::Config, paths, streams::Stream};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// One stream that was running when the app last went away, whether it was closed,
+// crashed or the machine rebooted. Enough to start it again on the next launch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastStream {
+    pub source: String, // Source name, as pactl lists it
+    pub target_ip: String,
+    pub target_port: u16,
+    pub bluetooth_sink: Option<String>,
+}
+
+impl LastStream {
+    pub fn of(stream: &Stream) -> Self {
+        Self {
+            source: stream.source.name.clone(),
+            target_ip: stream.config.target_ip.clone(),
+            target_port: stream.config.target_port,
+            bluetooth_sink: stream.config.bluetooth_sink.clone(),
+        }
+    }
+
+    pub fn target(&self) -> String {
+        match &self.bluetooth_sink {
+            Some(address) => format!("🎧 {}", address),
+            None => format!("{}:{}", self.target_ip, self.target_port),
+        }
+    }
+
+    // The current settings, pointed where this stream went.
+    pub fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        config.target_ip = self.target_ip.clone();
+        config.target_port = self.target_port;
+        config.bluetooth_sink = self.bluetooth_sink.clone();
+        config
+    }
+}
+
+pub fn path_for(config_path: &Path) -> PathBuf {
+    paths::state_dir(config_path).join("last-session.json")
+}
+
+// A missing or unreadable file just means nothing to resume.
+pub fn load(path: &Path) -> Vec<LastStream> {
+    fs::read_to_string(path).ok().and_then(|content| serde_json::from_str(&content).ok()).unwrap_or_default()
+}
+
+// Written whenever a stream starts or stops, so it is current however the app ends.
+pub fn save(path: &Path, streams: &[LastStream]) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(streams)?)?;
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use crate::{config::Config, supervisor::Supervisor, tag::StreamTag};
+use anyhow::{Context, Result};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    process::Stdio,
+};
+use tokio::{process::Command, runtime::Handle};
+
+// The relay can't speak RIST itself, so with that transport it forwards to a second
+// ffmpeg on loopback that re-sends the MPEG-TS with librist. Everything before the
+// gateway (pause, delay, outputs) works as with plain UDP.
+// Remuxing drops the service name, so `tag` is set again.
+pub fn gateway_args(input: SocketAddr, target: SocketAddr, buffer_ms: u32, packet_size: usize, tag: &StreamTag) -> Vec<String> {
+    let mut args = vec![
+        "-loglevel".to_string(),
+        "error".to_string(),
+        "-fflags".to_string(),
+        "nobuffer".to_string(),
+        "-f".to_string(),
+        "mpegts".to_string(),
+        "-i".to_string(),
+        format!("udp://{}?overrun_nonfatal=1", input),
+        "-c".to_string(),
+        "copy".to_string(),
+    ];
+    args.extend(tag.ffmpeg_args());
+    args.extend([
+        "-f".to_string(),
+        "mpegts".to_string(),
+        // The sender keeps this much for retransmission; the receiver's buffer should match.
+        format!("rist://{}?buffer_size={}&pkt_size={}", target, buffer_ms, packet_size),
+    ]);
+    args
+}
+
+// Returns the gateway and the loopback address the relay should send to.
+pub fn start_gateway(ffmpeg: &Path, config: &Config, target: SocketAddr, packet_size: usize, tag: &StreamTag, runtime_handle: &Handle) -> Result<(Supervisor, SocketAddr)> {
+    // Let the OS pick a free port, then hand it to ffmpeg.
+    let input = UdpSocket::bind("127.0.0.1:0").and_then(|socket| socket.local_addr()).context("No free port for the RIST gateway")?;
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(gateway_args(input, target, config.rist_buffer_ms, packet_size, tag))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    let gateway = Supervisor::spawn("RIST gateway", &mut command, runtime_handle)?;
+    Ok((gateway, input))
+}
@@ -0,0 +1,74 @@
+use crate::{config::Config, history::unix_now, paths, secrets};
+use anyhow::{Context, Result, bail};
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Enough to step back over a few bad saves without the directory growing forever.
+const KEEP: usize = 10;
+
+// Copies of the config file as it was before each save, in a `backups` directory in
+// the state directory, named by when they were replaced: config-1714588200.json.
+fn dir_for(config_path: &Path) -> PathBuf {
+    paths::state_dir(config_path).join("backups")
+}
+
+// Newest first, with when each was replaced.
+pub fn list(config_path: &Path) -> Vec<(u64, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir_for(config_path)) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let saved = path.file_stem()?.to_str()?.strip_prefix("config-")?.parse().ok()?;
+            Some((saved, path))
+        })
+        .collect();
+    backups.sort_by_key(|(saved, _)| Reverse(*saved));
+    backups
+}
+
+// Writes `config` to the config file, keeping what was there as a backup. Saving the
+// same config again adds none, and neither does a second save within the same second,
+// which keeps the older copy.
+pub fn save(config_path: &Path, config: &Config) -> Result<()> {
+    let json = config.to_json()?;
+    let mut dropped = Vec::new();
+    if let Ok(previous) = fs::read_to_string(config_path)
+        && previous != json
+    {
+        let dir = dir_for(config_path);
+        fs::create_dir_all(&dir)?;
+        let backup = dir.join(format!("config-{}.json", unix_now()));
+        if !backup.exists() {
+            fs::write(&backup, previous).with_context(|| format!("Failed to write {}", backup.display()))?;
+        }
+        for (_, old) in list(config_path).into_iter().skip(KEEP) {
+            dropped.extend(fs::read_to_string(&old).ok());
+            let _ = fs::remove_file(old);
+        }
+    }
+    fs::write(config_path, json).with_context(|| format!("Failed to write {}", config_path.display()))?;
+    secrets::prune(config_path, &dropped);
+    Ok(())
+}
+
+// Puts back the newest backup that differs from the config file, and saves it, so the
+// config it replaces becomes a backup in turn and the restore can be undone the same
+// way. Returns it with when it was replaced.
+pub fn restore_previous(config_path: &Path) -> Result<(Config, u64)> {
+    let current = fs::read_to_string(config_path).unwrap_or_default();
+    for (saved, path) in list(config_path) {
+        let json = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if json == current {
+            continue;
+        }
+        let config = Config::from_json(&json).with_context(|| format!("{} is not a valid config", path.display()))?;
+        save(config_path, &config)?;
+        return Ok((config, saved));
+    }
+    bail!("There is no earlier config to go back to")
+}
@@ -0,0 +1,115 @@
+use crate::tag::StreamTag;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const RTP_HEADER_LEN: usize = 12;
+// First dynamic payload type; receivers learn the format from the SDP.
+pub const DYNAMIC_PAYLOAD_TYPE: u8 = 96;
+
+const RTCP_SENDER_REPORT: u8 = 200;
+const RTCP_RECEIVER_REPORT: u8 = 201;
+const RTCP_SOURCE_DESCRIPTION: u8 = 202;
+// SDES item types, RFC 3550 section 6.5.
+const SDES_CNAME: u8 = 1;
+const SDES_NAME: u8 = 2;
+const SDES_TOOL: u8 = 6;
+const SDES_NOTE: u8 = 7;
+// NTP counts from 1900, Unix time from 1970.
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+// 64-bit NTP time: seconds in the upper half, the fraction of a second in the lower.
+pub fn ntp_timestamp(at: SystemTime) -> u64 {
+    let since_unix = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let fraction = (since_unix.subsec_nanos() as u64) * (1 << 32) / 1_000_000_000;
+    ((since_unix.as_secs() + NTP_UNIX_OFFSET_SECS) << 32) | fraction
+}
+
+// Builds RFC 3550 packets: V=2, no padding/extension/CSRCs.
+pub struct RtpPacketizer {
+    payload_type: u8,
+    ssrc: u32,
+    seq: u16,
+    timestamp: u32,
+    packets: u32, // Sent so far, for sender reports
+    octets: u32,  // Payload bytes sent so far
+}
+
+impl RtpPacketizer {
+    pub fn new(payload_type: u8) -> Self {
+        // Random enough to tell restarted sessions apart without pulling in an RNG.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() ^ d.as_secs() as u32)
+            .unwrap_or_default();
+        Self { payload_type, ssrc: nanos, seq: nanos as u16, timestamp: nanos.rotate_left(16), packets: 0, octets: 0 }
+    }
+
+    // RFC 7273's direct media clock: the first sample, captured at `captured`, is
+    // stamped with the samples since the NTP epoch, so receivers with synced clocks
+    // can tell when each one was captured without waiting for a sender report. Counting
+    // samples from there, the sound card's clock drifts from it slowly; sender reports
+    // say by how much.
+    pub fn wall_clock(payload_type: u8, sample_rate: u32, captured: SystemTime) -> Self {
+        let ntp = ntp_timestamp(captured);
+        let samples = (ntp >> 32) * sample_rate as u64 + (((ntp & 0xffff_ffff) * sample_rate as u64) >> 32);
+        Self { timestamp: samples as u32, ..Self::new(payload_type) }
+    }
+
+    // `samples` is the number of sample frames in `payload`, which advances the RTP clock.
+    pub fn packetize(&mut self, payload: &[u8], samples: u32) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(RTP_HEADER_LEN + payload.len());
+        packet.push(0x80);
+        packet.push(self.payload_type & 0x7f);
+        packet.extend_from_slice(&self.seq.to_be_bytes());
+        packet.extend_from_slice(&self.timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(payload);
+
+        self.seq = self.seq.wrapping_add(1);
+        self.timestamp = self.timestamp.wrapping_add(samples);
+        self.packets = self.packets.wrapping_add(1);
+        self.octets = self.octets.wrapping_add(payload.len() as u32);
+        packet
+    }
+
+    // When the next sample was captured in wall-clock time, against its RTP timestamp,
+    // so receivers can line this stream up with video or other senders (RFC 3550 6.4.1).
+    fn sender_report(&self, next_captured: SystemTime) -> Vec<u8> {
+        let mut packet = vec![0x80, RTCP_SENDER_REPORT, 0, 6];
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(&ntp_timestamp(next_captured).to_be_bytes());
+        packet.extend_from_slice(&self.timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.packets.to_be_bytes());
+        packet.extend_from_slice(&self.octets.to_be_bytes());
+        packet
+    }
+
+    // RTCP naming this stream's SSRC: a report, which every compound packet starts
+    // with, then the source description. With `next_captured` (see `sender_report`)
+    // the report is a sender report, otherwise an empty receiver report. Sent on the
+    // RTP port itself (RFC 5761), since it goes out through the relay like the audio.
+    pub fn source_description(&self, tag: &StreamTag, next_captured: Option<SystemTime>) -> Vec<u8> {
+        let mut packet = match next_captured {
+            Some(captured) => self.sender_report(captured),
+            None => [0x80, RTCP_RECEIVER_REPORT, 0, 1].into_iter().chain(self.ssrc.to_be_bytes()).collect(),
+        };
+        let mut chunk = self.ssrc.to_be_bytes().to_vec();
+        let items = [
+            (SDES_CNAME, format!("audio-streamer@{}", tag.sender)),
+            (SDES_NAME, tag.sender.clone()),
+            (SDES_TOOL, "audio-streamer".to_string()),
+            (SDES_NOTE, format!("session {}, {}", tag.session, tag.codec)),
+        ];
+        for (item, text) in items {
+            let text = &text.as_bytes()[..text.len().min(u8::MAX as usize)];
+            chunk.extend_from_slice(&[item, text.len() as u8]);
+            chunk.extend_from_slice(text);
+        }
+        // A null item ends the list, padded out to a 32-bit boundary.
+        chunk.push(0);
+        chunk.resize(chunk.len().next_multiple_of(4), 0);
+        packet.extend_from_slice(&[0x81, RTCP_SOURCE_DESCRIPTION]);
+        packet.extend_from_slice(&((chunk.len() / 4) as u16).to_be_bytes());
+        packet.extend_from_slice(&chunk);
+        packet
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{config::Config, fallback::{self, Engine}, log, netwatch, paths, rtp::DYNAMIC_PAYLOAD_TYPE, tag::StreamTag, transport::Transport};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::ChildStdout,
+    runtime::Handle,
+    task::JoinHandle,
+};
+
+// Plain RTP, which players can only make sense of with an SDP: the built-in engine's
+// unless it sends FLAC, ffmpeg's with the RTP transport.
+pub fn applies(config: &Config, engine: Engine) -> bool {
+    (engine == Engine::BuiltIn && !fallback::sends_flac(config)) || config.transport == Transport::Rtp
+}
+
+// Our address on the route to `target`, which is where a receiver fetches the SDP from.
+pub fn local_address(target: IpAddr) -> Option<IpAddr> {
+    netwatch::route(target).and_then(|route| route.source)
+}
+
+// Served on the TCP port with the target's port number, on this machine.
+pub fn url(local: Option<IpAddr>, port: u16) -> String {
+    let host = match local {
+        Some(IpAddr::V6(ip)) => format!("[{}]", ip),
+        Some(ip) => ip.to_string(),
+        None => "<this machine>".to_string(),
+    };
+    format!("http://{}:{}/stream.sdp", host, port)
+}
+
+// Saved too, for players that only open files, e.g. after copying it to the phone.
+pub fn path_for(target: SocketAddr) -> PathBuf {
+    paths::cache_dir().join(format!("{}-{}.sdp", target.ip(), target.port()).replace(':', "_"))
+}
+
+fn address(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => format!("IN IP4 {}", ip),
+        IpAddr::V6(ip) => format!("IN IP6 {}", ip),
+    }
+}
+
+// The session lines both engines share. The origin's session ID is the tag's, as a number.
+fn session_lines(tag: &StreamTag, target: SocketAddr, local: Option<IpAddr>) -> Vec<String> {
+    let id = u32::from_str_radix(tag.session.get(..8).unwrap_or_default(), 16).unwrap_or(0);
+    vec![
+        "v=0".to_string(),
+        format!("o=- {} 1 {}", id, address(local.unwrap_or(target.ip()))),
+        format!("s={}", tag.sender),
+        format!("i={}", tag.codec),
+        format!("c={}", address(target.ip())),
+        "t=0 0".to_string(),
+        "a=tool:audio-streamer".to_string(),
+    ]
+}
+
+// RFC 7273: the sender reports' clock is the system's, NTP-synced so that it lines up
+// with other machines'; `direct` says the timestamps themselves count from its epoch,
+// otherwise only the reports relate the two.
+fn clock_lines(direct: bool) -> [String; 2] {
+    ["a=ts-refclk:ntp=/traceable/".to_string(), if direct { "a=mediaclk:direct=0" } else { "a=mediaclk:sender" }.to_string()]
+}
+
+// RTP L16/L24 as the built-in engine sends it, with its RTCP on the same port.
+pub fn pcm(config: &Config, tag: &StreamTag, target: SocketAddr, local: Option<IpAddr>) -> String {
+    let mut lines = session_lines(tag, target, local);
+    lines.extend([
+        format!("m=audio {} RTP/AVP {}", target.port(), DYNAMIC_PAYLOAD_TYPE),
+        format!("a=rtpmap:{} L{}/{}/{}", DYNAMIC_PAYLOAD_TYPE, config.sample_format.rtp_bits(), config.sample_rate, config.channels),
+        format!("a=ptime:{}", config.packet_millis),
+        "a=rtcp-mux".to_string(),
+    ]);
+    if config.rtp_clock {
+        lines.extend(clock_lines(true));
+    }
+    lines.join("\r\n") + "\r\n"
+}
+
+// ffmpeg's own SDP knows the codec's parameters (e.g. AAC's `config=`), but was written
+// for the relay on 127.0.0.1; the media section is kept and the rest pointed at the target.
+// With `clock`, ffmpeg's sender reports come on the audio's port, see `Config::rtp_clock`.
+pub fn retarget(ffmpeg_sdp: &str, tag: &StreamTag, target: SocketAddr, local: Option<IpAddr>, clock: bool) -> String {
+    let mut lines = session_lines(tag, target, local);
+    let media = ffmpeg_sdp.lines().map(str::trim_end).skip_while(|line| !line.starts_with("m="));
+    for line in media.filter(|line| !line.is_empty() && !line.starts_with("c=")) {
+        match line.strip_prefix("m=audio ").and_then(|rest| rest.split_once(' ')) {
+            Some((_, formats)) => lines.push(format!("m=audio {} {}", target.port(), formats)),
+            None => lines.push(line.to_string()),
+        }
+    }
+    if clock {
+        lines.push("a=rtcp-mux".to_string());
+        lines.extend(clock_lines(false));
+    }
+    lines.join("\r\n") + "\r\n"
+}
+
+// Serves a stream's SDP over HTTP and keeps the saved copy current. The built-in engine's
+// is known at once; ffmpeg's arrives on its stdout once the encoder has started.
+pub struct SdpServer {
+    description: Arc<Mutex<Option<String>>>,
+    pub url: Option<String>, // None when the port is taken
+    pub path: PathBuf,
+    server: Option<JoinHandle<()>>,
+}
+
+impl SdpServer {
+    pub fn start(target: SocketAddr, runtime_handle: &Handle) -> Self {
+        let description = Arc::new(Mutex::new(None));
+        let path = path_for(target);
+        let listener = TcpListener::bind(SocketAddr::new(IpAddr::from([0u8; 16]), target.port()))
+            .or_else(|_| TcpListener::bind(("0.0.0.0", target.port())))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener));
+        let server = match listener {
+            Ok(listener) => {
+                let description = description.clone();
+                Some(runtime_handle.spawn(async move {
+                    if let Err(e) = serve(listener, description).await {
+                        log!("SDP server stopped: {:#}", e);
+                    }
+                }))
+            }
+            Err(e) => {
+                log!("Could not serve the SDP on TCP port {}: {}", target.port(), e);
+                None
+            }
+        };
+        let url = server.is_some().then(|| url(local_address(target.ip()), target.port()));
+        Self { description, url, path, server }
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.description.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, sdp: String) {
+        store(&self.description, &self.path, sdp);
+    }
+
+    // ffmpeg prints "SDP:", then the SDP, then an empty line, when it starts an RTP output.
+    pub fn read_ffmpeg(&self, stdout: ChildStdout, tag: StreamTag, target: SocketAddr, clock: bool, runtime_handle: &Handle) {
+        let (description, path) = (self.description.clone(), self.path.clone());
+        runtime_handle.spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            let mut sdp: Option<String> = None;
+            while let Ok(Some(line)) = lines.next_line().await {
+                match &mut sdp {
+                    None if line.trim() == "SDP:" => sdp = Some(String::new()),
+                    Some(text) if line.trim().is_empty() && !text.is_empty() => {
+                        store(&description, &path, retarget(text, &tag, target, local_address(target.ip()), clock));
+                        sdp = None;
+                    }
+                    Some(text) => text.push_str(&format!("{}\n", line)),
+                    None => {}
+                }
+            }
+        });
+    }
+
+    pub fn stop(self) {
+        if let Some(server) = self.server {
+            server.abort();
+        }
+    }
+}
+
+fn store(description: &Mutex<Option<String>>, path: &Path, sdp: String) {
+    if let Err(e) = save(path, &sdp) {
+        log!("Failed to save the SDP file: {:#}", e);
+    }
+    *description.lock().unwrap() = Some(sdp);
+}
+
+fn save(path: &Path, sdp: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, sdp).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// Any GET gets the SDP; players ask for whatever path they were given.
+async fn serve(listener: TcpListener, description: Arc<Mutex<Option<String>>>) -> Result<()> {
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    loop {
+        let (mut client, _) = listener.accept().await?;
+        let description = description.clone();
+        tokio::spawn(async move {
+            let mut request = [0u8; 2048];
+            let _ = client.read(&mut request).await;
+            let response = match description.lock().unwrap().clone() {
+                Some(sdp) => format!("HTTP/1.0 200 OK\r\nContent-Type: application/sdp\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n\r\n{}", sdp.len(), sdp),
+                None => {
+                    let body = "The encoder is still starting";
+                    format!("HTTP/1.0 503 Service Unavailable\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+                }
+            };
+            let _ = client.write_all(response.as_bytes()).await;
+        });
+    }
+}
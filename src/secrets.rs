@@ -0,0 +1,116 @@
+use crate::{config::Config, log, outputs::Output, profiles, rollback, tag::random_uuid};
+use anyhow::{Context, Result};
+use keyring::Entry;
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+const SERVICE: &str = "audio-streamer";
+// What a secret reads as in the config file once it is in the keyring.
+const MARKER: &str = "keyring:";
+
+// Secrets this process has stored or read, by value, with their keyring entries, so
+// saving again doesn't make new ones. A changed secret gets a new entry, since profiles
+// copied from the config may still refer to the old one.
+static ENTRIES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new()); // (value, entry id)
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+// The listening outputs' token, the MQTT password, the Icecast passwords, the relay
+// server key and the rendezvous secret, each with where it is in the config, e.g.
+// `outputs[2].password`. Bundles store them by that path.
+pub fn fields(config: &mut Config) -> Vec<(String, &mut String)> {
+    let mut secrets = vec![
+        ("listen_token".to_string(), &mut config.listen_token),
+        ("integrations.mqtt.password".to_string(), &mut config.integrations.mqtt.password),
+    ];
+    for (i, output) in config.outputs.iter_mut().enumerate() {
+        if let Output::Icecast { password, .. } = output {
+            secrets.push((format!("outputs[{}].password", i), password));
+        }
+    }
+    secrets.push(("relay_server_key".to_string(), &mut config.relay_server_key));
+    secrets.push(("rendezvous_secret".to_string(), &mut config.rendezvous_secret));
+    secrets
+}
+
+fn store(value: &str) -> Result<String> {
+    let mut entries = ENTRIES.lock().unwrap();
+    if let Some((_, id)) = entries.iter().find(|(known, _)| known == value) {
+        return Ok(id.clone());
+    }
+    let id = random_uuid();
+    Entry::new(SERVICE, &id)?.set_password(value)?;
+    entries.push((value.to_string(), id.clone()));
+    Ok(id)
+}
+
+// The config as it is written to disk: secrets in the OS keyring (the Secret Service,
+// e.g. GNOME Keyring or KWallet), and only their entry IDs in the file. Without a
+// keyring they stay in the file as before.
+pub fn seal(config: &Config) -> Config {
+    let mut sealed = config.clone();
+    for (_, secret) in fields(&mut sealed) {
+        if secret.is_empty() || secret.starts_with(MARKER) {
+            continue;
+        }
+        match store(secret) {
+            Ok(id) => *secret = format!("{}{}", MARKER, id),
+            Err(e) => {
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    log!("No keyring to keep secrets in, so they are saved in the config file: {}", e);
+                }
+            }
+        }
+    }
+    sealed
+}
+
+// The other way, after reading the file. A secret whose entry can't be read fails the
+// load: going on with the reference as the password would only fail later, at the
+// server, and saving would make it the secret.
+pub fn unseal(config: &mut Config) -> Result<()> {
+    for (_, secret) in fields(config) {
+        let Some(id) = secret.strip_prefix(MARKER).map(str::to_string) else {
+            continue;
+        };
+        let value = Entry::new(SERVICE, &id)
+            .and_then(|entry| entry.get_password())
+            .context("Could not read a secret from the keyring; is it locked?")?;
+        ENTRIES.lock().unwrap().push((value.clone(), id));
+        *secret = value;
+    }
+    Ok(())
+}
+
+// The entry IDs a config file, backup or profile refers to.
+fn references(json: &str) -> impl Iterator<Item = &str> {
+    json.split(MARKER).skip(1).filter_map(|rest| rest.split('"').next())
+}
+
+// After a save or a profile deleted: deletes the entries `dropped` (the files just
+// removed) and this process refer to that neither the config file nor any backup or
+// profile still does, so a changed secret's old entry goes with the last copy holding it.
+pub fn prune(config_path: &Path, dropped: &[String]) {
+    let mut kept: Vec<String> = fs::read_to_string(config_path).into_iter().collect();
+    kept.extend(rollback::list(config_path).into_iter().filter_map(|(_, path)| fs::read_to_string(path).ok()));
+    kept.extend(profiles::contents(config_path));
+    let referenced: HashSet<&str> = kept.iter().flat_map(|json| references(json)).collect();
+    let mut entries = ENTRIES.lock().unwrap();
+    let mut unused: HashSet<String> = dropped.iter().flat_map(|json| references(json)).map(str::to_string).collect();
+    unused.extend(entries.iter().map(|(_, id)| id.clone()));
+    unused.retain(|id| !referenced.contains(id.as_str()));
+    for id in &unused {
+        if let Err(e) = Entry::new(SERVICE, id).and_then(|entry| entry.delete_credential())
+            && !matches!(e, keyring::Error::NoEntry)
+        {
+            log!("Could not delete an unused secret from the keyring: {}", e);
+        }
+    }
+    entries.retain(|(_, id)| !unused.contains(id));
+}
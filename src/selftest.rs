@@ -0,0 +1,260 @@
+use crate::{
+    config::Config,
+    fallback::{self, Engine, FallbackStreamer},
+    flac,
+    mtu,
+    netwatch,
+    pacing::Pacing,
+    qos::Dscp,
+    relay::{Relay, RelayOptions},
+    rtp::{DYNAMIC_PAYLOAD_TYPE, RTP_HEADER_LEN},
+    supervisor::Supervisor,
+    tag::StreamTag,
+    transport::{Packet, PacketKind, Transport},
+    xrun::Xruns,
+};
+use anyhow::Result;
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    process::Stdio,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, process::Command, runtime::Handle};
+
+const TEST_DURATION: Duration = Duration::from_secs(3);
+const TS_PACKET_LEN: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub lines: Vec<String>, // Diagnostics, one finding per line
+}
+
+impl SelfTestReport {
+    fn failed(reason: String) -> Self {
+        Self { passed: false, lines: vec![reason] }
+    }
+}
+
+// What the encoder's packets should hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    MpegTs,
+    Pcm, // RTP L16/L24 from the built-in engine
+    Flac, // The built-in engine's, over the native transport
+    Rtp, // ffmpeg's, with the RTP transport
+}
+
+impl Format {
+    fn of(engine: Engine, transport: Transport) -> Self {
+        match (engine, transport) {
+            (Engine::BuiltIn, Transport::Native) => Format::Flac,
+            (Engine::BuiltIn, _) => Format::Pcm,
+            (Engine::Ffmpeg, Transport::Rtp) => Format::Rtp,
+            (Engine::Ffmpeg, _) => Format::MpegTs,
+        }
+    }
+}
+
+// What arrived at the loopback receiver, checked packet by packet.
+#[derive(Default)]
+struct Observed {
+    datagrams: u32,
+    bytes: u64,
+    undecodable: u32, // Native packets that failed to parse
+    malformed: u32,   // Payloads that aren't valid MPEG-TS or RTP
+    described: u32,   // RTCP source descriptions next to the RTP
+    parity: u32,
+    seq_gaps: u32,
+    last_seq: Option<u32>,
+}
+
+impl Observed {
+    // `mask` wraps the expected successor: RTP sequence numbers are only 16 bits.
+    fn observe_seq(&mut self, seq: u32, mask: u32) {
+        if let Some(last) = self.last_seq
+            && seq != last.wrapping_add(1) & mask
+        {
+            self.seq_gaps += 1;
+        }
+        self.last_seq = Some(seq);
+    }
+
+    fn observe_payload(&mut self, payload: &[u8], format: Format) {
+        // RTCP shares the port (RFC 5761); its packet types 200-204 can't be RTP's dynamic one.
+        if format == Format::Pcm && payload.len() > 1 && (200..=204).contains(&payload[1]) {
+            self.described += 1;
+            return;
+        }
+        let valid = match format {
+            Format::MpegTs => !payload.is_empty()
+                && payload.len().is_multiple_of(TS_PACKET_LEN)
+                && payload.chunks(TS_PACKET_LEN).all(|chunk| chunk[0] == TS_SYNC_BYTE),
+            Format::Pcm => {
+                payload.len() > RTP_HEADER_LEN && payload[0] >> 6 == 2 && payload[1] & 0x7f == DYNAMIC_PAYLOAD_TYPE
+            }
+            Format::Flac => flac::frame_format(payload).is_some(),
+            // ffmpeg picks the payload type per codec and says which in the SDP.
+            Format::Rtp => payload.len() > RTP_HEADER_LEN && payload[0] >> 6 == 2,
+        };
+        if !valid {
+            self.malformed += 1;
+        }
+        // Raw RTP carries its own sequence number; native packets are checked by the caller.
+        if matches!(format, Format::Pcm | Format::Rtp) && valid {
+            self.observe_seq(u16::from_be_bytes([payload[2], payload[3]]) as u32, u16::MAX as u32);
+        }
+    }
+}
+
+// Streams the selected source through the real capture, encoder and relay to a
+// receiver on 127.0.0.1 for a few seconds and checks what comes out.
+pub async fn run_self_test(config: Config, source: String, engine: Engine, ffmpeg_path: Option<PathBuf>, runtime_handle: Handle) -> SelfTestReport {
+    match self_test(&config, &source, engine, ffmpeg_path, &runtime_handle).await {
+        Ok(report) => report,
+        Err(e) => SelfTestReport::failed(format!("❌ Could not run the pipeline: {:#}", e)),
+    }
+}
+
+async fn self_test(config: &Config, source: &str, engine: Engine, ffmpeg_path: Option<PathBuf>, runtime_handle: &Handle) -> Result<SelfTestReport> {
+    let receiver = UdpSocket::bind("127.0.0.1:0").await?;
+    let transport = fallback::relay_transport(config, engine);
+    let tag = StreamTag::new(config, engine);
+    // Sized and paced for the real target, so the test sends what streaming would.
+    let target = config.target_ip.parse().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    netwatch::resolve(target).await;
+    let packets = mtu::plan(config, target, transport);
+    let options = RelayOptions {
+        transport,
+        fec_group: config.fec_group_size,
+        keepalive_while_paused: false,
+        failover: None,
+        dscp: Dscp::Off, // Loopback only
+        tag: Some(tag.clone()),
+        rendezvous: None,
+        pacing: (transport == Transport::Native).then(|| Pacing::from_config(config, packets.ts_size)).flatten(),
+        second_path: None,
+        relay_server_key: None,
+        xruns: None,
+    };
+    let relay = Relay::start(receiver.local_addr()?, options, runtime_handle)?;
+
+    // Either handle stops its process when dropped, including on early returns.
+    let mut ffmpeg = None;
+    let mut fallback = None;
+    match engine {
+        Engine::Ffmpeg => {
+            let output_url = format!("udp://{}?pkt_size={}", relay.local_addr, packets.ts_size);
+            let args = config.build_ffmpeg_command(source, &output_url, Some(&tag))?;
+            let mut command = Command::new(ffmpeg_path.unwrap_or_else(|| PathBuf::from("ffmpeg")));
+            command.args(&args).stdout(Stdio::null()).stderr(Stdio::null());
+            ffmpeg = Some(Supervisor::spawn("ffmpeg", &mut command, runtime_handle)?);
+        }
+        Engine::BuiltIn => {
+            fallback = Some(FallbackStreamer::start(config, source, relay.local_addr, &packets, tag, Arc::new(Xruns::new(config, Engine::BuiltIn)), runtime_handle)?);
+        }
+    }
+
+    let format = Format::of(engine, transport);
+    let mut observed = Observed::default();
+    let mut buf = vec![0u8; 65536];
+    let deadline = Instant::now() + TEST_DURATION;
+    while let Ok(received) = tokio::time::timeout_at(deadline.into(), receiver.recv(&mut buf)).await {
+        let len = received?;
+        observed.datagrams += 1;
+        observed.bytes += len as u64;
+        // RIST only starts at the gateway, after the relay, so up to here it is plain UDP.
+        if transport != Transport::Native {
+            observed.observe_payload(&buf[..len], format);
+            continue;
+        }
+        match Packet::decode(&buf[..len]) {
+            Some(packet) if packet.kind == PacketKind::Data => {
+                observed.observe_seq(packet.seq, u32::MAX);
+                observed.observe_payload(&packet.payload, format);
+            }
+            Some(packet) if packet.kind == PacketKind::Parity => observed.parity += 1,
+            Some(_) => {}
+            None => observed.undecodable += 1,
+        }
+    }
+
+    let exit_reason = ffmpeg.as_ref().and_then(Supervisor::exit_reason)
+        .or_else(|| fallback.as_ref().and_then(FallbackStreamer::exit_reason));
+    if let Some(process) = ffmpeg {
+        process.stop();
+    }
+    if let Some(fallback) = fallback {
+        fallback.stop();
+    }
+    relay.stop();
+
+    Ok(evaluate(&observed, engine, transport, config, exit_reason))
+}
+
+fn evaluate(observed: &Observed, engine: Engine, transport: Transport, config: &Config, exit_reason: Option<String>) -> SelfTestReport {
+    let mut lines = Vec::new();
+    let mut check = |ok: bool, pass: String, fail: String| lines.push(if ok { format!("✅ {}", pass) } else { format!("❌ {}", fail) });
+
+    // A capture that died during the test explains everything else, so it comes first.
+    if let Some(reason) = exit_reason {
+        check(false, String::new(), format!("Capture stopped during the test: {}", reason));
+    }
+    let kbps = observed.bytes * 8 / 1000 / TEST_DURATION.as_secs();
+    let via = match transport {
+        Transport::Native => "the native transport",
+        Transport::Rtp => "RTP",
+        _ => "plain UDP",
+    };
+    check(
+        observed.datagrams > 0,
+        format!("{} packets arrived ({} kbps) via {}", observed.datagrams, kbps, via),
+        format!("Nothing arrived in {} s; check the audio source", TEST_DURATION.as_secs()),
+    );
+    if observed.datagrams > 0 {
+        let format = match Format::of(engine, transport) {
+            Format::MpegTs => "MPEG-TS".to_string(),
+            Format::Pcm => format!("RTP L{}", config.sample_format.rtp_bits()),
+            Format::Rtp => "RTP".to_string(),
+            Format::Flac => "FLAC".to_string(),
+        };
+        check(
+            observed.malformed == 0,
+            format!("Every payload is valid {}", format),
+            format!("{} packets were not valid {}", observed.malformed, format),
+        );
+        if transport == Transport::Native {
+            check(
+                observed.undecodable == 0,
+                "Native transport headers decode".to_string(),
+                format!("{} native transport packets failed to decode", observed.undecodable),
+            );
+        }
+        if Format::of(engine, transport) == Format::Pcm {
+            check(
+                observed.described > 0,
+                "The sender is named in RTCP".to_string(),
+                "No RTCP source description was sent".to_string(),
+            );
+        }
+        // Loopback never drops, so a gap means packets were lost or reordered inside the pipeline.
+        check(
+            observed.seq_gaps == 0,
+            "Sequence numbers are continuous".to_string(),
+            format!("{} sequence gaps", observed.seq_gaps),
+        );
+        if transport == Transport::Native && config.fec_group_size > 1 {
+            check(
+                observed.parity > 0,
+                format!("{} FEC parity packets", observed.parity),
+                "No FEC parity packets were sent".to_string(),
+            );
+        }
+    }
+
+    let passed = !lines.iter().any(|line| line.starts_with('❌'));
+    SelfTestReport { passed, lines }
+}
@@ -0,0 +1,83 @@
+use crate::config::Config;
+
+// Sweeps run over the whole audible range.
+const SWEEP_START_HZ: u32 = 20;
+const SWEEP_END_HZ: u32 = 20000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestSignal {
+    Sine,
+    Sweep,
+    PinkNoise,
+    ChannelId,
+}
+
+impl TestSignal {
+    pub const ALL: [TestSignal; 4] = [TestSignal::Sine, TestSignal::Sweep, TestSignal::PinkNoise, TestSignal::ChannelId];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TestSignal::Sine => "Sine",
+            TestSignal::Sweep => "Sweep (20 Hz – 20 kHz)",
+            TestSignal::PinkNoise => "Pink noise",
+            TestSignal::ChannelId => "Left/right ID",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            TestSignal::Sine => "A steady tone at the chosen frequency",
+            TestSignal::Sweep => "Logarithmic sweep; dips and peaks show the receiver's EQ",
+            TestSignal::PinkNoise => "Equal energy per octave, for judging overall balance",
+            TestSignal::ChannelId => "Low beep on the left, then high beep on the right",
+        }
+    }
+
+    // An ffmpeg lavfi source. Commas inside expressions are escaped because the
+    // filtergraph parser would otherwise read them as filter separators.
+    fn lavfi_source(self, frequency: u32, duration_secs: u32, sample_rate: u32) -> String {
+        let d = duration_secs;
+        match self {
+            TestSignal::Sine => format!("sine=frequency={}:sample_rate={}:duration={}", frequency, sample_rate, d),
+            TestSignal::Sweep => {
+                // Phase of an exponential chirp: 2π·f0·T/ln(k)·(k^(t/T) − 1), k = f1/f0.
+                let k = SWEEP_END_HZ / SWEEP_START_HZ;
+                format!(
+                    "aevalsrc=exprs=0.5*sin(2*PI*{f0}*{d}/log({k})*(pow({k}\\,t/{d})-1)):sample_rate={sr}:duration={d}",
+                    f0 = SWEEP_START_HZ,
+                    k = k,
+                    d = d,
+                    sr = sample_rate
+                )
+            }
+            TestSignal::PinkNoise => format!("anoisesrc=color=pink:amplitude=0.5:sample_rate={}:duration={}", sample_rate, d),
+            // Spoken "left"/"right" needs ffmpeg built with flite, which distros rarely ship,
+            // so every two seconds the left channel beeps low and then the right beeps high.
+            TestSignal::ChannelId => format!(
+                "aevalsrc=exprs=if(lt(mod(t\\,2)\\,0.5)\\,0.5*sin(2*PI*440*t)\\,0)|if(between(mod(t\\,2)\\,1\\,1.5)\\,0.5*sin(2*PI*880*t)\\,0):sample_rate={}:duration={}",
+                sample_rate, d
+            ),
+        }
+    }
+}
+
+// ffmpeg arguments sending the signal straight to `target_url` with the configured codec.
+// Always stereo, so channel mapping problems show up even on mono-configured streams.
+pub fn test_signal_args(signal: TestSignal, frequency: u32, duration_secs: u32, config: &Config, target_url: &str) -> Vec<String> {
+    vec![
+        "-re".to_string(), // lavfi renders as fast as it can; pace it like a live source
+        "-f".to_string(),
+        "lavfi".to_string(),
+        "-i".to_string(),
+        signal.lavfi_source(frequency, duration_secs, config.sample_rate),
+        "-ac".to_string(),
+        "2".to_string(),
+        "-c:a".to_string(),
+        config.audio_codec.clone(),
+        "-b:a".to_string(),
+        config.bitrate.clone(),
+        "-f".to_string(),
+        "mpegts".to_string(),
+        target_url.to_string(),
+    ]
+}
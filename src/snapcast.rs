@@ -0,0 +1,66 @@
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use std::{path::Path, process::Stdio};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    process::{Child, Command},
+};
+
+// snapserver's default sampleformat; its pipe and tcp sources must be set to the same.
+pub const SAMPLE_FORMAT: &str = "48000:16:2";
+
+// Snapcast takes raw PCM, so the MPEG-TS is decoded again. `target` is "tcp://host:port"
+// for a tcp source in server mode, or the path of a pipe source's FIFO.
+pub fn start_decoder(ffmpeg: &Path, target: &str) -> Result<Child> {
+    if target.trim().is_empty() {
+        bail!("Snapcast output needs a tcp:// address or a FIFO path");
+    }
+    Command::new(ffmpeg)
+        .args(["-loglevel", "error", "-fflags", "nobuffer", "-f", "mpegts", "-i", "pipe:0"])
+        .args(["-f", "s16le", "-ar", "48000", "-ac", "2", "-y", target.trim()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true) // The output task owns it; aborting the task ends the decoder
+        .spawn()
+        .context("Failed to start the Snapcast decoder")
+}
+
+// JSON-RPC over snapserver's control port, one object per line. Notifications about
+// other clients can arrive in between, so lines are skipped until our id answers.
+async fn call(connection: &mut BufReader<TcpStream>, id: u64, method: &str, params: Value) -> Result<Value> {
+    let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    connection.get_mut().write_all(format!("{}\n", request).as_bytes()).await?;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if connection.read_line(&mut line).await? == 0 {
+            bail!("snapserver closed the control connection");
+        }
+        let Ok(reply) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if reply["id"].as_u64() != Some(id) {
+            continue;
+        }
+        if let Some(error) = reply.get("error") {
+            bail!("{} failed: {}", method, error["message"].as_str().unwrap_or("unknown error"));
+        }
+        return Ok(reply["result"].clone());
+    }
+}
+
+// Points every group at our stream, so all Snapcast clients start playing it.
+pub async fn switch_groups(control: &str, stream: &str) -> Result<usize> {
+    let socket = TcpStream::connect(control).await.with_context(|| format!("Failed to reach snapserver at {}", control))?;
+    let mut connection = BufReader::new(socket);
+    let status = call(&mut connection, 1, "Server.GetStatus", json!({})).await?;
+    let groups: Vec<String> = status["server"]["groups"]
+        .as_array()
+        .map(|groups| groups.iter().filter_map(|group| group["id"].as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    for (id, group) in (2..).zip(&groups) {
+        call(&mut connection, id, "Group.SetStream", json!({ "id": group, "stream_id": stream })).await?;
+    }
+    Ok(groups.len())
+}
@@ -0,0 +1,100 @@
+use crate::{
+    audio::get_audio_sources,
+    config::Config,
+    events::Event,
+    streams::{EngineOptions, StreamManager},
+};
+use anyhow::{Context, Result};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    runtime::Handle,
+    sync::mpsc,
+    task::JoinHandle,
+    time::interval,
+};
+
+// How often streams are checked for exits, failovers and Bluetooth connections.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// How often an `Event::StatsTick` goes out while streams run.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+// The engine for embedding elsewhere: a `StreamManager` polled on the runtime, with
+// its events and stats delivered as `Event`s. Dropping it stops every stream.
+pub struct Streamer {
+    manager: Arc<Mutex<StreamManager>>,
+    options: EngineOptions,
+    runtime_handle: Handle,
+    events_rx: mpsc::UnboundedReceiver<Event>,
+    poller: JoinHandle<()>,
+}
+
+impl Streamer {
+    pub fn new(options: EngineOptions, runtime_handle: Handle) -> Self {
+        let manager = Arc::new(Mutex::new(StreamManager::default()));
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let poller = runtime_handle.spawn({
+            let (manager, options, runtime_handle) = (manager.clone(), options.clone(), runtime_handle.clone());
+            async move {
+                let (mut polls, mut stats) = (interval(POLL_INTERVAL), interval(STATS_INTERVAL));
+                loop {
+                    tokio::select! {
+                        _ = polls.tick() => {
+                            for event in manager.lock().unwrap().poll(&options, &runtime_handle) {
+                                let _ = events_tx.send(Event::Stream(event)); // Fine if nobody listens
+                            }
+                        }
+                        _ = stats.tick() => {
+                            let stats = manager.lock().unwrap().stats();
+                            if !stats.is_empty() {
+                                let _ = events_tx.send(Event::StatsTick(stats));
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Self { manager, options, runtime_handle, events_rx, poller }
+    }
+
+    // Starts the source named `source` with `config`, next to any streams
+    // already running. Returns its id and the packet sizing warning, if any.
+    pub async fn start(&self, source: &str, config: Config) -> Result<(u64, Option<String>)> {
+        let source = get_audio_sources(config.capture_backend)
+            .await?
+            .into_iter()
+            .find(|candidate| candidate.name == source)
+            .with_context(|| format!("No audio source named '{}'", source))?;
+        self.manager.lock().unwrap().start(source, config, &self.options, &self.runtime_handle)
+    }
+
+    pub fn stop(&self, id: u64) {
+        self.manager.lock().unwrap().stop(id, &self.runtime_handle);
+    }
+
+    pub fn stop_all(&self) {
+        self.manager.lock().unwrap().stop_all(&self.runtime_handle);
+    }
+
+    // The encoder keeps running while paused, so resuming is instant.
+    pub fn set_paused(&self, id: u64, paused: bool) {
+        if let Some(relay) = self.manager.lock().unwrap().get(id).and_then(|stream| stream.relay()) {
+            relay.set_paused(paused);
+        }
+    }
+
+    // Waits for the next `Event::Stream` or `Event::StatsTick`. Never returns None
+    // while the streamer exists.
+    pub async fn next_event(&mut self) -> Option<Event> {
+        self.events_rx.recv().await
+    }
+}
+
+impl Drop for Streamer {
+    fn drop(&mut self) {
+        self.poller.abort();
+        self.stop_all();
+    }
+}
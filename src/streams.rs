@@ -0,0 +1,1106 @@
+use crate::{
+    access::Listeners,
+    audio::{AudioSource, CaptureBackend, set_capture_volume, switch_capture},
+    blocklist::Blocker,
+    bluetooth::{BluetoothRoute, paired_sinks},
+    config::Config,
+    ducking::Ducker,
+    fallback::{self, Engine, FallbackStreamer},
+    filters::NoiseSuppression,
+    ffmpeg::check_ffmpeg,
+    history::{Session, unix_now},
+    hooks::{self, HookEvent},
+    jack,
+    latency::LatencyMonitor,
+    live,
+    load::{self, EncoderLoad, PROGRESS_ARGS},
+    log,
+    monitor::Monitor,
+    mtu,
+    netwatch::{self, Route},
+    notify::{Notification, TrackWatcher},
+    mix,
+    outputs::{Output, Outputs, expand_home},
+    pacing::Pacing,
+    power,
+    presence::{ReceiverStatus, Resumption},
+    priority,
+    recovery::{self, Recovery},
+    relay::{Failover, Relay, RelayOptions},
+    rendezvous::Room,
+    rist,
+    sdp::{self, SdpServer},
+    supervisor::Supervisor,
+    tag::StreamTag,
+    transport::Transport,
+    xrun::Xruns,
+};
+use anyhow::{Result, anyhow, bail};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    process::Stdio,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+    time::{Duration, Instant},
+};
+use tokio::{process::Command, runtime::Handle};
+
+// One source sent to one target, with everything that lives exactly as long as the
+// stream does. Several can run at once, e.g. desktop audio to the phone and the
+// microphone to a tablet.
+pub struct Stream {
+    pub id: u64,
+    pub source: AudioSource,
+    // What it runs with, after the battery profile and its source's format; settings
+    // changed in the GUI afterwards reach it as `live.rs` says.
+    pub config: Config,
+    // What it was started from, before those; a restart starts from this again, so a
+    // change of power source or source is worked out anew. Restarts change it, not `config`.
+    pub base: Config,
+    // The settings it was started or last restarted with from the main window, before
+    // the load monitor or a negotiation with its receiver adjusted `base`; what
+    // `live::changes` compares the main window's against.
+    pub requested: Config,
+    pub muted: bool,
+    silenced: bool, // By `StreamManager::silence_for`, on top of `muted`
+    pub on_backup: bool, // Failed over to the backup target
+    pub route: Option<Route>, // How it leaves this machine, to notice network changes
+    pub session: Option<(Session, Instant)>, // Completed in the history when the stream ends
+    capture: Option<Supervisor>, // ffmpeg
+    fallback: Option<FallbackStreamer>,
+    rist_gateway: Option<Supervisor>, // Sends the relay's output on over RIST
+    relay: Option<Relay>,
+    outputs: Option<Outputs>,
+    bluetooth_route: Option<BluetoothRoute>, // Playing into a Bluetooth device instead of the network
+    bluetooth_route_rx: Option<Receiver<Result<BluetoothRoute, String>>>, // Set while connecting
+    sdp: Option<SdpServer>, // For plain RTP, which players can't open without one
+    latency: Option<LatencyMonitor>, // Pings the target while it runs
+    monitor: Option<Monitor>, // Plays it locally, see `monitor.rs`
+    backend: CaptureBackend, // What it captures through, after `CaptureBackend::effective`
+    jack_mute: Option<jack::Mute>, // How a JACK capture is muted, without PulseAudio's controls
+    load: Option<EncoderLoad>, // How hard ffmpeg works, see `load.rs`
+    overload_reported: bool,
+    xruns: Option<Arc<Xruns>>, // Dropouts in capture and encoding, see `xrun.rs`
+    track_watcher: Option<TrackWatcher>, // Tells receivers about track changes, see `notify.rs`
+    ducker: Option<Ducker>, // Turns it down under the microphone, see `ducking.rs`
+    switch_rx: Option<Receiver<Result<(), String>>>, // Set while moving to another source
+    volume_tx: Sender<String>, // Why muting through pactl failed, for `poll` to hold the relay back instead
+    volume_rx: Receiver<String>,
+    recovery_rx: Option<Receiver<Recovery>>, // Set once the capture ended, see `recovery.rs`
+    waiting_for_server: bool, // Its sound server went away; it resumes when that is back
+    blocker: Option<Blocker>, // Mutes apps on the do-not-stream list, see `blocklist.rs`
+    pub tag: Option<StreamTag>, // What receivers are told about it; None for Bluetooth
+    pub negotiated: Option<String>, // What its receiver and this end agreed on, see `negotiate.rs`
+}
+
+impl Stream {
+    fn new(id: u64, source: AudioSource, config: Config) -> Self {
+        let backend = config.capture_backend.effective();
+        let (volume_tx, volume_rx) = mpsc::channel();
+        Self {
+            id,
+            source,
+            requested: config.clone(),
+            base: config.clone(),
+            config,
+            muted: false,
+            silenced: false,
+            on_backup: false,
+            route: None,
+            session: None,
+            capture: None,
+            fallback: None,
+            rist_gateway: None,
+            relay: None,
+            outputs: None,
+            bluetooth_route: None,
+            bluetooth_route_rx: None,
+            sdp: None,
+            latency: None,
+            monitor: None,
+            backend,
+            jack_mute: None,
+            load: None,
+            overload_reported: false,
+            xruns: None,
+            track_watcher: None,
+            ducker: None,
+            switch_rx: None,
+            volume_tx,
+            volume_rx,
+            recovery_rx: None,
+            waiting_for_server: false,
+            blocker: None,
+            tag: None,
+            negotiated: None,
+        }
+    }
+
+    // Capture, relay and outputs for a network target. Returns the stream and the
+    // packet sizing warning, if any. `options.power_saving` starts it with the battery profile;
+    // `continues` is a restart, whose recordings go on in the same files.
+    pub fn start(
+        id: u64,
+        source: AudioSource,
+        base: Config,
+        options: &EngineOptions,
+        continues: bool,
+        runtime_handle: &Handle,
+    ) -> Result<(Self, Option<String>)> {
+        let (engine, ffmpeg, power_saving) = (options.engine, options.ffmpeg.as_path(), options.power_saving);
+        let mut config = if power_saving { power::power_saving(&base) } else { base.clone() };
+        if config.match_source_spec && let Some(spec) = &source.spec {
+            spec.match_config(&mut config);
+        }
+        // Noise suppression is for voices.
+        if source.is_monitor {
+            config.noise_suppression = NoiseSuppression::Off;
+        } else if engine == Engine::Ffmpeg && config.noise_suppression == NoiseSuppression::Rnnoise && !expand_home(config.noise_model.trim()).is_file() {
+            bail!("Voice isolation needs an RNNoise model file; none found at '{}'", config.noise_model);
+        }
+        let backend = config.capture_backend.effective();
+        if backend != CaptureBackend::Pulse && engine != Engine::Ffmpeg {
+            bail!("Capturing from {} needs ffmpeg", backend.label());
+        }
+        if config.outputs.iter().any(|output| matches!(output, Output::Sink { name } if mix::feeds_back(&source.name, name))) {
+            bail!("Pick a source other than the mix's own monitor, or it would stream itself");
+        }
+        let ip = config.target_ip.parse::<IpAddr>()?;
+        let target = SocketAddr::new(ip, config.target_port);
+        let rist = engine == Engine::Ffmpeg && config.transport == Transport::Rist;
+        // RIST retransmits instead, and the relay's health checks would only ever see the gateway.
+        let failover = if config.has_backup_target() && !rist {
+            let ip = config.backup_target_ip.parse::<IpAddr>().map_err(|e| anyhow!("Invalid backup IP: {}", e))?;
+            Some(Failover {
+                backup: SocketAddr::new(ip, config.backup_target_port),
+                after: Duration::from_secs(config.failover_after_secs as u64),
+            })
+        } else {
+            None
+        };
+        let tag = StreamTag::new(&config, engine);
+        let transport = fallback::relay_transport(&config, engine);
+        let packets = mtu::plan(&config, target.ip(), transport);
+        let xruns = Arc::new(Xruns::new(&config, engine));
+        // Pairing through a helper needs `--receive` on the other end, so the native transport.
+        let rendezvous = (transport == Transport::Native && !config.rendezvous_room.trim().is_empty())
+            .then(|| Room::new(&config.rendezvous_room, &config.rendezvous_secret))
+            .transpose()?;
+        let options = RelayOptions {
+            transport,
+            fec_group: config.fec_group_size,
+            keepalive_while_paused: config.pause_keepalive,
+            failover,
+            dscp: config.dscp,
+            tag: Some(tag.clone()),
+            rendezvous,
+            // Plain UDP goes to players with their own buffering; pacing is for our receiver's.
+            pacing: (transport == Transport::Native).then(|| Pacing::from_config(&config, packets.ts_size)).flatten(),
+            // Only our receiver drops the second copies; a player would play both.
+            second_path: (transport == Transport::Native && !config.redundant_interface.is_empty()).then(|| config.redundant_interface.clone()),
+            relay_server_key: (!rist && !config.relay_server_key.trim().is_empty()).then(|| config.relay_server_key.trim().to_string()),
+            xruns: Some(xruns.clone()),
+        };
+
+        // Pieces are stored as they start, so an error stops the ones already running.
+        let mut stream = Self::new(id, source, config.clone());
+        stream.requested = base.clone();
+        stream.base = base;
+        stream.xruns = Some(xruns.clone());
+        let relay_target = if rist {
+            let (gateway, input) = rist::start_gateway(ffmpeg, &config, target, packets.ts_size, &tag, runtime_handle)?;
+            stream.rist_gateway = Some(gateway);
+            input
+        } else {
+            target
+        };
+        let relay = match Relay::start(relay_target, options, runtime_handle) {
+            Ok(relay) => relay,
+            Err(e) => {
+                stream.stop(runtime_handle);
+                return Err(e);
+            }
+        };
+        relay.set_delay(Duration::from_millis(config.audio_delay_ms as u64));
+        // Extra outputs carry MPEG-TS, which only ffmpeg produces, and not with the RTP transport.
+        if engine == Engine::Ffmpeg && config.transport != Transport::Rtp && !config.outputs.is_empty() {
+            match Outputs::start(&config, &relay, continues, runtime_handle) {
+                Ok(outputs) => stream.outputs = Some(outputs),
+                Err(e) => {
+                    relay.stop();
+                    stream.stop(runtime_handle);
+                    return Err(e);
+                }
+            }
+        }
+        let relay_addr = relay.local_addr;
+        // Also after a restart, which may have changed the codec.
+        if transport == Transport::Native && config.push_notifications {
+            relay.notify(Notification::Started(tag.describe()));
+            stream.track_watcher = Some(TrackWatcher::start(relay.notifier(), runtime_handle));
+        }
+        stream.latency = Some(LatencyMonitor::start(target.ip(), relay.watch_receiver(), runtime_handle));
+        stream.relay = Some(relay);
+        if sdp::applies(&config, engine) {
+            stream.sdp = Some(SdpServer::start(target, runtime_handle));
+        }
+
+        let started = match engine {
+            Engine::Ffmpeg => {
+                let output_url = format!("udp://{}?pkt_size={}", relay_addr, packets.ts_size);
+                let input = if backend == CaptureBackend::Jack { jack::client(id) } else { stream.source.name.clone() };
+                config.build_ffmpeg_command(&input, &output_url, Some(&tag)).and_then(|args| {
+                    log!("FFmpeg command: ffmpeg {}", args.join(" "));
+                    let mut command = Command::new(ffmpeg);
+                    // With RTP, stdout has the SDP and is read to the end; otherwise keep these null to avoid blocking.
+                    let stdout = if stream.sdp.is_some() { Stdio::piped() } else { Stdio::null() };
+                    command.args(PROGRESS_ARGS).args(&args).stdout(stdout).stderr(Stdio::piped());
+                    let mut process = Supervisor::spawn("ffmpeg", &mut command, runtime_handle)?;
+                    if let (Some(pid), Some(stderr)) = (process.pid(), process.take_stderr()) {
+                        stream.load = Some(EncoderLoad::start(pid, stderr, xruns.clone(), runtime_handle));
+                    }
+                    if let (Some(sdp), Some(stdout)) = (&stream.sdp, process.take_stdout()) {
+                        sdp.read_ffmpeg(stdout, tag.clone(), target, config.rtp_clock, runtime_handle);
+                    }
+                    stream.capture = Some(process);
+                    Ok(())
+                })
+            }
+            Engine::BuiltIn => {
+                FallbackStreamer::start(&config, &stream.source.name, relay_addr, &packets, tag.clone(), xruns, runtime_handle).map(|fallback| {
+                    if let Some(sdp) = &stream.sdp {
+                        sdp.set(sdp::pcm(&config, &tag, target, sdp::local_address(target.ip())));
+                    }
+                    stream.fallback = Some(fallback);
+                })
+            }
+        };
+        if let Err(e) = started {
+            stream.stop(runtime_handle);
+            return Err(e);
+        }
+
+        if backend == CaptureBackend::Jack {
+            stream.jack_mute = Some(jack::Mute::default());
+            if !config.jack_connect.is_empty() {
+                runtime_handle.spawn(jack::connect(jack::client(id), config.jack_connect.clone()));
+            }
+        }
+        stream.tag = Some(tag);
+        stream.route = netwatch::route(target.ip());
+        if let Some(route) = &stream.route
+            && transport == Transport::Native
+            && route.interface == config.redundant_interface
+        {
+            log!("The second path {} is the one the stream already takes, so both copies share it", route.interface);
+        }
+        let session = Session {
+            started_at: unix_now(),
+            duration_secs: 0,
+            target: target.to_string(),
+            codec: if engine == Engine::BuiltIn { fallback::codec(&config) } else { config.audio_codec.clone() },
+            bytes_sent: 0,
+            end_reason: None,
+        };
+        stream.session = Some((session, Instant::now()));
+        Ok((stream, packets.warning))
+    }
+
+    // Bluetooth bypasses the network path entirely: no encoder, no relay. Connecting
+    // can take seconds, so it happens in the background and `poll_bluetooth` picks it up.
+    pub fn start_bluetooth(id: u64, source: AudioSource, config: Config, address: String, runtime_handle: &Handle) -> Self {
+        let (route_tx, route_rx) = mpsc::channel();
+        let source_name = source.name.clone();
+        runtime_handle.spawn(async move {
+            let route = match paired_sinks().await.into_iter().find(|sink| sink.address == address) {
+                Some(sink) => BluetoothRoute::start(&source_name, &sink).await.map_err(|e| format!("{:#}", e)),
+                None => Err(format!("{} is not among the paired Bluetooth devices; try Refresh", address)),
+            };
+            // Stopped while connecting: nobody is left to take the route, so undo it.
+            if let Err(mpsc::SendError(Ok(route))) = route_tx.send(route) {
+                route.stop().await;
+            }
+        });
+        let mut stream = Self::new(id, source, config);
+        stream.bluetooth_route_rx = Some(route_rx);
+        stream.start_blocking(runtime_handle);
+        stream
+    }
+
+    // The device name once connected, or why connecting failed.
+    fn poll_bluetooth(&mut self) -> Option<Result<String, String>> {
+        let result = self.bluetooth_route_rx.as_ref()?.try_recv().ok()?;
+        self.bluetooth_route_rx = None;
+        Some(result.map(|route| self.bluetooth_route.insert(route).device.clone()))
+    }
+
+    // "10.0.0.5:1234", or the Bluetooth device.
+    pub fn target(&self) -> String {
+        match (&self.config.bluetooth_sink, &self.bluetooth_route) {
+            (Some(_), Some(route)) => format!("🎧 {}", route.device),
+            (Some(address), None) => format!("🎧 {}", address),
+            (None, _) if self.on_backup => format!("{}:{}", self.config.backup_target_ip, self.config.backup_target_port),
+            (None, _) => format!("{}:{}", self.config.target_ip, self.config.target_port),
+        }
+    }
+
+    pub fn codec(&self) -> &str {
+        if self.config.bluetooth_sink.is_some() {
+            "A2DP"
+        } else if self.capture.is_some() {
+            &self.config.audio_codec
+        } else {
+            "PCM"
+        }
+    }
+
+    pub fn relay(&self) -> Option<&Relay> {
+        self.relay.as_ref()
+    }
+
+    pub fn latency(&self) -> Option<&LatencyMonitor> {
+        self.latency.as_ref()
+    }
+
+    pub fn load(&self) -> Option<&EncoderLoad> {
+        self.load.as_ref()
+    }
+
+    pub fn xruns(&self) -> Option<&Xruns> {
+        self.xruns.as_deref()
+    }
+
+    pub fn monitor(&self) -> Option<&Monitor> {
+        self.monitor.as_ref().filter(|monitor| monitor.is_running())
+    }
+
+    // Only ffmpeg's MPEG-TS can be played back from the relay, as with the extra outputs.
+    pub fn can_monitor(&self) -> bool {
+        self.relay.is_some() && self.capture.is_some() && self.config.transport != Transport::Rtp
+    }
+
+    // Returns the player it started.
+    pub fn start_monitor(&mut self, runtime_handle: &Handle) -> Result<&'static str> {
+        self.stop_monitor();
+        let Some(relay) = self.relay.as_ref().filter(|_| self.can_monitor()) else {
+            return Err(anyhow!("Only streams from ffmpeg in MPEG-TS can be monitored"));
+        };
+        let delay = Duration::from_millis(self.config.monitor_delay_ms as u64);
+        let monitor = Monitor::start(relay.subscribe(), delay, runtime_handle)?;
+        Ok(self.monitor.insert(monitor).player)
+    }
+
+    pub fn stop_monitor(&mut self) {
+        if let Some(monitor) = self.monitor.take() {
+            monitor.stop();
+        }
+    }
+
+    // For native receivers, when the stream's config allows it.
+    fn notify(&self, notification: Notification) {
+        if let Some(relay) = &self.relay
+            && self.config.push_notifications
+            && self.config.transport == Transport::Native
+        {
+            relay.notify(notification);
+        }
+    }
+
+    pub fn sdp(&self) -> Option<&SdpServer> {
+        self.sdp.as_ref()
+    }
+
+    pub fn listeners(&self) -> Option<&Listeners> {
+        self.outputs.as_ref().map(Outputs::listeners)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.relay.as_ref().is_some_and(Relay::is_paused)
+    }
+
+    // Why it stopped, once one of its processes has ended without being asked to.
+    fn exit_reason(&self) -> Option<String> {
+        self.capture.as_ref().and_then(Supervisor::exit_reason)
+            .or_else(|| self.fallback.as_ref().and_then(FallbackStreamer::exit_reason))
+            .or_else(|| self.rist_gateway.as_ref().and_then(Supervisor::exit_reason))
+    }
+
+    // Only PulseAudio captures have a recording stream to turn down; JACK and ALSA
+    // ones can just be muted.
+    pub fn has_volume(&self) -> bool {
+        self.backend == CaptureBackend::Pulse
+    }
+
+    // Muted by hand or for privacy, see `StreamManager::silence_for`.
+    pub fn is_muted(&self) -> bool {
+        self.muted || self.silenced
+    }
+
+    // Acts on the capture process's recording stream, so it takes effect without a restart.
+    pub fn apply_volume(&self, runtime_handle: &Handle) {
+        if let Some(mute) = self.jack_mute.clone() {
+            let (client, muted) = (jack::client(self.id), self.is_muted());
+            runtime_handle.spawn(async move {
+                if let Err(e) = mute.set(&client, muted).await {
+                    log!("Could not {} the JACK inputs: {:#}", if muted { "mute" } else { "unmute" }, e);
+                }
+            });
+            return;
+        }
+        // ALSA has no per-stream volume to turn down, so a muted capture is held back
+        // instead, apart from a pause so neither undoes the other.
+        if self.backend == CaptureBackend::Alsa {
+            if let Some(relay) = &self.relay {
+                relay.set_muted(self.is_muted());
+            }
+            return;
+        }
+        let Some(pid) = self.capture.as_ref().and_then(Supervisor::pid).or_else(|| self.fallback.as_ref().and_then(FallbackStreamer::pid)) else {
+            return;
+        };
+        let (percent, muted) = (self.config.volume_percent, self.is_muted());
+        // Held back after a mute that failed, see `poll`.
+        if let Some(relay) = &self.relay
+            && !muted
+        {
+            relay.set_muted(false);
+        }
+        // Ducking owns the volume while it runs.
+        if let Some(ducker) = &self.ducker {
+            ducker.set_volume(percent, muted);
+            return;
+        }
+        let failed = self.volume_tx.clone();
+        runtime_handle.spawn(async move {
+            if let Err(e) = set_capture_volume(pid, percent, muted).await {
+                log!("Could not set the stream volume: {:#}", e);
+                if muted {
+                    let _ = failed.send(format!("{:#}", e));
+                }
+            }
+        });
+    }
+
+    // Ducking under the source itself would only pump, so that one is left out.
+    fn start_ducking(&mut self, runtime_handle: &Handle) {
+        if self.backend != CaptureBackend::Pulse {
+            return; // It turns PulseAudio's capture stream down, which the others have none of
+        }
+        let Some(pid) = self.capture.as_ref().and_then(Supervisor::pid).or_else(|| self.fallback.as_ref().and_then(FallbackStreamer::pid)) else {
+            return;
+        };
+        let config = &self.config;
+        if config.duck_source.is_empty() || config.duck_source == self.source.name {
+            return;
+        }
+        match Ducker::start(pid, &config.duck_source, config.duck_threshold_db, config.duck_amount_db, config.volume_percent, self.is_muted(), runtime_handle) {
+            Ok(ducker) => self.ducker = Some(ducker),
+            Err(e) => log!("Could not listen to {} for ducking: {:#}", config.duck_source, e),
+        }
+    }
+
+    // Only monitors carry what apps play.
+    fn start_blocking(&mut self, runtime_handle: &Handle) {
+        if let Some(blocker) = self.blocker.take() {
+            blocker.stop();
+        }
+        if let Some(sink) = self.source.name.strip_suffix(".monitor")
+            && !self.config.do_not_stream.is_empty()
+        {
+            self.blocker = Some(Blocker::start(sink, self.config.do_not_stream.clone(), runtime_handle));
+        }
+    }
+
+    // Takes over the settings in `live::LIVE` from `wanted`; the rest need a restart.
+    pub fn apply_live(&mut self, wanted: &Config, runtime_handle: &Handle) {
+        let requested = &mut self.requested;
+        requested.overload_protection = wanted.overload_protection;
+        requested.hooks = wanted.hooks.clone();
+        requested.do_not_stream = wanted.do_not_stream.clone();
+        requested.duck_source = wanted.duck_source.clone();
+        requested.duck_threshold_db = wanted.duck_threshold_db;
+        requested.duck_amount_db = wanted.duck_amount_db;
+        for config in [&mut self.base, &mut self.config] {
+            config.overload_protection = wanted.overload_protection;
+            config.hooks = wanted.hooks.clone();
+        }
+        self.base.do_not_stream = wanted.do_not_stream.clone();
+        self.base.duck_source = wanted.duck_source.clone();
+        self.base.duck_threshold_db = wanted.duck_threshold_db;
+        self.base.duck_amount_db = wanted.duck_amount_db;
+        if self.config.do_not_stream != wanted.do_not_stream {
+            self.config.do_not_stream = wanted.do_not_stream.clone();
+            self.start_blocking(runtime_handle);
+        }
+        let config = &mut self.config;
+        let ducking = (&wanted.duck_source, wanted.duck_threshold_db, wanted.duck_amount_db);
+        if (&config.duck_source, config.duck_threshold_db, config.duck_amount_db) == ducking {
+            return;
+        }
+        config.duck_source = wanted.duck_source.clone();
+        config.duck_threshold_db = wanted.duck_threshold_db;
+        config.duck_amount_db = wanted.duck_amount_db;
+        if let Some(ducker) = self.ducker.take() {
+            ducker.stop();
+            // Back to the full volume, which the new ducker (if any) starts from.
+            self.apply_volume(runtime_handle);
+        }
+        self.start_ducking(runtime_handle);
+    }
+
+    // Moves the capture to `source` in place, so receivers keep the same session and
+    // hear a short fade instead of a dropout. Runs in the background; `poll` reports it.
+    pub fn switch_source(&mut self, source: AudioSource, runtime_handle: &Handle) -> Result<()> {
+        match self.backend {
+            CaptureBackend::Pulse => {}
+            CaptureBackend::Jack => bail!("A JACK capture has only its own inputs; re-patch them instead"),
+            CaptureBackend::Alsa => bail!("An ALSA capture holds its device; restart the stream on the other one"),
+        }
+        let Some(pid) = self.capture.as_ref().and_then(Supervisor::pid).or_else(|| self.fallback.as_ref().and_then(FallbackStreamer::pid)) else {
+            bail!("Only network streams can switch sources");
+        };
+        if self.switch_rx.is_some() {
+            bail!("Already switching sources");
+        }
+        if self.config.outputs.iter().any(|output| matches!(output, Output::Sink { name } if mix::feeds_back(&source.name, name))) {
+            bail!("Pick a source other than the mix's own monitor, or it would stream itself");
+        }
+        let (tx, rx) = mpsc::channel();
+        // The filters were picked for the old source (noise suppression only for
+        // microphones, the rate and channels to match it), so a different kind takes a
+        // restart, which `poll` does on the error.
+        let spec_differs = self.config.match_source_spec && source.spec != self.source.spec;
+        if source.is_monitor != self.source.is_monitor || spec_differs {
+            let _ = tx.send(Err("The new source needs other filters".to_string()));
+        } else {
+            // Ducking sets the volume on its own, which the fade would fight.
+            let (percent, muted, fade) = (self.config.volume_percent, self.is_muted(), self.config.duck_source.is_empty());
+            let name = source.name.clone();
+            runtime_handle.spawn(async move {
+                let _ = tx.send(switch_capture(pid, &name, percent, muted, fade).await.map_err(|e| format!("{:#}", e)));
+            });
+        }
+        self.switch_rx = Some(rx);
+        self.source = source;
+        self.start_blocking(runtime_handle);
+        // The ducked-under microphone may be the new source.
+        if let Some(ducker) = self.ducker.take() {
+            ducker.stop();
+        }
+        self.start_ducking(runtime_handle);
+        Ok(())
+    }
+
+    pub fn is_waiting_for_server(&self) -> bool {
+        self.waiting_for_server
+    }
+
+    fn poll_recovery(&mut self) -> Option<Recovery> {
+        let recovery = match self.recovery_rx.as_ref()?.try_recv() {
+            Ok(recovery) => recovery,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Recovery::Failed("Lost track of the sound server".to_string()),
+        };
+        match recovery {
+            Recovery::Lost => self.waiting_for_server = true,
+            _ => self.recovery_rx = None,
+        }
+        Some(recovery)
+    }
+
+    fn poll_switch(&mut self) -> Option<Result<(), String>> {
+        let result = self.switch_rx.as_ref()?.try_recv().ok()?;
+        self.switch_rx = None;
+        Some(result)
+    }
+
+    // Runs in the background, since the process's threads take a moment to appear.
+    pub fn raise_priority(&self, runtime_handle: &Handle) {
+        let Some(pid) = self.capture.as_ref().and_then(Supervisor::pid).or_else(|| self.fallback.as_ref().and_then(FallbackStreamer::pid)) else {
+            return;
+        };
+        runtime_handle.spawn(async move {
+            match priority::boost(pid).await {
+                Ok(boost) => log!("Capture runs with {}", boost.label()),
+                Err(e) => log!("Could not raise the capture priority: {:#}", e),
+            }
+        });
+    }
+
+    // Adds what this relay sent, for a session that continues on a new one.
+    fn take_session(&mut self) -> Option<(Session, Instant)> {
+        let bytes_sent = self.relay.as_ref().map_or(0, Relay::bytes_sent);
+        self.session.take().map(|(mut session, started)| {
+            session.bytes_sent += bytes_sent; // Earlier relays' share is in already
+            (session, started)
+        })
+    }
+
+    // The completed session, for the history. Must run before `stop`, since the byte
+    // count lives in the relay.
+    fn finish_session(&mut self, end_reason: Option<String>) -> Option<Session> {
+        let (mut session, started) = self.take_session()?;
+        session.duration_secs = started.elapsed().as_secs();
+        session.end_reason = end_reason;
+        Some(session)
+    }
+
+    fn stop(mut self, runtime_handle: &Handle) {
+        if let Some(process) = self.capture.take() {
+            process.stop();
+        }
+        if let Some(fallback) = self.fallback.take() {
+            fallback.stop();
+        }
+        if let Some(outputs) = self.outputs.take() {
+            outputs.stop();
+        }
+        // Before the relay, which waits for every notifier to go.
+        if let Some(watcher) = self.track_watcher.take() {
+            watcher.stop();
+        }
+        if let Some(relay) = self.relay.take() {
+            relay.stop();
+        }
+        if let Some(gateway) = self.rist_gateway.take() {
+            gateway.stop();
+        }
+        if let Some(sdp) = self.sdp.take() {
+            sdp.stop();
+        }
+        if let Some(latency) = self.latency.take() {
+            latency.stop();
+        }
+        self.stop_monitor();
+        if let Some(ducker) = self.ducker.take() {
+            ducker.stop();
+        }
+        if let Some(blocker) = self.blocker.take() {
+            blocker.stop();
+        }
+        if let Some(load) = self.load.take() {
+            load.stop();
+        }
+        if let Some(route) = self.bluetooth_route.take() {
+            runtime_handle.spawn(route.stop());
+        }
+    }
+}
+
+// What the quick privacy mute silences the streams for.
+pub const QUICK_MUTE: Duration = Duration::from_secs(5 * 60);
+
+// "🔇 Muted for privacy, 4:05 left", for the status line while `silence_for` runs.
+pub fn silence_countdown(remaining: Duration) -> String {
+    let secs = remaining.as_secs_f32().ceil() as u64;
+    format!("🔇 Muted for privacy, {}:{:02} left", secs / 60, secs % 60)
+}
+
+// How new streams are started; a front end decides these from the ffmpeg check and
+// the power source.
+#[derive(Debug, Clone)]
+pub struct EngineOptions {
+    pub engine: Engine,
+    pub ffmpeg: PathBuf, // Used even when the check failed, so the spawn error names it
+    pub power_saving: bool,
+}
+
+impl EngineOptions {
+    // ffmpeg if the check passes, else the built-in engine, as for a dry run.
+    pub async fn detect(config: &Config) -> Self {
+        match check_ffmpeg(config).await {
+            Ok(info) => Self { engine: Engine::Ffmpeg, ffmpeg: info.path, power_saving: false },
+            Err(_) => Self { engine: Engine::BuiltIn, ffmpeg: PathBuf::from("ffmpeg"), power_saving: false },
+        }
+    }
+}
+
+// What happened to the streams since the last `StreamManager::poll`, including what
+// `start`, `stop` and `restart` did.
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    // Started, or restarted in place; `warning` is about packet sizing.
+    Started { id: u64, warning: Option<String> },
+    // A Bluetooth stream is playing on the device.
+    Connected { id: u64, device: String },
+    // The primary target stopped responding and the stream moved to the backup.
+    FailedOver { id: u64 },
+    // It moved to another source in place; when that failed it was `restarted` on it instead.
+    SourceSwitched { id: u64, restarted: bool },
+    // The time given to `StreamManager::silence_for` ran out and the streams are heard again.
+    Unsilenced,
+    // Muting the capture failed, so its relay sends nothing instead until it is unmuted.
+    MuteFailed { id: u64, reason: String },
+    // `app`, on the do-not-stream list, started playing and was muted.
+    AppBlocked { id: u64, app: String },
+    // A native receiver lost the stream for a while and carried on where it was, see
+    // `presence::Resumption`; `receiver` is its name, when it gave one.
+    ReceiverResumed { id: u64, receiver: Option<String>, resumption: Resumption },
+    // The capture ended because the sound server went away, e.g. PipeWire restarting.
+    // The stream waits, with receivers still connected, for `SoundServerBack` or `Error`.
+    SoundServerLost { id: u64 },
+    // The server came back and the stream restarted on the same source.
+    SoundServerBack { id: u64 },
+    // ffmpeg encodes slower than real time. With `Config::overload_protection` the stream
+    // was restarted with `lighter` (its codec and bitrate); None when it can't go lighter.
+    Overloaded { id: u64, speed: f32, lighter: Option<String> },
+    // A stream's pipeline was torn down, when stopping or restarting it. The session
+    // goes in the history. `recordings` is only set once the stream is gone for good,
+    // not on a restart, which goes on writing the same files; they are complete then.
+    SessionEnded { id: u64, session: Option<Session>, recordings: Option<Box<Config>> },
+    // It ended on its own, because a process died or Bluetooth didn't connect. `Stopped` follows.
+    Error { id: u64, reason: String },
+    // The stream is gone.
+    Stopped { id: u64 },
+}
+
+// A snapshot of one stream, for status displays.
+#[derive(Debug, Clone)]
+pub struct StreamStats {
+    pub id: u64,
+    pub bytes_sent: u64,
+    pub paused: bool,
+    pub on_backup: bool,
+    pub receiver: Option<ReceiverStatus>, // While it keeps reporting back, see `Relay::receiver`
+    pub capture_overruns: u64,
+    pub encoder_underruns: u64,
+}
+
+// The running streams, in the order they were started.
+#[derive(Default)]
+pub struct StreamManager {
+    streams: Vec<Stream>,
+    next_id: u64,
+    events: Vec<StreamEvent>,
+    silenced_until: Option<Instant>, // See `silence_for`
+}
+
+impl StreamManager {
+    fn launch(id: u64, source: AudioSource, config: Config, options: &EngineOptions, continues: bool, runtime_handle: &Handle) -> Result<(Stream, Option<String>)> {
+        if let Some(address) = config.bluetooth_sink.clone() {
+            return Ok((Stream::start_bluetooth(id, source, config, address, runtime_handle), None));
+        }
+        let (mut stream, warning) = Stream::start(id, source, config, options, continues, runtime_handle)?;
+        stream.start_ducking(runtime_handle);
+        stream.start_blocking(runtime_handle);
+        if stream.config.volume_percent != 100 {
+            stream.apply_volume(runtime_handle);
+        }
+        if stream.config.realtime_priority {
+            stream.raise_priority(runtime_handle);
+        }
+        Ok((stream, warning))
+    }
+
+    // Starts `source` with `config`, into a Bluetooth device or over the network as the
+    // config says, next to any streams already running. Returns its id and the packet
+    // sizing warning, if any.
+    pub fn start(&mut self, source: AudioSource, config: Config, options: &EngineOptions, runtime_handle: &Handle) -> Result<(u64, Option<String>)> {
+        self.next_id += 1;
+        let pre_start = hooks::run(&config.hooks, HookEvent::PreStart, hooks::environment(self.next_id, &source, &config, None, None), runtime_handle);
+        let for_error = (source.clone(), config.clone());
+        let (mut stream, warning) = match Self::launch(self.next_id, source, config, options, false, runtime_handle) {
+            Ok(launched) => launched,
+            Err(e) => {
+                let (source, config) = for_error;
+                let environment = hooks::environment(self.next_id, &source, &config, None, Some(&format!("{:#}", e)));
+                hooks::run(&config.hooks, HookEvent::Error, environment, runtime_handle);
+                return Err(e);
+            }
+        };
+        let environment = hooks::environment(stream.id, &stream.source, &stream.config, None, None);
+        match pre_start {
+            // The stream is up meanwhile, but sends nothing until the hook is done.
+            // Bluetooth streams just take as long as they take to connect.
+            Some(pre_start) => {
+                let (hooks, handle) = (stream.config.hooks.clone(), runtime_handle.clone());
+                let done = async move {
+                    let _ = pre_start.await;
+                    hooks::run(&hooks, HookEvent::PostStart, environment, &handle);
+                };
+                match stream.relay() {
+                    Some(relay) => relay.hold_until(done),
+                    None => {
+                        runtime_handle.spawn(done);
+                    }
+                }
+            }
+            None => {
+                hooks::run(&stream.config.hooks, HookEvent::PostStart, environment, runtime_handle);
+            }
+        }
+        if self.silenced_until.is_some() {
+            stream.silenced = true;
+            stream.apply_volume(runtime_handle);
+        }
+        self.streams.push(stream);
+        self.events.push(StreamEvent::Started { id: self.next_id, warning: warning.clone() });
+        Ok((self.next_id, warning))
+    }
+
+    // Returns the session, for the hooks. Restarts end a stream too, so whether those
+    // run is up to the caller. Only `last` hands on the recordings: after a restart the
+    // new pipeline goes on writing them.
+    fn end(&mut self, mut stream: Stream, reason: Option<String>, last: bool, runtime_handle: &Handle) -> Option<Session> {
+        let session = stream.finish_session(reason);
+        let recordings = (last && stream.outputs.is_some()).then(|| Box::new(stream.config.clone()));
+        let id = stream.id;
+        stream.stop(runtime_handle);
+        self.events.push(StreamEvent::SessionEnded { id, session: session.clone(), recordings });
+        session
+    }
+
+    fn remove(&mut self, index: usize, reason: Option<String>, runtime_handle: &Handle) {
+        let stream = self.streams.remove(index);
+        let id = stream.id;
+        stream.notify(Notification::Stopped);
+        let (source, config) = (stream.source.clone(), stream.config.clone());
+        let session = self.end(stream, reason.clone(), true, runtime_handle);
+        let environment = hooks::environment(id, &source, &config, session.as_ref(), reason.as_deref());
+        if reason.is_some() {
+            hooks::run(&config.hooks, HookEvent::Error, environment.clone(), runtime_handle);
+        }
+        hooks::run(&config.hooks, HookEvent::Stop, environment, runtime_handle);
+        if let Some(reason) = reason {
+            self.events.push(StreamEvent::Error { id, reason });
+        }
+        self.events.push(StreamEvent::Stopped { id });
+    }
+
+    pub fn stop(&mut self, id: u64, runtime_handle: &Handle) {
+        if let Some(index) = self.streams.iter().position(|stream| stream.id == id) {
+            self.remove(index, None, runtime_handle);
+        }
+    }
+
+    pub fn stop_all(&mut self, runtime_handle: &Handle) {
+        while !self.streams.is_empty() {
+            self.remove(0, None, runtime_handle);
+        }
+    }
+
+    // Privacy: every stream, and any started meanwhile, sends silence for `duration`
+    // instead of stopping, so receivers stay connected and the session carries on.
+    // `poll` unmutes them when it runs out. Bluetooth streams play on regardless.
+    pub fn silence_for(&mut self, duration: Duration, runtime_handle: &Handle) {
+        self.silenced_until = Some(Instant::now() + duration);
+        self.set_silenced(true, runtime_handle);
+    }
+
+    // Ends `silence_for` early.
+    pub fn unsilence(&mut self, runtime_handle: &Handle) {
+        if self.silenced_until.take().is_some() {
+            self.set_silenced(false, runtime_handle);
+        }
+    }
+
+    fn set_silenced(&mut self, silenced: bool, runtime_handle: &Handle) {
+        for stream in self.streams.iter_mut().filter(|stream| stream.silenced != silenced) {
+            stream.silenced = silenced;
+            stream.apply_volume(runtime_handle);
+        }
+    }
+
+    // How much of `silence_for` is left.
+    pub fn silenced_for(&self) -> Option<Duration> {
+        self.silenced_until.map(|until| until.saturating_duration_since(Instant::now()))
+    }
+
+    // Stops a stream and starts it again in the same place, after `change` (e.g. a new
+    // source or codec in `base`). With `resume` it stays one session in the history.
+    pub fn restart(&mut self, id: u64, resume: bool, change: impl FnOnce(&mut Stream), options: &EngineOptions, runtime_handle: &Handle) -> Result<Option<String>> {
+        let Some(index) = self.streams.iter().position(|stream| stream.id == id) else {
+            return Ok(None);
+        };
+        let mut old = self.streams.remove(index);
+        change(&mut old);
+        let session = if resume { old.take_session() } else { None };
+        // The volume and delays may have been changed while it ran.
+        let config = live::merged(&old.config, &old.base);
+        let (source, requested, muted) = (old.source.clone(), old.requested.clone(), old.muted);
+        let recorded = old.outputs.is_some();
+        self.end(old, None, false, runtime_handle);
+        match Self::launch(id, source.clone(), config.clone(), options, true, runtime_handle) {
+            Ok((mut stream, warning)) => {
+                stream.requested = requested;
+                stream.muted = muted;
+                stream.silenced = self.silenced_until.is_some();
+                if stream.is_muted() {
+                    stream.apply_volume(runtime_handle);
+                }
+                if session.is_some() {
+                    stream.session = session;
+                }
+                self.streams.insert(index, stream);
+                self.events.push(StreamEvent::Started { id, warning: warning.clone() });
+                Ok(warning)
+            }
+            Err(e) => {
+                let session = session.map(|(mut session, started)| {
+                    session.duration_secs = started.elapsed().as_secs();
+                    session.end_reason = Some(format!("Restart failed: {:#}", e));
+                    session
+                });
+                let environment = hooks::environment(id, &source, &config, session.as_ref(), Some(&format!("Restart failed: {:#}", e)));
+                hooks::run(&config.hooks, HookEvent::Error, environment.clone(), runtime_handle);
+                hooks::run(&config.hooks, HookEvent::Stop, environment, runtime_handle);
+                let recordings = recorded.then(|| Box::new(config.clone()));
+                self.events.push(StreamEvent::SessionEnded { id, session, recordings });
+                self.events.push(StreamEvent::Stopped { id }); // The caller has the error
+                Err(e)
+            }
+        }
+    }
+
+    // Notices streams whose processes died, Bluetooth connections finishing, failovers,
+    // receivers resuming, source switches, failed mutes, blocked apps, overloaded
+    // encoders, sound server restarts and the end of `silence_for`, and returns those
+    // along with the streams stopped since last time.
+    pub fn poll(&mut self, options: &EngineOptions, runtime_handle: &Handle) -> Vec<StreamEvent> {
+        if self.silenced_until.is_some_and(|until| until <= Instant::now()) {
+            self.unsilence(runtime_handle);
+            self.events.push(StreamEvent::Unsilenced);
+        }
+        let mut overloaded = Vec::new();
+        let mut switch_failed = Vec::new();
+        let mut recovered = Vec::new();
+        let mut index = 0;
+        while index < self.streams.len() {
+            let stream = &mut self.streams[index];
+            let reason = match stream.poll_bluetooth() {
+                Some(Ok(device)) => {
+                    self.events.push(StreamEvent::Connected { id: stream.id, device });
+                    None
+                }
+                Some(Err(e)) => Some(format!("Connecting failed: {}", e)),
+                None if stream.recovery_rx.is_some() => match stream.poll_recovery() {
+                    Some(Recovery::Lost) => {
+                        self.events.push(StreamEvent::SoundServerLost { id: stream.id });
+                        None
+                    }
+                    Some(Recovery::Back(source)) => {
+                        recovered.push((stream.id, source));
+                        None
+                    }
+                    Some(Recovery::Failed(reason)) => Some(reason),
+                    None => None,
+                },
+                // Only PulseAudio and PipeWire restart under a running capture; JACK and
+                // ALSA failing is final.
+                None => match stream.exit_reason() {
+                    Some(reason) if stream.backend == CaptureBackend::Pulse => {
+                        stream.recovery_rx = Some(recovery::start(stream.source.name.clone(), reason, runtime_handle));
+                        None
+                    }
+                    reason => reason,
+                },
+            };
+            match stream.poll_switch() {
+                Some(Ok(())) => self.events.push(StreamEvent::SourceSwitched { id: stream.id, restarted: false }),
+                Some(Err(e)) => {
+                    log!("Could not switch the source in place, restarting on it: {}", e);
+                    switch_failed.push(stream.id);
+                }
+                None => {}
+            }
+            // Whatever the capture sends, a mute (for privacy, say) must keep it from the target.
+            for reason in stream.volume_rx.try_iter().collect::<Vec<_>>() {
+                if let Some(relay) = stream.relay.as_ref().filter(|_| stream.is_muted()) {
+                    relay.set_muted(true);
+                    self.events.push(StreamEvent::MuteFailed { id: stream.id, reason });
+                }
+            }
+            for app in stream.blocker.as_ref().map(Blocker::blocked).unwrap_or_default() {
+                self.events.push(StreamEvent::AppBlocked { id: stream.id, app });
+            }
+            if let Some(relay) = stream.relay() {
+                for resumption in relay.resumptions() {
+                    let receiver = relay.receiver().and_then(|receiver| receiver.name);
+                    self.events.push(StreamEvent::ReceiverResumed { id: stream.id, receiver, resumption });
+                }
+            }
+            if !stream.on_backup && stream.relay().is_some_and(Relay::has_failed_over) {
+                stream.on_backup = true;
+                self.events.push(StreamEvent::FailedOver { id: stream.id });
+            }
+            if !stream.overload_reported
+                && let Some(load) = stream.load.as_ref().filter(|load| load.is_overloaded())
+            {
+                stream.overload_reported = true; // A restart starts over with a fresh one
+                let lighter = stream.config.overload_protection.then(|| load::lighter(&stream.base)).flatten();
+                overloaded.push((stream.id, load.speed().unwrap_or(0.0), lighter));
+            }
+            match reason {
+                Some(reason) => self.remove(index, Some(reason), runtime_handle),
+                None => index += 1,
+            }
+        }
+        // The stream already has the new source, so a restart picks it up.
+        for id in switch_failed {
+            match self.restart(id, true, |_| {}, options, runtime_handle) {
+                Ok(_) => self.events.push(StreamEvent::SourceSwitched { id, restarted: true }),
+                Err(e) => log!("Could not restart the stream on its new source: {:#}", e),
+            }
+        }
+        // Under the same id and session, so to receivers and the history it never stopped.
+        for (id, source) in recovered {
+            match self.restart(id, true, |stream| stream.source = source, options, runtime_handle) {
+                Ok(_) => self.events.push(StreamEvent::SoundServerBack { id }),
+                Err(e) => log!("Could not resume the stream after the sound server came back: {:#}", e),
+            }
+        }
+        for (id, speed, lighter) in overloaded {
+            let label = lighter.as_ref().map(|config| format!("{} {}", config.audio_codec, config.bitrate));
+            if let Some(lighter) = lighter
+                && let Err(e) = self.restart(id, true, |stream| stream.base = lighter, options, runtime_handle)
+            {
+                log!("Could not restart the overloaded stream: {:#}", e);
+                continue;
+            }
+            self.events.push(StreamEvent::Overloaded { id, speed, lighter: label });
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn stats(&self) -> Vec<StreamStats> {
+        self.streams
+            .iter()
+            .map(|stream| StreamStats {
+                id: stream.id,
+                bytes_sent: stream.relay().map_or(0, Relay::bytes_sent),
+                paused: stream.is_paused(),
+                on_backup: stream.on_backup,
+                receiver: stream.relay().and_then(Relay::receiver),
+                capture_overruns: stream.xruns().map_or(0, Xruns::overruns),
+                encoder_underruns: stream.xruns().map_or(0, Xruns::underruns),
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    pub fn ids(&self) -> Vec<u64> {
+        self.streams.iter().map(|stream| stream.id).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Stream> {
+        self.streams.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Stream> {
+        self.streams.iter_mut()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Stream> {
+        self.streams.iter().find(|stream| stream.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Stream> {
+        self.streams.iter_mut().find(|stream| stream.id == id)
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result};
+use tokio::{
+    process::{ChildStderr, ChildStdout, Command},
+    runtime::Handle,
+    sync::{oneshot, watch},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ProcessState {
+    Running,
+    Exited(String), // On its own, with the reason
+    Stopped,        // Killed by `stop`
+}
+
+// Owns a child process on the runtime: one task waits for it to exit or kills it
+// on request, and reports through a watch channel. Nothing on the GUI thread
+// ever blocks on the process. Dropping the supervisor kills the process too.
+pub struct Supervisor {
+    state: watch::Receiver<ProcessState>,
+    stop: Option<oneshot::Sender<()>>,
+    stdout: Option<ChildStdout>,
+    stderr: Option<ChildStderr>,
+    pid: Option<u32>,
+}
+
+impl Supervisor {
+    pub fn spawn(name: &'static str, command: &mut Command, runtime_handle: &Handle) -> Result<Self> {
+        // tokio needs its runtime to spawn, and the GUI thread isn't inside it.
+        let _runtime = runtime_handle.enter();
+        let mut child = command.spawn().with_context(|| format!("Failed to start {}", name))?;
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let pid = child.id();
+
+        let (state_tx, state) = watch::channel(ProcessState::Running);
+        let (stop, stop_rx) = oneshot::channel::<()>();
+        runtime_handle.spawn(async move {
+            let state = tokio::select! {
+                status = child.wait() => match status {
+                    Ok(status) => ProcessState::Exited(format!("{} exited with {}", name, status)),
+                    Err(e) => ProcessState::Exited(format!("Lost track of {}: {}", name, e)),
+                },
+                _ = stop_rx => {
+                    let _ = child.kill().await; // Also reaps it
+                    ProcessState::Stopped
+                }
+            };
+            let _ = state_tx.send(state);
+        });
+
+        Ok(Self { state, stop: Some(stop), stdout, stderr, pid })
+    }
+
+    // The child's piped stdout, for a reader task of the caller's own.
+    pub fn take_stdout(&mut self) -> Option<ChildStdout> {
+        self.stdout.take()
+    }
+
+    pub fn take_stderr(&mut self) -> Option<ChildStderr> {
+        self.stderr.take()
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    // Why the process exited, once it has ended without being asked to.
+    pub fn exit_reason(&self) -> Option<String> {
+        match &*self.state.borrow() {
+            ProcessState::Exited(reason) => Some(reason.clone()),
+            _ => None,
+        }
+    }
+
+    // Returns immediately; the kill and the wait happen on the supervising task.
+    pub fn stop(mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::transport::{Packet, PacketKind};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Only the fastest exchanges are trusted: queueing delay makes slow ones asymmetric.
+const SAMPLES_KEPT: usize = 8;
+
+// Answers a receiver's clock request with our current stream clock. The request's
+// own timestamp is echoed back so the receiver can measure the round trip.
+pub fn time_reply(request: &Packet, sender_now_us: u64) -> Packet {
+    Packet {
+        kind: PacketKind::TimeReply,
+        seq: request.seq,
+        fec_group: 0,
+        session: 0,
+        timestamp_us: sender_now_us,
+        payload: request.timestamp_us.to_be_bytes().to_vec(),
+    }
+}
+
+// Receiver-side NTP-style estimate of how the sender's stream clock maps onto ours.
+pub struct SyncClock {
+    epoch: Instant,
+    next_seq: u32,
+    samples: VecDeque<(u64, f64)>, // (round trip µs, local minus sender offset µs)
+}
+
+impl Default for SyncClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncClock {
+    pub fn new() -> Self {
+        Self { epoch: Instant::now(), next_seq: 0, samples: VecDeque::new() }
+    }
+
+    fn local_us(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.epoch).as_micros() as u64
+    }
+
+    pub fn request_packet(&mut self, now: Instant) -> Packet {
+        self.next_seq = self.next_seq.wrapping_add(1);
+        Packet {
+            kind: PacketKind::TimeRequest,
+            seq: self.next_seq,
+            fec_group: 0,
+            session: 0,
+            timestamp_us: self.local_us(now),
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn handle_reply(&mut self, reply: &Packet, arrival: Instant) {
+        let Some(sent) = reply.payload.get(0..8).and_then(|b| b.try_into().ok()).map(u64::from_be_bytes) else {
+            return;
+        };
+        let received = self.local_us(arrival);
+        let Some(round_trip) = received.checked_sub(sent) else {
+            return;
+        };
+        // Assume the reply was stamped halfway through the round trip.
+        let offset = (sent + received) as f64 / 2.0 - reply.timestamp_us as f64;
+        self.samples.push_back((round_trip, offset));
+        if self.samples.len() > SAMPLES_KEPT {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+
+    fn best_sample(&self) -> Option<(u64, f64)> {
+        self.samples.iter().copied().min_by_key(|(round_trip, _)| *round_trip)
+    }
+
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.best_sample().map(|(round_trip, _)| Duration::from_micros(round_trip))
+    }
+
+    // When a sender timestamp happens on our clock, once at least one exchange succeeded.
+    pub fn sender_to_local(&self, sender_timestamp_us: u64) -> Option<Instant> {
+        let (_, offset) = self.best_sample()?;
+        let local_us = sender_timestamp_us as f64 + offset;
+        (local_us >= 0.0).then(|| self.epoch + Duration::from_micros(local_us as u64))
+    }
+
+    // Our `at` on the sender's stream clock; the inverse of `sender_to_local`.
+    pub fn local_to_sender(&self, at: Instant) -> Option<u64> {
+        let (_, offset) = self.best_sample()?;
+        let sender_us = self.local_us(at) as f64 - offset;
+        (sender_us >= 0.0).then_some(sender_us as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The sender's stream clock runs this far behind ours.
+    const OFFSET_US: u64 = 2_000;
+
+    // One exchange sent at `sent_us` on our clock, `there_us` on the way out and `back_us`
+    // on the way back, answered by a sender `OFFSET_US` behind.
+    fn exchange(clock: &mut SyncClock, sent_us: u64, there_us: u64, back_us: u64) {
+        let request = clock.request_packet(clock.epoch + Duration::from_micros(sent_us));
+        let reply = time_reply(&request, sent_us + there_us - OFFSET_US);
+        clock.handle_reply(&reply, clock.epoch + Duration::from_micros(sent_us + there_us + back_us));
+    }
+
+    #[test]
+    fn the_reply_echoes_the_request() {
+        let mut clock = SyncClock::new();
+        let request = clock.request_packet(clock.epoch + Duration::from_micros(1_234));
+        let reply = time_reply(&request, 99);
+        assert_eq!((reply.kind, reply.seq, reply.timestamp_us), (PacketKind::TimeReply, request.seq, 99));
+        assert_eq!(reply.payload, 1_234u64.to_be_bytes());
+    }
+
+    #[test]
+    fn requests_are_numbered() {
+        let mut clock = SyncClock::new();
+        let now = Instant::now();
+        let (first, second) = (clock.request_packet(now).seq, clock.request_packet(now).seq);
+        assert_eq!(second, first.wrapping_add(1));
+    }
+
+    #[test]
+    fn maps_the_sender_clock_onto_ours() {
+        let mut clock = SyncClock::new();
+        assert!(clock.round_trip().is_none());
+        assert!(clock.sender_to_local(0).is_none());
+        exchange(&mut clock, 10_000, 500, 500);
+        assert_eq!(clock.round_trip(), Some(Duration::from_micros(1_000)));
+        let at = clock.epoch + Duration::from_micros(50_000);
+        assert_eq!(clock.sender_to_local(50_000 - OFFSET_US), Some(at));
+        assert_eq!(clock.local_to_sender(at), Some(50_000 - OFFSET_US));
+    }
+
+    #[test]
+    fn trusts_the_fastest_exchange() {
+        let mut clock = SyncClock::new();
+        // Queued on the way back, so halfway is off by 4 ms.
+        exchange(&mut clock, 10_000, 500, 8_500);
+        exchange(&mut clock, 20_000, 300, 300);
+        exchange(&mut clock, 30_000, 2_000, 6_000);
+        assert_eq!(clock.round_trip(), Some(Duration::from_micros(600)));
+        assert_eq!(clock.local_to_sender(clock.epoch + Duration::from_micros(40_000)), Some(40_000 - OFFSET_US));
+    }
+
+    #[test]
+    fn forgets_old_exchanges() {
+        let mut clock = SyncClock::new();
+        exchange(&mut clock, 5_000, 100, 100);
+        for i in 0..SAMPLES_KEPT as u64 {
+            exchange(&mut clock, 10_000 * (i + 1), 1_000, 1_000);
+        }
+        assert_eq!(clock.round_trip(), Some(Duration::from_micros(2_000)));
+    }
+
+    #[test]
+    fn ignores_bad_replies() {
+        let mut clock = SyncClock::new();
+        let request = clock.request_packet(clock.epoch + Duration::from_micros(5_000));
+        let mut short = time_reply(&request, 0);
+        short.payload.truncate(4);
+        clock.handle_reply(&short, clock.epoch + Duration::from_micros(6_000));
+        // Arriving before it was sent: not a reply to that request.
+        clock.handle_reply(&time_reply(&request, 0), clock.epoch + Duration::from_micros(4_000));
+        assert!(clock.round_trip().is_none());
+    }
+
+    #[test]
+    fn reset_forgets_the_samples() {
+        let mut clock = SyncClock::new();
+        exchange(&mut clock, 10_000, 500, 500);
+        clock.reset();
+        assert!(clock.sender_to_local(0).is_none());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    config::Config,
+    fallback::{self, Engine},
+    presence::device_name,
+    transport::{Packet, PacketKind},
+};
+use std::{
+    fs::File,
+    io::Read,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// How often the native transport repeats it, so a receiver started late learns it soon.
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+
+// Who a stream comes from, so receivers can tell several senders on one network apart.
+// Carried as the MPEG-TS service, in RTCP SDES for the built-in engine's RTP, and in
+// `PacketKind::Announce` on the native transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamTag {
+    pub sender: String, // Hostname
+    pub session: String, // Random UUID, new for every stream
+    pub codec: String, // e.g. "aac 192k, 48000 Hz, 2 ch"
+}
+
+impl StreamTag {
+    pub fn new(config: &Config, engine: Engine) -> Self {
+        let codec = match engine {
+            Engine::Ffmpeg => format!("{} {}, {} Hz, {} ch", config.audio_codec, config.bitrate, config.sample_rate, config.channels),
+            Engine::BuiltIn => format!("{}, {} Hz, {} ch", fallback::codec(config), config.sample_rate, config.channels),
+        };
+        Self { sender: device_name().unwrap_or_else(|| "unknown".to_string()), session: random_uuid(), codec }
+    }
+
+    // "laptop (session 1b4e28ba, aac 192k, 48000 Hz, 2 ch)"
+    pub fn describe(&self) -> String {
+        format!("{} (session {}, {})", self.sender, self.session.get(..8).unwrap_or(&self.session), self.codec)
+    }
+
+    // Payload layout: sender, session and codec, one per line.
+    pub fn packet(&self, seq: u32, timestamp_us: u64) -> Packet {
+        let payload = format!("{}\n{}\n{}", self.sender, self.session, self.codec).into_bytes();
+        Packet { kind: PacketKind::Announce, seq, fec_group: 0, session: self.session_id(), timestamp_us, payload }
+    }
+
+    // The session in the native header's 24 bits: the UUID's first six hex digits, never 0.
+    pub fn session_id(&self) -> u32 {
+        u32::from_str_radix(self.session.get(..6).unwrap_or_default(), 16).unwrap_or(0).max(1)
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let payload = String::from_utf8_lossy(payload);
+        let mut lines = payload.lines();
+        let (sender, session, codec) = (lines.next()?, lines.next()?, lines.next()?);
+        Some(Self { sender: sender.to_string(), session: session.to_string(), codec: codec.to_string() })
+    }
+
+    // VLC and ffplay show the service name as the program's title.
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        vec![
+            "-metadata".to_string(),
+            format!("service_name={}", self.sender),
+            "-metadata".to_string(),
+            format!("service_provider=audio-streamer {}", self.session),
+        ]
+    }
+}
+
+// A version 4 UUID from the kernel's RNG, or from the clock if that can't be read.
+pub fn random_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    if File::open("/dev/urandom").and_then(|mut random| random.read_exact(&mut bytes)).is_err() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        bytes = (nanos ^ ((std::process::id() as u128) << 96)).to_be_bytes();
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
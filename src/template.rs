@@ -0,0 +1,90 @@
+use anyhow::{Result, bail};
+
+// Placeholders a template may use; `build_ffmpeg_command` supplies a value for each.
+pub const PLACEHOLDERS: [&str; 9] = ["source", "target", "codec", "bitrate", "sample_rate", "channels", "ip", "port", "filters"];
+// Without these ffmpeg would capture from nowhere or send somewhere other than our relay.
+const REQUIRED: [&str; 2] = ["source", "target"];
+
+// Splits like a shell would for a single command: whitespace separates arguments,
+// single or double quotes keep them together. No escapes or variables.
+fn split_args(template: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+
+    for c in template.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        bail!("Unterminated quote in argument template");
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+// Finds every `{name}` in the template, complaining about unknown or unclosed ones.
+fn placeholders_in(template: &str) -> Result<Vec<String>> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            bail!("Unclosed '{{' in argument template");
+        };
+        let name = &rest[start + 1..start + len];
+        if !PLACEHOLDERS.contains(&name) {
+            bail!("Unknown placeholder {{{}}}; available: {}", name, available());
+        }
+        found.push(name.to_string());
+        rest = &rest[start + len + 1..];
+    }
+    Ok(found)
+}
+
+fn available() -> String {
+    PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+}
+
+pub fn validate_template(template: &str) -> Result<()> {
+    let found = placeholders_in(template)?;
+    for required in REQUIRED {
+        if !found.iter().any(|name| name == required) {
+            bail!("Argument template must contain {{{}}}", required);
+        }
+    }
+    if split_args(template)?.is_empty() {
+        bail!("Argument template is empty");
+    }
+    Ok(())
+}
+
+// Substitutes after splitting, so values containing spaces stay a single argument.
+pub fn expand_template(template: &str, values: &[(&str, String)]) -> Result<Vec<String>> {
+    validate_template(template)?;
+    let args = split_args(template)?
+        .into_iter()
+        .map(|arg| {
+            values.iter().fold(arg, |arg, (name, value)| arg.replace(&format!("{{{}}}", name), value))
+        })
+        .collect();
+    Ok(args)
+}
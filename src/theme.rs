@@ -0,0 +1,128 @@
+use eframe::{Theme, egui};
+use egui::{Color32, Stroke};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_ACCENT: [u8; 3] = [110, 100, 255]; // A nice purple
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeMode {
+    Dark,
+    Light,
+    System,
+}
+
+impl ThemeMode {
+    pub const ALL: [ThemeMode; 3] = [ThemeMode::Dark, ThemeMode::Light, ThemeMode::System];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemeMode::Dark => "Dark",
+            ThemeMode::Light => "Light",
+            ThemeMode::System => "Follow system",
+        }
+    }
+
+    // The desktop doesn't always say; dark is what the app was designed around.
+    pub fn is_dark(self, system: Option<Theme>) -> bool {
+        match self {
+            ThemeMode::Dark => true,
+            ThemeMode::Light => false,
+            ThemeMode::System => system != Some(Theme::Light),
+        }
+    }
+}
+
+// Every color `update()` paints with, so the layout code never hard-codes one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub dark: bool,
+    pub accent: Color32,
+    pub text: Color32,
+    pub faint: Color32,
+    pub background: Color32,
+    pub title_bar: Color32,
+    pub border: Color32,
+    pub success: Color32,
+    pub warning: Color32,
+    pub error: Color32,
+    pub start_button: Color32,
+    pub stop_button: Color32,
+}
+
+impl Palette {
+    pub fn new(dark: bool, accent: [u8; 3]) -> Self {
+        let accent = Color32::from_rgb(accent[0], accent[1], accent[2]);
+        if dark {
+            Self {
+                dark,
+                accent,
+                text: Color32::from_gray(230),
+                faint: Color32::from_gray(120),
+                background: Color32::from_rgb(30, 30, 45),
+                title_bar: Color32::from_rgb(50, 50, 65),
+                border: Color32::WHITE,
+                success: Color32::from_rgb(76, 175, 80),
+                warning: Color32::from_rgb(255, 152, 0),
+                error: Color32::from_rgb(244, 67, 54),
+                start_button: Color32::from_rgb(70, 170, 70),
+                stop_button: Color32::from_rgb(200, 70, 70),
+            }
+        } else {
+            // Status colors are darkened to stay readable on the light background.
+            Self {
+                dark,
+                accent,
+                text: Color32::from_gray(30),
+                faint: Color32::from_gray(110),
+                background: Color32::from_rgb(245, 245, 250),
+                title_bar: Color32::from_rgb(222, 222, 235),
+                border: Color32::from_gray(160),
+                success: Color32::from_rgb(46, 125, 50),
+                warning: Color32::from_rgb(230, 120, 0),
+                error: Color32::from_rgb(198, 40, 40),
+                start_button: Color32::from_rgb(90, 185, 90),
+                stop_button: Color32::from_rgb(215, 90, 90),
+            }
+        }
+    }
+}
+
+pub fn apply(ctx: &egui::Context, palette: &Palette) {
+    let mut style = (*ctx.style()).clone();
+    style.visuals = if palette.dark { egui::Visuals::dark() } else { egui::Visuals::light() };
+    let visuals = &mut style.visuals;
+
+    visuals.window_rounding = 10.0.into();
+    visuals.window_shadow = if palette.dark { egui::epaint::Shadow::big_dark() } else { egui::epaint::Shadow::big_light() };
+    visuals.override_text_color = Some(palette.text);
+    visuals.selection.bg_fill = palette.accent;
+    // For text edit, etc.
+    visuals.extreme_bg_color = if palette.dark { Color32::from_rgb(10, 10, 15) } else { Color32::WHITE };
+
+    // Widget styling
+    let widget_visuals = &mut visuals.widgets;
+    widget_visuals.noninteractive.bg_fill = if palette.dark { Color32::from_gray(27) } else { Color32::from_gray(235) };
+    widget_visuals.noninteractive.bg_stroke = Stroke::new(1.0, if palette.dark { Color32::from_gray(200) } else { Color32::from_gray(180) });
+    widget_visuals.noninteractive.fg_stroke = Stroke::new(1.0, palette.faint);
+    widget_visuals.noninteractive.rounding = 5.0.into();
+
+    // Subtle backgrounds for buttons, darkening instead of lightening on light themes
+    let tint = |alpha| if palette.dark { Color32::from_white_alpha(alpha) } else { Color32::from_black_alpha(alpha) };
+    widget_visuals.inactive = widget_visuals.noninteractive;
+    widget_visuals.inactive.bg_fill = tint(10);
+
+    widget_visuals.hovered.bg_fill = tint(20);
+    widget_visuals.hovered.bg_stroke = Stroke::new(1.0, palette.accent);
+
+    widget_visuals.active.bg_fill = palette.accent;
+    widget_visuals.active.bg_stroke = Stroke::new(1.0, palette.accent);
+    widget_visuals.active.fg_stroke = Stroke::new(1.0, Color32::WHITE);
+
+    // Spacing and padding
+    style.spacing.item_spacing = egui::vec2(10.0, 8.0);
+    style.spacing.window_margin = egui::Margin::same(15.0);
+    style.spacing.button_padding = egui::vec2(12.0, 6.0);
+
+    ctx.set_style(style);
+}
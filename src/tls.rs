@@ -0,0 +1,87 @@
+use crate::{paths, presence::device_name};
+use anyhow::{Context, Result};
+use rcgen::{CertificateParams, DnType, KeyPair};
+use rustls::{
+    ServerConfig,
+    crypto::ring::default_provider,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+};
+use sha2::{Digest, Sha256};
+use std::{fs, io::Write, os::unix::fs::OpenOptionsExt, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{net::TcpStream, time::timeout};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+
+// A client that connects and never says hello shouldn't hold a connection open.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Where phones can download the certificate to install it, on every HTTPS output.
+pub const CERTIFICATE_PATH: &str = "/audio-streamer.crt";
+
+// Kept next to the config, so the fingerprint a phone was told to trust stays the same.
+fn directory() -> Result<PathBuf> {
+    Ok(paths::config_dir()?.join("tls"))
+}
+
+// A self-signed certificate for the HTTP and WebRTC outputs, made on first use.
+pub struct TlsIdentity {
+    acceptor: TlsAcceptor,
+    pub certificate: Arc<[u8]>, // DER
+    pub fingerprint: String,
+}
+
+impl TlsIdentity {
+    pub fn load_or_create() -> Result<Self> {
+        let dir = directory()?;
+        let (cert_path, key_path) = (dir.join("cert.der"), dir.join("key.der"));
+        let (certificate, key) = match (fs::read(&cert_path), fs::read(&key_path)) {
+            (Ok(certificate), Ok(key)) => (certificate, key),
+            _ => {
+                let (certificate, key) = generate()?;
+                fs::create_dir_all(&dir)?;
+                fs::write(&cert_path, &certificate).with_context(|| format!("Failed to write {}", cert_path.display()))?;
+                // Readable by us only, from the start.
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .mode(0o600)
+                    .open(&key_path)
+                    .and_then(|mut file| file.write_all(&key))
+                    .with_context(|| format!("Failed to write {}", key_path.display()))?;
+                (certificate, key)
+            }
+        };
+        let fingerprint = fingerprint(&certificate);
+        let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(vec![CertificateDer::from(certificate.clone())], PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)))
+            .context("The saved TLS certificate is invalid; delete it to make a new one")?;
+        Ok(Self { acceptor: TlsAcceptor::from(Arc::new(config)), certificate: certificate.into(), fingerprint })
+    }
+
+    pub async fn accept(&self, client: TcpStream) -> Result<TlsStream<TcpStream>> {
+        timeout(HANDSHAKE_TIMEOUT, self.acceptor.accept(client)).await.context("TLS handshake timed out")?.context("TLS handshake failed")
+    }
+}
+
+// Named for this machine, though phones reach it by IP and have to accept it either way.
+fn generate() -> Result<(Vec<u8>, Vec<u8>)> {
+    let host = device_name().unwrap_or_else(|| "audio-streamer".to_string());
+    let mut params = CertificateParams::new(vec![host.clone(), "localhost".to_string()])?;
+    params.distinguished_name.push(DnType::CommonName, format!("audio-streamer on {}", host));
+    let key = KeyPair::generate()?;
+    let certificate = params.self_signed(&key)?;
+    Ok((certificate.der().to_vec(), key.serialize_der()))
+}
+
+// SHA-256 of the certificate as browsers show it: "AB:CD:...".
+fn fingerprint(certificate: &[u8]) -> String {
+    Sha256::digest(certificate).iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(":")
+}
+
+pub fn certificate_response(certificate: &[u8]) -> Vec<u8> {
+    let mut response = format!("HTTP/1.0 200 OK\r\nContent-Type: application/x-x509-ca-cert\r\nContent-Length: {}\r\n\r\n", certificate.len()).into_bytes();
+    response.extend_from_slice(certificate);
+    response
+}
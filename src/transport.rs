@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    // Plain MPEG-TS over UDP; any player can receive it.
+    Udp,
+    // MPEG-TS wrapped with sequence numbers, timestamps and FEC;
+    // needs `audio-streamer --receive` on the other end.
+    Native,
+    // MPEG-TS over RIST (via ffmpeg's librist): retransmits lost packets within a
+    // recovery buffer, for lossy links where FEC alone isn't enough.
+    Rist,
+    // The encoder's own RTP packets, with no MPEG-TS, described by an SDP file that
+    // players fetch from us, see `sdp.rs`.
+    Rtp,
+}
+
+impl Transport {
+    pub const ALL: [Transport; 4] = [Transport::Udp, Transport::Native, Transport::Rist, Transport::Rtp];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Transport::Udp => "UDP (MPEG-TS)",
+            Transport::Native => "Native (sequenced + FEC)",
+            Transport::Rist => "RIST (retransmission)",
+            Transport::Rtp => "RTP (with SDP file)",
+        }
+    }
+}
+
+// Native packet layout: magic "AS" (2) | version (1) | kind (1) | seq (4) | fec group (1) | session (3) |
+// sender timestamp in µs (8) | payload. The session bytes were reserved before, so older
+// senders send 0 there and older receivers ignore them.
+pub const HEADER_LEN: usize = 20;
+const MAGIC: &[u8; 2] = b"AS";
+const VERSION: u8 = 2;
+const KIND_DATA: u8 = 0;
+const KIND_PARITY: u8 = 1;
+const KIND_TIME_REQUEST: u8 = 2;
+const KIND_TIME_REPLY: u8 = 3;
+const KIND_KEEPALIVE: u8 = 4;
+const KIND_CONTROL: u8 = 5;
+const KIND_ANNOUNCE: u8 = 6;
+const KIND_RESUMED: u8 = 7;
+const KIND_NOTIFY: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketKind {
+    Data,
+    Parity,
+    // Clock sync exchange between a receiver and the sender, see `sync.rs`.
+    TimeRequest,
+    TimeReply,
+    // Sent while the stream is paused so receivers know the sender is still there.
+    Keepalive,
+    // A receiver asking the sender to change something, see `remote.rs`.
+    Control,
+    // Who is sending, repeated every few seconds, see `tag.rs`.
+    Announce,
+    // A receiver picked the stream up again after a gap, see `presence::Resumption`.
+    Resumed,
+    // Something for a companion app to show, e.g. the track changed, see `notify.rs`.
+    Notify,
+}
+
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub kind: PacketKind,
+    // For data packets this is the packet's own sequence number; for parity packets
+    // it is the sequence number of the first data packet the parity covers.
+    pub seq: u32,
+    pub fec_group: u8,
+    // Which run of the sender it belongs to, see `StreamTag::session_id`; 0 when unknown, and
+    // from receivers, except in `Resumed`, where it names the session picked up again.
+    pub session: u32,
+    // Microseconds on the sender's monotonic clock since the relay started.
+    pub timestamp_us: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        buf.push(match self.kind {
+            PacketKind::Data => KIND_DATA,
+            PacketKind::Parity => KIND_PARITY,
+            PacketKind::TimeRequest => KIND_TIME_REQUEST,
+            PacketKind::TimeReply => KIND_TIME_REPLY,
+            PacketKind::Keepalive => KIND_KEEPALIVE,
+            PacketKind::Control => KIND_CONTROL,
+            PacketKind::Announce => KIND_ANNOUNCE,
+            PacketKind::Resumed => KIND_RESUMED,
+            PacketKind::Notify => KIND_NOTIFY,
+        });
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.push(self.fec_group);
+        buf.extend_from_slice(&self.session.to_be_bytes()[1..]);
+        buf.extend_from_slice(&self.timestamp_us.to_be_bytes());
+        buf.extend_from_slice(&self.payload);
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Packet> {
+        if data.len() < HEADER_LEN || &data[0..2] != MAGIC || data[2] != VERSION {
+            return None;
+        }
+        let kind = match data[3] {
+            KIND_DATA => PacketKind::Data,
+            KIND_PARITY => PacketKind::Parity,
+            KIND_TIME_REQUEST => PacketKind::TimeRequest,
+            KIND_TIME_REPLY => PacketKind::TimeReply,
+            KIND_KEEPALIVE => PacketKind::Keepalive,
+            KIND_CONTROL => PacketKind::Control,
+            KIND_ANNOUNCE => PacketKind::Announce,
+            KIND_RESUMED => PacketKind::Resumed,
+            KIND_NOTIFY => PacketKind::Notify,
+            _ => return None,
+        };
+        Some(Packet {
+            kind,
+            seq: u32::from_be_bytes(data[4..8].try_into().ok()?),
+            fec_group: data[8],
+            session: u32::from_be_bytes([0, data[9], data[10], data[11]]),
+            timestamp_us: u64::from_be_bytes(data[12..20].try_into().ok()?),
+            payload: data[HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+// Whether sequence number `a` comes before `b`, also across the wrap from u32::MAX to 0:
+// up to half the sequence space behind counts as before.
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+// XOR parity over a group of payloads. The parity payload starts with the XOR of
+// all payload lengths so a recovered packet can be trimmed back to its real size.
+pub fn xor_parity<'a>(payloads: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut parity: Vec<u8> = vec![0, 0];
+    for payload in payloads {
+        let len = (payload.len() as u16).to_be_bytes();
+        parity[0] ^= len[0];
+        parity[1] ^= len[1];
+        if parity.len() < payload.len() + 2 {
+            parity.resize(payload.len() + 2, 0);
+        }
+        for (p, b) in parity[2..].iter_mut().zip(payload) {
+            *p ^= b;
+        }
+    }
+    parity
+}
+
+// Rebuilds the single missing payload of a group from the parity and the others.
+pub fn recover_from_parity<'a>(parity: &[u8], others: impl Iterator<Item = &'a [u8]>) -> Option<Vec<u8>> {
+    if parity.len() < 2 {
+        return None;
+    }
+    let mut rebuilt = parity.to_vec();
+    for payload in others {
+        let len = (payload.len() as u16).to_be_bytes();
+        rebuilt[0] ^= len[0];
+        rebuilt[1] ^= len[1];
+        for (r, b) in rebuilt[2..].iter_mut().zip(payload) {
+            *r ^= b;
+        }
+    }
+    let len = u16::from_be_bytes([rebuilt[0], rebuilt[1]]) as usize;
+    if len > rebuilt.len() - 2 {
+        return None;
+    }
+    rebuilt.truncate(len + 2);
+    Some(rebuilt.split_off(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(kind: PacketKind, seq: u32, payload: &[u8]) -> Packet {
+        Packet { kind, seq, fec_group: 4, session: 0x00ab_cdef, timestamp_us: 123_456_789, payload: payload.to_vec() }
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let kinds = [
+            PacketKind::Data,
+            PacketKind::Parity,
+            PacketKind::TimeRequest,
+            PacketKind::TimeReply,
+            PacketKind::Keepalive,
+            PacketKind::Control,
+            PacketKind::Announce,
+            PacketKind::Resumed,
+            PacketKind::Notify,
+        ];
+        for kind in kinds {
+            let sent = packet(kind, u32::MAX, b"payload");
+            let bytes = sent.encode();
+            assert_eq!(bytes.len(), HEADER_LEN + 7);
+            let received = Packet::decode(&bytes).unwrap();
+            assert_eq!(received.kind, kind);
+            assert_eq!((received.seq, received.fec_group, received.session, received.timestamp_us), (u32::MAX, 4, 0x00ab_cdef, 123_456_789));
+            assert_eq!(received.payload, b"payload");
+        }
+    }
+
+    #[test]
+    fn the_session_keeps_three_bytes() {
+        let mut sent = packet(PacketKind::Data, 1, b"");
+        sent.session = 0x1234_5678;
+        assert_eq!(Packet::decode(&sent.encode()).unwrap().session, 0x0034_5678);
+    }
+
+    #[test]
+    fn decode_rejects_foreign_datagrams() {
+        let bytes = packet(PacketKind::Data, 1, b"x").encode();
+        assert!(Packet::decode(&bytes[..HEADER_LEN - 1]).is_none());
+        let mut wrong = bytes.clone();
+        wrong[0] = b'X';
+        assert!(Packet::decode(&wrong).is_none());
+        let mut wrong = bytes.clone();
+        wrong[2] = VERSION + 1;
+        assert!(Packet::decode(&wrong).is_none());
+        let mut wrong = bytes;
+        wrong[3] = 200;
+        assert!(Packet::decode(&wrong).is_none());
+    }
+
+    #[test]
+    fn seq_lt_wraps() {
+        assert!(seq_lt(1, 2));
+        assert!(!seq_lt(2, 1));
+        assert!(!seq_lt(5, 5));
+        assert!(seq_lt(u32::MAX, 0));
+        assert!(seq_lt(u32::MAX - 10, 3));
+        assert!(!seq_lt(3, u32::MAX - 10));
+    }
+
+    #[test]
+    fn parity_rebuilds_any_one_missing_payload() {
+        let payloads: [&[u8]; 4] = [b"first", b"second packet", b"", b"fourth!"];
+        let parity = xor_parity(payloads.iter().copied());
+        for missing in 0..payloads.len() {
+            let others = payloads.iter().enumerate().filter(|(i, _)| *i != missing).map(|(_, payload)| *payload);
+            assert_eq!(recover_from_parity(&parity, others).unwrap(), payloads[missing], "payload {}", missing);
+        }
+    }
+
+    #[test]
+    fn parity_rejects_a_short_payload() {
+        assert!(recover_from_parity(&[0], std::iter::empty()).is_none());
+        // The lengths claim more than the parity holds.
+        assert!(recover_from_parity(&[0, 9, 1, 2], std::iter::empty()).is_none());
+    }
+}
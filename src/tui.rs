@@ -0,0 +1,400 @@
+use anyhow::Result;
+use audio_streamer::{
+    audio::{AudioSource, get_audio_sources, get_best_source_index},
+    blocklist::blocked_message,
+    config::{Config, parse_target},
+    events::Event,
+    fallback::Engine,
+    history::History,
+    load::overload_message,
+    loudness,
+    meter::LevelMeter,
+    netwatch,
+    rollback,
+    streams::{EngineOptions, QUICK_MUTE, StreamEvent, StreamManager, silence_countdown},
+    xrun,
+};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event as TerminalEvent, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Gauge, List, ListItem, ListState, Paragraph},
+};
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+use tokio::runtime::Handle;
+
+// How long to wait for a key before redrawing, which also paces the level meter.
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+// The terminal front end for SSH sessions and headless machines: the same engine as
+// the GUI, driven from the keyboard.
+struct Tui {
+    config: Config,
+    config_path: PathBuf,
+    runtime_handle: Handle,
+    options: EngineOptions,
+    history: History,
+    events_tx: Sender<Event>,
+    events_rx: Receiver<Event>,
+    sources: Vec<AudioSource>, // Without the hidden ones
+    selected: ListState,
+    meter: Option<(String, LevelMeter)>, // Source name and its meter
+    streams: StreamManager,
+    target_input: Option<String>, // Set while the target is being typed
+    status: String,
+}
+
+// `options` as `EngineOptions::detect` found them, before the terminal is taken over.
+pub fn run(config: Config, config_path: PathBuf, options: EngineOptions, runtime_handle: Handle) -> Result<()> {
+    let (events_tx, events_rx) = mpsc::channel();
+    let mut tui = Tui {
+        options,
+        history: History::load(History::path_for(&config_path)),
+        status: if config.is_ip_configured() { "Ready to stream".to_string() } else { "Press t to set the target".to_string() },
+        config,
+        config_path,
+        runtime_handle,
+        events_tx,
+        events_rx,
+        sources: Vec::new(),
+        selected: ListState::default(),
+        meter: None,
+        streams: StreamManager::default(),
+        target_input: None,
+    };
+    tui.refresh_sources();
+
+    let mut terminal = ratatui::init();
+    let result = tui.run(&mut terminal);
+    ratatui::restore();
+
+    // Completes the sessions in the history before exiting.
+    tui.streams.stop_all(&tui.runtime_handle);
+    tui.poll_events();
+    if let Some((_, meter)) = tui.meter.take() {
+        meter.stop();
+    }
+    result
+}
+
+impl Tui {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            self.poll_events();
+            self.follow_selection();
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(FRAME_INTERVAL)? {
+                continue;
+            }
+            if let TerminalEvent::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key.code)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    fn refresh_sources(&self) {
+        let (events_tx, backend) = (self.events_tx.clone(), self.config.capture_backend);
+        self.runtime_handle.spawn(async move {
+            let _ = events_tx.send(Event::SourceListUpdated(get_audio_sources(backend).await.map_err(|e| e.to_string())));
+        });
+    }
+
+    fn selected_source(&self) -> Option<&AudioSource> {
+        self.sources.get(self.selected.selected()?)
+    }
+
+    // Keeps the meter on the highlighted source.
+    fn follow_selection(&mut self) {
+        let name = self.selected_source().map(|source| source.name.clone());
+        if self.meter.as_ref().map(|(metered, _)| metered) == name.as_ref() {
+            return;
+        }
+        if let Some((_, meter)) = self.meter.take() {
+            meter.stop();
+        }
+        let Some(name) = name else {
+            return;
+        };
+        match LevelMeter::start(&name, &self.runtime_handle) {
+            Ok(meter) => {
+                meter.set_gain(self.config.volume_percent, &self.runtime_handle);
+                self.meter = Some((name, meter));
+            }
+            Err(e) => self.status = format!("No level meter: {:#}", e),
+        }
+    }
+
+    fn poll_events(&mut self) {
+        let stream_events = self.streams.poll(&self.options, &self.runtime_handle);
+        let events: Vec<Event> = stream_events.into_iter().map(Event::Stream).chain(self.events_rx.try_iter()).collect();
+        for event in events {
+            match event {
+                Event::SourceListUpdated(Ok(sources)) => self.receive_sources(sources),
+                Event::SourceListUpdated(Err(e)) => self.status = format!("Failed to refresh sources: {}", e),
+                Event::Progress(message) => self.status = message,
+                Event::Stream(event) => self.stream_event(event),
+                _ => {} // Only the GUI starts the other background tasks
+            }
+        }
+    }
+
+    // The highlighted (or preferred) source stays selected if it still exists.
+    fn receive_sources(&mut self, sources: Vec<AudioSource>) {
+        let keep = self.selected_source().map(|source| source.name.clone()).or_else(|| self.config.preferred_source.clone());
+        self.sources = sources.into_iter().filter(|source| !source.is_hidden(&self.config.source_overrides)).collect();
+        let index = keep
+            .and_then(|name| self.sources.iter().position(|source| source.name == name))
+            .unwrap_or_else(|| get_best_source_index(&self.sources, &self.config.source_overrides));
+        self.selected.select((!self.sources.is_empty()).then_some(index));
+    }
+
+    fn stream_event(&mut self, event: StreamEvent) {
+        match event {
+            StreamEvent::Connected { device, .. } => self.status = format!("Playing on {} over Bluetooth", device),
+            StreamEvent::FailedOver { .. } => self.status = "⚠ Target stopped responding, switched to the backup".to_string(),
+            StreamEvent::ReceiverResumed { receiver, resumption, .. } => self.status = resumption.describe(receiver.as_deref()),
+            StreamEvent::SourceSwitched { id, restarted } => {
+                let source = self.streams.get(id).map(|stream| stream.source.label(&self.config.source_overrides).to_string()).unwrap_or_default();
+                self.status = if restarted { format!("Restarted on {}", source) } else { format!("Switched to {}", source) };
+            }
+            StreamEvent::Unsilenced => self.status = "🔊 Privacy mute over".to_string(),
+            StreamEvent::MuteFailed { reason, .. } => self.status = format!("⚠ Mute failed ({}), holding the stream back", reason),
+            StreamEvent::AppBlocked { app, .. } => self.status = blocked_message(&app),
+            StreamEvent::SoundServerLost { .. } => self.status = "⚠ Sound server gone, waiting for it".to_string(),
+            StreamEvent::SoundServerBack { .. } => self.status = "Sound server back, streaming again".to_string(),
+            StreamEvent::Overloaded { speed, lighter, .. } => self.status = overload_message(speed, lighter.as_deref()),
+            StreamEvent::SessionEnded { session, recordings, .. } => {
+                if let Some(session) = session
+                    && let Err(e) = self.history.record(session)
+                {
+                    self.status = format!("Failed to save session history: {}", e);
+                }
+                if let Some(config) = recordings {
+                    self.normalize_recordings(&config);
+                }
+            }
+            StreamEvent::Error { reason, .. } => self.status = format!("Streaming stopped unexpectedly: {}", reason),
+            StreamEvent::Started { .. } | StreamEvent::Stopped { .. } => {}
+        }
+    }
+
+    // As in the GUI: recordings are only complete once the stream ends.
+    fn normalize_recordings(&self, config: &Config) {
+        if !config.normalize_loudness || self.options.engine != Engine::Ffmpeg {
+            return;
+        }
+        for recording in loudness::recordings(config) {
+            let (ffmpeg, config, events_tx) = (self.options.ffmpeg.clone(), config.clone(), self.events_tx.clone());
+            self.runtime_handle.spawn(async move {
+                let message = match loudness::normalize_recording(&ffmpeg, &recording, &config).await {
+                    Ok(normalized) => format!("Normalized recording saved to {}", normalized.display()),
+                    Err(e) => format!("Loudness normalization failed: {:#}", e),
+                };
+                let _ = events_tx.send(Event::Progress(message));
+            });
+        }
+    }
+
+    // False to quit.
+    fn handle_key(&mut self, key: KeyCode) -> bool {
+        if let Some(input) = &mut self.target_input {
+            match key {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => self.apply_target(),
+                KeyCode::Esc => self.target_input = None,
+                _ => {}
+            }
+            return true;
+        }
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') if self.selected.selected().is_some_and(|index| index + 1 < self.sources.len()) => self.selected.select_next(),
+            KeyCode::Enter | KeyCode::Char('s') => self.start_stream(),
+            KeyCode::Char('x') => {
+                self.streams.stop_all(&self.runtime_handle);
+                self.status = "Streaming stopped".to_string();
+            }
+            KeyCode::Char('w') => self.switch_source(),
+            KeyCode::Char('m') => self.toggle_quick_mute(),
+            KeyCode::Char('p') | KeyCode::Char(' ') => self.toggle_pause(),
+            KeyCode::Char('t') => self.target_input = Some(format!("{}:{}", self.config.target_ip, self.config.target_port)),
+            KeyCode::Char('r') => {
+                self.refresh_sources();
+                self.status = "Refreshing sources...".to_string();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    // Takes the same forms as the GUI's target field, and saves the config.
+    fn apply_target(&mut self) {
+        let Some(input) = self.target_input.take() else {
+            return;
+        };
+        let (ip, port) = match parse_target(&input) {
+            Ok(target) => target,
+            Err(e) => {
+                self.status = format!("❌ {:#}", e);
+                return;
+            }
+        };
+        self.config.target_ip = ip.to_string();
+        if let Some(port) = port {
+            self.config.target_port = port;
+        }
+        // Looked up now, for the packet sizing when it starts.
+        netwatch::route(ip);
+        self.status = match rollback::save(&self.config_path, &self.config) {
+            Ok(()) => format!("Target set to {}:{}", self.config.target_ip, self.config.target_port),
+            Err(e) => format!("Target set, but saving the config failed: {:#}", e),
+        };
+    }
+
+    // Starts the highlighted source to the target, next to any streams already running.
+    fn start_stream(&mut self) {
+        if self.config.bluetooth_sink.is_none() && !self.config.is_ip_configured() {
+            self.status = "Press t to set the target first".to_string();
+            return;
+        }
+        let Some(source) = self.selected_source().cloned() else {
+            self.status = "No audio source selected".to_string();
+            return;
+        };
+        let label = source.label(&self.config.source_overrides).to_string();
+        self.status = match self.streams.start(source, self.config.clone(), &self.options, &self.runtime_handle) {
+            Ok((id, warning)) => format!(
+                "Streaming {} to {}{}",
+                label,
+                self.streams.get(id).map(|stream| stream.target()).unwrap_or_default(),
+                warning.map(|warning| format!(" ⚠ {}", warning)).unwrap_or_default()
+            ),
+            Err(e) => format!("Start failed: {:#}", e),
+        };
+    }
+
+    // Moves every running stream onto the highlighted source, without restarting them.
+    fn switch_source(&mut self) {
+        let Some(source) = self.selected_source().cloned() else {
+            return;
+        };
+        let label = source.label(&self.config.source_overrides).to_string();
+        let switching: Vec<u64> = self.streams.iter().filter(|stream| stream.source.name != source.name).map(|stream| stream.id).collect();
+        for id in switching {
+            if let Some(stream) = self.streams.get_mut(id)
+                && let Err(e) = stream.switch_source(source.clone(), &self.runtime_handle)
+            {
+                self.status = format!("Switching to {} failed: {:#}", label, e);
+                return;
+            }
+        }
+        self.status = format!("Switching to {}...", label);
+    }
+
+    // Pauses every stream, or resumes them all if any is paused.
+    fn toggle_pause(&mut self) {
+        let paused = !self.streams.iter().any(|stream| stream.is_paused());
+        for relay in self.streams.iter().filter_map(|stream| stream.relay()) {
+            relay.set_paused(paused);
+        }
+        if !self.streams.is_empty() {
+            self.status = if paused { "Paused (encoder still running)" } else { "Resumed" }.to_string();
+        }
+    }
+
+    // As in the GUI: silence for a few minutes, or the sound back early.
+    fn toggle_quick_mute(&mut self) {
+        if self.streams.silenced_for().is_some() {
+            self.streams.unsilence(&self.runtime_handle);
+            self.status = "🔊 Unmuted".to_string();
+        } else if !self.streams.is_empty() {
+            self.streams.silence_for(QUICK_MUTE, &self.runtime_handle);
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, meter, help] = Layout::vertical([Constraint::Length(3), Constraint::Min(6), Constraint::Length(3), Constraint::Length(1)]).areas(frame.area());
+        let [sources_area, streams_area] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body);
+
+        let target = match &self.target_input {
+            Some(input) => Line::styled(format!("Target: {}▏ (Enter to apply, Esc to cancel)", input), Style::new().fg(Color::Yellow)),
+            None => Line::from(format!("Target: {}:{} · {}", self.config.target_ip, self.config.target_port, self.config.audio_codec)),
+        };
+        let status = match self.streams.silenced_for() {
+            Some(left) => Line::styled(silence_countdown(left), Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+            None => Line::from(self.status.as_str()),
+        };
+        frame.render_widget(Paragraph::new(vec![target, status]).block(Block::bordered().title(" Audio Streamer ")), header);
+
+        let sources: Vec<ListItem> = self
+            .sources
+            .iter()
+            .map(|source| {
+                let marker = if source.is_running { "🔊 " } else { "   " };
+                ListItem::new(format!("{}{}", marker, source.label(&self.config.source_overrides)))
+            })
+            .collect();
+        let sources = List::new(sources)
+            .block(Block::bordered().title(" Sources "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(sources, sources_area, &mut self.selected);
+
+        let stats = self.streams.stats();
+        let mut lines = Vec::new();
+        for (stream, stats) in self.streams.iter().zip(&stats) {
+            lines.push(Line::styled(
+                format!("{} → {} · {}", stream.source.label(&self.config.source_overrides), stream.target(), stream.codec()),
+                Style::new().add_modifier(Modifier::BOLD),
+            ));
+            let elapsed = stream.session.as_ref().map_or(0, |(_, started)| started.elapsed().as_secs());
+            let kbps = (stats.bytes_sent * 8 / 1000).checked_div(elapsed).unwrap_or(0);
+            let receiver = match &stats.receiver {
+                Some(receiver) => receiver.describe(),
+                None if stream.relay().is_some() => "No receiver detected".to_string(),
+                None => String::new(),
+            };
+            let state = if stats.paused { " · paused" } else if stats.on_backup { " · on backup" } else { "" };
+            lines.push(Line::from(format!("  {:.1} MB, {} kbps · {}{}", stats.bytes_sent as f64 / 1_000_000.0, kbps, receiver, state)));
+            if let Some(xruns) = xrun::summary(stats.capture_overruns, stats.encoder_underruns) {
+                lines.push(Line::styled(format!("  {}", xruns), Style::new().fg(Color::Yellow)));
+            }
+        }
+        if lines.is_empty() {
+            lines.push(Line::from("Not streaming"));
+        }
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Streams ")), streams_area);
+
+        let (ratio, label) = match &self.meter {
+            // -60..0 dBFS across the bar, which reads like a mixer's meter.
+            Some((_, meter)) => {
+                let clipped = if meter.is_clipping() { " · clipping" } else { "" };
+                let phase = meter.correlation().map(|value| format!(" · phase {:+.2}", value)).unwrap_or_default();
+                (((meter.peak_dbfs() + 60.0) / 60.0) as f64, format!("{:.0} dBFS{}{}", meter.peak_dbfs(), phase, clipped))
+            }
+            None => (0.0, "No source".to_string()),
+        };
+        let color = if ratio > 0.95 { Color::Red } else if ratio > 0.8 { Color::Yellow } else { Color::Green };
+        frame.render_widget(
+            Gauge::default().block(Block::bordered().title(" Level ")).gauge_style(Style::new().fg(color)).ratio(ratio.clamp(0.0, 1.0)).label(label),
+            meter,
+        );
+
+        frame.render_widget(
+            Paragraph::new("↑↓ select · Enter start · w switch to it · p pause · m mute 5 min · x stop all · t target · r refresh · q quit").style(Style::new().fg(Color::DarkGray)),
+            help,
+        );
+    }
+}
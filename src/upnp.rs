@@ -0,0 +1,97 @@
+use crate::log;
+use anyhow::{Context, Result};
+use igd::{AddPortError, Gateway, PortMappingProtocol, SearchOptions, search_gateway};
+use std::{
+    future::Future,
+    net::{Ipv4Addr, SocketAddrV4, UdpSocket},
+    time::Duration,
+};
+use tokio::task::spawn_blocking;
+
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(3);
+// Short enough that a crashed receiver doesn't leave the port open for long.
+const LEASE_SECS: u32 = 600;
+const RENEW_INTERVAL: Duration = Duration::from_secs(LEASE_SECS as u64 / 2);
+const DESCRIPTION: &str = "audio-streamer receiver";
+
+// A UDP port forwarded from the router's public address to this machine.
+#[derive(Debug, Clone)]
+pub struct PortMapping {
+    gateway: Gateway,
+    local: SocketAddrV4,
+    lease_secs: u32,
+    pub external: SocketAddrV4,
+}
+
+// The address this machine uses on the gateway's network, which is what the router must forward to.
+fn local_ipv4_towards(gateway: SocketAddrV4) -> Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(gateway)?;
+    match socket.local_addr()?.ip() {
+        std::net::IpAddr::V4(ip) => Ok(ip),
+        std::net::IpAddr::V6(_) => anyhow::bail!("No IPv4 route to the router"),
+    }
+}
+
+impl PortMapping {
+    // Blocking: discovery and the SOAP requests are synchronous.
+    pub fn request(port: u16) -> Result<Self> {
+        let gateway = search_gateway(SearchOptions { timeout: Some(SEARCH_TIMEOUT), ..Default::default() })
+            .context("No UPnP router found (is UPnP enabled on it?)")?;
+        let local = SocketAddrV4::new(local_ipv4_towards(gateway.addr)?, port);
+        let external_ip = gateway.get_external_ip().context("The router did not report its public IP")?;
+
+        // Older routers only accept permanent mappings; we remove it on exit either way.
+        let lease_secs = match gateway.add_port(PortMappingProtocol::UDP, port, local, LEASE_SECS, DESCRIPTION) {
+            Err(AddPortError::OnlyPermanentLeasesSupported) => {
+                gateway.add_port(PortMappingProtocol::UDP, port, local, 0, DESCRIPTION)?;
+                0
+            }
+            result => {
+                result.with_context(|| format!("The router refused to forward UDP port {}", port))?;
+                LEASE_SECS
+            }
+        };
+
+        Ok(Self { gateway, local, lease_secs, external: SocketAddrV4::new(external_ip, port) })
+    }
+
+    fn renew(&self) -> Result<()> {
+        self.gateway.add_port(PortMappingProtocol::UDP, self.external.port(), self.local, self.lease_secs, DESCRIPTION)?;
+        Ok(())
+    }
+
+    fn remove(&self) -> Result<()> {
+        self.gateway.remove_port(PortMappingProtocol::UDP, self.external.port())?;
+        Ok(())
+    }
+}
+
+// Runs `task` with `port` forwarded on the router, renewing the lease as needed.
+// The mapping is removed when the task ends or on Ctrl+C.
+pub async fn with_port_forwarded(port: u16, task: impl Future<Output = Result<()>>) -> Result<()> {
+    let mapping = spawn_blocking(move || PortMapping::request(port)).await??;
+    println!("Router forwards {} to this machine; senders on the internet should target it.", mapping.external);
+    println!("Note: the stream is not encrypted while it crosses the internet.");
+
+    let mut renew_timer = tokio::time::interval(RENEW_INTERVAL);
+    renew_timer.tick().await; // The first tick is immediate
+    tokio::pin!(task);
+    let result = loop {
+        tokio::select! {
+            result = &mut task => break result,
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+            _ = renew_timer.tick(), if mapping.lease_secs > 0 => {
+                let renewing = mapping.clone();
+                if let Err(e) = spawn_blocking(move || renewing.renew()).await? {
+                    log!("\nFailed to renew the port mapping: {:#}", e);
+                }
+            }
+        }
+    };
+
+    if let Err(e) = spawn_blocking(move || mapping.remove()).await? {
+        log!("\nFailed to remove the port mapping: {:#}", e);
+    }
+    result
+}
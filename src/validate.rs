@@ -0,0 +1,99 @@
+use crate::config::Config;
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+use std::net::IpAddr;
+
+// Audio encoders the pipeline is known to work with; ffmpeg has others, but a typo
+// here otherwise only shows up as a failed start.
+pub const KNOWN_CODECS: [&str; 11] = ["aac", "libfdk_aac", "libopus", "libmp3lame", "mp2", "ac3", "eac3", "flac", "libvorbis", "pcm_s16be", "pcm_s24be"];
+
+// One setting in the config file that can't be used as it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub field: String,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self { field: field.to_string(), message: message.into() }
+    }
+
+    // "target_port: must be between 1 and 65535"
+    pub fn describe(&self) -> String {
+        format!("{}: {}", self.field, self.message)
+    }
+}
+
+// Values of the right type that still can't work.
+pub fn check(config: &Config) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if config.target_port == 0 {
+        problems.push(Problem::new("target_port", "must be between 1 and 65535"));
+    }
+    if !config.target_ip.is_empty() && config.target_ip.parse::<IpAddr>().is_err() {
+        problems.push(Problem::new("target_ip", format!("'{}' is not an IP address", config.target_ip)));
+    }
+    if !config.backup_target_ip.is_empty() {
+        if config.backup_target_ip.parse::<IpAddr>().is_err() {
+            problems.push(Problem::new("backup_target_ip", format!("'{}' is not an IP address", config.backup_target_ip)));
+        }
+        if config.backup_target_port == 0 {
+            problems.push(Problem::new("backup_target_port", "must be between 1 and 65535"));
+        }
+    }
+    if !(1..=8).contains(&config.channels) {
+        problems.push(Problem::new("channels", format!("must be between 1 and 8, not {}", config.channels)));
+    }
+    if !(8000..=192_000).contains(&config.sample_rate) {
+        problems.push(Problem::new("sample_rate", format!("must be between 8000 and 192000 Hz, not {}", config.sample_rate)));
+    }
+    if !KNOWN_CODECS.contains(&config.audio_codec.as_str()) {
+        problems.push(Problem::new("audio_codec", format!("'{}' is not one of {}", config.audio_codec, KNOWN_CODECS.join(", "))));
+    }
+    if !config.rendezvous_room.trim().is_empty() && config.rendezvous_secret.is_empty() {
+        problems.push(Problem::new("rendezvous_secret", "is needed with a rendezvous room, the same as the receiver's --room-secret"));
+    }
+    if config.bitrate_bps().is_none() && !(config.is_lossless() && config.bitrate.trim().is_empty()) {
+        problems.push(Problem::new("bitrate", format!("'{}' is not a bitrate like 192k, 1.5M or 128000", config.bitrate)));
+    }
+    problems
+}
+
+// Loads the config file, giving each field of the wrong type its default instead of
+// failing the whole file, and reports those along with what `check` finds. Only
+// broken JSON is an error.
+pub fn parse(json: &str) -> Result<(Config, Vec<Problem>)> {
+    let value: Value = serde_json::from_str(json).context("The config file is not valid JSON")?;
+    let Value::Object(fields) = value else {
+        bail!("The config file should hold a JSON object");
+    };
+    let mut problems = Vec::new();
+    let mut usable = Map::new();
+    for (field, value) in fields {
+        // Every other field has a default, so this fails only on this one.
+        let alone = Map::from_iter([(field.clone(), value.clone())]);
+        match serde_json::from_value::<Config>(Value::Object(alone)) {
+            Ok(_) => {
+                usable.insert(field, value);
+            }
+            Err(e) => problems.push(Problem::new(&field, format!("{} (using the default for now)", e))),
+        }
+    }
+    let config = Config::from_json(&Value::Object(usable).to_string())?;
+    problems.extend(check(&config));
+    Ok((config, problems))
+}
+
+// `config` with the fields of `problems` back at their defaults and the rest as it was.
+pub fn reset(config: &Config, problems: &[Problem]) -> Result<Config> {
+    let (Value::Object(mut fields), Value::Object(defaults)) = (serde_json::to_value(config)?, serde_json::to_value(Config::default())?) else {
+        bail!("A config always serializes to an object");
+    };
+    for problem in problems {
+        if let Some(default) = defaults.get(&problem.field) {
+            fields.insert(problem.field.clone(), default.clone());
+        }
+    }
+    Ok(serde_json::from_value(Value::Object(fields))?)
+}
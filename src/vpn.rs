@@ -0,0 +1,81 @@
+use serde_json::Value;
+use std::{net::IpAddr, process::Command};
+
+// A device reachable over a VPN mesh, offered as a streaming target.
+#[derive(Debug, Clone)]
+pub struct VpnPeer {
+    pub name: String,
+    pub ip: IpAddr,
+    pub network: &'static str, // "Tailscale" or "WireGuard"
+    pub online: bool,
+}
+
+impl VpnPeer {
+    pub fn label(&self) -> String {
+        let status = if self.online { "" } else { " (offline)" };
+        format!("{} {} — {}{}", self.network, self.name, self.ip, status)
+    }
+}
+
+// `tailscale status --json` lists peers under "Peer", keyed by node key.
+fn tailscale_peers() -> Vec<VpnPeer> {
+    let Ok(output) = Command::new("tailscale").args(["status", "--json"]).output() else {
+        return Vec::new();
+    };
+    let Ok(status) = serde_json::from_slice::<Value>(&output.stdout) else {
+        return Vec::new();
+    };
+    let Some(peers) = status["Peer"].as_object() else {
+        return Vec::new();
+    };
+
+    peers
+        .values()
+        .filter_map(|peer| {
+            // IPv4 first: players on phones handle it more reliably than the 100:: addresses.
+            let ips: Vec<IpAddr> = peer["TailscaleIPs"].as_array()?.iter().filter_map(|ip| ip.as_str()?.parse().ok()).collect();
+            let ip = ips.iter().find(|ip| ip.is_ipv4()).or(ips.first()).copied()?;
+            let name = peer["HostName"].as_str().filter(|n| !n.is_empty()).unwrap_or("unnamed").to_string();
+            Some(VpnPeer { name, ip, network: "Tailscale", online: peer["Online"].as_bool().unwrap_or(false) })
+        })
+        .collect()
+}
+
+// `wg show all allowed-ips` prints "<interface>\t<public key>\t<ip/prefix> ...". It needs
+// root or CAP_NET_ADMIN; without them there is nothing to suggest. Only single-host
+// routes are offered, since a wider range is a site behind the peer, not the peer itself.
+fn wireguard_peers() -> Vec<VpnPeer> {
+    let Ok(output) = Command::new("wg").args(["show", "all", "allowed-ips"]).output() else {
+        return Vec::new();
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let interface = fields.next()?;
+            let key = fields.next()?;
+            let ip = fields.next()?.split_whitespace().find_map(|range| {
+                let (ip, prefix) = range.split_once('/')?;
+                let ip: IpAddr = ip.parse().ok()?;
+                let host_prefix = if ip.is_ipv4() { "32" } else { "128" };
+                (prefix == host_prefix).then_some(ip)
+            })?;
+            let name = format!("{} peer {}", interface, key.chars().take(8).collect::<String>());
+            // WireGuard has no presence; a peer is only known to be configured.
+            Some(VpnPeer { name, ip, network: "WireGuard", online: true })
+        })
+        .collect()
+}
+
+// Online peers first; offline ones are still listed, as a phone may only be asleep.
+pub async fn discover_peers() -> Vec<VpnPeer> {
+    let mut peers = tailscale_peers();
+    // Tailscale on a kernel WireGuard interface would otherwise show up twice.
+    for peer in wireguard_peers() {
+        if !peers.iter().any(|known| known.ip == peer.ip) {
+            peers.push(peer);
+        }
+    }
+    peers.sort_by(|a, b| b.online.cmp(&a.online).then_with(|| a.name.cmp(&b.name)));
+    peers
+}
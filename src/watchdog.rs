@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+// How often the relay probes the active target.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// An ICMP error this recent means the target is still refusing.
+const REFUSED_WINDOW: Duration = Duration::from_secs(2);
+// Native receivers report back every second; this much silence means they're gone.
+const FEEDBACK_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Decides when the active target is gone, from two signals: ICMP port or host
+// unreachable errors on a socket connected to it, and the keepalives native
+// receivers send back. Receivers that never sent feedback (VLC, mpv) are judged
+// on ICMP alone, so a silently unplugged phone isn't detected for them.
+pub struct Watchdog {
+    after: Duration,
+    refused_at: Option<Instant>,
+    feedback_at: Option<Instant>,
+    down_since: Option<Instant>,
+}
+
+impl Watchdog {
+    pub fn new(after: Duration) -> Self {
+        Self { after, refused_at: None, feedback_at: None, down_since: None }
+    }
+
+    pub fn refused(&mut self, now: Instant) {
+        self.refused_at = Some(now);
+    }
+
+    pub fn feedback(&mut self, now: Instant) {
+        self.feedback_at = Some(now);
+    }
+
+    // For a new target, whose history says nothing about the old one.
+    pub fn reset(&mut self) {
+        self.refused_at = None;
+        self.feedback_at = None;
+        self.down_since = None;
+    }
+
+    // Called every `CHECK_INTERVAL`; true once the target has looked gone for `after`.
+    pub fn check(&mut self, now: Instant) -> bool {
+        let refusing = self.refused_at.is_some_and(|at| now.duration_since(at) < REFUSED_WINDOW);
+        let silent = self.feedback_at.is_some_and(|at| now.duration_since(at) > FEEDBACK_TIMEOUT);
+        if !refusing && !silent {
+            self.down_since = None;
+            return false;
+        }
+        let since = *self.down_since.get_or_insert(now);
+        now.duration_since(since) >= self.after
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{config::Config, fallback::Engine, log};
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+// Nothing from the encoder for this long while streaming is a gap the receiver hears.
+const UNDERRUN_GAP: Duration = Duration::from_millis(250);
+// Without -flush_packets ffmpeg fills whole UDP packets, which at low bitrates can be
+// a few hundred ms apart; only gaps well past the usual spacing count.
+const UNDERRUN_SPACINGS: u32 = 4;
+// Audio this far behind the wall clock went missing on the way in.
+const OVERRUN_TOLERANCE: Duration = Duration::from_millis(100);
+// A machine under load would otherwise log one warning per dropout; each kind has its own.
+const WARN_INTERVAL: Duration = Duration::from_secs(30);
+
+// Dropouts in one stream's pipeline. An overrun is captured audio lost before the
+// encoder got it, because the capture buffer filled up; an underrun is the encoder
+// falling silent while it should be sending, which empties the receiver's buffer.
+#[derive(Debug)]
+pub struct Xruns {
+    overruns: AtomicU64,
+    underruns: AtomicU64,
+    overrun_advice: String,
+    underrun_advice: String,
+    overrun_warned: Mutex<Option<Instant>>,
+    underrun_warned: Mutex<Option<Instant>>,
+}
+
+impl Xruns {
+    // The advice names the settings that give each stage more room.
+    pub fn new(config: &Config, engine: Engine) -> Self {
+        let overrun_advice = match engine {
+            Engine::BuiltIn => format!("raise the packet size from {} ms to {} ms, or turn on realtime priority", config.packet_millis, config.packet_millis * 2),
+            Engine::Ffmpeg if config.low_latency => "turn off low latency mode, which keeps ffmpeg's capture buffer small, or turn on realtime priority".to_string(),
+            Engine::Ffmpeg => "turn on realtime priority, or close other busy programs".to_string(),
+        };
+        let underrun_advice = format!(
+            "turn on realtime priority, or raise the receiver's jitter buffer from {} ms to {} ms so it rides out the gaps",
+            config.jitter_target_ms,
+            (config.jitter_target_ms * 2).min(config.jitter_max_ms.max(config.jitter_target_ms))
+        );
+        Self { overruns: AtomicU64::new(0), underruns: AtomicU64::new(0), overrun_advice, underrun_advice, overrun_warned: Mutex::new(None), underrun_warned: Mutex::new(None) }
+    }
+
+    fn warn(warned: &Mutex<Option<Instant>>, what: String, advice: &str) {
+        let mut warned = warned.lock().unwrap();
+        if warned.is_none_or(|at| at.elapsed() >= WARN_INTERVAL) {
+            log!("⚠ {}; {}", what, advice);
+            *warned = Some(Instant::now());
+        }
+    }
+
+    pub fn overrun(&self, lost: Option<Duration>) {
+        let count = self.overruns.fetch_add(1, Ordering::Relaxed) + 1;
+        let lost = lost.map_or_else(String::new, |lost| format!(", {} ms lost", lost.as_millis()));
+        Self::warn(&self.overrun_warned, format!("Capture overrun ({} so far{})", count, lost), &self.overrun_advice);
+    }
+
+    pub fn underrun(&self, gap: Duration) {
+        let count = self.underruns.fetch_add(1, Ordering::Relaxed) + 1;
+        Self::warn(&self.underrun_warned, format!("Encoder underrun: nothing to send for {} ms ({} so far)", gap.as_millis(), count), &self.underrun_advice);
+    }
+
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+}
+
+// For status displays; None while there have been none.
+pub fn summary(overruns: u64, underruns: u64) -> Option<String> {
+    (overruns > 0 || underruns > 0).then(|| format!("⚠ {} capture overruns · {} encoder underruns", overruns, underruns))
+}
+
+// Compares how much audio has arrived with how long it took. Capture runs on the sound
+// card's clock, so when the two part ways audio was dropped before we read it.
+#[derive(Debug, Default)]
+pub struct AudioClock {
+    anchor: Option<Instant>,
+    audio: Duration,
+}
+
+impl AudioClock {
+    // Returns how much went missing, when it did; counting then starts over.
+    pub fn advance(&mut self, audio: Duration, now: Instant) -> Option<Duration> {
+        let anchor = *self.anchor.get_or_insert(now);
+        self.audio += audio;
+        let behind = now.saturating_duration_since(anchor + self.audio);
+        if behind <= OVERRUN_TOLERANCE {
+            return None;
+        }
+        self.anchor = Some(now);
+        self.audio = Duration::ZERO;
+        Some(behind)
+    }
+}
+
+// Spacing of the encoder's chunks as they reach the relay.
+#[derive(Debug, Default)]
+pub struct ChunkGaps {
+    last: Option<Instant>,
+    spacing: Option<Duration>, // Smoothed
+}
+
+impl ChunkGaps {
+    // Returns the gap before this chunk when it was long enough to be an underrun.
+    pub fn arrived(&mut self, now: Instant) -> Option<Duration> {
+        let gap = now.duration_since(self.last.replace(now)?);
+        let usual = self.spacing.unwrap_or(gap);
+        // An underrun stays out of the average, so a run of them keeps counting.
+        if gap > UNDERRUN_GAP.max(usual * UNDERRUN_SPACINGS) {
+            return Some(gap);
+        }
+        self.spacing = Some((usual * 7 + gap) / 8);
+        None
+    }
+
+    // After a pause or anything else that stops the chunks on purpose.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}